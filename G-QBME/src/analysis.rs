@@ -0,0 +1,304 @@
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{Biomolecule, Vector3};
+use crate::linalg::symmetric_eigen;
+use std::collections::HashMap;
+
+/// Principal moments (ascending, Da*A^2) and the matching right-handed unit axes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrincipalAxes {
+    pub center: Vector3, // centre of mass, Angstroms
+    pub moments: [f64; 3],
+    pub axes: [Vector3; 3],
+}
+
+/// Optimal rigid-body fit of a mobile structure onto a reference:
+/// x' = R (x - mobile_center) + reference_center
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Superposition {
+    pub rotation: [[f64; 3]; 3],
+    pub mobile_center: Vector3,    // Angstroms
+    pub reference_center: Vector3, // Angstroms
+    pub rmsd: f64,                 // Angstroms, after the fit
+}
+
+impl Superposition {
+    pub fn apply(&self, position: &Vector3) -> Vector3 {
+        let s = *position - self.mobile_center;
+        let row = |r: &[f64; 3]| r[0] * s.x + r[1] * s.y + r[2] * s.z;
+        Vector3::new(row(&self.rotation[0]), row(&self.rotation[1]), row(&self.rotation[2])) + self.reference_center
+    }
+
+    /// t in the equivalent x' = R x + t, Angstroms
+    pub fn translation(&self) -> Vector3 {
+        self.apply(&Vector3::default())
+    }
+
+    /// Copy of `molecule` moved by the fit
+    pub fn transform(&self, molecule: &Biomolecule) -> Biomolecule {
+        let mut moved = molecule.clone();
+        for atom in &mut moved.atoms {
+            atom.position = self.apply(&atom.position);
+        }
+        moved
+    }
+}
+
+/// Deviation of one residue's atoms after superposition
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResidueDeviation {
+    pub chain_id: String,
+    pub residue_seq: i32,
+    pub residue_name: String,
+    pub atoms: usize,
+    pub rmsd: f64,          // Angstroms
+    pub max_deviation: f64, // Angstroms
+}
+
+impl Biomolecule {
+    /// I = \sum m (|r|^2 1 - r r^T) about the centre of mass, Da*A^2
+    pub fn inertia_tensor(&self) -> [[f64; 3]; 3] {
+        let com = self.center_of_mass();
+        let mut t = [[0.0; 3]; 3];
+        for atom in &self.atoms {
+            let r = atom.position - com;
+            let r = [r.x, r.y, r.z];
+            let r2 = r[0] * r[0] + r[1] * r[1] + r[2] * r[2];
+            for (i, row) in t.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    *value += atom.mass * (if i == j { r2 } else { 0.0 } - r[i] * r[j]);
+                }
+            }
+        }
+        t
+    }
+
+    /// Eigen-decomposition of the inertia tensor; axes[2] = axes[0] x axes[1]
+    pub fn principal_axes(&self) -> PrincipalAxes {
+        let eigen = symmetric_eigen(&self.inertia_tensor().map(|row| row.to_vec()));
+        let axis = |k: usize| Vector3::new(eigen.vectors[k][0], eigen.vectors[k][1], eigen.vectors[k][2]);
+        let (a, b) = (axis(0), axis(1));
+        PrincipalAxes {
+            center: self.center_of_mass(),
+            moments: [eigen.values[0], eigen.values[1], eigen.values[2]],
+            axes: [a, b, a.cross(&b)],
+        }
+    }
+
+    /// Mass-weighted radius of gyration about the centre of mass, Angstroms
+    pub fn radius_of_gyration(&self) -> f64 {
+        let total: f64 = self.atoms.iter().map(|a| a.mass).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let com = self.center_of_mass();
+        let second: f64 = self.atoms.iter().map(|a| a.mass * (a.position - com).dot(&(a.position - com))).sum();
+        (second / total).sqrt()
+    }
+
+    /// RMSD to `reference` in the current frames, without any fitting
+    pub fn rmsd_to(&self, reference: &Biomolecule) -> Result<f64, GqbmeError> {
+        check_matched(self, reference)?;
+        let sum: f64 = self.atoms.iter().zip(&reference.atoms).map(|(a, b)| (a.position - b.position).dot(&(a.position - b.position))).sum();
+        Ok((sum / self.atoms.len() as f64).sqrt())
+    }
+
+    /// Kabsch superposition of this molecule onto `reference`, atom i onto atom i, equal weights
+    /// The rotation comes from the top eigenvector of Horn's 4x4 quaternion matrix, so it is
+    /// always proper (no reflections) and needs no SVD.
+    pub fn superpose(&self, reference: &Biomolecule) -> Result<Superposition, GqbmeError> {
+        check_matched(self, reference)?;
+        let n = self.atoms.len() as f64;
+        let centroid = |m: &Biomolecule| m.atoms.iter().fold(Vector3::default(), |acc, a| acc + a.position) * (1.0 / n);
+        let (mobile_center, reference_center) = (centroid(self), centroid(reference));
+
+        // S_ab = \sum x_a y_b over centred mobile x and reference y
+        let mut s = [[0.0; 3]; 3];
+        for (a, b) in self.atoms.iter().zip(&reference.atoms) {
+            let x = a.position - mobile_center;
+            let y = b.position - reference_center;
+            let (x, y) = ([x.x, x.y, x.z], [y.x, y.y, y.z]);
+            for (i, row) in s.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    *value += x[i] * y[j];
+                }
+            }
+        }
+        let [[sxx, sxy, sxz], [syx, syy, syz], [szx, szy, szz]] = s;
+        let horn = vec![
+            vec![sxx + syy + szz, syz - szy, szx - sxz, sxy - syx],
+            vec![syz - szy, sxx - syy - szz, sxy + syx, szx + sxz],
+            vec![szx - sxz, sxy + syx, -sxx + syy - szz, syz + szy],
+            vec![sxy - syx, szx + sxz, syz + szy, -sxx - syy + szz],
+        ];
+        let eigen = symmetric_eigen(&horn);
+        let q = &eigen.vectors[3];
+        let (q0, q1, q2, q3) = (q[0], q[1], q[2], q[3]);
+        let rotation = [
+            [q0 * q0 + q1 * q1 - q2 * q2 - q3 * q3, 2.0 * (q1 * q2 - q0 * q3), 2.0 * (q1 * q3 + q0 * q2)],
+            [2.0 * (q1 * q2 + q0 * q3), q0 * q0 - q1 * q1 + q2 * q2 - q3 * q3, 2.0 * (q2 * q3 - q0 * q1)],
+            [2.0 * (q1 * q3 - q0 * q2), 2.0 * (q2 * q3 + q0 * q1), q0 * q0 - q1 * q1 - q2 * q2 + q3 * q3],
+        ];
+
+        let mut fit = Superposition { rotation, mobile_center, reference_center, rmsd: 0.0 };
+        // Measured directly rather than from the eigenvalue, which cancels badly near zero
+        let sum: f64 = self
+            .atoms
+            .iter()
+            .zip(&reference.atoms)
+            .map(|(a, b)| {
+                let d = fit.apply(&a.position) - b.position;
+                d.dot(&d)
+            })
+            .sum();
+        fit.rmsd = (sum / n).sqrt();
+        Ok(fit)
+    }
+
+    /// Copy of this molecule superposed onto `reference`
+    pub fn aligned_to(&self, reference: &Biomolecule) -> Result<Biomolecule, GqbmeError> {
+        Ok(self.superpose(reference)?.transform(self))
+    }
+
+    /// Per-atom distance to `reference` after applying `fit`, Angstroms
+    pub fn atom_deviations(&self, reference: &Biomolecule, fit: &Superposition) -> Result<Vec<f64>, GqbmeError> {
+        check_matched(self, reference)?;
+        Ok(self.atoms.iter().zip(&reference.atoms).map(|(a, b)| fit.apply(&a.position).dist(&b.position)).collect())
+    }
+
+    /// Per-residue RMSD and worst atom after applying `fit`, in order of first appearance
+    /// Residues come from the reference's PDB records; atoms without one are their own entry
+    /// (chain "", residue_seq = atom index, residue_name = element symbol).
+    pub fn residue_deviations(&self, reference: &Biomolecule, fit: &Superposition) -> Result<Vec<ResidueDeviation>, GqbmeError> {
+        let deviations = self.atom_deviations(reference, fit)?;
+        let mut profile: Vec<ResidueDeviation> = Vec::new();
+        let mut sums: Vec<f64> = Vec::new();
+        let mut slots: HashMap<(String, i32, String), usize> = HashMap::new();
+        for (index, (atom, d)) in reference.atoms.iter().zip(deviations).enumerate() {
+            let (chain_id, residue_seq, residue_name) = match &atom.record {
+                Some(r) => (r.chain_id.clone(), r.residue_seq, r.residue_name.clone()),
+                None => (String::new(), index as i32, atom.element.symbol().to_string()),
+            };
+            let slot = *slots.entry((chain_id, residue_seq, residue_name)).or_insert_with_key(|(chain_id, residue_seq, residue_name)| {
+                profile.push(ResidueDeviation { chain_id: chain_id.clone(), residue_seq: *residue_seq, residue_name: residue_name.clone(), atoms: 0, rmsd: 0.0, max_deviation: 0.0 });
+                sums.push(0.0);
+                profile.len() - 1
+            });
+            let entry = &mut profile[slot];
+            entry.atoms += 1;
+            entry.max_deviation = entry.max_deviation.max(d);
+            sums[slot] += d * d;
+        }
+        for (entry, sum) in profile.iter_mut().zip(sums) {
+            entry.rmsd = (sum / entry.atoms as f64).sqrt();
+        }
+        Ok(profile)
+    }
+}
+
+/// Same non-zero atom count, same element at every index, finite positions
+fn check_matched(mobile: &Biomolecule, reference: &Biomolecule) -> Result<(), GqbmeError> {
+    mobile.validate()?;
+    reference.validate()?;
+    if mobile.atoms.len() != reference.atoms.len() {
+        return Err(GqbmeError::AtomCountMismatch { expected: reference.atoms.len(), found: mobile.atoms.len() });
+    }
+    if mobile.atoms.is_empty() {
+        return Err(GqbmeError::EmptyMolecule);
+    }
+    if let Some(atom) = mobile.atoms.iter().zip(&reference.atoms).position(|(a, b)| a.element != b.element) {
+        return Err(GqbmeError::ElementMismatch { atom });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna_builder::{DnaBuilder, HelixForm};
+    use crate::gqbme_engine::Atom;
+
+    fn helix() -> Biomolecule {
+        DnaBuilder::new("ACGTTG", HelixForm::B).expect("valid sequence").build()
+    }
+
+    /// Copy of `molecule` with every position mapped by `f`
+    fn moved(molecule: &Biomolecule, f: impl Fn(Vector3) -> Vector3) -> Biomolecule {
+        let mut copy = molecule.clone();
+        for atom in &mut copy.atoms {
+            atom.position = f(atom.position);
+        }
+        copy
+    }
+
+    /// Rotation by `angle` radians about the unit `axis` (Rodrigues)
+    fn rotation(axis: Vector3, angle: f64) -> [[f64; 3]; 3] {
+        let (s, c) = angle.sin_cos();
+        let [x, y, z] = [axis.x, axis.y, axis.z];
+        [
+            [c + x * x * (1.0 - c), x * y * (1.0 - c) - z * s, x * z * (1.0 - c) + y * s],
+            [y * x * (1.0 - c) + z * s, c + y * y * (1.0 - c), y * z * (1.0 - c) - x * s],
+            [z * x * (1.0 - c) - y * s, z * y * (1.0 - c) + x * s, c + z * z * (1.0 - c)],
+        ]
+    }
+
+    fn determinant(m: &[[f64; 3]; 3]) -> f64 {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    #[test]
+    fn superpose_recovers_a_rigid_motion() {
+        let reference = helix();
+        let axis = Vector3::new(1.0, -2.0, 0.5) * (1.0 / 5.25f64.sqrt());
+        let r = rotation(axis, 2.1);
+        let shift = Vector3::new(12.0, -3.5, 40.0);
+        let mobile = moved(&reference, |p| Vector3::new(r[0][0] * p.x + r[0][1] * p.y + r[0][2] * p.z, r[1][0] * p.x + r[1][1] * p.y + r[1][2] * p.z, r[2][0] * p.x + r[2][1] * p.y + r[2][2] * p.z) + shift);
+
+        assert!(mobile.rmsd_to(&reference).unwrap() > 10.0);
+        let fit = mobile.superpose(&reference).unwrap();
+        assert!(fit.rmsd < 1e-9, "rmsd {}", fit.rmsd);
+        // The fit undoes R, so it is R^T
+        for (i, row) in fit.rotation.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                assert!((value - r[j][i]).abs() < 1e-9, "R[{}][{}] = {} vs {}", i, j, value, r[j][i]);
+            }
+        }
+        assert!(mobile.aligned_to(&reference).unwrap().rmsd_to(&reference).unwrap() < 1e-9);
+    }
+
+    #[test]
+    fn superpose_never_reflects() {
+        let reference = helix();
+        let mirrored = moved(&reference, |p| Vector3::new(-p.x, p.y, p.z));
+        let fit = mirrored.superpose(&reference).unwrap();
+        assert!((determinant(&fit.rotation) - 1.0).abs() < 1e-9, "det {}", determinant(&fit.rotation));
+        // A right-handed helix cannot be rotated onto its left-handed image
+        assert!(fit.rmsd > 1.0, "rmsd {}", fit.rmsd);
+        let deviations = mirrored.atom_deviations(&reference, &fit).unwrap();
+        let rms = (deviations.iter().map(|d| d * d).sum::<f64>() / deviations.len() as f64).sqrt();
+        assert!((rms - fit.rmsd).abs() < 1e-9);
+    }
+
+    #[test]
+    fn residue_deviations_group_by_residue_in_order_of_first_appearance() {
+        let reference = helix();
+        // Shift chain B's third residue only; the fit stays the identity
+        let shifted = |atom: &Atom| atom.record.as_ref().is_some_and(|r| r.chain_id == "B" && r.residue_seq == 9);
+        let mut mobile = reference.clone();
+        for atom in mobile.atoms.iter_mut().filter(|a| shifted(a)) {
+            atom.position += Vector3::new(0.3, 0.0, 0.4);
+        }
+        let identity = Superposition { rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], mobile_center: Vector3::default(), reference_center: Vector3::default(), rmsd: 0.0 };
+        let profile = mobile.residue_deviations(&reference, &identity).unwrap();
+
+        assert_eq!(profile.len(), 12);
+        let order: Vec<(&str, i32)> = profile.iter().map(|r| (r.chain_id.as_str(), r.residue_seq)).collect();
+        let expected: Vec<(&str, i32)> = (1..=6).map(|s| ("A", s)).chain((7..=12).map(|s| ("B", s))).collect();
+        assert_eq!(order, expected);
+        assert_eq!(profile.iter().map(|r| r.atoms).sum::<usize>(), reference.atoms.len());
+        for entry in &profile {
+            let moved = entry.chain_id == "B" && entry.residue_seq == 9;
+            let expected = if moved { 0.5 } else { 0.0 };
+            assert!((entry.rmsd - expected).abs() < 1e-12 && (entry.max_deviation - expected).abs() < 1e-12, "{:?}", entry);
+        }
+    }
+}
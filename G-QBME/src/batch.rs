@@ -0,0 +1,162 @@
+use crate::density_grid::DensityGrid;
+use crate::gqbme_engine::{RelativisticSolver, Vector3, ANGSTROM_TO_M};
+use crate::kernel::PairKernel;
+use crate::octree::OctreeSolver;
+use std::thread;
+
+/// Worker count from `std::thread::available_parallelism`, 1 if unknown
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// `items.iter().map(f)` split into contiguous chunks over `threads` scoped threads
+/// Each result is computed by exactly one thread and results keep their input order,
+/// so the output is bit-identical for every thread count.
+pub fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    if threads == 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk = items.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(chunk).map(|part| scope.spawn(move || part.iter().map(f).collect::<Vec<R>>())).collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    })
+}
+
+/// \phi from point `sources` (position in Angstroms, strength) at every target on `threads` workers
+/// Sources coinciding with a target are skipped. Each target sums the sources in order with the
+/// same arithmetic, so the values do not depend on `threads`.
+pub fn potentials_from_sources(targets: &[Vector3], sources: &[(Vector3, f64)], kernel: &PairKernel, threads: usize) -> Vec<f64> {
+    parallel_map(targets, threads, |target| {
+        let mut potential = 0.0;
+        for (position, strength) in sources {
+            let r = target.dist(position) * ANGSTROM_TO_M;
+            if r > 0.0 {
+                potential += kernel.potential(*strength, r);
+            }
+        }
+        potential
+    })
+}
+
+/// -\nabla\phi from point `sources` at every target on `threads` workers
+pub fn fields_from_sources(targets: &[Vector3], sources: &[(Vector3, f64)], kernel: &PairKernel, threads: usize) -> Vec<Vector3> {
+    parallel_map(targets, threads, |target| {
+        let mut field = Vector3::default();
+        for (position, strength) in sources {
+            let r = (*target - *position) * ANGSTROM_TO_M;
+            if r.norm() > 0.0 {
+                field += kernel.field(*strength, r);
+            }
+        }
+        field
+    })
+}
+
+impl RelativisticSolver {
+    /// `calculate_potential` at every target, spread over all cores
+    pub fn calculate_potentials<G: DensityGrid + ?Sized>(targets: &[Vector3], grid: &G) -> Vec<f64> {
+        Self::calculate_potentials_with_threads(targets, grid, default_threads())
+    }
+
+    /// As `calculate_potentials` on `threads` workers; every target sums the voxels in
+    /// storage order with the same arithmetic, so each value equals `calculate_potential`
+    pub fn calculate_potentials_with_threads<G: DensityGrid + ?Sized>(targets: &[Vector3], grid: &G, threads: usize) -> Vec<f64> {
+        potentials_from_sources(targets, &grid.occupied(), &PairKernel::gravity(), threads)
+    }
+
+    /// `calculate_field` at every target on `threads` workers
+    pub fn calculate_fields_with_threads<G: DensityGrid + ?Sized>(targets: &[Vector3], grid: &G, threads: usize) -> Vec<Vector3> {
+        fields_from_sources(targets, &grid.occupied(), &PairKernel::gravity(), threads)
+    }
+}
+
+impl OctreeSolver {
+    /// Tree-walk \Phi at every target on `threads` workers
+    pub fn potentials_with_threads(&self, targets: &[Vector3], threads: usize) -> Vec<f64> {
+        parallel_map(targets, threads, |p| self.potential_at(p))
+    }
+
+    /// Tree-walk g at every target on `threads` workers
+    pub fn fields_with_threads(&self, targets: &[Vector3], threads: usize) -> Vec<Vector3> {
+        parallel_map(targets, threads, |p| self.field_at(p))
+    }
+
+    /// Exact atom sums (\Phi, g) at every target on `threads` workers
+    pub fn direct_with_threads(&self, targets: &[Vector3], threads: usize) -> Vec<(f64, Vector3)> {
+        parallel_map(targets, threads, |p| self.direct_potential_and_field(p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deposition::DepositionScheme;
+    use crate::dna_builder::{DnaBuilder, HelixForm};
+    use crate::gqbme_engine::{Biomolecule, MassDensityGrid};
+    use crate::grid_sizing::GridFit;
+    use crate::octree::{MultipoleOrder, Octree};
+
+    fn duplex() -> Biomolecule {
+        DnaBuilder::new("ACGTAC", HelixForm::B).expect("valid sequence").build()
+    }
+
+    #[test]
+    fn parallel_map_keeps_input_order() {
+        let items: Vec<usize> = (0..101).collect();
+        for threads in [0, 1, 2, 7, 101, 500] {
+            assert_eq!(parallel_map(&items, threads, |&i| i * i), items.iter().map(|i| i * i).collect::<Vec<_>>());
+        }
+        assert!(parallel_map(&[] as &[usize], 4, |&i| i).is_empty());
+    }
+
+    #[test]
+    fn grid_potentials_are_bit_identical_across_thread_counts() {
+        let molecule = duplex();
+        let mut grid = MassDensityGrid::fitted_to(&molecule, &GridFit::new(2.0, 1.5)).expect("small grid");
+        assert!(grid.map_biomolecule_with(&molecule, DepositionScheme::CloudInCell).is_lossless());
+        let targets: Vec<Vector3> = molecule.atoms.iter().map(|a| a.position).collect();
+
+        let serial: Vec<f64> = targets.iter().map(|t| RelativisticSolver::calculate_potential(t, &grid)).collect();
+        for threads in [1, 2, 3, 8] {
+            assert_eq!(RelativisticSolver::calculate_potentials_with_threads(&targets, &grid, threads), serial, "{} threads", threads);
+        }
+        let fields = RelativisticSolver::calculate_fields_with_threads(&targets, &grid, 1);
+        for threads in [2, 5] {
+            assert_eq!(RelativisticSolver::calculate_fields_with_threads(&targets, &grid, threads), fields, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn tree_potentials_are_bit_identical_across_thread_counts() {
+        let molecule = duplex();
+        let solver = OctreeSolver::new(Octree::from_biomolecule(&molecule), 0.5, MultipoleOrder::Quadrupole);
+        let targets: Vec<Vector3> = molecule.atoms.iter().map(|a| a.position + Vector3::new(0.3, -0.2, 0.1)).collect();
+        let serial: Vec<f64> = targets.iter().map(|t| solver.potential_at(t)).collect();
+        for threads in [1, 3, 16] {
+            assert_eq!(solver.potentials_with_threads(&targets, threads), serial, "{} threads", threads);
+            assert_eq!(solver.direct_with_threads(&targets, threads), solver.direct_with_threads(&targets, 1));
+        }
+    }
+
+    #[test]
+    fn coincident_sources_are_skipped() {
+        let sources = [(Vector3::new(0.0, 0.0, 0.0), 2.0), (Vector3::new(1.0, 0.0, 0.0), 3.0)];
+        let targets = [sources[0].0, sources[1].0];
+        let kernel = PairKernel::gravity();
+        let phi = potentials_from_sources(&targets, &sources, &kernel, 2);
+        assert_eq!(phi[0], kernel.potential(3.0, ANGSTROM_TO_M));
+        assert_eq!(phi[1], kernel.potential(2.0, ANGSTROM_TO_M));
+        assert!(fields_from_sources(&targets, &sources, &kernel, 2).iter().all(|f| f.norm().is_finite()));
+    }
+}
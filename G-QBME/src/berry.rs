@@ -0,0 +1,263 @@
+use crate::batch::parallel_map;
+use crate::fft::Complex;
+use crate::linalg::{hermitian_lanczos_lowest, symmetric_eigen};
+use crate::tight_binding::{EigenSolver, TightBinding};
+use std::f64::consts::PI;
+use std::fmt;
+
+/// `angle` mapped into (-\pi, \pi]
+pub fn wrap_phase(angle: f64) -> f64 {
+    let wrapped = angle - 2.0 * PI * (angle / (2.0 * PI)).round();
+    if wrapped <= -PI { wrapped + 2.0 * PI } else { wrapped }
+}
+
+/// <a|b> = \sum_j conj(a_j) b_j
+fn overlap(a: &[Complex], b: &[Complex]) -> Complex {
+    a.iter().zip(b).fold(Complex::ZERO, |sum, (x, y)| sum + x.conj() * *y)
+}
+
+/// Geometric phase of one state along a sampled path, radians
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BerryPhase {
+    pub phase: f64,       // in (-\pi, \pi]
+    pub min_overlap: f64, // smallest |<\psi_k|\psi_{k+1}>| along the path; near 0 the sampling is too coarse
+    /// |<\psi_last|\psi_0>| of an open path's Pancharatnam closure, small when the ends differ a lot
+    pub closure_overlap: Option<f64>,
+    pub samples: usize,
+}
+
+impl fmt::Display for BerryPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.9} rad over {} samples (min overlap {:.4}", self.phase, self.samples, self.min_overlap)?;
+        match self.closure_overlap {
+            Some(closure) => write!(f, ", closure {:.4})", closure),
+            None => write!(f, ")"),
+        }
+    }
+}
+
+/// Discrete Berry phase \gamma = -arg \prod_k <\psi_k|\psi_{k+1}>, taken cyclically so the last
+/// state overlaps the first. Every state enters once as a bra and once as a ket, so each
+/// state's arbitrary phase cancels: the result is gauge invariant. A `closed` path's list
+/// stops one sample short of the start; an open path's final overlap is the Pancharatnam
+/// closure back to the first state.
+pub fn discrete_berry_phase(states: &[Vec<Complex>], closed: bool) -> BerryPhase {
+    let mut phase = 0.0;
+    let mut overlaps = Vec::with_capacity(states.len());
+    for (k, state) in states.iter().enumerate() {
+        let o = overlap(state, &states[(k + 1) % states.len()]);
+        overlaps.push(o.norm_sqr().sqrt());
+        phase -= o.im.atan2(o.re);
+    }
+    let closure_overlap = if closed { None } else { overlaps.pop() };
+    BerryPhase { phase: wrap_phase(phase), min_overlap: overlaps.iter().copied().fold(1.0, f64::min), closure_overlap, samples: states.len() }
+}
+
+/// How the twist phase \theta is swept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TwistPath {
+    /// \theta over [0, 2\pi) in `samples` steps; H(2\pi) = H(0), so the loop closes exactly
+    Closed { samples: usize },
+    /// \theta from 0 to `end` radians at `samples` points, both ends included
+    Open { samples: usize, end: f64 },
+}
+
+impl TwistPath {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TwistPath::Closed { .. } => "closed",
+            TwistPath::Open { .. } => "open",
+        }
+    }
+
+    /// The sampled twist phases
+    pub fn angles(&self) -> Vec<f64> {
+        match *self {
+            TwistPath::Closed { samples } => (0..samples).map(|k| 2.0 * PI * k as f64 / samples as f64).collect(),
+            TwistPath::Open { samples, end } => (0..samples).map(|k| end * k as f64 / (samples.max(2) - 1) as f64).collect(),
+        }
+    }
+}
+
+/// Berry phases of H0 and H0 + V along one path, and how much of the difference V explains
+/// Only the non-uniform part of V can move eigenvectors; a constant m\Phi shift, however large,
+/// leaves the phase untouched. `linear` extrapolates the response to a probe of that part,
+/// `probe` eV at its largest site, so it resolves perturbations far below the rounding of the
+/// direct difference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BerryResponse {
+    pub unperturbed: BerryPhase,
+    pub perturbed: BerryPhase,
+    pub direct: f64,  // wrapped perturbed - unperturbed phase, rad
+    pub linear: f64,  // first-order phase change from the non-uniform part of V, rad
+    pub uniform: f64, // mean of V, eV
+    pub spread: f64,  // largest |V - mean|, eV
+    pub probe: f64,   // eV
+}
+
+impl TightBinding {
+    /// Eigenvector `band` of H(\theta) = H0 + diag(`on_site`), every stacking coupling carrying the
+    /// Peierls phase e^{i\theta} a carrier picks up per base-pair step, as a complex site vector
+    /// The dense solver works on the real symmetric [[A, -B], [B, A]] of H = A + iB, whose
+    /// levels come in pairs whose vectors are the same complex state up to a phase; Lanczos
+    /// iterates on H itself, so its levels are not doubled.
+    pub fn twisted_state(&self, on_site: &[f64], theta: f64, band: usize, solver: EigenSolver) -> Vec<Complex> {
+        let n = self.len();
+        assert!(band < n, "band {} outside a {}-site model", band, n);
+        let (cos, sin) = (theta.cos(), theta.sin());
+        let vector = match solver.resolve(n) {
+            EigenSolver::Dense => {
+                let mut m = vec![vec![0.0; 2 * n]; 2 * n];
+                for (i, site) in self.sites.iter().enumerate() {
+                    m[i][i] = site.energy + on_site[i];
+                    m[n + i][n + i] = site.energy + on_site[i];
+                }
+                // H_ij = -t e^{i\theta} for i < j
+                for &(i, j, t) in &self.couplings {
+                    let (re, im) = (-t * cos, -t * sin);
+                    for (r, c, a, b) in [(i, j, re, im), (j, i, re, -im)] {
+                        m[r][c] = a;
+                        m[n + r][n + c] = a;
+                        m[r][n + c] = -b;
+                        m[n + r][c] = b;
+                    }
+                }
+                symmetric_eigen(&m).vectors.swap_remove(2 * band)
+            }
+            _ => {
+                let apply = |x: &[f64], y: &mut [f64]| {
+                    let (x_re, x_im) = x.split_at(n);
+                    for (i, site) in self.sites.iter().enumerate() {
+                        let e = site.energy + on_site[i];
+                        y[i] = e * x_re[i];
+                        y[n + i] = e * x_im[i];
+                    }
+                    for &(i, j, t) in &self.couplings {
+                        let (re, im) = (-t * cos, -t * sin);
+                        // (re + i im) x_j into row i, (re - i im) x_i into row j
+                        y[i] += re * x_re[j] - im * x_im[j];
+                        y[n + i] += im * x_re[j] + re * x_im[j];
+                        y[j] += re * x_re[i] + im * x_im[i];
+                        y[n + j] += re * x_im[i] - im * x_re[i];
+                    }
+                };
+                let mut eigen = hermitian_lanczos_lowest(n, band + 1, 1e-12, apply);
+                assert!(eigen.vectors.len() > band, "Lanczos found only {} levels", eigen.vectors.len());
+                eigen.vectors.swap_remove(band)
+            }
+        };
+        (0..n).map(|i| Complex::new(vector[i], vector[n + i])).collect()
+    }
+
+    /// Discrete Berry phase of level `band` along `path`, the samples solved on `threads` workers
+    pub fn berry_phase(&self, on_site: &[f64], band: usize, path: &TwistPath, solver: EigenSolver, threads: usize) -> BerryPhase {
+        let states = parallel_map(&path.angles(), threads, |&theta| self.twisted_state(on_site, theta, band, solver));
+        discrete_berry_phase(&states, matches!(path, TwistPath::Closed { .. }))
+    }
+
+    /// Berry phase of level `band` with and without the on-site perturbation `on_site` (eV)
+    pub fn berry_response(&self, on_site: &[f64], band: usize, path: &TwistPath, solver: EigenSolver, threads: usize) -> BerryResponse {
+        const PROBE: f64 = 1e-4; // eV; small against the hopping, large against rounding
+
+        let zero = vec![0.0; self.len()];
+        let unperturbed = self.berry_phase(&zero, band, path, solver, threads);
+        let perturbed = self.berry_phase(on_site, band, path, solver, threads);
+        let uniform = on_site.iter().sum::<f64>() / on_site.len().max(1) as f64;
+        let spread = on_site.iter().map(|v| (v - uniform).abs()).fold(0.0, f64::max);
+        let linear = if spread > 0.0 {
+            // Central difference along the normalised non-uniform part, scaled back to its size
+            let probe = |sign: f64| -> Vec<f64> { on_site.iter().map(|v| sign * PROBE * (v - uniform) / spread).collect() };
+            let plus = self.berry_phase(&probe(1.0), band, path, solver, threads);
+            let minus = self.berry_phase(&probe(-1.0), band, path, solver, threads);
+            wrap_phase(plus.phase - minus.phase) / (2.0 * PROBE) * spread
+        } else {
+            0.0
+        };
+        BerryResponse { unperturbed, perturbed, direct: wrap_phase(perturbed.phase - unperturbed.phase), linear, uniform, spread, probe: PROBE }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gqbme_engine::{Vector3, ELECTRON_MASS_DA};
+    use crate::tight_binding::Site;
+
+    fn chain(energies: &[f64], t: f64) -> TightBinding {
+        let sites = energies
+            .iter()
+            .enumerate()
+            .map(|(i, &energy)| Site { label: format!("A{}", i + 1), position: Vector3::new(0.0, 0.0, 3.38 * i as f64), energy })
+            .collect();
+        let couplings = (1..energies.len()).map(|i| (i - 1, i, t)).collect();
+        TightBinding { sites, couplings, particle_mass: ELECTRON_MASS_DA }
+    }
+
+    fn sequence_chain(n: usize) -> TightBinding {
+        let energies: Vec<f64> = (0..n).map(|i| [8.24, 8.87, 7.75, 9.14][i * 7 % 4] + 0.01 * i as f64).collect();
+        chain(&energies, 0.1)
+    }
+
+    #[test]
+    fn wrap_phase_lands_in_half_open_interval() {
+        assert!((wrap_phase(3.0 * PI) - PI).abs() < 1e-12);
+        assert!((wrap_phase(-PI) - PI).abs() < 1e-12);
+        assert!((wrap_phase(2.0 * PI + 0.25) - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn phase_ignores_the_gauge_of_each_state() {
+        let states: Vec<Vec<Complex>> = (0..12)
+            .map(|k| {
+                let theta = 2.0 * PI * k as f64 / 12.0;
+                vec![Complex::from_polar(0.6, 0.0), Complex::from_polar(0.8, theta)]
+            })
+            .collect();
+        let gauged: Vec<Vec<Complex>> = states.iter().enumerate().map(|(k, s)| s.iter().map(|c| *c * Complex::from_polar(1.0, 0.7 * k as f64)).collect()).collect();
+        let (a, b) = (discrete_berry_phase(&states, true), discrete_berry_phase(&gauged, true));
+        assert!((a.phase - b.phase).abs() < 1e-12, "{} vs {}", a.phase, b.phase);
+        assert!((a.min_overlap - b.min_overlap).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dense_and_lanczos_agree_above_the_lowest_band() {
+        for n in [6, 20] {
+            let model = sequence_chain(n);
+            let zero = vec![0.0; n];
+            for band in 0..3 {
+                for theta in [0.0, 0.9, 2.5] {
+                    let dense = model.twisted_state(&zero, theta, band, EigenSolver::Dense);
+                    let lanczos = model.twisted_state(&zero, theta, band, EigenSolver::Lanczos);
+                    let o = overlap(&dense, &lanczos).norm_sqr().sqrt();
+                    assert!((o - 1.0).abs() < 1e-8, "n = {}, band {}, theta {}: |<dense|lanczos>| = {}", n, band, theta, o);
+                }
+                let path = TwistPath::Closed { samples: 32 };
+                let dense = model.berry_phase(&zero, band, &path, EigenSolver::Dense, 1);
+                let lanczos = model.berry_phase(&zero, band, &path, EigenSolver::Lanczos, 1);
+                assert!(wrap_phase(dense.phase - lanczos.phase).abs() < 1e-8, "n = {}, band {}: {} vs {}", n, band, dense.phase, lanczos.phase);
+            }
+        }
+    }
+
+    #[test]
+    fn open_chain_twist_is_a_gauge_winding() {
+        // On an open chain the Peierls phase is psi_j -> e^{-ij\theta} psi_j, so the loop's
+        // phase is 2\pi <j> (mod 2\pi) up to the discretization
+        let model = sequence_chain(6);
+        let zero = vec![0.0; 6];
+        let band = 1;
+        let spectrum = model.spectrum(&zero, band + 1, EigenSolver::Dense);
+        let mean_site: f64 = spectrum.vectors[band].iter().enumerate().map(|(j, a)| j as f64 * a * a).sum();
+        let berry = model.berry_phase(&zero, band, &TwistPath::Closed { samples: 256 }, EigenSolver::Lanczos, 2);
+        assert!(wrap_phase(berry.phase - 2.0 * PI * mean_site).abs() < 1e-3, "{} vs {}", berry.phase, wrap_phase(2.0 * PI * mean_site));
+    }
+
+    #[test]
+    fn uniform_shift_leaves_the_phase_unchanged() {
+        let model = sequence_chain(8);
+        let response = model.berry_response(&[0.05; 8], 1, &TwistPath::Closed { samples: 24 }, EigenSolver::Dense, 1);
+        assert!(response.direct.abs() < 1e-9, "{}", response.direct);
+        assert!(response.linear.abs() < 1e-12, "{}", response.linear);
+        assert!((response.uniform - 0.05).abs() < 1e-15);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::gqbme_engine::Biomolecule;
+use std::collections::HashMap;
+
+/// Slack (Angstroms) added to the sum of covalent radii when inferring bonds
+pub const BOND_TOLERANCE: f64 = 0.45;
+
+/// Pairs closer than this are treated as overlapping copies, not bonds
+const MIN_BOND_LENGTH: f64 = 0.4;
+
+/// A covalent bond between atoms `i < j`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bond {
+    pub i: usize,
+    pub j: usize,
+    pub length: f64, // Angstroms
+}
+
+impl Biomolecule {
+    /// Bonds from interatomic distances, d <= r_i + r_j + `BOND_TOLERANCE`
+    pub fn infer_bonds(&self) -> Vec<Bond> {
+        self.infer_bonds_with(BOND_TOLERANCE)
+    }
+
+    /// As `infer_bonds` with a custom tolerance; atoms are hashed into cells so this is O(N)
+    pub fn infer_bonds_with(&self, tolerance: f64) -> Vec<Bond> {
+        let max_radius = self.atoms.iter().map(|a| a.element.covalent_radius()).fold(0.0, f64::max);
+        let cell = 2.0 * max_radius + tolerance;
+        if cell <= 0.0 {
+            return Vec::new();
+        }
+        let key = |i: usize| {
+            let p = self.atoms[i].position;
+            ((p.x / cell).floor() as i64, (p.y / cell).floor() as i64, (p.z / cell).floor() as i64)
+        };
+
+        let mut cells: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        for i in 0..self.atoms.len() {
+            cells.entry(key(i)).or_default().push(i);
+        }
+
+        let mut bonds = Vec::new();
+        for i in 0..self.atoms.len() {
+            let (cx, cy, cz) = key(i);
+            let ri = self.atoms[i].element.covalent_radius();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let Some(members) = cells.get(&(cx + dx, cy + dy, cz + dz)) else {
+                            continue;
+                        };
+                        for &j in members.iter().filter(|&&j| j > i) {
+                            let d = self.atoms[i].position.dist(&self.atoms[j].position);
+                            let limit = ri + self.atoms[j].element.covalent_radius() + tolerance;
+                            if d > MIN_BOND_LENGTH && d <= limit {
+                                bonds.push(Bond { i, j, length: d });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        bonds.sort_by_key(|b| (b.i, b.j));
+        bonds
+    }
+}
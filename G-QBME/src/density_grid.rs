@@ -0,0 +1,202 @@
+use crate::deposition::{self, DepositionReport, DepositionScheme};
+use crate::diagnostics::{self, Diagnostics};
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{Biomolecule, MassDensityGrid, Vector3};
+use crate::mrc;
+use crate::poisson::lattice_corners;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Voxel masses (Daltons per voxel) on a cubic lattice, independent of how they are stored
+/// Voxel (x, y, z) has linear index (x * ny + y) * nz + z, the `MassDensityGrid` layout.
+/// Visitors run in ascending index, so sums over a grid come out bit-identical whichever
+/// backend holds it.
+pub trait DensityGrid {
+    fn origin(&self) -> Vector3;
+    fn resolution(&self) -> f64; // voxel edge in Angstroms
+    fn dimensions(&self) -> (usize, usize, usize);
+
+    /// Daltons in voxel (x, y, z); zero where nothing is stored
+    fn mass_at(&self, x: usize, y: usize, z: usize) -> f64;
+
+    /// Adds `mass` Daltons to voxel (x, y, z), which must lie inside the grid
+    fn add_mass(&mut self, x: usize, y: usize, z: usize, mass: f64);
+
+    /// Calls `f(index, mass)` for every voxel with non-zero mass, in ascending linear index
+    fn for_each_nonzero(&self, f: &mut dyn FnMut(usize, f64));
+
+    /// Bytes allocated for voxel values
+    fn storage_bytes(&self) -> usize;
+
+    fn voxel_count(&self) -> usize {
+        let (nx, ny, nz) = self.dimensions();
+        nx * ny * nz
+    }
+
+    /// Position of voxel (x, y, z) in Angstroms
+    fn voxel_position(&self, x: usize, y: usize, z: usize) -> Vector3 {
+        let (origin, resolution) = (self.origin(), self.resolution());
+        Vector3::new(origin.x + x as f64 * resolution, origin.y + y as f64 * resolution, origin.z + z as f64 * resolution)
+    }
+
+    /// Position of the voxel with linear index `index`
+    fn index_position(&self, index: usize) -> Vector3 {
+        let (_, ny, nz) = self.dimensions();
+        self.voxel_position(index / (ny * nz), (index / nz) % ny, index % nz)
+    }
+
+    /// Voxels with positive mass as (position, Daltons), in ascending index
+    /// These are the point sources the direct and tree solvers sum over.
+    fn occupied(&self) -> Vec<(Vector3, f64)> {
+        let mut sources = self.sources();
+        sources.retain(|&(_, mass)| mass > 0.0);
+        sources
+    }
+
+    /// Every non-zero voxel as (position, value), in ascending index, whatever its sign
+    fn sources(&self) -> Vec<(Vector3, f64)> {
+        let mut sources = Vec::new();
+        self.for_each_nonzero(&mut |index, value| sources.push((self.index_position(index), value)));
+        sources
+    }
+
+    /// Daltons over the whole grid
+    fn total_mass(&self) -> f64 {
+        let mut total = 0.0;
+        self.for_each_nonzero(&mut |_, mass| total += mass);
+        total
+    }
+
+    /// Every voxel centre, in index order
+    fn voxel_centres(&self) -> Vec<Vector3> {
+        let (nx, ny, nz) = self.dimensions();
+        let mut points = Vec::with_capacity(nx * ny * nz);
+        for x in 0..nx {
+            for y in 0..ny {
+                for z in 0..nz {
+                    points.push(self.voxel_position(x, y, z));
+                }
+            }
+        }
+        points
+    }
+
+    /// Trilinear interpolation of the density (Da/A^3) between voxel centres; `None` outside the lattice
+    fn sample(&self, position: &Vector3) -> Option<f64> {
+        let corners = lattice_corners(self.origin(), self.resolution(), self.dimensions(), position)?;
+        let mass: f64 = corners.iter().map(|&(x, y, z, w)| w * self.mass_at(x, y, z)).sum();
+        Some(mass / self.resolution().powi(3))
+    }
+
+    /// Copy into a dense `MassDensityGrid`; fails if the dense array cannot be allocated
+    fn to_dense(&self) -> Result<MassDensityGrid, GqbmeError> {
+        let mut dense = MassDensityGrid::try_new(self.origin(), self.resolution(), self.dimensions())?;
+        self.for_each_nonzero(&mut |index, mass| dense.data[index] = mass);
+        Ok(dense)
+    }
+
+    /// Writes the grid as a float32 MRC2014 map, voxel by voxel without densifying it
+    fn write_density_mrc(&self, out: &mut dyn Write) -> io::Result<()> {
+        mrc::write_density(out, self)
+    }
+
+    fn export_mrc(&self, path: &Path) -> io::Result<()> {
+        let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_density_mrc(&mut out)?;
+        out.flush()
+    }
+
+    /// Maps atoms into the density grid (nearest-grid-point assignment)
+    /// See `map_biomolecule_with` for smoother kernels.
+    fn map_biomolecule(&mut self, molecule: &Biomolecule) -> DepositionReport {
+        self.map_biomolecule_with(molecule, DepositionScheme::NearestGridPoint)
+    }
+
+    /// Maps atoms into the density grid with the chosen kernel
+    /// Kernel weights are normalised over their full support, so any weight
+    /// landing outside the box is reported as lost rather than renormalised away.
+    fn map_biomolecule_with(&mut self, molecule: &Biomolecule, scheme: DepositionScheme) -> DepositionReport {
+        deposition::deposit(self, molecule, scheme, |atom| atom.mass)
+    }
+
+    /// Deposits partial charges instead of masses, making this a charge-density grid (e per voxel)
+    /// The report's masses are then charges in e. Charge grids feed the electrostatic solvers
+    /// through `sources`, which keeps the negative voxels that `occupied` drops.
+    fn map_charges_with(&mut self, molecule: &Biomolecule, scheme: DepositionScheme) -> DepositionReport {
+        deposition::deposit(self, molecule, scheme, |atom| atom.charge)
+    }
+
+    /// As `map_charges_with`, but any charge falling off the grid is an error
+    fn try_map_charges_with(&mut self, molecule: &Biomolecule, scheme: DepositionScheme) -> Result<DepositionReport, GqbmeError> {
+        let deposition = self.map_charges_with(molecule, scheme);
+        if !deposition.is_lossless() {
+            return Err(GqbmeError::ChargeLost(deposition));
+        }
+        Ok(deposition)
+    }
+
+    /// Combines a deposition report for `molecule` with the grid's current density range
+    fn diagnostics(&self, molecule: &Biomolecule, deposition: &DepositionReport) -> Diagnostics {
+        diagnostics::grid_diagnostics(self, molecule, deposition)
+    }
+
+    /// Validates `molecule`, deposits it and reports any loss without failing on it
+    fn map_biomolecule_diagnosed(&mut self, molecule: &Biomolecule, scheme: DepositionScheme) -> Result<Diagnostics, GqbmeError> {
+        molecule.validate()?;
+        let deposition = self.map_biomolecule_with(molecule, scheme);
+        Ok(self.diagnostics(molecule, &deposition))
+    }
+
+    /// As `map_biomolecule_diagnosed`, but any mass falling off the grid is an error
+    fn try_map_biomolecule_with(&mut self, molecule: &Biomolecule, scheme: DepositionScheme) -> Result<Diagnostics, GqbmeError> {
+        let diagnostics = self.map_biomolecule_diagnosed(molecule, scheme)?;
+        if !diagnostics.is_lossless() {
+            return Err(GqbmeError::MassLost(diagnostics));
+        }
+        Ok(diagnostics)
+    }
+
+    /// Checked nearest-grid-point deposition
+    fn try_map_biomolecule(&mut self, molecule: &Biomolecule) -> Result<Diagnostics, GqbmeError> {
+        self.try_map_biomolecule_with(molecule, DepositionScheme::NearestGridPoint)
+    }
+}
+
+impl DensityGrid for MassDensityGrid {
+    fn origin(&self) -> Vector3 {
+        self.origin
+    }
+
+    fn resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    fn dimensions(&self) -> (usize, usize, usize) {
+        self.dimensions
+    }
+
+    fn mass_at(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[self.get_index(x, y, z)]
+    }
+
+    fn add_mass(&mut self, x: usize, y: usize, z: usize, mass: f64) {
+        let idx = self.get_index(x, y, z);
+        self.data[idx] += mass;
+    }
+
+    fn for_each_nonzero(&self, f: &mut dyn FnMut(usize, f64)) {
+        for (index, &mass) in self.data.iter().enumerate() {
+            if mass != 0.0 {
+                f(index, mass);
+            }
+        }
+    }
+
+    fn storage_bytes(&self) -> usize {
+        self.data.len() * std::mem::size_of::<f64>()
+    }
+
+    fn to_dense(&self) -> Result<MassDensityGrid, GqbmeError> {
+        Ok(self.clone())
+    }
+}
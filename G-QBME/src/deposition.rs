@@ -0,0 +1,250 @@
+use crate::density_grid::DensityGrid;
+use crate::gqbme_engine::{Atom, Biomolecule};
+
+/// Mass-assignment kernels for `DensityGrid::map_biomolecule_with`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DepositionScheme {
+    /// Whole atom mass goes to the closest voxel (the original behaviour)
+    #[default]
+    NearestGridPoint,
+    /// Trilinear split over the 8 surrounding voxels
+    CloudInCell,
+    /// Quadratic spline over the 27 surrounding voxels
+    TriangularShapedCloud,
+    /// Gaussian smearing with sigma = `width_scale` x van der Waals radius,
+    /// truncated at `cutoff_sigmas` standard deviations
+    Gaussian { width_scale: f64, cutoff_sigmas: f64 },
+}
+
+impl std::str::FromStr for DepositionScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ngp" | "nearest" => Ok(DepositionScheme::NearestGridPoint),
+            "cic" => Ok(DepositionScheme::CloudInCell),
+            "tsc" => Ok(DepositionScheme::TriangularShapedCloud),
+            "gaussian" => Ok(DepositionScheme::Gaussian { width_scale: 0.5, cutoff_sigmas: 3.0 }),
+            other => Err(format!("unknown deposition scheme '{}' (expected ngp, cic, tsc or gaussian)", other)),
+        }
+    }
+}
+
+/// Book-keeping returned by a deposition pass
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[must_use = "mass that misses the grid is only reported here"]
+pub struct DepositionReport {
+    pub deposited_mass: f64,  // Daltons that landed inside the grid
+    pub lost_mass: f64,       // Daltons whose kernel weight fell outside the grid
+    pub atoms_outside: usize, // atoms that contributed nothing
+    pub atoms_clipped: usize, // atoms that lost part of their kernel at the boundary
+}
+
+impl DepositionReport {
+    pub fn is_lossless(&self) -> bool {
+        self.atoms_outside == 0 && self.atoms_clipped == 0
+    }
+}
+
+/// One-dimensional kernel weights for a coordinate `rel` measured in voxels
+fn axis_weights(rel: f64, scheme: DepositionScheme, sigma_voxels: f64) -> Vec<(i64, f64)> {
+    match scheme {
+        DepositionScheme::NearestGridPoint => vec![(rel.round() as i64, 1.0)],
+        DepositionScheme::CloudInCell => {
+            let i0 = rel.floor();
+            let f = rel - i0;
+            vec![(i0 as i64, 1.0 - f), (i0 as i64 + 1, f)]
+        }
+        DepositionScheme::TriangularShapedCloud => {
+            let ic = rel.round();
+            let d = rel - ic;
+            let ic = ic as i64;
+            vec![
+                (ic - 1, 0.5 * (0.5 - d).powi(2)),
+                (ic, 0.75 - d * d),
+                (ic + 1, 0.5 * (0.5 + d).powi(2)),
+            ]
+        }
+        DepositionScheme::Gaussian { cutoff_sigmas, .. } => {
+            let reach = cutoff_sigmas * sigma_voxels;
+            let lo = (rel - reach).ceil() as i64;
+            let hi = (rel + reach).floor() as i64;
+            let mut weights: Vec<(i64, f64)> = (lo..=hi)
+                .map(|i| {
+                    let d = i as f64 - rel;
+                    (i, (-d * d / (2.0 * sigma_voxels * sigma_voxels)).exp())
+                })
+                .collect();
+            let total: f64 = weights.iter().map(|(_, w)| w).sum();
+            if weights.is_empty() || total <= 0.0 {
+                // Kernel narrower than a voxel: degrade to nearest grid point
+                return vec![(rel.round() as i64, 1.0)];
+            }
+            for (_, w) in &mut weights {
+                *w /= total;
+            }
+            weights
+        }
+    }
+}
+
+/// Kernel deposition behind `DensityGrid::map_biomolecule_with` and `map_charges_with`, for any
+/// backend; `strength` picks what each atom deposits, and the report counts it in the same unit
+pub(crate) fn deposit<G: DensityGrid + ?Sized>(grid: &mut G, molecule: &Biomolecule, scheme: DepositionScheme, strength: fn(&Atom) -> f64) -> DepositionReport {
+    let mut report = DepositionReport::default();
+    let (origin, resolution) = (grid.origin(), grid.resolution());
+    let (nx, ny, nz) = grid.dimensions();
+    let dims = [nx as i64, ny as i64, nz as i64];
+
+    for atom in &molecule.atoms {
+        let amount = strength(atom);
+        // Nothing to place or lose, e.g. the neutral atoms of a charge grid
+        if amount == 0.0 {
+            continue;
+        }
+        let rel = [
+            (atom.position.x - origin.x) / resolution,
+            (atom.position.y - origin.y) / resolution,
+            (atom.position.z - origin.z) / resolution,
+        ];
+        let sigma_voxels = match scheme {
+            DepositionScheme::Gaussian { width_scale, .. } => width_scale * atom.element.vdw_radius() / resolution,
+            _ => 0.0,
+        };
+        let wx = axis_weights(rel[0], scheme, sigma_voxels);
+        let wy = axis_weights(rel[1], scheme, sigma_voxels);
+        let wz = axis_weights(rel[2], scheme, sigma_voxels);
+
+        let mut inside = 0.0;
+        for &(ix, fx) in &wx {
+            if ix < 0 || ix >= dims[0] || fx == 0.0 {
+                continue;
+            }
+            for &(iy, fy) in &wy {
+                if iy < 0 || iy >= dims[1] || fy == 0.0 {
+                    continue;
+                }
+                for &(iz, fz) in &wz {
+                    if iz < 0 || iz >= dims[2] || fz == 0.0 {
+                        continue;
+                    }
+                    let w = fx * fy * fz;
+                    grid.add_mass(ix as usize, iy as usize, iz as usize, amount * w);
+                    inside += w;
+                }
+            }
+        }
+
+        // Kernel weights sum to one only up to rounding
+        if inside >= 1.0 - 1e-12 {
+            report.deposited_mass += amount;
+            continue;
+        }
+        report.deposited_mass += amount * inside;
+        report.lost_mass += amount * (1.0 - inside);
+        if inside <= 0.0 {
+            report.atoms_outside += 1;
+        } else {
+            report.atoms_clipped += 1;
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::Element;
+    use crate::gqbme_engine::{MassDensityGrid, Vector3};
+
+    const SCHEMES: [DepositionScheme; 4] = [
+        DepositionScheme::NearestGridPoint,
+        DepositionScheme::CloudInCell,
+        DepositionScheme::TriangularShapedCloud,
+        DepositionScheme::Gaussian { width_scale: 0.5, cutoff_sigmas: 3.0 },
+    ];
+
+    fn grid() -> MassDensityGrid {
+        MassDensityGrid::new(Vector3::new(-5.0, -5.0, -5.0), 0.5, (21, 21, 21))
+    }
+
+    fn single(element: Element, x: f64, y: f64, z: f64) -> Biomolecule {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(element, x, y, z);
+        molecule
+    }
+
+    /// Mass-weighted mean voxel position
+    fn centroid(grid: &MassDensityGrid) -> Vector3 {
+        let mut sum = Vector3::default();
+        grid.for_each_nonzero(&mut |index, mass| sum += grid.index_position(index) * mass);
+        sum * (1.0 / grid.total_mass())
+    }
+
+    #[test]
+    fn weights_sum_to_the_atom_mass() {
+        let molecule = single(Element::P, 0.13, -0.71, 1.38);
+        for scheme in SCHEMES {
+            let mut grid = grid();
+            let report = grid.map_biomolecule_with(&molecule, scheme);
+            let mass = Element::P.mass();
+            assert!(report.is_lossless(), "{:?}: {:?}", scheme, report);
+            assert!((report.deposited_mass - mass).abs() < 1e-12 * mass, "{:?}: {}", scheme, report.deposited_mass);
+            assert!((grid.total_mass() - mass).abs() < 1e-12 * mass, "{:?}: grid holds {}", scheme, grid.total_mass());
+            assert!(grid.data.iter().all(|&m| m >= 0.0), "{:?} deposited negative mass", scheme);
+        }
+    }
+
+    #[test]
+    fn smooth_kernels_keep_the_centre_of_mass() {
+        let position = Vector3::new(0.13, -0.71, 1.38);
+        let molecule = single(Element::C, position.x, position.y, position.z);
+        // CIC and TSC conserve the first moment exactly; the sampled Gaussian only closely
+        for (scheme, tolerance) in [(SCHEMES[1], 1e-9), (SCHEMES[2], 1e-9), (SCHEMES[3], 1e-2)] {
+            let mut grid = grid();
+            let _ = grid.map_biomolecule_with(&molecule, scheme);
+            assert!((centroid(&grid) - position).norm() < tolerance, "{:?}: {:?}", scheme, centroid(&grid));
+        }
+        // Nearest grid point snaps to the closest node instead
+        let mut grid = grid();
+        let _ = grid.map_biomolecule(&molecule);
+        assert!((centroid(&grid) - Vector3::new(0.0, -0.5, 1.5)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn mass_off_the_grid_is_reported_not_renormalised() {
+        // Just past the last node, so part of every smooth stencil hangs outside
+        let mut molecule = single(Element::O, 5.2, 0.0, 0.0);
+        molecule.add_element(Element::N, 40.0, 0.0, 0.0);
+        for scheme in &SCHEMES[1..] {
+            let mut grid = grid();
+            let report = grid.map_biomolecule_with(&molecule, *scheme);
+            assert_eq!((report.atoms_clipped, report.atoms_outside), (1, 1), "{:?}", scheme);
+            assert!(report.lost_mass > Element::N.mass(), "{:?}: {:?}", scheme, report);
+            let total = Element::O.mass() + Element::N.mass();
+            assert!((report.deposited_mass + report.lost_mass - total).abs() < 1e-9, "{:?}: {:?}", scheme, report);
+            assert!((grid.total_mass() - report.deposited_mass).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn charges_deposit_with_sign_and_skip_neutral_atoms() {
+        let mut molecule = single(Element::O, 0.3, 0.2, 0.1);
+        molecule.add_element(Element::C, 100.0, 0.0, 0.0);
+        molecule.atoms[0].charge = -0.8;
+        for scheme in SCHEMES {
+            let mut grid = grid();
+            let report = grid.map_charges_with(&molecule, scheme);
+            // The far atom is neutral, so it is neither deposited nor lost
+            assert!(report.is_lossless(), "{:?}: {:?}", scheme, report);
+            assert!((grid.total_mass() + 0.8).abs() < 1e-12, "{:?}: {}", scheme, grid.total_mass());
+        }
+    }
+
+    #[test]
+    fn scheme_names() {
+        assert_eq!("CIC".parse::<DepositionScheme>(), Ok(DepositionScheme::CloudInCell));
+        assert_eq!("nearest".parse::<DepositionScheme>(), Ok(DepositionScheme::NearestGridPoint));
+        assert!("spline".parse::<DepositionScheme>().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::deposition::DepositionReport;
+use crate::density_grid::DensityGrid;
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{Biomolecule, MassDensityGrid};
+use crate::grid_sizing::GridFit;
+use crate::json::Json;
+use std::fmt;
+
+/// What a deposition did with a molecule's mass, and the density it left behind
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Diagnostics {
+    pub atoms: usize,
+    pub dropped_atoms: usize, // atoms whose whole kernel missed the grid
+    pub clipped_atoms: usize, // atoms that lost part of their kernel at the boundary
+    pub input_mass: f64,      // Daltons in the molecule
+    pub deposited_mass: f64,  // Daltons on the grid from this molecule
+    pub lost_mass: f64,       // Daltons that fell outside
+    pub min_density: f64,     // Da/A^3 over all voxels
+    pub max_density: f64,     // Da/A^3
+    pub occupied_voxels: usize,
+}
+
+impl Diagnostics {
+    pub fn is_lossless(&self) -> bool {
+        self.dropped_atoms == 0 && self.clipped_atoms == 0
+    }
+
+    /// Lost mass as a fraction of the input mass
+    pub fn lost_fraction(&self) -> f64 {
+        if self.input_mass > 0.0 { self.lost_mass / self.input_mass } else { 0.0 }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} atoms, {:.4} of {:.4} Da deposited ({} dropped, {} clipped, {:.4} Da lost); density {:.4e} to {:.4e} Da/A^3 over {} occupied voxels",
+            self.atoms,
+            self.deposited_mass,
+            self.input_mass,
+            self.dropped_atoms,
+            self.clipped_atoms,
+            self.lost_mass,
+            self.min_density,
+            self.max_density,
+            self.occupied_voxels
+        )
+    }
+}
+
+impl From<&Diagnostics> for Json {
+    fn from(diagnostics: &Diagnostics) -> Self {
+        Json::object()
+            .with("atoms", diagnostics.atoms)
+            .with("dropped_atoms", diagnostics.dropped_atoms)
+            .with("clipped_atoms", diagnostics.clipped_atoms)
+            .with("input_mass_da", diagnostics.input_mass)
+            .with("deposited_mass_da", diagnostics.deposited_mass)
+            .with("lost_mass_da", diagnostics.lost_mass)
+            .with("min_density_da_a3", diagnostics.min_density)
+            .with("max_density_da_a3", diagnostics.max_density)
+            .with("occupied_voxels", diagnostics.occupied_voxels)
+    }
+}
+
+/// Body of `DensityGrid::diagnostics`; unstored voxels count as zero density
+pub(crate) fn grid_diagnostics<G: DensityGrid + ?Sized>(grid: &G, molecule: &Biomolecule, deposition: &DepositionReport) -> Diagnostics {
+    let voxel_volume = grid.resolution().powi(3);
+    let (mut min, mut max, mut occupied) = (f64::INFINITY, f64::NEG_INFINITY, 0usize);
+    grid.for_each_nonzero(&mut |_, m| {
+        min = min.min(m);
+        max = max.max(m);
+        occupied += 1;
+    });
+    if occupied < grid.voxel_count() {
+        min = min.min(0.0);
+        max = max.max(0.0);
+    }
+    Diagnostics {
+        atoms: molecule.atoms.len(),
+        dropped_atoms: deposition.atoms_outside,
+        clipped_atoms: deposition.atoms_clipped,
+        input_mass: molecule.atoms.iter().map(|a| a.mass).sum(),
+        deposited_mass: deposition.deposited_mass,
+        lost_mass: deposition.lost_mass,
+        min_density: if grid.voxel_count() == 0 { 0.0 } else { min / voxel_volume },
+        max_density: if grid.voxel_count() == 0 { 0.0 } else { max / voxel_volume },
+        occupied_voxels: occupied,
+    }
+}
+
+/// The checks `try_fitted_to` makes before sizing a grid around `molecule`
+pub(crate) fn check_fit(molecule: &Biomolecule, fit: &GridFit) -> Result<(), GqbmeError> {
+    molecule.validate()?;
+    if molecule.atoms.is_empty() {
+        return Err(GqbmeError::EmptyMolecule);
+    }
+    fit.validate()
+}
+
+impl MassDensityGrid {
+    /// `fitted_to` for a validated, non-empty molecule and a usable `fit`
+    pub fn try_fitted_to(molecule: &Biomolecule, fit: &GridFit) -> Result<Self, GqbmeError> {
+        check_fit(molecule, fit)?;
+        Self::fitted_to(molecule, fit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deposition::DepositionScheme;
+    use crate::gqbme_engine::generate_dna_helix;
+    use crate::sparse_grid::SparseDensityGrid;
+
+    #[test]
+    fn fits_too_large_to_allocate_are_errors() {
+        let molecule = generate_dna_helix(3, 10.0, 34.0);
+        let fit = GridFit::new(5.0, 1e-6);
+        assert!(matches!(MassDensityGrid::try_fitted_to(&molecule, &fit), Err(GqbmeError::GridTooLarge { .. })));
+        assert!(matches!(SparseDensityGrid::try_fitted_to(&molecule, &fit), Err(GqbmeError::GridTooLarge { .. })));
+        // Countable, but hundreds of terabytes of dense storage
+        assert!(matches!(MassDensityGrid::try_fitted_to(&molecule, &GridFit::new(5.0, 1e-3)), Err(GqbmeError::GridTooLarge { .. })));
+    }
+
+    #[test]
+    fn unusable_fits_and_molecules_are_rejected_before_sizing() {
+        let molecule = generate_dna_helix(3, 10.0, 34.0);
+        assert!(matches!(MassDensityGrid::try_fitted_to(&molecule, &GridFit::new(5.0, 0.0)), Err(GqbmeError::InvalidResolution(_))));
+        assert!(matches!(MassDensityGrid::try_fitted_to(&molecule, &GridFit::new(f64::NAN, 1.0)), Err(GqbmeError::InvalidPadding(_))));
+        assert!(matches!(MassDensityGrid::try_fitted_to(&Biomolecule::new(), &GridFit::default()), Err(GqbmeError::EmptyMolecule)));
+        let mut broken = molecule.clone();
+        broken.atoms[1].position.y = f64::INFINITY;
+        assert!(matches!(MassDensityGrid::try_fitted_to(&broken, &GridFit::default()), Err(GqbmeError::NonFinitePosition { atom: 1 })));
+    }
+
+    #[test]
+    fn diagnostics_account_for_clipped_mass() {
+        let molecule = generate_dna_helix(3, 10.0, 34.0);
+        let mut grid = MassDensityGrid::try_fitted_to(&molecule, &GridFit::new(0.0, 1.0)).expect("small grid");
+        let diagnostics = grid.map_biomolecule_diagnosed(&molecule, DepositionScheme::TriangularShapedCloud).expect("valid molecule");
+        assert_eq!(diagnostics.atoms, molecule.atoms.len());
+        assert!(!diagnostics.is_lossless());
+        assert!((diagnostics.deposited_mass + diagnostics.lost_mass - diagnostics.input_mass).abs() < 1e-9);
+        assert!(matches!(grid.try_map_biomolecule_with(&molecule, DepositionScheme::TriangularShapedCloud), Err(GqbmeError::MassLost(_))));
+        let mut padded = MassDensityGrid::try_fitted_to(&molecule, &GridFit::new(3.0, 1.0)).expect("small grid");
+        let diagnostics = padded.try_map_biomolecule(&molecule).expect("padding keeps every atom");
+        assert_eq!(diagnostics.lost_mass, 0.0);
+        assert!(diagnostics.min_density == 0.0 && diagnostics.max_density > 0.0);
+    }
+}
@@ -0,0 +1,481 @@
+use crate::element::Element;
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{Atom, AtomRecord, Biomolecule, Vector3};
+use std::fmt;
+
+/// A DNA nucleotide
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nucleotide {
+    A,
+    C,
+    G,
+    T,
+}
+
+impl Nucleotide {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_uppercase() {
+            'A' => Some(Nucleotide::A),
+            'C' => Some(Nucleotide::C),
+            'G' => Some(Nucleotide::G),
+            'T' => Some(Nucleotide::T),
+            _ => None,
+        }
+    }
+
+    /// Watson-Crick partner
+    pub fn complement(self) -> Self {
+        match self {
+            Nucleotide::A => Nucleotide::T,
+            Nucleotide::T => Nucleotide::A,
+            Nucleotide::C => Nucleotide::G,
+            Nucleotide::G => Nucleotide::C,
+        }
+    }
+
+    /// Adenine and guanine; these take the syn conformation in Z-DNA
+    pub fn is_purine(self) -> bool {
+        matches!(self, Nucleotide::A | Nucleotide::G)
+    }
+
+    /// PDB residue name ("DA", "DC", ...)
+    pub fn residue_name(self) -> &'static str {
+        match self {
+            Nucleotide::A => "DA",
+            Nucleotide::C => "DC",
+            Nucleotide::G => "DG",
+            Nucleotide::T => "DT",
+        }
+    }
+
+    /// Base atoms in the standard reference frame (Olson et al., J. Mol. Biol. 2001)
+    fn base_atoms(self) -> &'static [(&'static str, [f64; 3])] {
+        match self {
+            Nucleotide::A => &[
+                ("C1'", [-2.479, 5.346, 0.0]),
+                ("N9", [-1.291, 4.498, 0.0]),
+                ("C8", [0.024, 4.897, 0.0]),
+                ("N7", [0.877, 3.902, 0.0]),
+                ("C5", [0.071, 2.771, 0.0]),
+                ("C6", [0.369, 1.398, 0.0]),
+                ("N6", [1.611, 0.909, 0.0]),
+                ("N1", [-0.668, 0.532, 0.0]),
+                ("C2", [-1.912, 1.023, 0.0]),
+                ("N3", [-2.320, 2.290, 0.0]),
+                ("C4", [-1.267, 3.124, 0.0]),
+            ],
+            Nucleotide::G => &[
+                ("C1'", [-2.477, 5.399, 0.0]),
+                ("N9", [-1.289, 4.551, 0.0]),
+                ("C8", [0.023, 4.962, 0.0]),
+                ("N7", [0.870, 3.969, 0.0]),
+                ("C5", [0.071, 2.833, 0.0]),
+                ("C6", [0.424, 1.460, 0.0]),
+                ("O6", [1.554, 0.955, 0.0]),
+                ("N1", [-0.700, 0.641, 0.0]),
+                ("C2", [-1.999, 1.087, 0.0]),
+                ("N2", [-2.949, 0.139, 0.0]),
+                ("N3", [-2.342, 2.364, 0.0]),
+                ("C4", [-1.265, 3.177, 0.0]),
+            ],
+            Nucleotide::C => &[
+                ("C1'", [-2.477, 5.402, 0.0]),
+                ("N1", [-1.285, 4.542, 0.0]),
+                ("C2", [-1.472, 3.158, 0.0]),
+                ("O2", [-2.628, 2.709, 0.0]),
+                ("N3", [-0.391, 2.344, 0.0]),
+                ("C4", [0.837, 2.868, 0.0]),
+                ("N4", [1.875, 2.027, 0.0]),
+                ("C5", [1.056, 4.275, 0.0]),
+                ("C6", [-0.023, 5.068, 0.0]),
+            ],
+            Nucleotide::T => &[
+                ("C1'", [-2.481, 5.354, 0.0]),
+                ("N1", [-1.284, 4.500, 0.0]),
+                ("C2", [-1.462, 3.135, 0.0]),
+                ("O2", [-2.562, 2.608, 0.0]),
+                ("N3", [-0.298, 2.407, 0.0]),
+                ("C4", [0.994, 2.897, 0.0]),
+                ("O4", [1.944, 2.119, 0.0]),
+                ("C5", [1.106, 4.338, 0.0]),
+                ("C7", [2.466, 4.961, 0.0]),
+                ("C6", [-0.024, 5.057, 0.0]),
+            ],
+        }
+    }
+}
+
+/// Sequence parsing failure: position and offending character
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceError {
+    pub position: usize,
+    pub found: char,
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid residue '{}' at position {}", self.found, self.position + 1)
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+/// Parses an A/C/G/T string (case-insensitive, whitespace ignored)
+pub fn parse_sequence(sequence: &str) -> Result<Vec<Nucleotide>, SequenceError> {
+    sequence
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .enumerate()
+        .map(|(position, found)| Nucleotide::from_char(found).ok_or(SequenceError { position, found }))
+        .collect()
+}
+
+/// Double-helix family
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HelixForm {
+    A,
+    #[default]
+    B,
+    /// Left-handed dinucleotide repeat: syn purine, anti pyrimidine
+    Z,
+}
+
+impl std::str::FromStr for HelixForm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(HelixForm::A),
+            "B" => Ok(HelixForm::B),
+            "Z" => Ok(HelixForm::Z),
+            other => Err(format!("unknown helix form '{}' (expected A, B or Z)", other)),
+        }
+    }
+}
+
+/// Helical parameters of a base-pair step (3DNA conventions)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HelixGeometry {
+    pub twist: f64,          // degrees per base pair, negative = left-handed
+    pub rise: f64,           // Angstroms per base pair
+    pub x_displacement: f64, // Angstroms from the helix axis along the base-pair x axis
+    pub inclination: f64,    // degrees about the base-pair x axis
+    /// Angstroms along the base-pair y axis, + at anti pairs and - at syn pairs (the Z zigzag)
+    pub y_displacement: f64,
+    /// Degrees added to steps leaving an anti pair and taken from steps leaving a syn pair
+    pub twist_alternation: f64,
+}
+
+/// Sugar-phosphate atoms of one nucleotide in its base reference frame
+/// Starting from fiber-model torsions, each set was refined to Parkinson et al. (1996) bond
+/// lengths and angles, including the O3'(i)-P(i+1) link (1.607 A) at the form's twist and rise;
+/// built helices close within 0.002 A of it. Bonds are within 0.005 A of target; angles within
+/// 2 deg, the largest misfits inside the strained sugar ring.
+/// Z-DNA has two: its anti (C2'-endo) and syn (C3'-endo) sugars are the B and A sugars turned
+/// to the Z glycosyl angles, and gamma, beta, alpha and the helix placement were fitted so both
+/// the CpG (-9 deg) and GpC (-51 deg) links close at 1.607 A with A/B link angles. Epsilon, zeta
+/// and syn chi land within 10 deg of Wang et al. (1979); anti gamma and syn gamma are 35-40 deg off.
+type BackboneTemplate = [(&'static str, [f64; 3]); 10];
+
+const B_BACKBONE: BackboneTemplate = [
+    ("P", [0.505, 9.045, -1.515]),
+    ("OP1", [-0.086, 10.401, -1.381]),
+    ("OP2", [1.703, 8.685, -0.715]),
+    ("O5'", [-0.637, 7.976, -1.213]),
+    ("C5'", [-1.686, 7.726, -2.167]),
+    ("C4'", [-2.902, 7.169, -1.467]),
+    ("O4'", [-2.768, 5.735, -1.345]),
+    ("C3'", [-3.101, 7.676, -0.042]),
+    ("O3'", [-4.486, 7.627, 0.315]),
+    ("C2'", [-2.298, 6.673, 0.777]),
+];
+
+const A_BACKBONE: BackboneTemplate = [
+    ("P", [1.087, 9.408, 0.841]),
+    ("OP1", [2.277, 8.692, 1.368]),
+    ("OP2", [0.647, 10.671, 1.487]),
+    ("O5'", [-0.141, 8.392, 0.842]),
+    ("C5'", [-1.439, 8.807, 0.378]),
+    ("C4'", [-2.429, 7.671, 0.482]),
+    ("O4'", [-2.120, 6.659, -0.503]),
+    ("C3'", [-2.416, 6.916, 1.805]),
+    ("O3'", [-3.221, 7.587, 2.780]),
+    ("C2'", [-3.055, 5.596, 1.395]),
+];
+
+const Z_ANTI_BACKBONE: BackboneTemplate = [
+    ("P", [-0.746, 9.393, 2.530]),
+    ("OP1", [-1.334, 10.595, 1.886]),
+    ("OP2", [-1.276, 8.929, 3.838]),
+    ("O5'", [-0.843, 8.182, 1.500]),
+    ("C5'", [-1.192, 8.411, 0.122]),
+    ("C4'", [-2.498, 7.729, -0.203]),
+    ("O4'", [-2.230, 6.481, -0.882]),
+    ("C3'", [-3.336, 7.350, 1.014]),
+    ("O3'", [-4.723, 7.298, 0.667]),
+    ("C2'", [-2.813, 5.960, 1.352]),
+];
+
+const Z_SYN_BACKBONE: BackboneTemplate = [
+    ("P", [-4.699, 3.640, 4.761]),
+    ("OP1", [-3.965, 4.341, 5.845]),
+    ("OP2", [-6.158, 3.397, 4.902]),
+    ("O5'", [-4.460, 4.440, 3.404]),
+    ("C5'", [-4.930, 3.916, 2.148]),
+    ("C4'", [-4.483, 4.794, 1.004]),
+    ("O4'", [-3.129, 5.246, 1.233]),
+    ("C3'", [-4.419, 4.109, -0.355]),
+    ("O3'", [-5.707, 4.105, -0.981]),
+    ("C2'", [-3.461, 5.027, -1.101]),
+];
+
+impl HelixForm {
+    pub fn geometry(self) -> HelixGeometry {
+        match self {
+            HelixForm::A => HelixGeometry { twist: 32.7, rise: 2.56, x_displacement: -4.5, inclination: 20.0, y_displacement: 0.0, twist_alternation: 0.0 },
+            HelixForm::B => HelixGeometry { twist: 36.0, rise: 3.38, x_displacement: 0.0, inclination: 0.0, y_displacement: 0.0, twist_alternation: 0.0 },
+            // Pairs stand upside down relative to B, hence the ~180 deg inclination
+            HelixForm::Z => HelixGeometry { twist: -30.0, rise: 3.76, x_displacement: 2.40, inclination: 180.9, y_displacement: 2.72, twist_alternation: 21.0 },
+        }
+    }
+
+    fn backbone(self, syn: bool) -> &'static BackboneTemplate {
+        match self {
+            HelixForm::A => &A_BACKBONE,
+            HelixForm::B => &B_BACKBONE,
+            HelixForm::Z if syn => &Z_SYN_BACKBONE,
+            HelixForm::Z => &Z_ANTI_BACKBONE,
+        }
+    }
+}
+
+/// Rigid transform of a base-pair reference frame
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Frame {
+    pub rotation: [[f64; 3]; 3],
+    pub origin: Vector3,
+}
+
+impl Frame {
+    fn apply(&self, p: [f64; 3]) -> Vector3 {
+        let r = &self.rotation;
+        Vector3::new(
+            r[0][0] * p[0] + r[0][1] * p[1] + r[0][2] * p[2],
+            r[1][0] * p[0] + r[1][1] * p[1] + r[1][2] * p[2],
+            r[2][0] * p[0] + r[2][1] * p[1] + r[2][2] * p[2],
+        ) + self.origin
+    }
+
+    /// The same frame flipped 180 deg about its x axis (the complementary base's frame)
+    fn flipped(&self) -> Frame {
+        let mut rotation = self.rotation;
+        for row in rotation.iter_mut() {
+            row[1] = -row[1];
+            row[2] = -row[2];
+        }
+        Frame { rotation, origin: self.origin }
+    }
+}
+
+/// Base-pair frames along a helix whose axis is the z axis
+/// `twists[k]` is the twist (degrees) between pair k and k+1; `syn(i)` flips the sign of the
+/// y displacement at pair i.
+fn helix_frames(geometry: &HelixGeometry, twists: &[f64], syn: impl Fn(usize) -> bool) -> Vec<Frame> {
+    let (si, ci) = geometry.inclination.to_radians().sin_cos();
+    let mut frames = Vec::with_capacity(twists.len() + 1);
+    let mut angle: f64 = 0.0;
+    for i in 0..=twists.len() {
+        if i > 0 {
+            angle += twists[i - 1].to_radians();
+        }
+        let (s, c) = angle.sin_cos();
+        // Rz(angle) * Rx(inclination)
+        let rotation = [[c, -s * ci, s * si], [s, c * ci, -c * si], [0.0, si, ci]];
+        let (x, y) = (geometry.x_displacement, if syn(i) { -geometry.y_displacement } else { geometry.y_displacement });
+        let origin = Vector3::new(c * x - s * y, s * x + c * y, i as f64 * geometry.rise);
+        frames.push(Frame { rotation, origin });
+    }
+    frames
+}
+
+/// Full-atom double-helix builder
+/// Strand I (chain A) follows the given sequence 5'->3' along +z; strand II (chain B)
+/// is its antiparallel complement. 5'-terminal residues carry no phosphate group.
+#[derive(Debug, Clone)]
+pub struct DnaBuilder {
+    pub sequence: Vec<Nucleotide>,
+    pub form: HelixForm,
+    pub double_stranded: bool,
+}
+
+impl DnaBuilder {
+    pub fn new(sequence: &str, form: HelixForm) -> Result<Self, SequenceError> {
+        Ok(Self { sequence: parse_sequence(sequence)?, form, double_stranded: true })
+    }
+
+    pub fn single_stranded(mut self) -> Self {
+        self.double_stranded = false;
+        self
+    }
+
+    /// Whether strand I's nucleotide at pair `i` is syn
+    /// Only Z-DNA has syn nucleotides. It alternates from pair 0, syn if that base is a purine;
+    /// sequences that do not alternate purine/pyrimidine get the same syn/anti pattern.
+    pub fn is_syn(&self, i: usize) -> bool {
+        self.form == HelixForm::Z && self.sequence.first().is_some_and(|b| b.is_purine() == i.is_multiple_of(2))
+    }
+
+    /// The form's twist (degrees) for each base-pair step: uniform for A and B, alternating for Z
+    pub fn canonical_twists(&self) -> Vec<f64> {
+        let geometry = self.form.geometry();
+        (0..self.sequence.len().saturating_sub(1))
+            .map(|k| if self.is_syn(k) { geometry.twist - geometry.twist_alternation } else { geometry.twist + geometry.twist_alternation })
+            .collect()
+    }
+
+    /// Base-pair frames of the helix built with `twists`
+    pub(crate) fn frames(&self, twists: &[f64]) -> Vec<Frame> {
+        helix_frames(&self.form.geometry(), twists, |i| self.is_syn(i))
+    }
+
+    /// Builds the helix with the form's canonical twists
+    pub fn build(&self) -> Biomolecule {
+        self.assemble(&self.canonical_twists())
+    }
+
+    /// Builds the helix with an explicit twist (degrees) for each base-pair step
+    pub fn build_with_twists(&self, twists: &[f64]) -> Result<Biomolecule, GqbmeError> {
+        let expected = self.sequence.len().saturating_sub(1);
+        if twists.len() != expected {
+            return Err(GqbmeError::TwistCountMismatch { expected, found: twists.len() });
+        }
+        Ok(self.assemble(twists))
+    }
+
+    fn assemble(&self, twists: &[f64]) -> Biomolecule {
+        let mut molecule = Biomolecule::new();
+        if self.sequence.is_empty() {
+            return molecule;
+        }
+        let frames = self.frames(twists);
+        let n = self.sequence.len();
+
+        for (i, (&base, frame)) in self.sequence.iter().zip(&frames).enumerate() {
+            self.emit_nucleotide(&mut molecule, base, self.backbone(self.is_syn(i), i == 0), frame, "A", i as i32 + 1);
+        }
+        if self.double_stranded {
+            // Strand II runs 5'->3' from the last base pair back to the first; in Z-DNA each
+            // partner takes the opposite conformation
+            for i in (0..n).rev() {
+                let residue_seq = (n + (n - i)) as i32;
+                let syn = self.form == HelixForm::Z && !self.is_syn(i);
+                self.emit_nucleotide(&mut molecule, self.sequence[i].complement(), self.backbone(syn, i == n - 1), &frames[i].flipped(), "B", residue_seq);
+            }
+        }
+        molecule
+    }
+
+    /// Backbone atoms of one nucleotide; 5'-terminal ones start at O5' (templates lead with P, OP1, OP2)
+    fn backbone(&self, syn: bool, five_prime: bool) -> &'static [(&'static str, [f64; 3])] {
+        let template = self.form.backbone(syn);
+        if five_prime { &template[3..] } else { template }
+    }
+
+    fn emit_nucleotide(&self, molecule: &mut Biomolecule, base: Nucleotide, backbone: &[(&str, [f64; 3])], frame: &Frame, chain: &str, residue_seq: i32) {
+        for &(name, local) in backbone.iter().chain(base.base_atoms()) {
+            // Atom names start with their element; none of these are two-letter elements
+            let element = Element::from_symbol(&name[..1]).expect("nucleotide elements are tabulated");
+            molecule.atoms.push(Atom {
+                record: Some(AtomRecord {
+                    serial: molecule.atoms.len() + 1,
+                    name: name.to_string(),
+                    residue_name: base.residue_name().to_string(),
+                    residue_seq,
+                    chain_id: chain.to_string(),
+                    hetero: false,
+                }),
+                ..Atom::new(element, frame.apply(local))
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(molecule: &Biomolecule, chain: &str, seq: i32, name: &str) -> Option<Vector3> {
+        molecule.atoms.iter().find(|a| a.record.as_ref().is_some_and(|r| r.chain_id == chain && r.residue_seq == seq && r.name == name)).map(|a| a.position)
+    }
+
+    /// Torsion a-b-c-d in degrees
+    fn dihedral(a: Vector3, b: Vector3, c: Vector3, d: Vector3) -> f64 {
+        let (b1, b2, b3) = (b - a, c - b, d - c);
+        let (m, n) = (b1.cross(&b2), b2.cross(&b3));
+        (b2.norm() * b1.dot(&n)).atan2(m.dot(&n)).to_degrees()
+    }
+
+    #[test]
+    fn backbone_links_close_at_the_ideal_length() {
+        for form in [HelixForm::A, HelixForm::B, HelixForm::Z] {
+            let molecule = DnaBuilder::new("ACGTTGCA", form).unwrap().build();
+            let mut links = 0;
+            for (chain, seqs) in [("A", 1..8), ("B", 9..16)] {
+                for seq in seqs {
+                    let (o3, p) = (find(&molecule, chain, seq, "O3'").unwrap(), find(&molecule, chain, seq + 1, "P").unwrap());
+                    assert!((o3.dist(&p) - 1.607).abs() < 0.005, "{:?}-DNA {}{}: O3'-P {:.3} A", form, chain, seq, o3.dist(&p));
+                    links += 1;
+                }
+            }
+            assert_eq!(links, 14);
+        }
+    }
+
+    #[test]
+    fn z_dna_is_a_left_handed_syn_anti_dinucleotide_repeat() {
+        let builder = DnaBuilder::new("CGCGCG", HelixForm::Z).unwrap();
+        assert_eq!(builder.canonical_twists(), [-9.0, -51.0, -9.0, -51.0, -9.0]);
+        assert_eq!((0..6).map(|i| builder.is_syn(i)).collect::<Vec<_>>(), [false, true, false, true, false, true]);
+        assert!(DnaBuilder::new("GCGC", HelixForm::Z).unwrap().is_syn(0));
+        assert!(!DnaBuilder::new("GCGC", HelixForm::B).unwrap().is_syn(0));
+
+        let molecule = builder.build();
+        // Glycosyl torsion: anti cytosines near -140 deg, syn guanines near +70 deg, both strands
+        for (chain, seqs) in [("A", 1..7), ("B", 7..13)] {
+            for seq in seqs {
+                let atom = |name| find(&molecule, chain, seq, name).unwrap();
+                let purine = find(&molecule, chain, seq, "N9").is_some();
+                let chi = if purine { dihedral(atom("O4'"), atom("C1'"), atom("N9"), atom("C4")) } else { dihedral(atom("O4'"), atom("C1'"), atom("N1"), atom("C2")) };
+                let expected = if purine { 68.0 } else { -139.0 };
+                assert!((chi - expected).abs() < 5.0, "{}{} chi {:.1}", chain, seq, chi);
+            }
+        }
+        // Strand I climbs +z while its phosphates turn clockwise seen from above
+        let phosphates: Vec<Vector3> = (2..7).map(|seq| find(&molecule, "A", seq, "P").unwrap()).collect();
+        let turn: f64 = phosphates
+            .windows(2)
+            .map(|w| {
+                let delta = w[1].y.atan2(w[1].x) - w[0].y.atan2(w[0].x);
+                delta - std::f64::consts::TAU * (delta / std::f64::consts::TAU).round()
+            })
+            .sum();
+        assert!(turn < -(100.0f64).to_radians(), "P azimuth turned {:.1} deg", turn.to_degrees());
+        assert!(phosphates[4].z - phosphates[0].z > 10.0);
+    }
+
+    #[test]
+    fn forms_parse_case_insensitively() {
+        assert_eq!("b".parse::<HelixForm>(), Ok(HelixForm::B));
+        assert_eq!("z".parse::<HelixForm>(), Ok(HelixForm::Z));
+        assert!("C".parse::<HelixForm>().unwrap_err().contains("A, B or Z"));
+    }
+
+    #[test]
+    fn twist_count_must_match_the_steps() {
+        let builder = DnaBuilder::new("ACGT", HelixForm::B).unwrap();
+        assert!(matches!(builder.build_with_twists(&[36.0]), Err(GqbmeError::TwistCountMismatch { expected: 3, found: 1 })));
+        let molecule = builder.build_with_twists(&builder.canonical_twists()).unwrap();
+        assert!(molecule.atoms.iter().zip(&builder.build().atoms).all(|(a, b)| a.position == b.position));
+        assert!(DnaBuilder::new("", HelixForm::B).unwrap().build_with_twists(&[]).unwrap().atoms.is_empty());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::batch::potentials_from_sources;
+use crate::deposition::DepositionReport;
+use crate::gqbme_engine::{Biomolecule, Vector3};
+use crate::json::Json;
+use crate::kernel::{PairKernel, ELEMENTARY_CHARGE};
+use std::fmt;
+
+pub const VACUUM_PERMITTIVITY: f64 = 8.854_187_812_8e-12; // F/m
+pub const BOLTZMANN: f64 = 1.380_649e-23; // J/K
+pub const AVOGADRO: f64 = 6.022_140_76e23; // 1/mol
+pub const WATER_PERMITTIVITY: f64 = 78.5; // relative, at 298 K
+
+/// Partial-charge templates for `Biomolecule::assign_charges`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChargeModel {
+    /// Every atom neutral
+    None,
+    /// -1 e per nucleotide phosphate, split over OP1/OP2 (O1P/O2P), or on P when the
+    /// oxygens are absent (the placeholder helix)
+    Phosphate,
+    /// Phosphates plus the groups ionized at pH 7: Asp, Glu, Lys, Arg and the peptide termini
+    #[default]
+    Standard,
+}
+
+impl ChargeModel {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChargeModel::None => "none",
+            ChargeModel::Phosphate => "phosphate",
+            ChargeModel::Standard => "standard",
+        }
+    }
+}
+
+impl std::str::FromStr for ChargeModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(ChargeModel::None),
+            "phosphate" => Ok(ChargeModel::Phosphate),
+            "standard" => Ok(ChargeModel::Standard),
+            other => Err(format!("unknown charge model '{}' (expected none, phosphate or standard)", other)),
+        }
+    }
+}
+
+/// Phosphate oxygens, each carrying half of the group's -1 e
+const PHOSPHATE_OXYGENS: &[&str] = &["OP1", "OP2", "O1P", "O2P"];
+
+/// (residue, atom, charge in e) for side chains ionized at pH 7
+const SIDE_CHAIN_CHARGES: &[(&str, &str, f64)] = &[
+    ("ASP", "OD1", -0.5),
+    ("ASP", "OD2", -0.5),
+    ("GLU", "OE1", -0.5),
+    ("GLU", "OE2", -0.5),
+    ("LYS", "NZ", 1.0),
+    ("ARG", "NH1", 0.5),
+    ("ARG", "NH2", 0.5),
+];
+
+/// Uniform dielectric with a 1:1 salt, screening by Debye-Hueckel when the ionic strength is positive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+    pub relative_permittivity: f64,
+    pub ionic_strength: f64, // mol/L
+    pub temperature: f64,    // K
+}
+
+impl Default for Medium {
+    /// Water at 298 K with physiological salt (0.15 M)
+    fn default() -> Self {
+        Self::water(0.15)
+    }
+}
+
+impl Medium {
+    pub fn vacuum() -> Self {
+        Self { relative_permittivity: 1.0, ionic_strength: 0.0, temperature: 298.15 }
+    }
+
+    pub fn water(ionic_strength: f64) -> Self {
+        Self { relative_permittivity: WATER_PERMITTIVITY, ionic_strength, temperature: 298.15 }
+    }
+
+    /// Positive permittivity and temperature, non-negative ionic strength, all finite
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.relative_permittivity.is_finite() && self.relative_permittivity > 0.0) {
+            return Err(format!("relative permittivity must be positive, got {}", self.relative_permittivity));
+        }
+        if !(self.ionic_strength.is_finite() && self.ionic_strength >= 0.0) {
+            return Err(format!("ionic strength must be non-negative, got {}", self.ionic_strength));
+        }
+        if !(self.temperature.is_finite() && self.temperature > 0.0) {
+            return Err(format!("temperature must be positive, got {}", self.temperature));
+        }
+        Ok(())
+    }
+
+    /// \lambda_D = \sqrt{\epsilon_0 \epsilon_r k_B T / (2 N_A e^2 I)} in Angstroms; `None` without salt
+    pub fn debye_length(&self) -> Option<f64> {
+        if self.ionic_strength <= 0.0 {
+            return None;
+        }
+        let ions_per_m3 = self.ionic_strength * 1000.0 * AVOGADRO;
+        let lambda = (VACUUM_PERMITTIVITY * self.relative_permittivity * BOLTZMANN * self.temperature / (2.0 * ions_per_m3 * ELEMENTARY_CHARGE * ELEMENTARY_CHARGE)).sqrt();
+        Some(lambda * 1e10)
+    }
+
+    /// Coulomb kernel without salt, Debye-Hueckel otherwise
+    pub fn kernel(&self) -> PairKernel {
+        match self.debye_length() {
+            Some(lambda) => PairKernel::debye_huckel(self.relative_permittivity, lambda),
+            None => PairKernel::coulomb(self.relative_permittivity),
+        }
+    }
+}
+
+/// Pair interaction energies of a molecule with itself, J, from exact atom sums
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EnergyComparison {
+    pub gravitational: f64,
+    pub electrostatic: f64,
+}
+
+impl EnergyComparison {
+    /// |U_grav / U_elec|; `None` when the molecule carries no electrostatic energy
+    pub fn ratio(&self) -> Option<f64> {
+        (self.electrostatic != 0.0).then(|| (self.gravitational / self.electrostatic).abs())
+    }
+}
+
+impl fmt::Display for EnergyComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U_grav {:.6e} J, U_elec {:.6e} J", self.gravitational, self.electrostatic)?;
+        match self.ratio() {
+            Some(ratio) => write!(f, ", ratio {:.6e}", ratio),
+            None => write!(f, ", ratio undefined (no charge)"),
+        }
+    }
+}
+
+impl From<&Medium> for Json {
+    fn from(medium: &Medium) -> Self {
+        Json::object()
+            .with("relative_permittivity", medium.relative_permittivity)
+            .with("ionic_strength_m", medium.ionic_strength)
+            .with("temperature_k", medium.temperature)
+            .with("debye_length_a", medium.debye_length())
+    }
+}
+
+impl From<&EnergyComparison> for Json {
+    fn from(energy: &EnergyComparison) -> Self {
+        Json::object()
+            .with("gravitational_j", energy.gravitational)
+            .with("electrostatic_j", energy.electrostatic)
+            .with("gravity_to_electrostatic", energy.ratio())
+    }
+}
+
+/// A charge-grid `DepositionReport`, whose masses are charges in e
+pub fn charge_deposition_json(report: &DepositionReport) -> Json {
+    Json::object()
+        .with("deposited_charge_e", report.deposited_mass)
+        .with("lost_charge_e", report.lost_mass)
+        .with("dropped_atoms", report.atoms_outside)
+        .with("clipped_atoms", report.atoms_clipped)
+}
+
+impl Biomolecule {
+    /// Replaces every atom's charge with `model`'s template and returns how many atoms are charged
+    /// Templates match PDB atom and residue names; atoms without records only get the
+    /// phosphate charge, on P.
+    pub fn assign_charges(&mut self, model: ChargeModel) -> usize {
+        let mut charges = vec![0.0; self.atoms.len()];
+        if model != ChargeModel::None {
+            // Residues by (chain, seq) in order of appearance, with the indices of their atoms
+            let mut residues: Vec<((&str, i32), Vec<usize>)> = Vec::new();
+            for (index, atom) in self.atoms.iter().enumerate() {
+                let Some(record) = &atom.record else {
+                    if atom.element.symbol() == "P" {
+                        charges[index] = -1.0;
+                    }
+                    continue;
+                };
+                let key = (record.chain_id.as_str(), record.residue_seq);
+                match residues.last_mut() {
+                    Some((last, members)) if *last == key => members.push(index),
+                    _ => residues.push((key, vec![index])),
+                }
+            }
+
+            let name = |i: usize| self.atoms[i].record.as_ref().map_or("", |r| r.name.as_str());
+            let residue_name = |i: usize| self.atoms[i].record.as_ref().map_or("", |r| r.residue_name.as_str());
+            for (r, (key, members)) in residues.iter().enumerate() {
+                let oxygens: Vec<usize> = members.iter().copied().filter(|&i| PHOSPHATE_OXYGENS.contains(&name(i))).collect();
+                if oxygens.len() >= 2 {
+                    for &i in &oxygens {
+                        charges[i] = -1.0 / oxygens.len() as f64;
+                    }
+                } else if let Some(&p) = members.iter().find(|&&i| name(i) == "P") {
+                    charges[p] = -1.0;
+                }
+                if model != ChargeModel::Standard {
+                    continue;
+                }
+
+                for &i in members {
+                    if let Some(&(_, _, q)) = SIDE_CHAIN_CHARGES.iter().find(|(res, at, _)| *res == residue_name(i) && *at == name(i)) {
+                        charges[i] = q;
+                    }
+                }
+                // Peptide termini: N of the first residue in a chain, O and OXT of a residue with OXT
+                let first_in_chain = r == 0 || residues[r - 1].0.0 != key.0;
+                if first_in_chain && members.iter().any(|&i| name(i) == "CA") && let Some(&n) = members.iter().find(|&&i| name(i) == "N") {
+                    charges[n] += 1.0;
+                }
+                if members.iter().any(|&i| name(i) == "OXT") {
+                    for &i in members.iter().filter(|&&i| matches!(name(i), "O" | "OXT")) {
+                        charges[i] = -0.5;
+                    }
+                }
+            }
+        }
+        for (atom, q) in self.atoms.iter_mut().zip(charges) {
+            atom.charge = q;
+        }
+        self.atoms.iter().filter(|a| a.charge != 0.0).count()
+    }
+
+    /// Sum of the partial charges, e
+    pub fn net_charge(&self) -> f64 {
+        self.atoms.iter().map(|a| a.charge).sum()
+    }
+
+    /// Charged atoms as (position, charge in e), the sources of the electrostatic solvers
+    pub fn charge_sources(&self) -> Vec<(Vector3, f64)> {
+        self.atoms.iter().filter(|a| a.charge != 0.0).map(|a| (a.position, a.charge)).collect()
+    }
+
+    /// U = 1/2 \sum_i s_i \phi_i over distinct atom pairs, for gravity and for the charges in `medium`
+    /// Both sums run in atom order, so the result does not depend on `threads`.
+    pub fn interaction_energies(&self, medium: &Medium, threads: usize) -> EnergyComparison {
+        let positions: Vec<Vector3> = self.atoms.iter().map(|a| a.position).collect();
+        let gravity = PairKernel::gravity();
+        let masses: Vec<(Vector3, f64)> = self.atoms.iter().map(|a| (a.position, a.mass)).collect();
+        let phi = potentials_from_sources(&positions, &masses, &gravity, threads);
+        let gravitational = 0.5 * self.atoms.iter().zip(&phi).map(|(a, p)| a.mass * gravity.unit * p).sum::<f64>();
+
+        let charges = self.charge_sources();
+        if charges.is_empty() {
+            return EnergyComparison { gravitational, electrostatic: 0.0 };
+        }
+        let charged: Vec<Vector3> = charges.iter().map(|&(p, _)| p).collect();
+        let kernel = medium.kernel();
+        let phi = potentials_from_sources(&charged, &charges, &kernel, threads);
+        let electrostatic = 0.5 * charges.iter().zip(&phi).map(|((_, q), p)| q * kernel.unit * p).sum::<f64>();
+        EnergyComparison { gravitational, electrostatic }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna_builder::{DnaBuilder, HelixForm};
+    use crate::element::Element;
+    use crate::gqbme_engine::ANGSTROM_TO_M;
+    use crate::kernel::COULOMB_CONSTANT;
+
+    #[test]
+    fn debye_length_matches_the_textbook_value() {
+        // 3.04 A / sqrt(I) in water at 25 C
+        for ionic_strength in [0.01, 0.15, 1.0] {
+            let lambda = Medium::water(ionic_strength).debye_length().expect("salt screens");
+            assert!((lambda * ionic_strength.sqrt() / 3.04 - 1.0).abs() < 0.01, "{} M: {} A", ionic_strength, lambda);
+        }
+        assert_eq!(Medium::water(0.0).debye_length(), None);
+    }
+
+    #[test]
+    fn debye_huckel_recovers_coulomb_without_salt() {
+        assert_eq!(Medium::water(0.0).kernel(), PairKernel::coulomb(WATER_PERMITTIVITY));
+        let coulomb = PairKernel::coulomb(WATER_PERMITTIVITY);
+        let r = 5.0 * ANGSTROM_TO_M;
+        let mut previous = 0.0;
+        for ionic_strength in [1.0, 1e-2, 1e-4, 1e-8] {
+            let screened = Medium::water(ionic_strength).kernel();
+            let ratio = screened.potential(1.0, r) / coulomb.potential(1.0, r);
+            assert!(ratio < 1.0 && ratio > previous, "{} M: {}", ionic_strength, ratio);
+            previous = ratio;
+        }
+        assert!(1.0 - previous < 1e-3, "{}", previous);
+        // A proton 1 A away in vacuum sits at 14.4 V
+        assert!((PairKernel::coulomb(1.0).potential(1.0, ANGSTROM_TO_M) / 14.3996 - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn screened_field_is_minus_the_potential_gradient() {
+        let kernel = Medium::water(0.15).kernel();
+        let r = Vector3::new(3.0, -4.0, 6.0) * ANGSTROM_TO_M;
+        let field = kernel.field(-1.0, r);
+        let h = 1e-4 * ANGSTROM_TO_M;
+        for (axis, step) in [Vector3::new(h, 0.0, 0.0), Vector3::new(0.0, h, 0.0), Vector3::new(0.0, 0.0, h)].into_iter().enumerate() {
+            let numeric = -(kernel.potential(-1.0, (r + step).norm()) - kernel.potential(-1.0, (r - step).norm())) / (2.0 * h);
+            let analytic = [field.x, field.y, field.z][axis];
+            assert!((numeric / analytic - 1.0).abs() < 1e-6, "axis {}: {} vs {}", axis, numeric, analytic);
+        }
+    }
+
+    #[test]
+    fn ion_pair_energy_is_coulombs_law() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::Na, 0.0, 0.0, 0.0);
+        molecule.add_element(Element::Cl, 0.0, 2.8, 0.0);
+        molecule.atoms[0].charge = 1.0;
+        molecule.atoms[1].charge = -1.0;
+        let r = 2.8 * ANGSTROM_TO_M;
+        let expected = -COULOMB_CONSTANT * ELEMENTARY_CHARGE * ELEMENTARY_CHARGE / (WATER_PERMITTIVITY * r);
+        let unscreened = molecule.interaction_energies(&Medium::water(0.0), 1);
+        assert!((unscreened.electrostatic / expected - 1.0).abs() < 1e-12, "{:?}", unscreened);
+        let screened = molecule.interaction_energies(&Medium::water(0.15), 2);
+        let lambda = Medium::water(0.15).debye_length().expect("salt screens");
+        assert!((screened.electrostatic / (expected * (-2.8 / lambda).exp()) - 1.0).abs() < 1e-12);
+        assert!(unscreened.gravitational < 0.0 && unscreened.gravitational == screened.gravitational);
+        assert!(unscreened.ratio().expect("charged") < 1e-30);
+    }
+
+    #[test]
+    fn phosphate_model_puts_one_charge_on_each_backbone_phosphate() {
+        let mut molecule = DnaBuilder::new("ACGTAC", HelixForm::B).expect("valid sequence").build();
+        let phosphorus = molecule.atoms.iter().filter(|a| a.element == Element::P).count();
+        assert!(phosphorus > 0);
+        molecule.assign_charges(ChargeModel::Phosphate);
+        assert!((molecule.net_charge() + phosphorus as f64).abs() < 1e-12, "{} for {} P", molecule.net_charge(), phosphorus);
+        // DNA has no ionizable side chains, so the standard model adds nothing
+        molecule.assign_charges(ChargeModel::Standard);
+        assert!((molecule.net_charge() + phosphorus as f64).abs() < 1e-12);
+        assert_eq!(molecule.assign_charges(ChargeModel::None), 0);
+        assert!(molecule.charge_sources().is_empty());
+    }
+
+    #[test]
+    fn invalid_media_are_rejected() {
+        assert!(Medium::default().validate().is_ok());
+        assert!(Medium { ionic_strength: -0.1, ..Medium::default() }.validate().is_err());
+        assert!(Medium { relative_permittivity: 0.0, ..Medium::default() }.validate().is_err());
+        assert!(Medium { temperature: f64::NAN, ..Medium::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use std::fmt;
+
+/// Chemical elements, hydrogen through uranium
+/// Deuterium is kept as its own entry because PDB files label it "D".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Element {
+    H,
+    D,
+    He,
+    Li,
+    Be,
+    B,
+    C,
+    N,
+    O,
+    F,
+    Ne,
+    Na,
+    Mg,
+    Al,
+    Si,
+    P,
+    S,
+    Cl,
+    Ar,
+    K,
+    Ca,
+    Sc,
+    Ti,
+    V,
+    Cr,
+    Mn,
+    Fe,
+    Co,
+    Ni,
+    Cu,
+    Zn,
+    Ga,
+    Ge,
+    As,
+    Se,
+    Br,
+    Kr,
+    Rb,
+    Sr,
+    Y,
+    Zr,
+    Nb,
+    Mo,
+    Tc,
+    Ru,
+    Rh,
+    Pd,
+    Ag,
+    Cd,
+    In,
+    Sn,
+    Sb,
+    Te,
+    I,
+    Xe,
+    Cs,
+    Ba,
+    La,
+    Ce,
+    Pr,
+    Nd,
+    Pm,
+    Sm,
+    Eu,
+    Gd,
+    Tb,
+    Dy,
+    Ho,
+    Er,
+    Tm,
+    Yb,
+    Lu,
+    Hf,
+    Ta,
+    W,
+    Re,
+    Os,
+    Ir,
+    Pt,
+    Au,
+    Hg,
+    Tl,
+    Pb,
+    Bi,
+    Po,
+    At,
+    Rn,
+    Fr,
+    Ra,
+    Ac,
+    Th,
+    Pa,
+    U,
+}
+
+/// Tabulated properties of one element
+struct ElementData {
+    symbol: &'static str,
+    atomic_number: u32,
+    mass: f64,            // standard atomic weight, Daltons
+    vdw_radius: f64,      // Angstroms
+    covalent_radius: f64, // Angstroms, single bond
+}
+
+const fn data(symbol: &'static str, atomic_number: u32, mass: f64, vdw_radius: f64, covalent_radius: f64) -> ElementData {
+    ElementData { symbol, atomic_number, mass, vdw_radius, covalent_radius }
+}
+
+// Masses: IUPAC 2021 conventional weights; elements without a stable isotope take the
+// mass number of their longest-lived one. Van der Waals radii: Bondi (1964), with
+// Rowland-Taylor for H, Mantina et al. (2009) for the main-group elements Bondi does not
+// list, Alvarez (2013) for the lanthanides and actinides, and 2.0 for the transition
+// metals none of these covers.
+// Covalent radii: Cordero et al. (2008), low-spin metals.
+const TABLE: [ElementData; 93] = [
+    data("H", 1, 1.008, 1.10, 0.31),
+    data("D", 1, 2.014, 1.10, 0.31),
+    data("He", 2, 4.0026, 1.40, 0.28),
+    data("Li", 3, 6.94, 1.82, 1.28),
+    data("Be", 4, 9.0122, 1.53, 0.96),
+    data("B", 5, 10.81, 1.92, 0.84),
+    data("C", 6, 12.011, 1.70, 0.76),
+    data("N", 7, 14.007, 1.55, 0.71),
+    data("O", 8, 15.999, 1.52, 0.66),
+    data("F", 9, 18.998, 1.47, 0.57),
+    data("Ne", 10, 20.180, 1.54, 0.58),
+    data("Na", 11, 22.990, 2.27, 1.66),
+    data("Mg", 12, 24.305, 1.73, 1.41),
+    data("Al", 13, 26.982, 1.84, 1.21),
+    data("Si", 14, 28.085, 2.10, 1.11),
+    data("P", 15, 30.974, 1.80, 1.07),
+    data("S", 16, 32.06, 1.80, 1.05),
+    data("Cl", 17, 35.45, 1.75, 1.02),
+    data("Ar", 18, 39.95, 1.88, 1.06),
+    data("K", 19, 39.098, 2.75, 2.03),
+    data("Ca", 20, 40.078, 2.31, 1.76),
+    data("Sc", 21, 44.956, 2.00, 1.70),
+    data("Ti", 22, 47.867, 2.00, 1.60),
+    data("V", 23, 50.942, 2.00, 1.53),
+    data("Cr", 24, 51.996, 2.00, 1.39),
+    data("Mn", 25, 54.938, 2.00, 1.39),
+    data("Fe", 26, 55.845, 2.00, 1.32),
+    data("Co", 27, 58.933, 2.00, 1.26),
+    data("Ni", 28, 58.693, 1.63, 1.24),
+    data("Cu", 29, 63.546, 1.40, 1.32),
+    data("Zn", 30, 65.38, 1.39, 1.22),
+    data("Ga", 31, 69.723, 1.87, 1.22),
+    data("Ge", 32, 72.630, 2.11, 1.20),
+    data("As", 33, 74.922, 1.85, 1.19),
+    data("Se", 34, 78.971, 1.90, 1.20),
+    data("Br", 35, 79.904, 1.85, 1.20),
+    data("Kr", 36, 83.798, 2.02, 1.16),
+    data("Rb", 37, 85.468, 3.03, 2.20),
+    data("Sr", 38, 87.62, 2.49, 1.95),
+    data("Y", 39, 88.906, 2.00, 1.90),
+    data("Zr", 40, 91.224, 2.00, 1.75),
+    data("Nb", 41, 92.906, 2.00, 1.64),
+    data("Mo", 42, 95.95, 2.00, 1.54),
+    data("Tc", 43, 98.0, 2.00, 1.47),
+    data("Ru", 44, 101.07, 2.00, 1.46),
+    data("Rh", 45, 102.906, 2.00, 1.42),
+    data("Pd", 46, 106.42, 1.63, 1.39),
+    data("Ag", 47, 107.868, 1.72, 1.45),
+    data("Cd", 48, 112.414, 1.58, 1.44),
+    data("In", 49, 114.818, 1.93, 1.42),
+    data("Sn", 50, 118.710, 2.17, 1.39),
+    data("Sb", 51, 121.760, 2.06, 1.39),
+    data("Te", 52, 127.60, 2.06, 1.38),
+    data("I", 53, 126.904, 1.98, 1.39),
+    data("Xe", 54, 131.293, 2.16, 1.40),
+    data("Cs", 55, 132.905, 3.43, 2.44),
+    data("Ba", 56, 137.327, 2.68, 2.15),
+    data("La", 57, 138.905, 2.98, 2.07),
+    data("Ce", 58, 140.116, 2.88, 2.04),
+    data("Pr", 59, 140.908, 2.92, 2.03),
+    data("Nd", 60, 144.242, 2.95, 2.01),
+    data("Pm", 61, 145.0, 2.90, 1.99),
+    data("Sm", 62, 150.36, 2.90, 1.98),
+    data("Eu", 63, 151.964, 2.87, 1.98),
+    data("Gd", 64, 157.25, 2.83, 1.96),
+    data("Tb", 65, 158.925, 2.79, 1.94),
+    data("Dy", 66, 162.500, 2.87, 1.92),
+    data("Ho", 67, 164.930, 2.81, 1.92),
+    data("Er", 68, 167.259, 2.83, 1.89),
+    data("Tm", 69, 168.934, 2.79, 1.90),
+    data("Yb", 70, 173.045, 2.80, 1.87),
+    data("Lu", 71, 174.967, 2.74, 1.87),
+    data("Hf", 72, 178.486, 2.00, 1.75),
+    data("Ta", 73, 180.948, 2.00, 1.70),
+    data("W", 74, 183.84, 2.00, 1.62),
+    data("Re", 75, 186.207, 2.00, 1.51),
+    data("Os", 76, 190.23, 2.00, 1.44),
+    data("Ir", 77, 192.217, 2.00, 1.41),
+    data("Pt", 78, 195.084, 1.72, 1.36),
+    data("Au", 79, 196.967, 1.66, 1.36),
+    data("Hg", 80, 200.592, 1.55, 1.32),
+    data("Tl", 81, 204.38, 1.96, 1.45),
+    data("Pb", 82, 207.2, 2.02, 1.46),
+    data("Bi", 83, 208.980, 2.07, 1.48),
+    data("Po", 84, 209.0, 1.97, 1.40),
+    data("At", 85, 210.0, 2.02, 1.50),
+    data("Rn", 86, 222.0, 2.20, 1.50),
+    data("Fr", 87, 223.0, 3.48, 2.60),
+    data("Ra", 88, 226.0, 2.83, 2.21),
+    data("Ac", 89, 227.0, 2.80, 2.15),
+    data("Th", 90, 232.038, 2.93, 2.06),
+    data("Pa", 91, 231.036, 2.88, 2.00),
+    data("U", 92, 238.029, 2.71, 1.96),
+];
+
+impl Element {
+    /// Every tabulated element, in table order
+    pub const ALL: [Element; 93] = [
+        Element::H,
+        Element::D,
+        Element::He,
+        Element::Li,
+        Element::Be,
+        Element::B,
+        Element::C,
+        Element::N,
+        Element::O,
+        Element::F,
+        Element::Ne,
+        Element::Na,
+        Element::Mg,
+        Element::Al,
+        Element::Si,
+        Element::P,
+        Element::S,
+        Element::Cl,
+        Element::Ar,
+        Element::K,
+        Element::Ca,
+        Element::Sc,
+        Element::Ti,
+        Element::V,
+        Element::Cr,
+        Element::Mn,
+        Element::Fe,
+        Element::Co,
+        Element::Ni,
+        Element::Cu,
+        Element::Zn,
+        Element::Ga,
+        Element::Ge,
+        Element::As,
+        Element::Se,
+        Element::Br,
+        Element::Kr,
+        Element::Rb,
+        Element::Sr,
+        Element::Y,
+        Element::Zr,
+        Element::Nb,
+        Element::Mo,
+        Element::Tc,
+        Element::Ru,
+        Element::Rh,
+        Element::Pd,
+        Element::Ag,
+        Element::Cd,
+        Element::In,
+        Element::Sn,
+        Element::Sb,
+        Element::Te,
+        Element::I,
+        Element::Xe,
+        Element::Cs,
+        Element::Ba,
+        Element::La,
+        Element::Ce,
+        Element::Pr,
+        Element::Nd,
+        Element::Pm,
+        Element::Sm,
+        Element::Eu,
+        Element::Gd,
+        Element::Tb,
+        Element::Dy,
+        Element::Ho,
+        Element::Er,
+        Element::Tm,
+        Element::Yb,
+        Element::Lu,
+        Element::Hf,
+        Element::Ta,
+        Element::W,
+        Element::Re,
+        Element::Os,
+        Element::Ir,
+        Element::Pt,
+        Element::Au,
+        Element::Hg,
+        Element::Tl,
+        Element::Pb,
+        Element::Bi,
+        Element::Po,
+        Element::At,
+        Element::Rn,
+        Element::Fr,
+        Element::Ra,
+        Element::Ac,
+        Element::Th,
+        Element::Pa,
+        Element::U,
+    ];
+
+    /// Looks up a symbol case-insensitively ("CL", "cl" and "Cl" all give chlorine)
+    pub fn from_symbol(symbol: &str) -> Result<Self, UnknownElement> {
+        let trimmed = symbol.trim();
+        Element::ALL
+            .iter()
+            .copied()
+            .find(|e| e.symbol().eq_ignore_ascii_case(trimmed))
+            .ok_or_else(|| UnknownElement(trimmed.to_string()))
+    }
+
+    fn data(self) -> &'static ElementData {
+        &TABLE[self as usize]
+    }
+
+    /// Conventionally capitalised symbol
+    pub fn symbol(self) -> &'static str {
+        self.data().symbol
+    }
+
+    pub fn atomic_number(self) -> u32 {
+        self.data().atomic_number
+    }
+
+    /// Standard atomic weight in Daltons
+    pub fn mass(self) -> f64 {
+        self.data().mass
+    }
+
+    /// Van der Waals radius in Angstroms
+    pub fn vdw_radius(self) -> f64 {
+        self.data().vdw_radius
+    }
+
+    /// Single-bond covalent radius in Angstroms
+    pub fn covalent_radius(self) -> f64 {
+        self.data().covalent_radius
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.symbol())
+    }
+}
+
+impl std::str::FromStr for Element {
+    type Err = UnknownElement;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Element::from_symbol(s)
+    }
+}
+
+/// A symbol that is not in the element table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownElement(pub String);
+
+impl fmt::Display for UnknownElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown element '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownElement {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_runs_from_hydrogen_to_uranium_in_enum_order() {
+        for (i, element) in Element::ALL.iter().enumerate() {
+            assert_eq!(*element as usize, i, "{} is out of place", element);
+            assert_eq!(Element::from_symbol(element.symbol()), Ok(*element));
+        }
+        let numbers: Vec<u32> = Element::ALL.iter().filter(|&&e| e != Element::D).map(|e| e.atomic_number()).collect();
+        assert_eq!(numbers, (1..=92).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn masses_and_radii_are_physical() {
+        for element in Element::ALL {
+            assert!(element.mass() >= element.atomic_number() as f64, "{} mass {}", element, element.mass());
+            assert!((1.0..3.5).contains(&element.vdw_radius()), "{} vdW radius {}", element, element.vdw_radius());
+            assert!(element.covalent_radius() > 0.2 && element.covalent_radius() < element.vdw_radius(), "{} covalent radius {}", element, element.covalent_radius());
+        }
+    }
+
+    #[test]
+    fn looks_up_pdb_style_symbols_of_ions_and_cofactors() {
+        for (symbol, number) in [("MO", 42), ("LI", 3), ("W", 74), ("SI", 14), ("AU", 79), ("pt", 78), ("Gd", 64), (" U ", 92)] {
+            assert_eq!(Element::from_symbol(symbol).map(|e| e.atomic_number()), Ok(number), "{}", symbol);
+        }
+        assert_eq!(Element::from_symbol("XX"), Err(UnknownElement("XX".to_string())));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::density_grid::DensityGrid;
+use crate::gqbme_engine::{RelativisticSolver, Vector3, ANGSTROM_TO_M, G_CONSTANT};
+use crate::poisson::PotentialGrid;
+
+pub const EARTH_MASS: f64 = 5.9722e24; // kg
+pub const EARTH_RADIUS: f64 = 6.371e6; // m, mean
+pub const SOLAR_MASS: f64 = 1.98847e30; // kg
+const LEO_ALTITUDE: f64 = 4.0e5; // m, roughly the ISS
+const WHITE_DWARF_MASS: f64 = 0.6 * SOLAR_MASS; // typical DA white dwarf
+const WHITE_DWARF_RADIUS: f64 = 8.7e6; // m
+const NEUTRON_STAR_MASS: f64 = 1.4 * SOLAR_MASS;
+const NEUTRON_STAR_RADIUS: f64 = 1.2e4; // m
+
+/// A distant body treated exactly as a point mass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointMass {
+    pub mass: f64,          // kg
+    pub distance: f64,      // m from `Environment::reference`
+    pub direction: Vector3, // unit vector from the reference towards the body
+}
+
+impl PointMass {
+    /// Body of `mass` kg at `distance` m along `direction` (normalised here)
+    pub fn new(mass: f64, distance: f64, direction: Vector3) -> Self {
+        Self { mass, distance, direction: direction * (1.0 / direction.norm()) }
+    }
+
+    /// (\Phi at the reference, \Phi(s) - \Phi(0), g(s)) for a displacement `s` in metres
+    /// |d n - s| differs from d by ~1e-19 relative at molecular scales, so the variation is
+    /// taken from (1 + x)^{-1/2} - 1 with x = (|s|^2 - 2d n.s)/d^2 rather than by subtraction.
+    fn evaluate(&self, s: Vector3) -> (f64, f64, Vector3) {
+        let (d, n) = (self.distance, self.direction);
+        let gm = G_CONSTANT * self.mass;
+        let x = (s.dot(&s) - 2.0 * d * n.dot(&s)) / (d * d);
+        let root = (1.0 + x).sqrt();
+        let phi0 = -gm / d;
+        let variation = phi0 * (-x / (root * (1.0 + root)));
+        let field = (n - s * (1.0 / d)) * (gm / (d * d * root * root * root));
+        (phi0, variation, field)
+    }
+
+    /// \partial_i\partial_j\Phi at the reference, GM/d^3 (\delta_{ij} - 3 n_i n_j), in s^-2
+    pub fn tidal_tensor(&self) -> [[f64; 3]; 3] {
+        let scale = G_CONSTANT * self.mass / self.distance.powi(3);
+        let n = [self.direction.x, self.direction.y, self.direction.z];
+        let mut t = [[0.0; 3]; 3];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = scale * (if i == j { 1.0 } else { 0.0 } - 3.0 * n[i] * n[j]);
+            }
+        }
+        t
+    }
+}
+
+/// Background gravity from sources outside the molecule, expanded about `reference`:
+/// \Phi_{env}(r) = \Phi_0 - g.s + s.T.s/2 + \Phi_{body}(s) with s = r - reference
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Environment {
+    pub reference: Vector3,           // Angstroms
+    pub potential_offset: f64,        // \Phi_0, J/kg
+    pub uniform_field: Vector3,       // g, m/s^2
+    pub tidal_tensor: [[f64; 3]; 3],  // T, s^-2
+    pub point_mass: Option<PointMass>,
+}
+
+impl Environment {
+    /// No external gravity; the molecule's self-gravity is all there is
+    pub fn vacuum() -> Self {
+        Self::default()
+    }
+
+    pub fn uniform(field: Vector3) -> Self {
+        Self { uniform_field: field, ..Self::default() }
+    }
+
+    pub fn point_mass(body: PointMass) -> Self {
+        Self { point_mass: Some(body), ..Self::default() }
+    }
+
+    pub fn tidal(tensor: [[f64; 3]; 3]) -> Self {
+        Self { tidal_tensor: tensor, ..Self::default() }
+    }
+
+    pub fn preset(preset: EnvironmentPreset) -> Self {
+        preset.environment()
+    }
+
+    /// Moves the expansion point (usually to the molecule's centre of mass)
+    pub fn with_reference(mut self, reference: Vector3) -> Self {
+        self.reference = reference;
+        self
+    }
+
+    pub fn is_vacuum(&self) -> bool {
+        Self { reference: self.reference, ..Self::default() } == *self
+    }
+
+    /// \Phi_{env} at the reference point, J/kg
+    pub fn reference_potential(&self) -> f64 {
+        self.potential_offset + self.point_mass.map_or(0.0, |b| -G_CONSTANT * b.mass / b.distance)
+    }
+
+    /// \Phi_{env}(position) - \Phi_{env}(reference), J/kg, without the cancellation of subtracting totals
+    pub fn potential_variation_at(&self, position: &Vector3) -> f64 {
+        let s = (*position - self.reference) * ANGSTROM_TO_M;
+        let tidal = mat_vec(&self.tidal_tensor, s);
+        let body = self.point_mass.map_or(0.0, |b| b.evaluate(s).1);
+        -self.uniform_field.dot(&s) + 0.5 * s.dot(&tidal) + body
+    }
+
+    /// \Phi_{env}(position), J/kg
+    pub fn potential_at(&self, position: &Vector3) -> f64 {
+        self.reference_potential() + self.potential_variation_at(position)
+    }
+
+    /// g_{env} = -\nabla\Phi_{env} at `position`, m/s^2
+    pub fn field_at(&self, position: &Vector3) -> Vector3 {
+        let s = (*position - self.reference) * ANGSTROM_TO_M;
+        let body = self.point_mass.map_or(Vector3::default(), |b| b.evaluate(s).2);
+        self.uniform_field - mat_vec(&self.tidal_tensor, s) + body
+    }
+
+    /// Total tidal tensor at the reference, including the point mass's contribution
+    pub fn total_tidal_tensor(&self) -> [[f64; 3]; 3] {
+        let mut t = self.tidal_tensor;
+        if let Some(body) = self.point_mass {
+            let b = body.tidal_tensor();
+            for (row, brow) in t.iter_mut().zip(b) {
+                for (v, bv) in row.iter_mut().zip(brow) {
+                    *v += bv;
+                }
+            }
+        }
+        t
+    }
+}
+
+fn mat_vec(m: &[[f64; 3]; 3], v: Vector3) -> Vector3 {
+    let row = |r: &[f64; 3]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+    Vector3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+/// Named environments; the body sits along -z ("down") from the molecule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvironmentPreset {
+    #[default]
+    Vacuum,
+    /// Resting on the ground at Earth's mean radius
+    EarthSurface,
+    /// Free fall 400 km up: the uniform field drops out (equivalence principle), leaving
+    /// \Phi_0 and the tidal tensor
+    LowEarthOrbit,
+    /// Resting on a 0.6 solar-mass, 8700 km white dwarf
+    WhiteDwarf,
+    /// Resting on a 1.4 solar-mass, 12 km neutron star; GM/(Rc^2) ~ 0.17, beyond the weak-field regime
+    NeutronStar,
+}
+
+impl EnvironmentPreset {
+    pub const ALL: [EnvironmentPreset; 5] = [
+        EnvironmentPreset::Vacuum,
+        EnvironmentPreset::EarthSurface,
+        EnvironmentPreset::LowEarthOrbit,
+        EnvironmentPreset::WhiteDwarf,
+        EnvironmentPreset::NeutronStar,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EnvironmentPreset::Vacuum => "vacuum",
+            EnvironmentPreset::EarthSurface => "earth",
+            EnvironmentPreset::LowEarthOrbit => "leo",
+            EnvironmentPreset::WhiteDwarf => "white-dwarf",
+            EnvironmentPreset::NeutronStar => "neutron-star",
+        }
+    }
+
+    pub fn environment(self) -> Environment {
+        let down = Vector3::new(0.0, 0.0, -1.0);
+        match self {
+            EnvironmentPreset::Vacuum => Environment::vacuum(),
+            EnvironmentPreset::EarthSurface => Environment::point_mass(PointMass::new(EARTH_MASS, EARTH_RADIUS, down)),
+            EnvironmentPreset::LowEarthOrbit => {
+                let body = PointMass::new(EARTH_MASS, EARTH_RADIUS + LEO_ALTITUDE, down);
+                Environment {
+                    potential_offset: -G_CONSTANT * body.mass / body.distance,
+                    tidal_tensor: body.tidal_tensor(),
+                    ..Environment::default()
+                }
+            }
+            EnvironmentPreset::WhiteDwarf => Environment::point_mass(PointMass::new(WHITE_DWARF_MASS, WHITE_DWARF_RADIUS, down)),
+            EnvironmentPreset::NeutronStar => Environment::point_mass(PointMass::new(NEUTRON_STAR_MASS, NEUTRON_STAR_RADIUS, down)),
+        }
+    }
+}
+
+impl std::str::FromStr for EnvironmentPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vacuum" | "none" => Ok(EnvironmentPreset::Vacuum),
+            "earth" | "earth-surface" => Ok(EnvironmentPreset::EarthSurface),
+            "leo" | "low-earth-orbit" => Ok(EnvironmentPreset::LowEarthOrbit),
+            "white-dwarf" | "wd" => Ok(EnvironmentPreset::WhiteDwarf),
+            "neutron-star" | "ns" => Ok(EnvironmentPreset::NeutronStar),
+            other => Err(format!("unknown environment '{}' (expected vacuum, earth, leo, white-dwarf or neutron-star)", other)),
+        }
+    }
+}
+
+impl RelativisticSolver {
+    /// `calculate_potential` plus the environment's \Phi at the same point
+    pub fn calculate_potential_in<G: DensityGrid + ?Sized>(position: &Vector3, grid: &G, environment: &Environment) -> f64 {
+        Self::calculate_potential(position, grid) + environment.potential_at(position)
+    }
+
+    /// `calculate_field` plus the environment's g
+    pub fn calculate_field_in<G: DensityGrid + ?Sized>(position: &Vector3, grid: &G, environment: &Environment) -> Vector3 {
+        Self::calculate_field(position, grid) + environment.field_at(position)
+    }
+
+    /// \Omega = \Omega_0 + \alpha(\Phi_{self} + \Phi_{env}) at `position`
+    pub fn compute_metric_torsion_in(base_twist: f64, self_potential: f64, position: &Vector3, environment: &Environment, alpha: f64) -> f64 {
+        Self::compute_metric_torsion_with(base_twist, self_potential + environment.potential_at(position), alpha)
+    }
+}
+
+impl PotentialGrid {
+    /// Adds \Phi_{env} at every node
+    pub fn add_environment(&mut self, environment: &Environment) {
+        let (_, ny, nz) = self.dimensions;
+        for (idx, value) in self.data.iter_mut().enumerate() {
+            let (x, y, z) = (idx / (ny * nz), (idx / nz) % ny, idx % nz);
+            let position = self.origin + Vector3::new(x as f64, y as f64, z as f64) * self.resolution;
+            *value += environment.potential_at(&position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_g(preset: EnvironmentPreset) -> Vector3 {
+        Environment::preset(preset).field_at(&Vector3::default())
+    }
+
+    #[test]
+    fn presets_give_the_expected_surface_gravity() {
+        let earth = surface_g(EnvironmentPreset::EarthSurface);
+        assert!((earth.z + 9.82).abs() < 0.01, "{:?}", earth);
+        assert!(earth.x == 0.0 && earth.y == 0.0);
+        // About 1e6 and 1.3e12 m/s^2
+        let white_dwarf = surface_g(EnvironmentPreset::WhiteDwarf).z;
+        assert!((white_dwarf / -(G_CONSTANT * WHITE_DWARF_MASS / (WHITE_DWARF_RADIUS * WHITE_DWARF_RADIUS)) - 1.0).abs() < 1e-12);
+        assert!((-white_dwarf / 1.05e6 - 1.0).abs() < 0.01, "{}", white_dwarf);
+        let neutron_star = surface_g(EnvironmentPreset::NeutronStar).z;
+        assert!((-neutron_star / 1.30e12 - 1.0).abs() < 0.01, "{}", neutron_star);
+        assert_eq!(surface_g(EnvironmentPreset::Vacuum), Vector3::default());
+        // Free fall cancels the uniform part
+        assert_eq!(surface_g(EnvironmentPreset::LowEarthOrbit), Vector3::default());
+    }
+
+    #[test]
+    fn orbit_keeps_the_depth_and_tides_of_earth() {
+        let orbit = Environment::preset(EnvironmentPreset::LowEarthOrbit);
+        let body = PointMass::new(EARTH_MASS, EARTH_RADIUS + LEO_ALTITUDE, Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(orbit.reference_potential(), -G_CONSTANT * EARTH_MASS / (EARTH_RADIUS + LEO_ALTITUDE));
+        assert_eq!(orbit.total_tidal_tensor(), body.tidal_tensor());
+        let t = orbit.total_tidal_tensor();
+        // Stretched along the vertical, squeezed across it, traceless in vacuum
+        assert!(t[2][2] < 0.0 && t[0][0] > 0.0);
+        assert!((t[0][0] + t[1][1] + t[2][2]).abs() < 1e-25);
+    }
+
+    #[test]
+    fn field_is_minus_the_potential_gradient() {
+        let environment = Environment {
+            uniform_field: Vector3::new(0.3, -0.1, 2.0),
+            tidal_tensor: [[1e-6, 2e-7, 0.0], [2e-7, -3e-6, 1e-7], [0.0, 1e-7, 2e-6]],
+            point_mass: Some(PointMass::new(WHITE_DWARF_MASS, WHITE_DWARF_RADIUS, Vector3::new(1.0, 1.0, -1.0))),
+            ..Environment::default()
+        }
+        .with_reference(Vector3::new(5.0, -3.0, 2.0));
+        // 1 um from the reference with 10 nm steps, where the variation is still resolved
+        let position = environment.reference + Vector3::new(7e3, -4e3, 1e4);
+        let h = 100.0;
+        let field = environment.field_at(&position);
+        for (axis, step) in [Vector3::new(h, 0.0, 0.0), Vector3::new(0.0, h, 0.0), Vector3::new(0.0, 0.0, h)].into_iter().enumerate() {
+            let numeric = -(environment.potential_variation_at(&(position + step)) - environment.potential_variation_at(&(position - step))) / (2.0 * h * ANGSTROM_TO_M);
+            let analytic = [field.x, field.y, field.z][axis];
+            assert!((numeric / analytic - 1.0).abs() < 1e-6, "axis {}: {} vs {}", axis, numeric, analytic);
+        }
+    }
+
+    #[test]
+    fn point_mass_variation_matches_subtraction_far_out() {
+        let body = PointMass::new(EARTH_MASS, EARTH_RADIUS, Vector3::new(0.0, 0.0, -1.0));
+        // 1 km up, where subtracting the two totals is still accurate
+        let s = Vector3::new(0.0, 0.0, 1e3);
+        let (phi0, variation, _) = body.evaluate(s);
+        let exact = -G_CONSTANT * EARTH_MASS / (EARTH_RADIUS + 1e3) - phi0;
+        assert!((variation / exact - 1.0).abs() < 1e-9, "{} vs {}", variation, exact);
+        // Near the surface this is g h
+        assert!((variation / (9.82 * 1e3) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn preset_names_parse_back() {
+        for preset in EnvironmentPreset::ALL {
+            assert_eq!(preset.name().parse::<EnvironmentPreset>(), Ok(preset));
+        }
+        assert_eq!("WD".parse::<EnvironmentPreset>(), Ok(EnvironmentPreset::WhiteDwarf));
+        assert!("jupiter".parse::<EnvironmentPreset>().is_err());
+        assert!(Environment::preset(EnvironmentPreset::Vacuum).is_vacuum());
+        assert!(!Environment::preset(EnvironmentPreset::EarthSurface).is_vacuum());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::deposition::DepositionReport;
+use crate::diagnostics::Diagnostics;
+use crate::dna_builder::SequenceError;
+use crate::element::UnknownElement;
+use crate::gqbme_engine::Vector3;
+use crate::md::CheckpointError;
+use crate::mrc::MrcError;
+use crate::scenario::ScenarioError;
+use crate::structure_io::StructureError;
+use std::fmt;
+
+/// Errors raised by the engine's fallible constructors and checked operations
+/// The module-specific errors convert into it, so `?` works across a whole pipeline.
+#[derive(Debug)]
+pub enum GqbmeError {
+    /// Voxel edge must be finite and positive
+    InvalidResolution(f64),
+    /// Every grid axis needs at least one voxel
+    EmptyGrid { dimensions: (usize, usize, usize) },
+    /// The voxel count does not fit in memory addressing, or the allocation was refused
+    GridTooLarge { dimensions: (usize, usize, usize) },
+    /// Grid origin is NaN or infinite
+    NonFiniteOrigin(Vector3),
+    /// Fit padding must be finite and non-negative
+    InvalidPadding(f64),
+    /// An atom position is NaN or infinite
+    NonFinitePosition { atom: usize },
+    /// Atomic masses must be finite and non-negative
+    InvalidMass { atom: usize, mass: f64 },
+    /// The operation needs at least one atom with mass
+    EmptyMolecule,
+    /// Deposition lost mass off the grid edge; the grid keeps the part that landed
+    MassLost(Diagnostics),
+    /// Charge deposition lost charge off the grid edge; the report counts it in e
+    ChargeLost(DepositionReport),
+    /// Structures compared atom-by-atom must have the same number of atoms
+    AtomCountMismatch { expected: usize, found: usize },
+    /// Matched atoms must be the same element
+    ElementMismatch { atom: usize },
+    /// A tight-binding model needs nucleotide residues with base atoms
+    NoNucleotides,
+    /// Torsion feedback produced a non-finite twist at this iteration (1-based)
+    TorsionDiverged { iteration: usize },
+    /// A helix of N base pairs takes exactly N - 1 step twists
+    TwistCountMismatch { expected: usize, found: usize },
+    Structure(StructureError),
+    Mrc(MrcError),
+    UnknownElement(UnknownElement),
+    Sequence(SequenceError),
+    Checkpoint(CheckpointError),
+    Scenario(ScenarioError),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for GqbmeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GqbmeError::InvalidResolution(r) => write!(f, "grid resolution must be finite and positive, got {}", r),
+            GqbmeError::EmptyGrid { dimensions } => write!(f, "grid dimensions {:?} contain a zero axis", dimensions),
+            GqbmeError::GridTooLarge { dimensions } => write!(f, "grid dimensions {:?} are too large to allocate", dimensions),
+            GqbmeError::NonFiniteOrigin(origin) => write!(f, "grid origin {:?} is not finite", origin),
+            GqbmeError::InvalidPadding(p) => write!(f, "grid padding must be finite and non-negative, got {}", p),
+            GqbmeError::NonFinitePosition { atom } => write!(f, "atom {} has a non-finite position", atom),
+            GqbmeError::InvalidMass { atom, mass } => write!(f, "atom {} has invalid mass {}", atom, mass),
+            GqbmeError::EmptyMolecule => write!(f, "molecule has no atoms with mass"),
+            GqbmeError::MassLost(d) => write!(
+                f,
+                "{:.4} Da ({:.3}%) fell outside the grid: {} atoms dropped, {} clipped",
+                d.lost_mass,
+                100.0 * d.lost_fraction(),
+                d.dropped_atoms,
+                d.clipped_atoms
+            ),
+            GqbmeError::ChargeLost(d) => write!(f, "{:.4} e of net charge fell outside the grid: {} charged atoms dropped, {} clipped", d.lost_mass, d.atoms_outside, d.atoms_clipped),
+            GqbmeError::AtomCountMismatch { expected, found } => write!(f, "structures differ in size: reference has {} atoms, mobile has {}", expected, found),
+            GqbmeError::ElementMismatch { atom } => write!(f, "atom {} is a different element in the two structures", atom),
+            GqbmeError::NoNucleotides => write!(f, "molecule has no nucleotide residues with base atoms to place tight-binding sites on"),
+            GqbmeError::TorsionDiverged { iteration } => write!(f, "torsion feedback diverged to a non-finite twist at iteration {}", iteration),
+            GqbmeError::TwistCountMismatch { expected, found } => write!(f, "helix has {} base-pair steps but {} twists were given", expected, found),
+            GqbmeError::Structure(err) => write!(f, "{}", err),
+            GqbmeError::Mrc(err) => write!(f, "{}", err),
+            GqbmeError::UnknownElement(err) => write!(f, "{}", err),
+            GqbmeError::Sequence(err) => write!(f, "{}", err),
+            GqbmeError::Checkpoint(err) => write!(f, "{}", err),
+            GqbmeError::Scenario(err) => write!(f, "{}", err),
+            GqbmeError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for GqbmeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GqbmeError::Structure(err) => Some(err),
+            GqbmeError::Mrc(err) => Some(err),
+            GqbmeError::UnknownElement(err) => Some(err),
+            GqbmeError::Sequence(err) => Some(err),
+            GqbmeError::Checkpoint(err) => Some(err),
+            GqbmeError::Scenario(err) => Some(err),
+            GqbmeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<StructureError> for GqbmeError {
+    fn from(err: StructureError) -> Self {
+        GqbmeError::Structure(err)
+    }
+}
+
+impl From<MrcError> for GqbmeError {
+    fn from(err: MrcError) -> Self {
+        GqbmeError::Mrc(err)
+    }
+}
+
+impl From<UnknownElement> for GqbmeError {
+    fn from(err: UnknownElement) -> Self {
+        GqbmeError::UnknownElement(err)
+    }
+}
+
+impl From<SequenceError> for GqbmeError {
+    fn from(err: SequenceError) -> Self {
+        GqbmeError::Sequence(err)
+    }
+}
+
+impl From<CheckpointError> for GqbmeError {
+    fn from(err: CheckpointError) -> Self {
+        GqbmeError::Checkpoint(err)
+    }
+}
+
+impl From<ScenarioError> for GqbmeError {
+    fn from(err: ScenarioError) -> Self {
+        GqbmeError::Scenario(err)
+    }
+}
+
+impl From<std::io::Error> for GqbmeError {
+    fn from(err: std::io::Error) -> Self {
+        GqbmeError::Io(err)
+    }
+}
@@ -0,0 +1,363 @@
+use std::f64::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+/// Minimal complex number for the in-crate FFT
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    /// e^{i theta}
+    pub fn from_polar(r: f64, theta: f64) -> Self {
+        Self::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn scale(self, k: f64) -> Self {
+        Self::new(self.re * k, self.im * k)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(self.re * o.re - self.im * o.im, self.re * o.im + self.im * o.re)
+    }
+}
+
+/// Precomputed plan for a 1D transform of fixed length
+/// Powers of two use iterative radix-2, other products of 2, 3 and 5 a recursive mixed-radix
+/// split, and every other length Bluestein's chirp-z.
+#[derive(Debug, Clone)]
+pub struct FftPlan {
+    len: usize,
+    kind: PlanKind,
+}
+
+#[derive(Debug, Clone)]
+enum PlanKind {
+    Radix2 { twiddles: Vec<Complex> },
+    /// `factors` multiply to the length; `twiddles[k]` = e^{-2 pi i k/n}
+    MixedRadix { factors: Vec<usize>, twiddles: Vec<Complex> },
+    Bluestein { inner: Box<FftPlan>, chirp: Vec<Complex>, kernel_hat: Vec<Complex> },
+}
+
+impl FftPlan {
+    pub fn new(len: usize) -> Self {
+        assert!(len > 0, "FFT length must be positive");
+        if len.is_power_of_two() {
+            let twiddles = (0..len / 2).map(|k| Complex::from_polar(1.0, -2.0 * PI * k as f64 / len as f64)).collect();
+            return Self { len, kind: PlanKind::Radix2 { twiddles } };
+        }
+        if let Some(factors) = small_factors(len) {
+            let twiddles = (0..len).map(|k| Complex::from_polar(1.0, -2.0 * PI * k as f64 / len as f64)).collect();
+            return Self { len, kind: PlanKind::MixedRadix { factors, twiddles } };
+        }
+
+        // Bluestein: x_k * w_k convolved with conj(w) where w_k = e^{-i pi k^2 / n}
+        let m = (2 * len - 1).next_power_of_two();
+        let inner = FftPlan::new(m);
+        let chirp: Vec<Complex> = (0..len)
+            .map(|k| {
+                // k^2 mod 2n keeps the angle accurate for large k
+                let k2 = (k as u128 * k as u128 % (2 * len as u128)) as f64;
+                Complex::from_polar(1.0, -PI * k2 / len as f64)
+            })
+            .collect();
+        let mut kernel = vec![Complex::ZERO; m];
+        kernel[0] = chirp[0].conj();
+        for k in 1..len {
+            kernel[k] = chirp[k].conj();
+            kernel[m - k] = chirp[k].conj();
+        }
+        inner.forward(&mut kernel);
+        Self { len, kind: PlanKind::Bluestein { inner: Box::new(inner), chirp, kernel_hat: kernel } }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// In-place forward transform X_k = sum_j x_j e^{-2 pi i jk/n}
+    pub fn forward(&self, data: &mut [Complex]) {
+        self.transform(data);
+    }
+
+    /// In-place inverse transform, normalised by 1/n
+    pub fn inverse(&self, data: &mut [Complex]) {
+        for v in data.iter_mut() {
+            *v = v.conj();
+        }
+        self.transform(data);
+        let scale = 1.0 / self.len as f64;
+        for v in data.iter_mut() {
+            *v = v.conj().scale(scale);
+        }
+    }
+
+    fn transform(&self, data: &mut [Complex]) {
+        assert_eq!(data.len(), self.len, "FFT buffer length does not match plan");
+        match &self.kind {
+            PlanKind::Radix2 { twiddles } => radix2(data, twiddles),
+            PlanKind::MixedRadix { factors, twiddles } => {
+                let input = data.to_vec();
+                mixed_radix(&input, 1, data, factors, twiddles);
+            }
+            PlanKind::Bluestein { inner, chirp, kernel_hat } => {
+                let m = inner.len();
+                let mut buf = vec![Complex::ZERO; m];
+                for k in 0..self.len {
+                    buf[k] = data[k] * chirp[k];
+                }
+                inner.forward(&mut buf);
+                for (b, h) in buf.iter_mut().zip(kernel_hat) {
+                    *b = *b * *h;
+                }
+                inner.inverse(&mut buf);
+                for k in 0..self.len {
+                    data[k] = buf[k] * chirp[k];
+                }
+            }
+        }
+    }
+}
+
+fn radix2(data: &mut [Complex], twiddles: &[Complex]) {
+    let n = data.len();
+    if n <= 1 {
+        return;
+    }
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let mut size = 2;
+    while size <= n {
+        let half = size / 2;
+        let stride = n / size;
+        for start in (0..n).step_by(size) {
+            for k in 0..half {
+                let t = data[start + k + half] * twiddles[k * stride];
+                let u = data[start + k];
+                data[start + k] = u + t;
+                data[start + k + half] = u - t;
+            }
+        }
+        size *= 2;
+    }
+}
+
+/// Prime factors of `n` when all of them are 2, 3 or 5
+fn small_factors(mut n: usize) -> Option<Vec<usize>> {
+    let mut factors = Vec::new();
+    for p in [5, 3, 2] {
+        while n > 1 && n.is_multiple_of(p) {
+            factors.push(p);
+            n /= p;
+        }
+    }
+    (n == 1).then_some(factors)
+}
+
+/// Smallest length >= `n` whose prime factors are all 2, 3 or 5, which `FftPlan` transforms
+/// without Bluestein's detour through a longer power of two
+pub fn next_fast_len(n: usize) -> usize {
+    (n.max(1)..).find(|&m| small_factors(m).is_some()).expect("5-smooth numbers are unbounded")
+}
+
+/// Decimation in time: `out` (length n) receives the DFT of input[0], input[stride], ...
+/// Each radix-r stage combines r interleaved sub-transforms with an r-point DFT.
+fn mixed_radix(input: &[Complex], stride: usize, out: &mut [Complex], factors: &[usize], twiddles: &[Complex]) {
+    let Some((&r, rest)) = factors.split_first() else {
+        out[0] = input[0];
+        return;
+    };
+    let n = out.len();
+    let m = n / r;
+    for q in 0..r {
+        mixed_radix(&input[q * stride..], stride * r, &mut out[q * m..(q + 1) * m], rest, twiddles);
+    }
+    // X[k + p m] = \sum_q W_r^{pq} W_n^{qk} Y_q[k]
+    let (big, step) = (twiddles.len(), twiddles.len() / n);
+    let mut t = [Complex::ZERO; 5];
+    for k in 0..m {
+        for (q, tq) in t.iter_mut().enumerate().take(r) {
+            *tq = out[q * m + k] * twiddles[q * k * step];
+        }
+        for p in 0..r {
+            out[p * m + k] = (0..r).fold(Complex::ZERO, |sum, q| sum + t[q] * twiddles[(p * q % r) * (big / r)]);
+        }
+    }
+}
+
+/// 3D transform over a row-major (x slowest, z fastest) buffer
+/// This matches the `MassDensityGrid` layout.
+#[derive(Debug, Clone)]
+pub struct Fft3 {
+    dims: (usize, usize, usize),
+    plans: [FftPlan; 3],
+}
+
+impl Fft3 {
+    pub fn new(dims: (usize, usize, usize)) -> Self {
+        Self {
+            dims,
+            plans: [FftPlan::new(dims.0), FftPlan::new(dims.1), FftPlan::new(dims.2)],
+        }
+    }
+
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        self.dims
+    }
+
+    pub fn forward(&self, data: &mut [Complex]) {
+        self.apply(data, false);
+    }
+
+    pub fn inverse(&self, data: &mut [Complex]) {
+        self.apply(data, true);
+    }
+
+    fn apply(&self, data: &mut [Complex], inverse: bool) {
+        let (nx, ny, nz) = self.dims;
+        assert_eq!(data.len(), nx * ny * nz, "3D FFT buffer length does not match dimensions");
+        let run = |plan: &FftPlan, line: &mut [Complex]| {
+            if inverse { plan.inverse(line) } else { plan.forward(line) }
+        };
+
+        // z lines are contiguous
+        for line in data.chunks_mut(nz) {
+            run(&self.plans[2], line);
+        }
+
+        let mut line = vec![Complex::ZERO; ny.max(nx)];
+        for x in 0..nx {
+            for z in 0..nz {
+                for y in 0..ny {
+                    line[y] = data[(x * ny + y) * nz + z];
+                }
+                run(&self.plans[1], &mut line[..ny]);
+                for y in 0..ny {
+                    data[(x * ny + y) * nz + z] = line[y];
+                }
+            }
+        }
+        for y in 0..ny {
+            for z in 0..nz {
+                for x in 0..nx {
+                    line[x] = data[(x * ny + y) * nz + z];
+                }
+                run(&self.plans[0], &mut line[..nx]);
+                for x in 0..nx {
+                    data[(x * ny + y) * nz + z] = line[x];
+                }
+            }
+        }
+    }
+}
+
+/// Signed frequency index for bin `k` of an `n`-point transform
+pub fn frequency_index(k: usize, n: usize) -> f64 {
+    if k <= n / 2 { k as f64 } else { k as f64 - n as f64 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic, non-symmetric test signal
+    fn signal(n: usize) -> Vec<Complex> {
+        (0..n).map(|j| Complex::new((0.37 * j as f64).sin() + 0.1 * j as f64, (1.3 * j as f64).cos())).collect()
+    }
+
+    fn max_error(a: &[Complex], b: &[Complex]) -> f64 {
+        a.iter().zip(b).map(|(x, y)| (*x - *y).norm_sqr().sqrt()).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn round_trip_radix2_mixed_radix_and_bluestein() {
+        for n in [1, 2, 8, 64, 3, 7, 12, 100, 243, 14, 1000] {
+            let plan = FftPlan::new(n);
+            let original = signal(n);
+            let mut data = original.clone();
+            plan.forward(&mut data);
+            plan.inverse(&mut data);
+            assert!(max_error(&data, &original) < 1e-10, "length {} round trip off by {}", n, max_error(&data, &original));
+        }
+    }
+
+    #[test]
+    fn forward_matches_direct_dft() {
+        for n in [16, 15, 6, 45, 60, 7, 22] {
+            let input = signal(n);
+            let expected: Vec<Complex> = (0..n)
+                .map(|k| {
+                    input.iter().enumerate().fold(Complex::ZERO, |sum, (j, x)| sum + *x * Complex::from_polar(1.0, -2.0 * PI * (j * k) as f64 / n as f64))
+                })
+                .collect();
+            let mut data = input;
+            FftPlan::new(n).forward(&mut data);
+            assert!(max_error(&data, &expected) < 1e-9, "length {} differs from the DFT by {}", n, max_error(&data, &expected));
+        }
+    }
+
+    #[test]
+    fn fast_lengths_are_5_smooth() {
+        let fast: Vec<usize> = [0, 1, 7, 11, 13, 17, 49, 97, 101, 129].iter().map(|&n| next_fast_len(n)).collect();
+        assert_eq!(fast, [1, 1, 8, 12, 15, 18, 50, 100, 108, 135]);
+        assert!(matches!(FftPlan::new(45).kind, PlanKind::MixedRadix { .. }));
+        assert!(matches!(FftPlan::new(14).kind, PlanKind::Bluestein { .. }));
+    }
+
+    #[test]
+    fn round_trip_3d() {
+        let fft = Fft3::new((4, 6, 5));
+        let original = signal(4 * 6 * 5);
+        let mut data = original.clone();
+        fft.forward(&mut data);
+        fft.inverse(&mut data);
+        assert!(max_error(&data, &original) < 1e-10);
+    }
+}
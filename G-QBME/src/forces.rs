@@ -0,0 +1,153 @@
+use crate::density_grid::DensityGrid;
+use crate::gqbme_engine::{Biomolecule, RelativisticSolver, Vector3, ANGSTROM_TO_M, DALTON_TO_KG, G_CONSTANT};
+use crate::kernel::PairKernel;
+use crate::poisson::PotentialGrid;
+
+/// Per-atom gravitational forces with their resultants
+#[derive(Debug, Clone, Default)]
+pub struct AtomForces {
+    pub forces: Vec<Vector3>, // Newtons, one per atom
+    pub net_force: Vector3,   // Newtons
+    pub torque: Vector3,      // N m about the molecule's centre of mass
+}
+
+impl AtomForces {
+    fn from_forces(molecule: &Biomolecule, forces: Vec<Vector3>) -> Self {
+        let net_force = forces.iter().fold(Vector3::default(), |acc, f| acc + *f);
+        let torque = molecule.torque_about_center_of_mass(&forces);
+        Self { forces, net_force, torque }
+    }
+}
+
+impl Biomolecule {
+    /// Total torque \tau = \sum (r_i - r_{com}) \times F_i in N m
+    pub fn torque_about_center_of_mass(&self, forces: &[Vector3]) -> Vector3 {
+        let com = self.center_of_mass();
+        self.atoms
+            .iter()
+            .zip(forces)
+            .fold(Vector3::default(), |acc, (atom, f)| acc + ((atom.position - com) * ANGSTROM_TO_M).cross(f))
+    }
+}
+
+impl RelativisticSolver {
+    /// Gravitational field g = -\nabla\Phi (m/s^2) at a point, summed over occupied voxels
+    /// Voxels coinciding with `position` are skipped, as in `calculate_potential`.
+    pub fn calculate_field<G: DensityGrid + ?Sized>(position: &Vector3, grid: &G) -> Vector3 {
+        let kernel = PairKernel::gravity();
+        let mut field = Vector3::default();
+        grid.for_each_nonzero(&mut |idx, mass| {
+            if mass <= 0.0 {
+                return;
+            }
+            let r = (*position - grid.index_position(idx)) * ANGSTROM_TO_M;
+            if r.norm() > 0.0 {
+                field += kernel.field(mass, r);
+            }
+        });
+        field
+    }
+
+    /// Exact pairwise Newtonian forces between all atoms, O(N^2)
+    pub fn atom_forces_direct(molecule: &Biomolecule) -> AtomForces {
+        let atoms = &molecule.atoms;
+        let mut forces = vec![Vector3::default(); atoms.len()];
+        for i in 0..atoms.len() {
+            for j in (i + 1)..atoms.len() {
+                let r = (atoms[j].position - atoms[i].position) * ANGSTROM_TO_M;
+                let dist = r.norm();
+                if dist <= 0.0 {
+                    continue;
+                }
+                let mi = atoms[i].mass * DALTON_TO_KG;
+                let mj = atoms[j].mass * DALTON_TO_KG;
+                // Force on i points towards j
+                let f = r * (G_CONSTANT * mi * mj / (dist * dist * dist));
+                forces[i] += f;
+                forces[j] += -f;
+            }
+        }
+        AtomForces::from_forces(molecule, forces)
+    }
+
+    /// Forces F_i = m_i g(r_i) from the gradient of a solved potential grid
+    /// Returns `None` if any atom lies outside the grid. The grid potential includes
+    /// each atom's own smeared mass, so expect a small self-force at coarse resolution.
+    pub fn atom_forces_from_grid(molecule: &Biomolecule, potential: &PotentialGrid) -> Option<AtomForces> {
+        let forces = molecule
+            .atoms
+            .iter()
+            .map(|atom| potential.field_at(&atom.position).map(|g| g * (atom.mass * DALTON_TO_KG)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(AtomForces::from_forces(molecule, forces))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna_builder::{DnaBuilder, HelixForm};
+    use crate::element::Element;
+    use crate::gqbme_engine::MassDensityGrid;
+    use crate::grid_sizing::GridFit;
+    use crate::poisson::Boundary;
+
+    fn pair(separation: f64) -> Biomolecule {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::P, 0.0, 0.0, 0.0);
+        molecule.add_element(Element::Fe, separation, 0.0, 0.0);
+        molecule
+    }
+
+    #[test]
+    fn pair_force_is_newtonian_and_equal_and_opposite() {
+        let molecule = pair(4.0);
+        let forces = RelativisticSolver::atom_forces_direct(&molecule);
+        let r = 4.0 * ANGSTROM_TO_M;
+        let expected = G_CONSTANT * Element::P.mass() * Element::Fe.mass() * DALTON_TO_KG * DALTON_TO_KG / (r * r);
+        assert!((forces.forces[0].x / expected - 1.0).abs() < 1e-12, "{:?}", forces.forces[0]);
+        assert_eq!(forces.forces[0].y, 0.0);
+        assert_eq!(forces.forces[1], -forces.forces[0]);
+    }
+
+    #[test]
+    fn internal_forces_have_no_resultant_or_torque() {
+        let molecule = DnaBuilder::new("ACGTAC", HelixForm::B).expect("valid sequence").build();
+        let forces = RelativisticSolver::atom_forces_direct(&molecule);
+        let scale = forces.forces.iter().map(|f| f.norm()).fold(0.0, f64::max);
+        assert!(forces.net_force.norm() < 1e-12 * scale, "net {:?} against {}", forces.net_force, scale);
+        // Torques are forces times lever arms of ~1e-9 m
+        assert!(forces.torque.norm() < 1e-21 * scale, "torque {:?}", forces.torque);
+    }
+
+    #[test]
+    fn field_is_minus_the_gradient_of_the_potential() {
+        let molecule = DnaBuilder::new("ACG", HelixForm::B).expect("valid sequence").build();
+        let mut grid = MassDensityGrid::fitted_to(&molecule, &GridFit::new(2.0, 1.0)).expect("small grid");
+        let _ = grid.map_biomolecule(&molecule);
+        let point = Vector3::new(31.3, -17.2, 24.9);
+        let g = RelativisticSolver::calculate_field(&point, &grid);
+        let h = 1e-3; // Angstroms
+        let phi = |p: Vector3| RelativisticSolver::calculate_potential(&p, &grid);
+        let axes = [Vector3::new(h, 0.0, 0.0), Vector3::new(0.0, h, 0.0), Vector3::new(0.0, 0.0, h)];
+        let gradient = axes.map(|d| -(phi(point + d) - phi(point - d)) / (2.0 * h * ANGSTROM_TO_M));
+        let numeric = Vector3::new(gradient[0], gradient[1], gradient[2]);
+        assert!((numeric - g).norm() < 1e-6 * g.norm(), "{:?} vs {:?}", numeric, g);
+        // Gravity pulls towards the molecule
+        assert!(g.dot(&(grid.occupied()[0].0 - point)) > 0.0);
+    }
+
+    #[test]
+    fn grid_forces_approach_the_direct_forces() {
+        let molecule = pair(6.0);
+        let mut grid = MassDensityGrid::new(Vector3::new(-5.0, -5.0, -5.0), 0.5, (33, 21, 21));
+        let _ = grid.map_biomolecule(&molecule);
+        let potential = RelativisticSolver::calculate_potential_field(&grid, Boundary::Isolated);
+        let from_grid = RelativisticSolver::atom_forces_from_grid(&molecule, &potential).expect("atoms lie inside the grid");
+        let direct = RelativisticSolver::atom_forces_direct(&molecule);
+        for (a, b) in from_grid.forces.iter().zip(&direct.forces) {
+            assert!((*a - *b).norm() < 0.05 * b.norm(), "{:?} vs {:?}", a, b);
+        }
+        assert!(RelativisticSolver::atom_forces_from_grid(&pair(20.0), &potential).is_none());
+    }
+}
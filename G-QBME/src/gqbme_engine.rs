@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use crate::density_grid::DensityGrid;
+use crate::element::{Element, UnknownElement};
+use crate::error::GqbmeError;
+use crate::kernel::PairKernel;
+use crate::poisson::{Boundary, PoissonSolver, PotentialGrid};
 
 /// Represents a 3D coordinate in space
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vector3 {
     pub x: f64,
     pub y: f64,
@@ -16,18 +20,100 @@ impl Vector3 {
     pub fn dist(&self, other: &Vector3) -> f64 {
         ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2)).sqrt()
     }
+
+    pub fn dot(&self, other: &Vector3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: &Vector3) -> Vector3 {
+        Vector3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn scale(&self, k: f64) -> Vector3 {
+        Vector3::new(self.x * k, self.y * k, self.z * k)
+    }
+}
+
+impl std::ops::Add for Vector3 {
+    type Output = Vector3;
+    fn add(self, o: Vector3) -> Vector3 {
+        Vector3::new(self.x + o.x, self.y + o.y, self.z + o.z)
+    }
+}
+
+impl std::ops::Sub for Vector3 {
+    type Output = Vector3;
+    fn sub(self, o: Vector3) -> Vector3 {
+        Vector3::new(self.x - o.x, self.y - o.y, self.z - o.z)
+    }
+}
+
+impl std::ops::Mul<f64> for Vector3 {
+    type Output = Vector3;
+    fn mul(self, k: f64) -> Vector3 {
+        self.scale(k)
+    }
+}
+
+impl std::ops::Neg for Vector3 {
+    type Output = Vector3;
+    fn neg(self) -> Vector3 {
+        Vector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl std::ops::AddAssign for Vector3 {
+    fn add_assign(&mut self, o: Vector3) {
+        self.x += o.x;
+        self.y += o.y;
+        self.z += o.z;
+    }
+}
+
+/// Residue/chain bookkeeping carried over from PDB or mmCIF records
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AtomRecord {
+    pub serial: usize,
+    pub name: String, // atom name, e.g. "CA" or "O5'"
+    pub residue_name: String,
+    pub residue_seq: i32,
+    pub chain_id: String,
+    pub hetero: bool, // HETATM rather than ATOM
 }
 
 /// Represents an atom in a biomolecule
 #[derive(Debug, Clone)]
 pub struct Atom {
-    pub element: String,
+    pub element: Element,
     pub position: Vector3,
-    pub mass: f64, // atomic mass in Daltons
+    pub mass: f64,   // atomic mass in Daltons
+    pub charge: f64, // partial charge in e; see `Biomolecule::assign_charges`
+    pub record: Option<AtomRecord>,
+}
+
+impl Atom {
+    /// Uncharged atom at `position` with the element's standard atomic weight
+    pub fn new(element: Element, position: Vector3) -> Self {
+        Self {
+            element,
+            position,
+            mass: element.mass(),
+            charge: 0.0,
+            record: None,
+        }
+    }
 }
 
 /// A biomolecule (DNA or Protein)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Biomolecule {
     pub atoms: Vec<Atom>,
 }
@@ -37,17 +123,22 @@ impl Biomolecule {
         Self { atoms: Vec::new() }
     }
 
-    pub fn add_atom(&mut self, element: String, x: f64, y: f64, z: f64, mass: f64) {
-        self.atoms.push(Atom {
-            element,
-            position: Vector3::new(x, y, z),
-            mass,
-        });
+    /// Adds an atom by element symbol; the mass comes from the element table
+    pub fn add_atom(&mut self, symbol: &str, x: f64, y: f64, z: f64) -> Result<(), UnknownElement> {
+        self.add_element(Element::from_symbol(symbol)?, x, y, z);
+        Ok(())
+    }
+
+    pub fn add_element(&mut self, element: Element, x: f64, y: f64, z: f64) {
+        self.atoms.push(Atom::new(element, Vector3::new(x, y, z)));
     }
 
-    /// Calculates the Center of Mass
+    /// Calculates the Center of Mass; the origin for an empty (massless) molecule
     pub fn center_of_mass(&self) -> Vector3 {
         let total_mass: f64 = self.atoms.iter().map(|a| a.mass).sum();
+        if total_mass <= 0.0 {
+            return Vector3::default();
+        }
         let mut com = Vector3::new(0.0, 0.0, 0.0);
         for atom in &self.atoms {
             com.x += atom.position.x * atom.mass;
@@ -56,10 +147,34 @@ impl Biomolecule {
         }
         Vector3::new(com.x / total_mass, com.y / total_mass, com.z / total_mass)
     }
+
+    /// Center of mass of a valid molecule; errors instead of falling back to the origin
+    pub fn try_center_of_mass(&self) -> Result<Vector3, GqbmeError> {
+        self.validate()?;
+        if self.atoms.iter().all(|a| a.mass == 0.0) {
+            return Err(GqbmeError::EmptyMolecule);
+        }
+        Ok(self.center_of_mass())
+    }
+
+    /// Checks that every position is finite and every mass finite and non-negative
+    pub fn validate(&self) -> Result<(), GqbmeError> {
+        for (atom, a) in self.atoms.iter().enumerate() {
+            let p = a.position;
+            if !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()) {
+                return Err(GqbmeError::NonFinitePosition { atom });
+            }
+            if !(a.mass.is_finite() && a.mass >= 0.0) {
+                return Err(GqbmeError::InvalidMass { atom, mass: a.mass });
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A voxel grid for mass density mapping \rho(\mathbf{r})
-#[derive(Debug)]
+/// Dense storage; `SparseDensityGrid` holds only occupied blocks. Both implement `DensityGrid`.
+#[derive(Debug, Clone)]
 pub struct MassDensityGrid {
     pub origin: Vector3,
     pub resolution: f64, // size of one voxel in Angstroms
@@ -68,39 +183,45 @@ pub struct MassDensityGrid {
 }
 
 impl MassDensityGrid {
+    /// Zeroed grid; panics on the inputs `try_new` rejects
     pub fn new(origin: Vector3, resolution: f64, dims: (usize, usize, usize)) -> Self {
-        let size = dims.0 * dims.1 * dims.2;
-        Self {
+        Self::try_new(origin, resolution, dims).unwrap_or_else(|e| panic!("MassDensityGrid::new: {}", e))
+    }
+
+    /// Zeroed grid with a finite origin, positive resolution and at least one voxel per axis
+    pub fn try_new(origin: Vector3, resolution: f64, dims: (usize, usize, usize)) -> Result<Self, GqbmeError> {
+        let size = check_lattice(origin, resolution, dims)?;
+        let mut data = Vec::new();
+        // Within isize::MAX bytes the allocator can still refuse, which `vec!` would turn into an abort
+        if data.try_reserve_exact(size).is_err() {
+            return Err(GqbmeError::GridTooLarge { dimensions: dims });
+        }
+        data.resize(size, 0.0);
+        Ok(Self {
             origin,
             resolution,
             dimensions: dims,
-            data: vec![0.0; size],
-        }
+            data,
+        })
     }
 
-    fn get_index(&self, x: usize, y: usize, z: usize) -> usize {
+    pub(crate) fn get_index(&self, x: usize, y: usize, z: usize) -> usize {
         x * self.dimensions.1 * self.dimensions.2 + y * self.dimensions.2 + z
     }
+}
 
-    /// Maps atoms into the density grid
-    pub fn map_biomolecule(&mut self, molecule: &Biomolecule) {
-        for atom in &molecule.atoms {
-            let rel_x = (atom.position.x - self.origin.x) / self.resolution;
-            let rel_y = (atom.position.y - self.origin.y) / self.resolution;
-            let rel_z = (atom.position.z - self.origin.z) / self.resolution;
-
-            let ix = rel_x.round() as i32;
-            let iy = rel_y.round() as i32;
-            let iz = rel_z.round() as i32;
-
-            if ix >= 0 && ix < self.dimensions.0 as i32 &&
-               iy >= 0 && iy < self.dimensions.1 as i32 &&
-               iz >= 0 && iz < self.dimensions.2 as i32 {
-                let idx = self.get_index(ix as usize, iy as usize, iz as usize);
-                self.data[idx] += atom.mass;
-            }
-        }
+/// Validates a lattice shared by every grid backend and returns its voxel count
+pub(crate) fn check_lattice(origin: Vector3, resolution: f64, dims: (usize, usize, usize)) -> Result<usize, GqbmeError> {
+    if !(resolution.is_finite() && resolution > 0.0) {
+        return Err(GqbmeError::InvalidResolution(resolution));
+    }
+    if !(origin.x.is_finite() && origin.y.is_finite() && origin.z.is_finite()) {
+        return Err(GqbmeError::NonFiniteOrigin(origin));
     }
+    if dims.0 == 0 || dims.1 == 0 || dims.2 == 0 {
+        return Err(GqbmeError::EmptyGrid { dimensions: dims });
+    }
+    dims.0.checked_mul(dims.1).and_then(|n| n.checked_mul(dims.2)).ok_or(GqbmeError::GridTooLarge { dimensions: dims })
 }
 
 /// DNA Double Helix Generator (Unprecedented Geometry)
@@ -114,16 +235,21 @@ pub fn generate_dna_helix(num_base_pairs: usize, radius: f64, pitch: f64) -> Bio
         let angle = i as f64 * angle_step;
 
         // Strand 1 (Phosphate placeholder)
-        dna.add_atom("P".to_string(), radius * angle.cos(), radius * angle.sin(), z, 30.97);
+        dna.add_element(Element::P, radius * angle.cos(), radius * angle.sin(), z);
         // Strand 2 (Phosphate placeholder, shifted by PI)
-        dna.add_atom("P".to_string(), radius * (angle + std::f64::consts::PI).cos(), radius * (angle + std::f64::consts::PI).sin(), z, 30.97);
+        dna.add_element(Element::P, radius * (angle + std::f64::consts::PI).cos(), radius * (angle + std::f64::consts::PI).sin(), z);
     }
     dna
 }
 
 /// CONSTANTS
-const G_CONSTANT: f64 = 6.67430e-11; // m^3 kg^-1 s^-2
-const DALTON_TO_KG: f64 = 1.660539e-27;
+pub const G_CONSTANT: f64 = 6.67430e-11; // m^3 kg^-1 s^-2
+pub const DALTON_TO_KG: f64 = 1.660539e-27;
+pub const ANGSTROM_TO_M: f64 = 1e-10;
+pub const TORSION_COUPLING: f64 = 1.0e-30; // "Gravi-Genetic Coupling Constant" \alpha, degrees per J/kg
+pub const SPEED_OF_LIGHT: f64 = 299_792_458.0; // m/s
+pub const HBAR: f64 = 1.054571817e-34; // J s
+pub const ELECTRON_MASS_DA: f64 = 5.48579909e-4;
 
 /// Relativistic Perturbation Module
 pub struct RelativisticSolver;
@@ -131,35 +257,35 @@ pub struct RelativisticSolver;
 impl RelativisticSolver {
     /// Calculates the Gravitational Potential at a point r
     /// \Phi(r) = - \sum (G * m_i / |r - r_i|)
-    pub fn calculate_potential(position: &Vector3, grid: &MassDensityGrid) -> f64 {
+    pub fn calculate_potential<G: DensityGrid + ?Sized>(position: &Vector3, grid: &G) -> f64 {
+        let kernel = PairKernel::gravity();
         let mut potential = 0.0;
-        
-        for (idx, &mass) in grid.data.iter().enumerate() {
-            if mass <= 0.0 { continue; }
-            
-            // Convert index back to grid coordinates
-            let x = idx / (grid.dimensions.1 * grid.dimensions.2);
-            let y = (idx / grid.dimensions.2) % grid.dimensions.1;
-            let z = idx % grid.dimensions.2;
-            
-            let voxel_pos = Vector3::new(
-                grid.origin.x + x as f64 * grid.resolution,
-                grid.origin.y + y as f64 * grid.resolution,
-                grid.origin.z + z as f64 * grid.resolution,
-            );
-            
-            let r = position.dist(&voxel_pos) * 1e-10; // Convert Angstroms to Meters
+        grid.for_each_nonzero(&mut |idx, mass| {
+            if mass <= 0.0 {
+                return;
+            }
+            let r = position.dist(&grid.index_position(idx)) * ANGSTROM_TO_M; // Convert Angstroms to Meters
             if r > 0.0 {
-                potential -= G_CONSTANT * (mass * DALTON_TO_KG) / r;
+                potential += kernel.potential(mass, r);
             }
-        }
+        });
         potential
     }
 
+    /// Solves \nabla^2 \Phi = 4 \pi G \rho for the whole grid in O(N log N)
+    /// Unlike `calculate_potential`, each voxel's own mass is included via a uniform-cube self term.
+    pub fn calculate_potential_field<G: DensityGrid + ?Sized>(grid: &G, boundary: Boundary) -> PotentialGrid {
+        PoissonSolver::new(boundary).solve(grid)
+    }
+
     /// Calculates "Gravitational Torsion" effect on DNA twist
     /// \Omega = \Omega_0 + \alpha * \Phi
     pub fn compute_metric_torsion(base_twist: f64, potential: f64) -> f64 {
-        let alpha = 1.0e-30; // Hypothesized "Gravi-Genetic Coupling Constant"
+        Self::compute_metric_torsion_with(base_twist, potential, TORSION_COUPLING)
+    }
+
+    /// As `compute_metric_torsion` with an explicit coupling \alpha
+    pub fn compute_metric_torsion_with(base_twist: f64, potential: f64, alpha: f64) -> f64 {
         base_twist + alpha * potential
     }
 }
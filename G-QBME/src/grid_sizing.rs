@@ -0,0 +1,186 @@
+use crate::error::GqbmeError;
+use crate::fft::next_fast_len;
+use crate::gqbme_engine::{Biomolecule, MassDensityGrid, Vector3};
+
+/// Bytes stored per voxel of a `MassDensityGrid`
+const BYTES_PER_VOXEL: usize = std::mem::size_of::<f64>();
+/// Most lattice nodes along one axis; far past anything a grid could allocate
+const MAX_AXIS_NODES: f64 = (1u64 << 24) as f64;
+
+/// (origin, resolution, dimensions) of a fitted lattice, in Angstroms and voxels
+pub type Lattice = (Vector3, f64, (usize, usize, usize));
+
+/// How to fit a density grid around a molecule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridFit {
+    pub padding: f64,    // Angstroms of empty space added on every side
+    pub resolution: f64, // requested voxel edge in Angstroms
+    /// Upper bound on the density data in bytes; the resolution is coarsened to fit
+    pub max_bytes: Option<usize>,
+    /// Round each axis up to a product of 2, 3 and 5, the sizes `FftPlan` transforms directly;
+    /// usually only a few voxels more, where a power of two could nearly double an axis
+    pub fft_friendly: bool,
+}
+
+impl Default for GridFit {
+    fn default() -> Self {
+        Self {
+            padding: 5.0,
+            resolution: 1.0,
+            max_bytes: None,
+            fft_friendly: true,
+        }
+    }
+}
+
+impl GridFit {
+    pub fn new(padding: f64, resolution: f64) -> Self {
+        Self { padding, resolution, ..Self::default() }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Finite, positive resolution and finite, non-negative padding
+    pub fn validate(&self) -> Result<(), GqbmeError> {
+        if !(self.resolution.is_finite() && self.resolution > 0.0) {
+            return Err(GqbmeError::InvalidResolution(self.resolution));
+        }
+        if !(self.padding.is_finite() && self.padding >= 0.0) {
+            return Err(GqbmeError::InvalidPadding(self.padding));
+        }
+        Ok(())
+    }
+
+    /// Nodes per axis at `resolution`; `GridTooLarge` past `MAX_AXIS_NODES`
+    fn dimensions(&self, extent: Vector3, resolution: f64) -> Result<(usize, usize, usize), GqbmeError> {
+        // Counted in f64, since a tiny resolution or huge padding overflows usize arithmetic;
+        // +1 because the lattice has a node on both faces of the padded box
+        let nodes = |len: f64| ((len + 2.0 * self.padding) / resolution).ceil().max(0.0) + 1.0;
+        let (nx, ny, nz) = (nodes(extent.x), nodes(extent.y), nodes(extent.z));
+        if !(nx <= MAX_AXIS_NODES && ny <= MAX_AXIS_NODES && nz <= MAX_AXIS_NODES) {
+            return Err(GqbmeError::GridTooLarge { dimensions: (nx as usize, ny as usize, nz as usize) });
+        }
+        let axis = |n: f64| if self.fft_friendly { next_fast_len(n as usize) } else { n as usize };
+        Ok((axis(nx), axis(ny), axis(nz)))
+    }
+}
+
+/// Dense storage for `dims` fits in `max_bytes`; an overflowing byte count never does
+fn fits(dims: (usize, usize, usize), max_bytes: usize) -> bool {
+    dims.0
+        .checked_mul(dims.1)
+        .and_then(|n| n.checked_mul(dims.2))
+        .and_then(|n| n.checked_mul(BYTES_PER_VOXEL))
+        .is_some_and(|bytes| bytes <= max_bytes)
+}
+
+/// Axis-aligned bounding box of the atom positions as (min, max)
+/// An empty molecule gives a zero-size box at the origin.
+pub fn bounding_box(molecule: &Biomolecule) -> (Vector3, Vector3) {
+    let mut atoms = molecule.atoms.iter().map(|a| a.position);
+    let Some(first) = atoms.next() else {
+        return (Vector3::default(), Vector3::default());
+    };
+    atoms.fold((first, first), |(lo, hi), p| {
+        (
+            Vector3::new(lo.x.min(p.x), lo.y.min(p.y), lo.z.min(p.z)),
+            Vector3::new(hi.x.max(p.x), hi.y.max(p.y), hi.z.max(p.z)),
+        )
+    })
+}
+
+impl GridFit {
+    /// (origin, resolution, dimensions) of the lattice centred on the molecule's bounding box,
+    /// large enough for every atom plus `padding`. If `max_bytes` would be exceeded by dense
+    /// storage the voxels are enlarged until it fits; without it, an axis too long to count
+    /// is `GridTooLarge`.
+    pub fn lattice(&self, molecule: &Biomolecule) -> Result<Lattice, GqbmeError> {
+        self.validate()?;
+        let (lo, hi) = bounding_box(molecule);
+        let extent = hi - lo;
+        let center = (lo + hi) * 0.5;
+
+        let mut resolution = self.resolution;
+        let mut dims = self.dimensions(extent, resolution);
+        if let Some(max_bytes) = self.max_bytes {
+            while resolution.is_finite() && !dims.as_ref().is_ok_and(|&d| fits(d, max_bytes) || d == (1, 1, 1)) {
+                resolution *= 1.05;
+                dims = self.dimensions(extent, resolution);
+            }
+        }
+        let dims = dims?;
+
+        let half = |n: usize| (n - 1) as f64 * resolution / 2.0;
+        Ok((center - Vector3::new(half(dims.0), half(dims.1), half(dims.2)), resolution, dims))
+    }
+}
+
+impl MassDensityGrid {
+    /// Empty grid on `fit.lattice(molecule)`, or `GridTooLarge` when it cannot be allocated;
+    /// check `resolution` on the result, since `fit.max_bytes` may have coarsened it
+    pub fn fitted_to(molecule: &Biomolecule, fit: &GridFit) -> Result<Self, GqbmeError> {
+        let (origin, resolution, dims) = fit.lattice(molecule)?;
+        MassDensityGrid::try_new(origin, resolution, dims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::Element;
+
+    #[test]
+    fn fft_friendly_axes_round_to_nearby_5_smooth_sizes() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, 0.0, 0.0, 0.0);
+        molecule.add_element(Element::C, 55.0, 22.0, 6.0);
+        // 1 A voxels and 5 A padding need 66, 33 and 17 nodes
+        let (origin, resolution, dims) = GridFit::default().lattice(&molecule).expect("small grid");
+        assert_eq!(resolution, 1.0);
+        assert_eq!(dims, (72, 36, 18));
+        let exact = GridFit { fft_friendly: false, ..GridFit::default() }.lattice(&molecule).expect("small grid");
+        assert_eq!(exact.2, (66, 33, 17));
+        // The box stays centred on the molecule
+        let far = origin + Vector3::new((dims.0 - 1) as f64, (dims.1 - 1) as f64, (dims.2 - 1) as f64) * resolution;
+        assert!(((origin + far) * 0.5 - Vector3::new(27.5, 11.0, 3.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn max_bytes_coarsens_the_voxels() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, 0.0, 0.0, 0.0);
+        molecule.add_element(Element::C, 40.0, 40.0, 40.0);
+        let fit = GridFit::new(5.0, 0.5).with_max_bytes(1 << 20);
+        let (_, resolution, dims) = fit.lattice(&molecule).expect("small grid");
+        assert!(resolution > 0.5);
+        assert!(dims.0 * dims.1 * dims.2 * BYTES_PER_VOXEL <= 1 << 20, "{:?}", dims);
+    }
+
+    #[test]
+    fn axes_too_long_to_count_are_errors() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, 0.0, 0.0, 0.0);
+        for fit in [GridFit::new(5.0, 1e-300), GridFit::new(1e300, 1.0)] {
+            assert!(matches!(fit.lattice(&molecule), Err(GqbmeError::GridTooLarge { .. })), "{:?}", fit);
+            assert!(matches!(MassDensityGrid::fitted_to(&molecule, &fit), Err(GqbmeError::GridTooLarge { .. })));
+        }
+        assert!(matches!(GridFit::new(5.0, f64::NAN).lattice(&molecule), Err(GqbmeError::InvalidResolution(_))));
+        assert!(matches!(GridFit::new(-1.0, 1.0).lattice(&molecule), Err(GqbmeError::InvalidPadding(_))));
+    }
+
+    #[test]
+    fn max_bytes_coarsens_even_uncountable_lattices() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, 0.0, 0.0, 0.0);
+        molecule.add_element(Element::C, 10.0, 0.0, 0.0);
+        // The first product of these axes overflows usize; it must read as too big, not wrap
+        let fit = GridFit::new(1e6, 1e-3).with_max_bytes(1 << 20);
+        let (origin, resolution, dims) = fit.lattice(&molecule).expect("coarsened to fit");
+        assert!(fits(dims, 1 << 20), "{:?}", dims);
+        assert!(resolution.is_finite() && origin.x.is_finite());
+        assert!(!fits((usize::MAX, 2, 1), usize::MAX));
+    }
+}
@@ -0,0 +1,63 @@
+use crate::gqbme_engine::{Vector3, ANGSTROM_TO_M, DALTON_TO_KG, G_CONSTANT};
+
+/// Coulomb's constant k_e = 1 / (4 \pi \epsilon_0), N m^2 C^-2
+pub const COULOMB_CONSTANT: f64 = 8.987_551_792_3e9;
+pub const ELEMENTARY_CHARGE: f64 = 1.602_176_634e-19; // C
+
+/// Radial pair interaction shared by the gravity and electrostatics solvers
+/// \phi(r) = \pm coupling (s unit) e^{-r/\lambda} / r for a source of strength s: gravity is
+/// attractive with s in Daltons, Coulomb and Debye-Hueckel repel like charges with s in e.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairKernel {
+    pub coupling: f64,                 // G or k_e / \epsilon_r, SI
+    pub unit: f64,                     // kg per Dalton or C per e
+    pub attractive: bool,              // like sources attract, so \phi < 0
+    pub screening_length: Option<f64>, // Debye length \lambda in metres; None is unscreened
+}
+
+impl Default for PairKernel {
+    fn default() -> Self {
+        Self::gravity()
+    }
+}
+
+impl PairKernel {
+    /// Newtonian gravity: \Phi in J/kg from masses in Daltons
+    pub fn gravity() -> Self {
+        Self { coupling: G_CONSTANT, unit: DALTON_TO_KG, attractive: true, screening_length: None }
+    }
+
+    /// Coulomb potential in volts from charges in e, in a uniform dielectric
+    pub fn coulomb(relative_permittivity: f64) -> Self {
+        Self { coupling: COULOMB_CONSTANT / relative_permittivity, unit: ELEMENTARY_CHARGE, attractive: false, screening_length: None }
+    }
+
+    /// Screened Coulomb (Debye-Hueckel) potential with a Debye length in Angstroms
+    pub fn debye_huckel(relative_permittivity: f64, debye_length: f64) -> Self {
+        Self { screening_length: Some(debye_length * ANGSTROM_TO_M), ..Self::coulomb(relative_permittivity) }
+    }
+
+    /// Inverse screening length \kappa in 1/m, zero when unscreened
+    pub fn kappa(&self) -> f64 {
+        self.screening_length.map_or(0.0, |l| 1.0 / l)
+    }
+
+    /// \phi at `r` metres (r > 0) from a source of strength `source`
+    pub fn potential(&self, source: f64, r: f64) -> f64 {
+        let mut phi = self.coupling * (source * self.unit) / r;
+        if let Some(l) = self.screening_length {
+            phi *= (-r / l).exp();
+        }
+        if self.attractive { -phi } else { phi }
+    }
+
+    /// -\nabla\phi for a separation `r` = target - source in metres (|r| > 0)
+    pub fn field(&self, source: f64, r: Vector3) -> Vector3 {
+        let dist = r.norm();
+        let mut magnitude = self.coupling * source * self.unit / (dist * dist * dist);
+        if let Some(l) = self.screening_length {
+            magnitude *= (-dist / l).exp() * (1.0 + dist / l);
+        }
+        r * if self.attractive { -magnitude } else { magnitude }
+    }
+}
@@ -1 +1,31 @@
+pub mod analysis;
+pub mod batch;
+pub mod berry;
+pub mod bonds;
+pub mod density_grid;
+pub mod deposition;
+pub mod diagnostics;
+pub mod dna_builder;
+pub mod electrostatics;
+pub mod element;
+pub mod environment;
+pub mod error;
+pub mod fft;
+pub mod forces;
 pub mod gqbme_engine;
+pub mod grid_sizing;
+pub mod json;
+pub mod kernel;
+pub mod linalg;
+pub mod md;
+pub mod metric;
+pub mod mrc;
+pub mod octree;
+pub mod peptide_builder;
+pub mod poisson;
+pub mod scenario;
+pub mod schrodinger;
+pub mod sparse_grid;
+pub mod structure_io;
+pub mod tight_binding;
+pub mod torsion_feedback;
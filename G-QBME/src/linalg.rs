@@ -0,0 +1,318 @@
+/// Eigen-decomposition of a real symmetric matrix, eigenvalues ascending
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymmetricEigen {
+    pub values: Vec<f64>,
+    /// `vectors[k]` is the unit eigenvector for `values[k]`
+    pub vectors: Vec<Vec<f64>>,
+    pub sweeps: usize,
+}
+
+/// Cyclic Jacobi rotations until the off-diagonal norm is below 1e-15 of the
+/// Frobenius norm (or 100 sweeps); only the upper triangle's symmetry is assumed
+pub fn symmetric_eigen(matrix: &[Vec<f64>]) -> SymmetricEigen {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+    let frobenius: f64 = a.iter().flatten().map(|x| x * x).sum::<f64>().sqrt();
+
+    let mut sweeps = 0;
+    while sweeps < 100 {
+        let off: f64 = (0..n).flat_map(|p| ((p + 1)..n).map(move |q| (p, q))).map(|(p, q)| a[p][q] * a[p][q]).sum::<f64>().sqrt();
+        if off <= 1e-15 * frobenius || off == 0.0 {
+            break;
+        }
+        sweeps += 1;
+        for p in 0..n {
+            for q in (p + 1)..n {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                // Rotation angle that zeroes a[p][q] (Numerical Recipes 11.1)
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (upper, lower) = a.split_at_mut(q);
+                for (apk, aqk) in upper[p].iter_mut().zip(lower[0].iter_mut()) {
+                    let (x, y) = (*apk, *aqk);
+                    *apk = c * x - s * y;
+                    *aqk = s * x + c * y;
+                }
+                a[p][q] = 0.0;
+                a[q][p] = 0.0;
+                for row in v.iter_mut() {
+                    let (vkp, vkq) = (row[p], row[q]);
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| a[i][i].total_cmp(&a[j][j]));
+    SymmetricEigen {
+        values: order.iter().map(|&k| a[k][k]).collect(),
+        vectors: order.iter().map(|&k| v.iter().map(|row| row[k]).collect()).collect(),
+        sweeps,
+    }
+}
+
+/// Implicit QL with Wilkinson shifts on the tridiagonal matrix (`diagonal`, `off` with
+/// off[i] = T[i][i+1] and a trailing 0), leaving the eigenvalues in `diagonal` (unsorted).
+/// Each row of `z` is rotated as a row of the eigenvector matrix, so the identity yields every
+/// eigenvector as a column and a single unit row tracks just that component (Numerical Recipes 11.3).
+fn implicit_ql(diagonal: &mut [f64], off: &mut [f64], z: &mut [Vec<f64>]) {
+    let n = diagonal.len();
+    for l in 0..n {
+        for _ in 0..60 {
+            let mut m = l;
+            while m + 1 < n && off[m].abs() > f64::EPSILON * (diagonal[m].abs() + diagonal[m + 1].abs()) {
+                m += 1;
+            }
+            if m == l {
+                break;
+            }
+            let mut g = (diagonal[l + 1] - diagonal[l]) / (2.0 * off[l]);
+            let mut r = g.hypot(1.0);
+            g = diagonal[m] - diagonal[l] + off[l] / (g + r.copysign(g));
+            let (mut s, mut c, mut p) = (1.0, 1.0, 0.0);
+            let mut underflow = false;
+            for i in (l..m).rev() {
+                let f = s * off[i];
+                let b = c * off[i];
+                r = f.hypot(g);
+                off[i + 1] = r;
+                if r == 0.0 {
+                    diagonal[i + 1] -= p;
+                    off[m] = 0.0;
+                    underflow = true;
+                    break;
+                }
+                s = f / r;
+                c = g / r;
+                g = diagonal[i + 1] - p;
+                r = (diagonal[i] - g) * s + 2.0 * c * b;
+                p = s * r;
+                diagonal[i + 1] = g + p;
+                g = c * r - b;
+                for row in z.iter_mut() {
+                    let f = row[i + 1];
+                    row[i + 1] = s * row[i] + c * f;
+                    row[i] = c * row[i] - s * f;
+                }
+            }
+            if !underflow {
+                diagonal[l] -= p;
+                off[l] = g;
+                off[m] = 0.0;
+            }
+        }
+    }
+}
+
+/// Eigen-decomposition of the symmetric tridiagonal matrix with `diagonal` and
+/// `off_diagonal` (one shorter), eigenvalues ascending; `sweeps` is left at 0
+pub fn tridiagonal_eigen(diagonal: &[f64], off_diagonal: &[f64]) -> SymmetricEigen {
+    let n = diagonal.len();
+    let (mut d, mut e) = (diagonal.to_vec(), off_diagonal.to_vec());
+    e.resize(n, 0.0);
+    let mut z: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+    implicit_ql(&mut d, &mut e, &mut z);
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| d[i].total_cmp(&d[j]));
+    SymmetricEigen { values: order.iter().map(|&k| d[k]).collect(), vectors: order.iter().map(|&k| z.iter().map(|row| row[k]).collect()).collect(), sweeps: 0 }
+}
+
+/// Lowest eigenpairs of a symmetric operator from `lanczos_lowest`, eigenvalues ascending
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanczosEigen {
+    pub values: Vec<f64>,
+    /// `vectors[k]` is the unit Ritz vector for `values[k]`
+    pub vectors: Vec<Vec<f64>>,
+    /// Lanczos steps, i.e. the dimension of the final Krylov space
+    pub steps: usize,
+    /// Every returned pair met the tolerance (always true once the space spans all n dimensions)
+    pub converged: bool,
+}
+
+/// Lowest `count` eigenpairs of the n x n symmetric operator `apply` (writes A x into its
+/// second argument) by Lanczos with full reorthogonalization. The Krylov space grows until
+/// each wanted Ritz pair's residual is below `tolerance` times the largest Ritz value's
+/// magnitude, or until it is invariant. A single Krylov space holds one vector per
+/// eigenspace, so an exactly degenerate eigenvalue is returned once, and fewer than `count`
+/// pairs come back when the space closes early.
+pub fn lanczos_lowest(n: usize, count: usize, tolerance: f64, apply: impl Fn(&[f64], &mut [f64])) -> LanczosEigen {
+    lanczos(n, count, tolerance, false, apply)
+}
+
+/// `lanczos_lowest` for an n x n Hermitian operator, vectors stored as 2n reals with the real
+/// parts first and the imaginary parts after. Each new direction is orthogonalized against
+/// both v and iv of every basis vector, so the Krylov space is complex and T stays real: a
+/// level is found once, not once per copy as on the real doubled matrix [[A, -B], [B, A]].
+pub fn hermitian_lanczos_lowest(n: usize, count: usize, tolerance: f64, apply: impl Fn(&[f64], &mut [f64])) -> LanczosEigen {
+    lanczos(n, count, tolerance, true, apply)
+}
+
+fn lanczos(n: usize, count: usize, tolerance: f64, hermitian: bool, apply: impl Fn(&[f64], &mut [f64])) -> LanczosEigen {
+    let count = count.min(n);
+    let len = if hermitian { 2 * n } else { n };
+    if count == 0 {
+        return LanczosEigen { values: Vec::new(), vectors: Vec::new(), steps: 0, converged: true };
+    }
+
+    // Fixed pseudo-random start (xorshift), so runs are reproducible and no symmetry class is missed
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut q: Vec<f64> = (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        })
+        .collect();
+    let norm = q.iter().map(|x| x * x).sum::<f64>().sqrt();
+    q.iter_mut().for_each(|x| *x /= norm);
+
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+    let mut basis: Vec<Vec<f64>> = Vec::new();
+    let (mut alpha, mut beta): (Vec<f64>, Vec<f64>) = (Vec::new(), Vec::new());
+    let mut w = vec![0.0; len];
+    loop {
+        apply(&q, &mut w);
+        let a = dot(&q, &w);
+        basis.push(q);
+        alpha.push(a);
+        // Full reorthogonalization, twice, in place of the three-term recurrence
+        for _ in 0..2 {
+            for v in &basis {
+                let overlap = dot(v, &w);
+                w.iter_mut().zip(v).for_each(|(x, y)| *x -= overlap * y);
+                if hermitian {
+                    // Real overlap with iv = (-v_im, v_re)
+                    let (v_re, v_im) = v.split_at(n);
+                    let overlap: f64 = (0..n).map(|i| v_re[i] * w[n + i] - v_im[i] * w[i]).sum();
+                    for i in 0..n {
+                        w[i] += overlap * v_im[i];
+                        w[n + i] -= overlap * v_re[i];
+                    }
+                }
+            }
+        }
+        let b = dot(&w, &w).sqrt();
+
+        let m = basis.len();
+        let invariant = m == n || b <= 1e-14 * alpha.iter().map(|x| x.abs()).fold(b, f64::max);
+        if invariant || (m >= count && m.is_multiple_of(8)) {
+            // Residual of Ritz pair k is b |y_k[m-1]|, so only the last row of Y is needed here
+            let (mut values, mut off) = (alpha.clone(), beta.clone());
+            off.push(0.0);
+            let mut last = vec![(0..m).map(|i| if i + 1 == m { 1.0 } else { 0.0 }).collect::<Vec<f64>>()];
+            implicit_ql(&mut values, &mut off, &mut last);
+            let mut order: Vec<usize> = (0..m).collect();
+            order.sort_by(|&i, &j| values[i].total_cmp(&values[j]));
+            let scale = values.iter().map(|x| x.abs()).fold(f64::MIN_POSITIVE, f64::max);
+            let wanted = count.min(m);
+            let converged = order[..wanted].iter().all(|&k| b * last[0][k].abs() <= tolerance * scale);
+            if converged || invariant {
+                let ritz = tridiagonal_eigen(&alpha, &beta);
+                let vectors = (0..wanted)
+                    .map(|k| {
+                        let mut x = vec![0.0; len];
+                        for (coefficient, v) in ritz.vectors[k].iter().zip(&basis) {
+                            x.iter_mut().zip(v).for_each(|(xi, vi)| *xi += coefficient * vi);
+                        }
+                        x
+                    })
+                    .collect();
+                return LanczosEigen { values: ritz.values[..wanted].to_vec(), vectors, steps: m, converged: converged || m == n };
+            }
+        }
+        beta.push(b);
+        q = w.iter().map(|x| x / b).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Open chain with on-site `energy` and hopping -`t`, levels `energy - 2t cos(k \pi / (n + 1))`
+    fn chain(n: usize, energy: f64, t: f64) -> Vec<Vec<f64>> {
+        (0..n).map(|i| (0..n).map(|j| if i == j { energy } else if i.abs_diff(j) == 1 { -t } else { 0.0 }).collect()).collect()
+    }
+
+    fn chain_levels(n: usize, energy: f64, t: f64) -> Vec<f64> {
+        (1..=n).map(|k| energy - 2.0 * t * (k as f64 * PI / (n + 1) as f64).cos()).collect()
+    }
+
+    fn assert_orthonormal(vectors: &[Vec<f64>]) {
+        for (a, u) in vectors.iter().enumerate() {
+            for (b, v) in vectors.iter().enumerate() {
+                let dot: f64 = u.iter().zip(v).map(|(x, y)| x * y).sum();
+                let expected = if a == b { 1.0 } else { 0.0 };
+                assert!((dot - expected).abs() < 1e-10, "<v{}|v{}> = {}", a, b, dot);
+            }
+        }
+    }
+
+    #[test]
+    fn jacobi_reproduces_chain_levels() {
+        let eigen = symmetric_eigen(&chain(12, 8.0, 0.1));
+        for (got, expected) in eigen.values.iter().zip(chain_levels(12, 8.0, 0.1)) {
+            assert!((got - expected).abs() < 1e-12, "{} vs {}", got, expected);
+        }
+        assert_orthonormal(&eigen.vectors);
+    }
+
+    #[test]
+    fn tridiagonal_matches_jacobi() {
+        let diagonal = [1.0, -0.5, 2.0, 0.3, 0.7, -1.2];
+        let off = [0.4, 0.9, -0.2, 0.6, 0.1];
+        let mut dense = vec![vec![0.0; 6]; 6];
+        for i in 0..6 {
+            dense[i][i] = diagonal[i];
+            if i < 5 {
+                dense[i][i + 1] = off[i];
+                dense[i + 1][i] = off[i];
+            }
+        }
+        let jacobi = symmetric_eigen(&dense);
+        let ql = tridiagonal_eigen(&diagonal, &off);
+        for (a, b) in jacobi.values.iter().zip(&ql.values) {
+            assert!((a - b).abs() < 1e-12, "{} vs {}", a, b);
+        }
+        assert_orthonormal(&ql.vectors);
+    }
+
+    #[test]
+    fn lanczos_agrees_with_jacobi_on_lowest_levels() {
+        let n = 40;
+        // Uneven on-site energies so no level is degenerate
+        let mut matrix = chain(n, 8.0, 0.1);
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] += 0.05 * (0.7 * i as f64).sin();
+        }
+        let jacobi = symmetric_eigen(&matrix);
+        let lanczos = lanczos_lowest(n, 5, 1e-12, |x, y| {
+            for (i, row) in matrix.iter().enumerate() {
+                y[i] = row.iter().zip(x).map(|(a, b)| a * b).sum();
+            }
+        });
+        assert!(lanczos.converged);
+        assert_eq!(lanczos.values.len(), 5);
+        for k in 0..5 {
+            assert!((lanczos.values[k] - jacobi.values[k]).abs() < 1e-10, "level {}: {} vs {}", k, lanczos.values[k], jacobi.values[k]);
+            // Same state up to sign
+            let dot: f64 = lanczos.vectors[k].iter().zip(&jacobi.vectors[k]).map(|(a, b)| a * b).sum();
+            assert!((dot.abs() - 1.0).abs() < 1e-8, "level {}: |<lanczos|jacobi>| = {}", k, dot.abs());
+        }
+        assert_orthonormal(&lanczos.vectors);
+    }
+}
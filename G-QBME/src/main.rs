@@ -1,38 +1,1555 @@
-use g_qbme::gqbme_engine::{generate_dna_helix, MassDensityGrid, Vector3, RelativisticSolver};
+use g_qbme::analysis::ResidueDeviation;
+use g_qbme::berry::{BerryPhase, TwistPath};
+use g_qbme::batch::{default_threads, fields_from_sources, potentials_from_sources};
+use g_qbme::density_grid::DensityGrid;
+use g_qbme::deposition::{DepositionReport, DepositionScheme};
+use g_qbme::diagnostics::Diagnostics;
+use g_qbme::dna_builder::{DnaBuilder, HelixForm};
+use g_qbme::electrostatics::{charge_deposition_json, ChargeModel, EnergyComparison, Medium};
+use g_qbme::environment::{Environment, EnvironmentPreset};
+use g_qbme::error::GqbmeError;
+use g_qbme::gqbme_engine::{generate_dna_helix, Biomolecule, MassDensityGrid, RelativisticSolver, Vector3};
+use g_qbme::grid_sizing::{bounding_box, GridFit};
+use g_qbme::json::Json;
+use g_qbme::md::{MdOutput, MdParameters, MdSimulation, MAX_TIME_STEP};
+use g_qbme::octree::{MultipoleOrder, Octree, OctreeSolver};
+use g_qbme::peptide_builder::{PeptideBuilder, SecondaryStructure};
+use g_qbme::poisson::{Boundary, PoissonSolver};
+use g_qbme::scenario::{load_scenarios, write_potential_table, PotentialMethod, Scenario};
+use g_qbme::sparse_grid::SparseDensityGrid;
+use g_qbme::structure_io::{read_structure, write_structure, StructureFormat};
+use g_qbme::tight_binding::{EigenSolver, TightBinding, TightBindingParameters};
+use g_qbme::torsion_feedback::TorsionFeedback;
 use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+
+/// Why a command stopped: bad invocation (exit 2) or a failure while running (exit 1)
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Runtime(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => 2,
+            CliError::Runtime(_) => 1,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            CliError::Usage(_) => "usage",
+            CliError::Runtime(_) => "runtime",
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(msg) | CliError::Runtime(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl From<GqbmeError> for CliError {
+    fn from(err: GqbmeError) -> Self {
+        CliError::Runtime(err.to_string())
+    }
+}
+
+/// `{"command", "error", "kind"}` object printed for a failed command under --json
+fn error_json(command: &str, err: &CliError) -> Json {
+    Json::object().with("command", command).with("error", err.to_string()).with("kind", err.kind())
+}
+
+/// Prefixes a runtime error with the file it concerns
+fn runtime(path: &str, err: impl fmt::Display) -> CliError {
+    CliError::Runtime(format!("{}: {}", path, err))
+}
+
+/// A documented `--name [VALUE]` option; `value: None` marks a flag
+struct OptSpec {
+    name: &'static str,
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+const fn opt(name: &'static str, value: &'static str, help: &'static str) -> OptSpec {
+    OptSpec { name, value: Some(value), help }
+}
+
+const fn flag(name: &'static str, help: &'static str) -> OptSpec {
+    OptSpec { name, value: None, help }
+}
+
+const COMMON_OPTIONS: &[OptSpec] = &[
+    flag("--json", "print the result as a single JSON object"),
+    flag("--help", "show this help"),
+];
+
+const STRUCTURE_OPTIONS: &[OptSpec] = &[
+    opt("--input", "PATH", "read a PDB, mmCIF or XYZ file"),
+    opt("--sequence", "ACGT", "build A/B/Z-DNA from a sequence"),
+    opt("--form", "A|B|Z", "helix form for --sequence (default B)"),
+    opt("--peptide", "ACDE", "build a peptide from one-letter codes"),
+    opt("--ss", "FORM", "helix|strand|ppii|extended for --peptide (default helix)"),
+    opt("--num-bp", "N", "coarse phosphate helix of N base pairs (the default source, N = 10)"),
+    opt("--radius", "A", "radius of the --num-bp helix (default 10)"),
+    opt("--pitch", "A", "pitch of the --num-bp helix (default 34)"),
+];
+
+const GRID_OPTIONS: &[OptSpec] = &[
+    opt("--resolution", "A", "voxel edge (default 1.0)"),
+    opt("--padding", "A", "margin around the molecule (default 5.0)"),
+    opt("--max-mem-mb", "N", "coarsen the grid until it fits in N MiB"),
+    opt("--scheme", "KERNEL", "ngp|cic|tsc|gaussian mass assignment (default ngp)"),
+    flag("--strict", "fail if any mass or charge falls outside the grid"),
+    flag("--sparse", "store only occupied 8^3 voxel blocks (fine grids around long molecules)"),
+];
+
+const ELECTROSTATICS_OPTIONS: &[OptSpec] = &[
+    opt("--charges", "MODEL", "none|phosphate|standard partial charges for electrostatics (default none)"),
+    opt("--dielectric", "EPS", "relative permittivity with --charges (default 78.5, water)"),
+    opt("--ionic-strength", "M", "1:1 salt in mol/L for Debye-Hueckel screening, 0 for plain Coulomb (default 0.15)"),
+];
+
+const ENVIRONMENT_OPTION: OptSpec = opt("--environment", "PRESET", "vacuum|earth|leo|white-dwarf|neutron-star background gravity (default vacuum)");
+
+/// Parsed options of one command, checked against its specs
+struct Options {
+    values: Vec<(&'static str, Option<String>)>,
+}
+
+impl Options {
+    /// Accepts `--name value`, `--name=value` and bare flags; anything not in `specs` is rejected
+    fn parse(args: &[String], specs: &[&[OptSpec]]) -> Result<Self, CliError> {
+        let mut values: Vec<(&'static str, Option<String>)> = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if arg.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let spec = specs
+                .iter()
+                .flat_map(|group| group.iter())
+                .find(|s| s.name == name)
+                .ok_or_else(|| CliError::Usage(if name.starts_with("--") { format!("unknown option '{}'", name) } else { format!("unexpected argument '{}'", name) }))?;
+            if values.iter().any(|(n, _)| *n == spec.name) {
+                return Err(CliError::Usage(format!("{} given more than once", spec.name)));
+            }
+            let value = match (spec.value, inline) {
+                (None, None) => None,
+                (None, Some(_)) => return Err(CliError::Usage(format!("{} does not take a value", spec.name))),
+                (Some(_), Some(v)) => Some(v),
+                (Some(placeholder), None) => match iter.next() {
+                    Some(v) if !v.starts_with("--") => Some(v.clone()),
+                    _ => return Err(CliError::Usage(format!("{} requires a value <{}>", spec.name, placeholder))),
+                },
+            };
+            values.push((spec.name, value));
+        }
+        Ok(Self { values })
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|(n, _)| *n == name).and_then(|(_, v)| v.as_deref())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.values.iter().any(|(n, _)| *n == name)
+    }
+
+    fn require(&self, name: &str) -> Result<&str, CliError> {
+        self.value(name).ok_or_else(|| CliError::Usage(format!("missing required option {}", name)))
+    }
+
+    fn parse_value<T: FromStr>(&self, name: &str) -> Result<Option<T>, CliError>
+    where
+        T::Err: fmt::Display,
+    {
+        self.value(name)
+            .map(|v| v.parse::<T>().map_err(|e| CliError::Usage(format!("invalid {} '{}': {}", name, v, e))))
+            .transpose()
+    }
+
+    /// A finite number strictly above zero
+    fn positive(&self, name: &str) -> Result<Option<f64>, CliError> {
+        match self.parse_value::<f64>(name)? {
+            Some(v) if !(v.is_finite() && v > 0.0) => Err(CliError::Usage(format!("{} must be positive, got {}", name, v))),
+            other => Ok(other),
+        }
+    }
+
+    fn finite(&self, name: &str) -> Result<Option<f64>, CliError> {
+        match self.parse_value::<f64>(name)? {
+            Some(v) if !v.is_finite() => Err(CliError::Usage(format!("{} must be finite, got {}", name, v))),
+            other => Ok(other),
+        }
+    }
+
+    fn non_negative(&self, name: &str) -> Result<Option<f64>, CliError> {
+        match self.parse_value::<f64>(name)? {
+            Some(v) if !(v.is_finite() && v >= 0.0) => Err(CliError::Usage(format!("{} must be non-negative, got {}", name, v))),
+            other => Ok(other),
+        }
+    }
+
+    fn count(&self, name: &str) -> Result<Option<usize>, CliError> {
+        match self.parse_value::<usize>(name)? {
+            Some(0) => Err(CliError::Usage(format!("{} must be at least 1", name))),
+            other => Ok(other),
+        }
+    }
+}
+
+/// What a command produced, as text for people and JSON for scripts
+struct Report {
+    text: Vec<String>,
+    json: Json,
+}
+
+struct Command {
+    name: &'static str,
+    summary: &'static str,
+    options: &'static [&'static [OptSpec]],
+    run: fn(&Options) -> Result<Report, CliError>,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "generate",
+        summary: "build a structure and report its size, mass and centre of mass",
+        options: &[STRUCTURE_OPTIONS, &[opt("--out", "PATH", "also write the structure"), opt("--format", "pdb|xyz", "output format (default from the --out extension)")], COMMON_OPTIONS],
+        run: cmd_generate,
+    },
+    Command {
+        name: "map",
+        summary: "deposit a structure's mass onto a fitted density grid",
+        options: &[STRUCTURE_OPTIONS, GRID_OPTIONS, &[opt("--out", "MAP", "write the density as an MRC/CCP4 map")], COMMON_OPTIONS],
+        run: cmd_map,
+    },
+    Command {
+        name: "potential",
+        summary: "gravitational potential and field at a point, the atoms or a grid",
+        options: &[
+            STRUCTURE_OPTIONS,
+            GRID_OPTIONS,
+            ELECTROSTATICS_OPTIONS,
+            &[
+                opt("--at", "WHERE", "com, X,Y,Z (Angstroms), atoms, grid voxel centres or file (default com)"),
+                opt("--targets", "PATH", "points for --at file, one 'x y z' per line"),
+                opt("--method", "direct|octree|grid", "exact atom sum, Barnes-Hut tree or voxel sum (default direct)"),
+                opt("--theta", "T", "octree opening angle (default 0.5)"),
+                opt("--threads", "N", "worker threads for target sets (default: all cores)"),
+                opt("--out", "TSV", "write every target's potential and field"),
+                ENVIRONMENT_OPTION,
+            ],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_potential,
+    },
+    Command {
+        name: "solve",
+        summary: "FFT Poisson solve for the potential field on the fitted grid",
+        options: &[
+            STRUCTURE_OPTIONS,
+            GRID_OPTIONS,
+            ELECTROSTATICS_OPTIONS,
+            &[opt("--boundary", "isolated|periodic", "boundary condition (default isolated)"), opt("--out", "MAP", "write the potential as an MRC/CCP4 map")],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_solve,
+    },
+    Command {
+        name: "quantum",
+        summary: "low-lying levels of a base-stacking tight-binding Hamiltonian and their shifts under m*Phi",
+        options: &[
+            STRUCTURE_OPTIONS,
+            GRID_OPTIONS,
+            &[
+                opt("--states", "N", "number of lowest levels (default 4)"),
+                opt("--solver", "auto|dense|lanczos", "Jacobi on the full matrix or Lanczos (default auto: dense up to 128 sites)"),
+                opt("--method", "direct|octree|grid", "how the potential is sampled at each site (default direct)"),
+                opt("--theta", "T", "octree opening angle (default 0.5)"),
+                opt("--threads", "N", "worker threads for the site potentials (default: all cores)"),
+                ENVIRONMENT_OPTION,
+            ],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_quantum,
+    },
+    Command {
+        name: "berry",
+        summary: "discrete Berry phase of a tight-binding level along the twist path, with and without m*Phi",
+        options: &[
+            STRUCTURE_OPTIONS,
+            GRID_OPTIONS,
+            &[
+                opt("--state", "K", "level to follow, 0 = lowest (default 0)"),
+                opt("--path", "closed|open", "twist phase over a full 2pi loop or from 0 to --end (default closed)"),
+                opt("--samples", "N", "points along the path (default one per site, at least 2)"),
+                opt("--end", "DEG", "final twist phase of --path open (default 180)"),
+                opt("--solver", "auto|dense|lanczos", "Jacobi on the full matrix or Lanczos (default auto: dense up to 128 sites)"),
+                opt("--method", "direct|octree|grid", "how the potential is sampled at each site (default direct)"),
+                opt("--theta", "T", "octree opening angle (default 0.5)"),
+                opt("--threads", "N", "worker threads for the path samples (default: all cores)"),
+                ENVIRONMENT_OPTION,
+            ],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_berry,
+    },
+    Command {
+        name: "torsion",
+        summary: "relax a DNA helix's twist self-consistently against its own potential",
+        options: &[
+            &[
+                opt("--sequence", "ACGT", "DNA sequence (required)"),
+                opt("--form", "A|B|Z", "helix form (default B)"),
+                opt("--coupling", "ALPHA", "degrees of twist per J/kg"),
+                opt("--max-iter", "N", "iteration limit (default 50)"),
+                ENVIRONMENT_OPTION,
+                opt("--out", "PDB", "write the relaxed structure"),
+            ],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_torsion,
+    },
+    Command {
+        name: "export",
+        summary: "write a structure as PDB or XYZ",
+        options: &[STRUCTURE_OPTIONS, &[opt("--out", "PATH", "output file (required)"), opt("--format", "pdb|xyz", "output format (default from the --out extension)")], COMMON_OPTIONS],
+        run: cmd_export,
+    },
+    Command {
+        name: "compare",
+        summary: "superpose a structure onto a reference and report RMSD and per-residue deviations",
+        options: &[
+            STRUCTURE_OPTIONS,
+            &[
+                opt("--reference", "PATH", "reference structure with the same atoms in the same order (required)"),
+                opt("--profile", "TSV", "write per-residue deviations"),
+                opt("--out", "PATH", "write the superposed structure"),
+                opt("--format", "pdb|xyz", "output format (default from the --out extension)"),
+            ],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_compare,
+    },
+    Command {
+        name: "run",
+        summary: "run the scenarios in JSON files and write a results manifest",
+        options: &[
+            &[
+                opt("--scenario", "PATH[,PATH]", "scenario file(s) (required)"),
+                opt("--manifest", "PATH", "results manifest (default <first file>.manifest.json beside it)"),
+                opt("--threads", "N", "worker threads per scenario (default: all cores)"),
+                flag("--dry-run", "expand and validate the scenarios without running them"),
+            ],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_run,
+    },
+    Command {
+        name: "md",
+        summary: "Langevin molecular dynamics with optional scaled gravity",
+        options: &[
+            STRUCTURE_OPTIONS,
+            &[
+                opt("--steps", "N", "number of steps (default 1000)"),
+                opt("--dt", "PS", "time step (default 0.001, at most 0.003)"),
+                opt("--temperature", "K", "thermostat temperature (default 300)"),
+                opt("--friction", "1/PS", "Langevin collision rate; 0 gives NVE (default 1)"),
+                opt("--gravity-scale", "S", "include pairwise gravity multiplied by S"),
+                opt("--seed", "N", "random seed"),
+                opt("--traj", "XYZ", "write frames every --every steps"),
+                opt("--energy", "TSV", "write energies every --every steps"),
+                opt("--every", "N", "output interval (default 100)"),
+                opt("--checkpoint", "PATH", "checkpoint file, also written after the last step"),
+                opt("--checkpoint-every", "N", "checkpoint interval (default 10 x --every)"),
+                opt("--restart", "PATH", "continue from a checkpoint of the same structure"),
+            ],
+            COMMON_OPTIONS,
+        ],
+        run: cmd_md,
+    },
+];
+
+fn print_usage() {
+    println!("Usage: gqbme <command> [options]");
+    println!();
+    println!("Commands:");
+    for command in COMMANDS {
+        println!("  {:<10} {}", command.name, command.summary);
+    }
+    println!();
+    println!("Run 'gqbme help <command>' or 'gqbme <command> --help' for its options.");
+    println!("Exit codes: 0 success, 1 runtime failure, 2 invalid usage.");
+}
+
+fn print_command_help(command: &Command) {
+    println!("Usage: gqbme {} [options]", command.name);
+    println!("{}", command.summary);
+    println!();
+    for spec in command.options.iter().flat_map(|group| group.iter()) {
+        let head = match spec.value {
+            Some(v) => format!("{} <{}>", spec.name, v),
+            None => spec.name.to_string(),
+        };
+        println!("  {:<32} {}", head, spec.help);
+    }
+}
+
+/// Structure selected by --input, --sequence, --peptide or --num-bp; at most one may be given
+fn load_molecule(opts: &Options) -> Result<Biomolecule, CliError> {
+    let sources: Vec<&str> = ["--input", "--sequence", "--peptide", "--num-bp"].into_iter().filter(|s| opts.flag(s)).collect();
+    if sources.len() > 1 {
+        return Err(CliError::Usage(format!("choose one structure source, got {}", sources.join(" and "))));
+    }
+    let misplaced = |option: &str, source: &str| -> Result<(), CliError> {
+        if opts.flag(option) && !opts.flag(source) {
+            return Err(CliError::Usage(format!("{} only applies with {}", option, source)));
+        }
+        Ok(())
+    };
+    misplaced("--form", "--sequence")?;
+    misplaced("--ss", "--peptide")?;
+
+    if let Some(path) = opts.value("--input") {
+        let molecule = read_structure(path).map_err(|e| runtime(path, e))?;
+        molecule.validate().map_err(|e| runtime(path, e))?;
+        return Ok(molecule);
+    }
+    if let Some(sequence) = opts.value("--sequence") {
+        return Ok(dna_builder(opts, sequence)?.build());
+    }
+    if let Some(sequence) = opts.value("--peptide") {
+        let ss: SecondaryStructure = opts.parse_value("--ss")?.unwrap_or(SecondaryStructure::AlphaHelix);
+        let builder = PeptideBuilder::new(sequence).map_err(|e| CliError::Usage(e.to_string()))?;
+        if builder.sequence.is_empty() {
+            return Err(CliError::Usage("--peptide has no residues".to_string()));
+        }
+        return Ok(builder.with_secondary_structure(ss).build());
+    }
+    let num_bp = opts.count("--num-bp")?.unwrap_or(10);
+    let radius = opts.positive("--radius")?.unwrap_or(10.0);
+    let pitch = opts.positive("--pitch")?.unwrap_or(34.0);
+    Ok(generate_dna_helix(num_bp, radius, pitch))
+}
+
+/// DNA builder for `sequence` in the --form helix; an empty sequence is a usage error
+fn dna_builder(opts: &Options, sequence: &str) -> Result<DnaBuilder, CliError> {
+    let form: HelixForm = opts.parse_value("--form")?.unwrap_or(HelixForm::B);
+    let builder = DnaBuilder::new(sequence, form).map_err(|e| CliError::Usage(e.to_string()))?;
+    if builder.sequence.is_empty() {
+        return Err(CliError::Usage("--sequence has no bases".to_string()));
+    }
+    Ok(builder)
+}
+
+/// --environment preset centred on `center`
+fn environment(opts: &Options, center: Vector3) -> Result<(EnvironmentPreset, Environment), CliError> {
+    let preset: EnvironmentPreset = opts.parse_value("--environment")?.unwrap_or_default();
+    Ok((preset, preset.environment().with_reference(center)))
+}
+
+/// Grid sized around `molecule` from --resolution, --padding and --max-mem-mb,
+/// dense unless --sparse is given
+fn fitted_grid(opts: &Options, molecule: &Biomolecule) -> Result<Box<dyn DensityGrid + Send + Sync>, CliError> {
+    let mut fit = GridFit::default();
+    if let Some(resolution) = opts.positive("--resolution")? {
+        fit.resolution = resolution;
+    }
+    if let Some(padding) = opts.non_negative("--padding")? {
+        fit.padding = padding;
+    }
+    if let Some(mb) = opts.count("--max-mem-mb")? {
+        if opts.flag("--sparse") {
+            return Err(CliError::Usage("--max-mem-mb sizes dense storage and cannot be combined with --sparse".to_string()));
+        }
+        let bytes = mb.checked_mul(1 << 20).ok_or_else(|| CliError::Usage(format!("--max-mem-mb {} is too large", mb)))?;
+        fit = fit.with_max_bytes(bytes);
+    }
+    if opts.flag("--sparse") {
+        return Ok(Box::new(SparseDensityGrid::try_fitted_to(molecule, &fit)?));
+    }
+    Ok(Box::new(MassDensityGrid::try_fitted_to(molecule, &fit)?))
+}
+
+/// Fits a grid and deposits `molecule` with --scheme; --strict turns lost mass into an error
+fn mapped_grid(opts: &Options, molecule: &Biomolecule) -> Result<(Box<dyn DensityGrid + Send + Sync>, Diagnostics), CliError> {
+    let scheme: DepositionScheme = opts.parse_value("--scheme")?.unwrap_or_default();
+    let mut grid = fitted_grid(opts, molecule)?;
+    let diagnostics = if opts.flag("--strict") {
+        grid.try_map_biomolecule_with(molecule, scheme)?
+    } else {
+        grid.map_biomolecule_diagnosed(molecule, scheme)?
+    };
+    Ok((grid, diagnostics))
+}
+
+/// A grid fitted like `mapped_grid`'s holding `molecule`'s charges; --strict turns lost charge into an error
+fn charge_grid(opts: &Options, molecule: &Biomolecule) -> Result<(Box<dyn DensityGrid + Send + Sync>, DepositionReport), CliError> {
+    let scheme: DepositionScheme = opts.parse_value("--scheme")?.unwrap_or_default();
+    let mut grid = fitted_grid(opts, molecule)?;
+    let deposition = if opts.flag("--strict") { grid.try_map_charges_with(molecule, scheme)? } else { grid.map_charges_with(molecule, scheme) };
+    Ok((grid, deposition))
+}
+
+/// Assigns --charges to `molecule` and reads --dielectric and --ionic-strength;
+/// `None` when no charge model was asked for
+fn electrostatics(opts: &Options, molecule: &mut Biomolecule) -> Result<Option<(ChargeModel, Medium)>, CliError> {
+    let model: ChargeModel = opts.parse_value("--charges")?.unwrap_or(ChargeModel::None);
+    if model == ChargeModel::None {
+        if let Some(name) = ["--dielectric", "--ionic-strength"].into_iter().find(|name| opts.flag(name)) {
+            return Err(CliError::Usage(format!("{} only applies with --charges phosphate|standard", name)));
+        }
+        return Ok(None);
+    }
+    let mut medium = Medium::default();
+    if let Some(dielectric) = opts.positive("--dielectric")? {
+        medium.relative_permittivity = dielectric;
+    }
+    if let Some(ionic_strength) = opts.non_negative("--ionic-strength")? {
+        medium.ionic_strength = ionic_strength;
+    }
+    molecule.assign_charges(model);
+    Ok(Some((model, medium)))
+}
+
+/// Text lines for the charges, the medium and the gravity-to-electrostatic energy ratio
+fn electrostatics_text(model: ChargeModel, molecule: &Biomolecule, medium: &Medium, energy: &EnergyComparison) -> Vec<String> {
+    let screening = match medium.debye_length() {
+        Some(lambda) => format!("Debye length {:.3} A", lambda),
+        None => "unscreened".to_string(),
+    };
+    vec![
+        format!(
+            "Charges ({}): {} atoms, net {:.3} e; eps_r {}, {} M salt ({})",
+            model.name(),
+            molecule.atoms.iter().filter(|a| a.charge != 0.0).count(),
+            molecule.net_charge(),
+            medium.relative_permittivity,
+            medium.ionic_strength,
+            screening
+        ),
+        format!("Self energy: {}", energy),
+    ]
+}
+
+/// JSON shared by the commands that report electrostatics
+fn electrostatics_summary(model: ChargeModel, molecule: &Biomolecule, medium: &Medium, energy: &EnergyComparison) -> Json {
+    Json::object()
+        .with("charges", model.name())
+        .with("charged_atoms", molecule.atoms.iter().filter(|a| a.charge != 0.0).count())
+        .with("net_charge_e", molecule.net_charge())
+        .with("medium", medium)
+        .with("energy", energy)
+}
+
+/// Text lines for a deposition: a summary, plus a warning when mass was lost
+fn diagnostics_text(diagnostics: &Diagnostics) -> Vec<String> {
+    let mut text = vec![format!(
+        "Deposited {:.2} of {:.2} Da; density {:.4e} to {:.4e} Da/A^3 over {} voxels",
+        diagnostics.deposited_mass, diagnostics.input_mass, diagnostics.min_density, diagnostics.max_density, diagnostics.occupied_voxels
+    )];
+    if !diagnostics.is_lossless() {
+        text.push(format!(
+            "Warning: {:.2} Da ({:.3}%) fell outside the grid ({} atoms dropped, {} clipped); rerun with more --padding",
+            diagnostics.lost_mass,
+            100.0 * diagnostics.lost_fraction(),
+            diagnostics.dropped_atoms,
+            diagnostics.clipped_atoms
+        ));
+    }
+    text
+}
+
+/// Warning for charge that missed the charge grid, if any did
+fn charge_deposition_text(deposition: &DepositionReport) -> Option<String> {
+    (!deposition.is_lossless()).then(|| {
+        format!(
+            "Warning: {:.4} e of net charge fell outside the charge grid ({} charged atoms dropped, {} clipped); rerun with more --padding",
+            deposition.lost_mass, deposition.atoms_outside, deposition.atoms_clipped
+        )
+    })
+}
+
+/// "(x, y, z)" in Angstroms for text output
+fn point(v: Vector3) -> String {
+    format!("({:.3}, {:.3}, {:.3})", v.x, v.y, v.z)
+}
+
+fn molecule_summary(molecule: &Biomolecule) -> Json {
+    let (min, max) = bounding_box(molecule);
+    Json::object()
+        .with("atoms", molecule.atoms.len())
+        .with("mass_da", molecule.atoms.iter().map(|a| a.mass).sum::<f64>())
+        .with("center_of_mass", molecule.center_of_mass())
+        .with("bounding_box", Json::object().with("min", min).with("max", max))
+        .with("radius_of_gyration", molecule.radius_of_gyration())
+        .with("principal_moments", molecule.principal_axes().moments.to_vec())
+}
+
+fn grid_summary(origin: Vector3, resolution: f64, dimensions: (usize, usize, usize)) -> Json {
+    Json::object()
+        .with("origin", origin)
+        .with("resolution", resolution)
+        .with("dimensions", vec![dimensions.0, dimensions.1, dimensions.2])
+}
+
+/// --format if given, otherwise the extension of `out`, otherwise PDB
+fn output_format(opts: &Options, out: &str) -> Result<StructureFormat, CliError> {
+    if let Some(format) = opts.parse_value("--format")? {
+        return Ok(format);
+    }
+    let extension = std::path::Path::new(out).extension().and_then(|e| e.to_str()).unwrap_or("pdb");
+    Ok(extension.parse().unwrap_or(StructureFormat::Pdb))
+}
+
+fn write_output(molecule: &Biomolecule, opts: &Options, out: &str) -> Result<(), CliError> {
+    let format = output_format(opts, out)?;
+    write_structure(molecule, format, out).map_err(|e| runtime(out, e))
+}
+
+fn cmd_generate(opts: &Options) -> Result<Report, CliError> {
+    if opts.flag("--format") && !opts.flag("--out") {
+        return Err(CliError::Usage("--format only applies with --out".to_string()));
+    }
+    let molecule = load_molecule(opts)?;
+    let summary = molecule_summary(&molecule);
+    let mass: f64 = molecule.atoms.iter().map(|a| a.mass).sum();
+    let (min, max) = bounding_box(&molecule);
+    let mut text = vec![
+        format!("Generated {} atoms ({:.2} Da)", molecule.atoms.len(), mass),
+        format!("Center of mass: {} A", point(molecule.center_of_mass())),
+        format!("Bounding box: {} to {} A", point(min), point(max)),
+        format!("Radius of gyration: {:.4} A", molecule.radius_of_gyration()),
+    ];
+    let mut json = summary;
+    if let Some(out) = opts.value("--out") {
+        write_output(&molecule, opts, out)?;
+        text.push(format!("Wrote {}", out));
+        json = json.with("out", out);
+    }
+    Ok(Report { text, json })
+}
+
+fn cmd_map(opts: &Options) -> Result<Report, CliError> {
+    let molecule = load_molecule(opts)?;
+    let (grid, diagnostics) = mapped_grid(opts, &molecule)?;
+
+    let storage = if opts.flag("--sparse") { "sparse" } else { "dense" };
+    let mut text = vec![
+        format!("Mapped {} atoms onto {:?} voxels at {:.3} A", molecule.atoms.len(), grid.dimensions(), grid.resolution()),
+        format!("Storage: {:.2} MiB ({})", grid.storage_bytes() as f64 / (1024.0 * 1024.0), storage),
+    ];
+    text.extend(diagnostics_text(&diagnostics));
+    let mut json = Json::object()
+        .with("molecule", molecule_summary(&molecule))
+        .with("grid", grid_summary(grid.origin(), grid.resolution(), grid.dimensions()).with("storage", storage).with("storage_bytes", grid.storage_bytes()))
+        .with("diagnostics", &diagnostics);
+    if let Some(out) = opts.value("--out") {
+        grid.export_mrc(std::path::Path::new(out)).map_err(|e| runtime(out, e))?;
+        text.push(format!("Wrote density map to {}", out));
+        json = json.with("out", out);
+    }
+    Ok(Report { text, json })
+}
+
+/// Parses "x,y,z" in Angstroms
+fn parse_point(text: &str) -> Result<Vector3, CliError> {
+    parse_xyz(text).ok_or_else(|| CliError::Usage(format!("invalid --at '{}' (expected com, atoms, grid, file or x,y,z)", text)))
+}
+
+/// Three numbers separated by commas and/or whitespace
+fn parse_xyz(text: &str) -> Option<Vector3> {
+    let parts: Vec<f64> = text.split(|c: char| c == ',' || c.is_whitespace()).filter(|p| !p.is_empty()).map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    match parts.as_slice() {
+        [x, y, z] => Some(Vector3::new(*x, *y, *z)),
+        _ => None,
+    }
+}
+
+/// Target points, one "x y z" or "x,y,z" per line; blank lines and '#' comments are skipped
+fn read_targets(path: &str) -> Result<Vec<Vector3>, CliError> {
+    let text = std::fs::read_to_string(path).map_err(|e| runtime(path, e))?;
+    let mut targets = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let point = parse_xyz(line).ok_or_else(|| runtime(path, format!("line {}: expected three coordinates, got '{}'", n + 1, line)))?;
+        targets.push(point);
+    }
+    if targets.is_empty() {
+        return Err(runtime(path, "no target points"));
+    }
+    Ok(targets)
+}
+
+/// \Phi and g at each target, plus the deposition diagnostics when a grid was mapped
+type SelfGravity = (Vec<f64>, Vec<Vector3>, Option<Diagnostics>);
+
+/// The molecule's own \Phi and g at `targets` by `method`
+fn self_gravity(opts: &Options, molecule: &Biomolecule, method: PotentialMethod, targets: &[Vector3], threads: usize) -> Result<SelfGravity, CliError> {
+    Ok(match method {
+        PotentialMethod::Direct => {
+            let solver = OctreeSolver::new(Octree::from_biomolecule(molecule), 0.0, MultipoleOrder::default());
+            let (potentials, fields) = solver.direct_with_threads(targets, threads).into_iter().unzip();
+            (potentials, fields, None)
+        }
+        PotentialMethod::Octree => {
+            let theta = opts.non_negative("--theta")?.unwrap_or(0.5);
+            let solver = OctreeSolver::new(Octree::from_biomolecule(molecule), theta, MultipoleOrder::default());
+            (solver.potentials_with_threads(targets, threads), solver.fields_with_threads(targets, threads), None)
+        }
+        PotentialMethod::Grid => {
+            let (grid, diagnostics) = mapped_grid(opts, molecule)?;
+            (
+                RelativisticSolver::calculate_potentials_with_threads(targets, &*grid, threads),
+                RelativisticSolver::calculate_fields_with_threads(targets, &*grid, threads),
+                Some(diagnostics),
+            )
+        }
+    })
+}
+
+fn cmd_potential(opts: &Options) -> Result<Report, CliError> {
+    let method: PotentialMethod = opts.parse_value("--method")?.unwrap_or(PotentialMethod::Direct);
+    let at = opts.value("--at").unwrap_or("com");
+    let sizes_grid = method == PotentialMethod::Grid || at == "grid";
+    for spec in GRID_OPTIONS {
+        let deposits = matches!(spec.name, "--scheme" | "--strict");
+        if opts.flag(spec.name) && !(if deposits { method == PotentialMethod::Grid } else { sizes_grid }) {
+            return Err(CliError::Usage(format!("{} only applies with --method grid{}", spec.name, if deposits { "" } else { " or --at grid" })));
+        }
+    }
+    if method != PotentialMethod::Octree && opts.flag("--theta") {
+        return Err(CliError::Usage("--theta only applies with --method octree".to_string()));
+    }
+    if (at == "file") != opts.flag("--targets") {
+        return Err(CliError::Usage("--at file and --targets <PATH> go together".to_string()));
+    }
+    let threads = opts.count("--threads")?.unwrap_or_else(default_threads);
+    let mut molecule = load_molecule(opts)?;
+    let charges = electrostatics(opts, &mut molecule)?;
+
+    let mut grid_info = None;
+    let targets = match at {
+        "com" => vec![molecule.center_of_mass()],
+        "atoms" => molecule.atoms.iter().map(|a| a.position).collect(),
+        "grid" => {
+            let grid = fitted_grid(opts, &molecule)?;
+            grid_info = Some(grid_summary(grid.origin(), grid.resolution(), grid.dimensions()));
+            grid.voxel_centres()
+        }
+        "file" => read_targets(opts.require("--targets")?)?,
+        point => vec![parse_point(point)?],
+    };
+
+    let (potentials, fields, diagnostics) = self_gravity(opts, &molecule, method, &targets, threads)?;
+
+    // Totals keep the environment; the self-gravity values stay separate since \Phi_0 swamps them
+    let (preset, env) = environment(opts, molecule.center_of_mass())?;
+    let env_potentials: Vec<f64> = targets.iter().map(|p| env.potential_at(p)).collect();
+    let self_potentials = potentials;
+    let potentials: Vec<f64> = self_potentials.iter().zip(&env_potentials).map(|(a, b)| a + b).collect();
+    let fields: Vec<Vector3> = fields.iter().zip(&targets).map(|(g, p)| *g + env.field_at(p)).collect();
+
+    let mut text = Vec::new();
+    let mut json = Json::object().with("method", method.name()).with("at", at).with("environment", preset.name());
+    if let ([target], [phi], [field]) = (targets.as_slice(), potentials.as_slice(), fields.as_slice()) {
+        text.push(format!("Target: {} A", point(*target)));
+        if !env.is_vacuum() {
+            text.push(format!("Self potential: {:.6e} J/kg", self_potentials[0]));
+            text.push(format!("Environment ({}): {:.6e} J/kg", preset.name(), env_potentials[0]));
+        }
+        text.push(format!("Gravitational Potential: {:.6e} J/kg", phi));
+        text.push(format!("Field: ({:.6e}, {:.6e}, {:.6e}) m/s^2, |g| = {:.6e}", field.x, field.y, field.z, field.norm()));
+        json = json
+            .with("target", *target)
+            .with("potential", *phi)
+            .with("potential_self", self_potentials[0])
+            .with("potential_environment", env_potentials[0])
+            .with("field", *field)
+            .with("field_magnitude", field.norm());
+    } else {
+        let (min, max) = potentials.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let mean = potentials.iter().sum::<f64>() / potentials.len() as f64;
+        let strongest = fields.iter().map(|g| g.norm()).fold(0.0, f64::max);
+        text.push(format!("Evaluated {} targets ({}) on {} threads", targets.len(), at, threads));
+        text.push(format!("Potential: min {:.6e}, max {:.6e}, mean {:.6e} J/kg", min, max, mean));
+        if !env.is_vacuum() {
+            let (lo, hi) = self_potentials.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+            text.push(format!("Self potential: min {:.6e}, max {:.6e} J/kg ({} environment excluded)", lo, hi, preset.name()));
+        }
+        text.push(format!("Largest |g|: {:.6e} m/s^2", strongest));
+        json = json
+            .with("count", targets.len())
+            .with("potential_min", min)
+            .with("potential_max", max)
+            .with("potential_mean", mean)
+            .with("field_magnitude_max", strongest)
+            .with("targets", targets.clone())
+            .with("potentials", potentials.as_slice())
+            .with("self_potentials", self_potentials.as_slice())
+            .with("fields", fields.clone());
+    }
+    json = json.with("threads", threads).with("grid", grid_info).with("diagnostics", diagnostics.as_ref().map(Json::from));
+    if let Some(diagnostics) = &diagnostics {
+        text.extend(diagnostics_text(diagnostics));
+    }
+
+    // Electrostatics at the same targets: the charge grid for --method grid, exact charge sums
+    // otherwise, since the octree's multipoles assume same-sign sources
+    if let Some((model, medium)) = &charges {
+        let (charge_method, sources, deposition) = if method == PotentialMethod::Grid {
+            let (charge_grid, deposition) = charge_grid(opts, &molecule)?;
+            ("grid", charge_grid.sources(), Some(deposition))
+        } else {
+            ("direct", molecule.charge_sources(), None)
+        };
+        let kernel = medium.kernel();
+        let phi = potentials_from_sources(&targets, &sources, &kernel, threads);
+        let fields = fields_from_sources(&targets, &sources, &kernel, threads);
+        let energy = molecule.interaction_energies(medium, threads);
+        text.extend(electrostatics_text(*model, &molecule, medium, &energy));
+        text.extend(deposition.as_ref().and_then(charge_deposition_text));
+        let mut summary = electrostatics_summary(*model, &molecule, medium, &energy)
+            .with("method", charge_method)
+            .with("deposition", deposition.as_ref().map(charge_deposition_json));
+        if let ([phi], [field]) = (phi.as_slice(), fields.as_slice()) {
+            text.push(format!("Electrostatic Potential: {:.6e} V", phi));
+            text.push(format!("Electric Field: ({:.6e}, {:.6e}, {:.6e}) V/m, |E| = {:.6e}", field.x, field.y, field.z, field.norm()));
+            summary = summary.with("potential", *phi).with("field", *field).with("field_magnitude", field.norm());
+        } else {
+            let (min, max) = phi.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+            let mean = phi.iter().sum::<f64>() / phi.len() as f64;
+            let strongest = fields.iter().map(|e| e.norm()).fold(0.0, f64::max);
+            text.push(format!("Electrostatic Potential: min {:.6e}, max {:.6e}, mean {:.6e} V", min, max, mean));
+            text.push(format!("Largest |E|: {:.6e} V/m", strongest));
+            summary = summary
+                .with("potential_min", min)
+                .with("potential_max", max)
+                .with("potential_mean", mean)
+                .with("field_magnitude_max", strongest)
+                .with("potentials", phi.as_slice())
+                .with("fields", fields);
+        }
+        json = json.with("electrostatics", summary);
+    }
+    if let Some(out) = opts.value("--out") {
+        write_potential_table(out, &targets, &self_potentials, &env_potentials, &fields).map_err(|e| runtime(out, e))?;
+        text.push(format!("Wrote {} rows to {}", targets.len(), out));
+        json = json.with("out", out);
+    }
+    Ok(Report { text, json })
+}
+
+fn cmd_solve(opts: &Options) -> Result<Report, CliError> {
+    let mut molecule = load_molecule(opts)?;
+    let charges = electrostatics(opts, &mut molecule)?;
+    let boundary: Boundary = opts.parse_value("--boundary")?.unwrap_or_default();
+    let (grid, diagnostics) = mapped_grid(opts, &molecule)?;
+    let potential = PoissonSolver::new(boundary).solve(&*grid);
+
+    let (min, max) = potential.data.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let com = molecule.center_of_mass();
+    let at_com = potential.sample(&com);
+    let mut text = vec![
+        format!("Solved {:?} voxels at {:.3} A ({:?} boundary)", potential.dimensions, potential.resolution, boundary),
+        format!("Potential range: {:.6e} to {:.6e} J/kg", min, max),
+    ];
+    if let Some(phi) = at_com {
+        text.push(format!("Potential at center of mass: {:.6e} J/kg", phi));
+    }
+    text.extend(diagnostics_text(&diagnostics));
+    let mut json = Json::object()
+        .with("molecule", molecule_summary(&molecule))
+        .with("grid", grid_summary(potential.origin, potential.resolution, potential.dimensions))
+        .with("boundary", format!("{:?}", boundary).to_ascii_lowercase())
+        .with("potential_min", min)
+        .with("potential_max", max)
+        .with("potential_at_com", at_com)
+        .with("diagnostics", &diagnostics);
+
+    // The same solver with the medium's kernel on a charge grid of the same backend
+    if let Some((model, medium)) = &charges {
+        let (charge_grid, deposition) = charge_grid(opts, &molecule)?;
+        let solved = PoissonSolver::new(boundary).with_kernel(medium.kernel()).solve(&*charge_grid);
+        let (lo, hi) = solved.data.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let at_com = solved.sample(&com);
+        let energy = molecule.interaction_energies(medium, default_threads());
+        text.extend(electrostatics_text(*model, &molecule, medium, &energy));
+        text.extend(charge_deposition_text(&deposition));
+        text.push(format!("Electrostatic potential range: {:.6e} to {:.6e} V", lo, hi));
+        if let Some(phi) = at_com {
+            text.push(format!("Electrostatic potential at center of mass: {:.6e} V", phi));
+        }
+        json = json.with(
+            "electrostatics",
+            electrostatics_summary(*model, &molecule, medium, &energy)
+                .with("deposition", charge_deposition_json(&deposition))
+                .with("potential_min", lo)
+                .with("potential_max", hi)
+                .with("potential_at_com", at_com),
+        );
+    }
+    if let Some(out) = opts.value("--out") {
+        potential.save_mrc(out).map_err(|e| runtime(out, e))?;
+        text.push(format!("Wrote potential map to {}", out));
+        json = json.with("out", out);
+    }
+    Ok(Report { text, json })
+}
+
+/// A tight-binding model of the loaded molecule with \Phi and m\Phi at each of its sites
+struct SiteGravity {
+    molecule: Biomolecule,
+    model: TightBinding,
+    method: PotentialMethod,
+    preset: EnvironmentPreset,
+    potentials: Vec<f64>,   // J/kg, self plus environment
+    perturbation: Vec<f64>, // eV
+    diagnostics: Option<Diagnostics>,
+}
+
+/// Builds the model and samples \Phi at its sites by --method, adding any --environment
+fn site_gravity(opts: &Options, threads: usize) -> Result<SiteGravity, CliError> {
+    let method: PotentialMethod = opts.parse_value("--method")?.unwrap_or(PotentialMethod::Direct);
+    if let Some(spec) = GRID_OPTIONS.iter().find(|spec| opts.flag(spec.name)) && method != PotentialMethod::Grid {
+        return Err(CliError::Usage(format!("{} only applies with --method grid", spec.name)));
+    }
+    if method != PotentialMethod::Octree && opts.flag("--theta") {
+        return Err(CliError::Usage("--theta only applies with --method octree".to_string()));
+    }
+    let molecule = load_molecule(opts)?;
+    let model = TightBinding::from_biomolecule(&molecule, &TightBindingParameters::default())?;
+    let positions = model.site_positions();
+    let (self_potentials, _, diagnostics) = self_gravity(opts, &molecule, method, &positions, threads)?;
+    let (preset, env) = environment(opts, molecule.center_of_mass())?;
+    let potentials: Vec<f64> = self_potentials.iter().zip(&positions).map(|(phi, p)| phi + env.potential_at(p)).collect();
+    let perturbation = model.gravitational_terms(&potentials);
+    Ok(SiteGravity { molecule, model, method, preset, potentials, perturbation, diagnostics })
+}
+
+fn cmd_quantum(opts: &Options) -> Result<Report, CliError> {
+    let states = opts.count("--states")?.unwrap_or(4);
+    let solver: EigenSolver = opts.parse_value("--solver")?.unwrap_or_default();
+    let threads = opts.count("--threads")?.unwrap_or_else(default_threads);
+    // H' = H0 + m\Phi on each site, \Phi from the molecule plus any background environment
+    let SiteGravity { molecule, model, method, preset, potentials, perturbation, diagnostics } = site_gravity(opts, threads)?;
+    let shifts = model.energy_shifts(&perturbation, states, solver);
+
+    let (t_min, t_max) = model.couplings.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &(_, _, t)| (lo.min(t), hi.max(t)));
+    let (v_min, v_max) = perturbation.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let mut text = vec![format!("Tight-binding model: {} sites, {} couplings", model.len(), model.couplings.len())];
+    if !model.couplings.is_empty() {
+        text.push(format!("Hopping: {:.4} to {:.4} eV", t_min, t_max));
+    }
+    text.push(format!("On-site m*Phi: {:.6e} to {:.6e} eV ({} method, {} environment)", v_min, v_max, method.name(), preset.name()));
+    text.push(format!("Solver: {} ({} iterations)", shifts.unperturbed.solver.name(), shifts.unperturbed.iterations));
+    let mut levels = Vec::new();
+    for (k, (e0, e)) in shifts.unperturbed.values.iter().zip(&shifts.perturbed.values).enumerate() {
+        let (first_order, direct) = (shifts.first_order[k], shifts.direct[k]);
+        text.push(format!("State {}: E0 = {:.6} eV, E' = {:.6} eV, shift {:.6e} eV (first order), {:.6e} eV (direct)", k, e0, e, first_order, direct));
+        levels.push(
+            Json::object()
+                .with("index", k)
+                .with("energy_ev", *e0)
+                .with("perturbed_energy_ev", *e)
+                .with("first_order_shift_ev", first_order)
+                .with("direct_shift_ev", direct)
+                .with("amplitudes", shifts.unperturbed.vectors[k].as_slice()),
+        );
+    }
+    let sites: Vec<Json> = model
+        .sites
+        .iter()
+        .zip(potentials.iter().zip(&perturbation))
+        .map(|(site, (phi, v))| Json::object().with("label", site.label.as_str()).with("position", site.position).with("energy_ev", site.energy).with("potential", *phi).with("perturbation_ev", *v))
+        .collect();
+    let mut json = Json::object()
+        .with("molecule", molecule_summary(&molecule))
+        .with("method", method.name())
+        .with("environment", preset.name())
+        .with("solver", shifts.unperturbed.solver.name())
+        .with("iterations", shifts.unperturbed.iterations)
+        .with("sites", sites)
+        .with("states", levels);
+    if let Some(diagnostics) = &diagnostics {
+        text.extend(diagnostics_text(diagnostics));
+        json = json.with("diagnostics", diagnostics);
+    }
+    Ok(Report { text, json })
+}
+
+fn cmd_berry(opts: &Options) -> Result<Report, CliError> {
+    let band = opts.parse_value::<usize>("--state")?.unwrap_or(0);
+    let solver: EigenSolver = opts.parse_value("--solver")?.unwrap_or_default();
+    let threads = opts.count("--threads")?.unwrap_or_else(default_threads);
+    let closed = match opts.value("--path").unwrap_or("closed") {
+        "closed" => true,
+        "open" => false,
+        other => return Err(CliError::Usage(format!("invalid --path '{}' (expected closed or open)", other))),
+    };
+    if closed && opts.flag("--end") {
+        return Err(CliError::Usage("--end only applies with --path open".to_string()));
+    }
+    let end = opts.parse_value::<f64>("--end")?.unwrap_or(180.0);
+    if !end.is_finite() {
+        return Err(CliError::Usage(format!("--end must be finite, got {}", end)));
+    }
+    let SiteGravity { molecule, model, method, preset, potentials, perturbation, diagnostics } = site_gravity(opts, threads)?;
+    if band >= model.len() {
+        return Err(CliError::Usage(format!("--state {} is out of range for {} sites", band, model.len())));
+    }
+    let samples = opts.count("--samples")?.unwrap_or(model.len()).max(2);
+    let path = if closed { TwistPath::Closed { samples } } else { TwistPath::Open { samples, end: end.to_radians() } };
+    let response = model.berry_response(&perturbation, band, &path, solver, threads);
+
+    let (phi_min, phi_max) = potentials.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let per_ev = if response.spread > 0.0 { Some(response.linear / response.spread) } else { None };
+    let mut text = vec![
+        format!("Tight-binding model: {} sites; level {} along the {} twist path ({} solver)", model.len(), band, path.name(), solver.resolve(model.len()).name()),
+        format!("Berry phase (H0): {}", response.unperturbed),
+        format!("Berry phase (H0 + m*Phi): {}", response.perturbed),
+        format!("Potential at the sites: {:.6e} to {:.6e} J/kg ({} method, {} environment)", phi_min, phi_max, method.name(), preset.name()),
+        format!("m*Phi: uniform {:.6e} eV (cannot move the phase), non-uniform up to {:.6e} eV", response.uniform, response.spread),
+        format!("Phase change: {:.6e} rad direct, {:.6e} rad to first order", response.direct, response.linear),
+    ];
+    if let Some(per_ev) = per_ev {
+        text.push(format!("Sensitivity: {:.6e} rad per eV of non-uniform m*Phi", per_ev));
+    }
+    if response.unperturbed.min_overlap < 0.5 {
+        text.push(format!("Warning: neighbouring samples overlap by only {:.3}; rerun with more --samples", response.unperturbed.min_overlap));
+    }
+    let phase = |b: &BerryPhase| Json::object().with("phase_rad", b.phase).with("min_overlap", b.min_overlap).with("closure_overlap", b.closure_overlap).with("samples", b.samples);
+    let mut json = Json::object()
+        .with("molecule", molecule_summary(&molecule))
+        .with("sites", model.len())
+        .with("state", band)
+        .with("path", path.name())
+        .with("end_deg", if closed { 360.0 } else { end })
+        .with("solver", solver.resolve(model.len()).name())
+        .with("method", method.name())
+        .with("environment", preset.name())
+        .with("potential_min", phi_min)
+        .with("potential_max", phi_max)
+        .with("unperturbed", phase(&response.unperturbed))
+        .with("perturbed", phase(&response.perturbed))
+        .with("uniform_ev", response.uniform)
+        .with("spread_ev", response.spread)
+        .with("probe_ev", response.probe)
+        .with("phase_change_direct_rad", response.direct)
+        .with("phase_change_linear_rad", response.linear)
+        .with("phase_per_ev", per_ev);
+    if let Some(diagnostics) = &diagnostics {
+        text.extend(diagnostics_text(diagnostics));
+        json = json.with("diagnostics", diagnostics);
+    }
+    Ok(Report { text, json })
+}
+
+fn cmd_torsion(opts: &Options) -> Result<Report, CliError> {
+    let builder = dna_builder(opts, opts.require("--sequence")?)?;
+    let mut feedback = TorsionFeedback::new(builder);
+    if let Some(coupling) = opts.finite("--coupling")? {
+        feedback.coupling = coupling;
+    }
+    if let Some(max_iterations) = opts.count("--max-iter")? {
+        feedback.max_iterations = max_iterations;
+    }
+    let (preset, env) = environment(opts, feedback.builder.build().center_of_mass())?;
+    feedback.environment = env;
+
+    let profile = feedback.run()?;
+    let residual = profile.residuals.last().copied().unwrap_or(0.0);
+    let mut text = vec![
+        format!("{} after {} iterations (residual {:.3e} deg)", if profile.converged { "Converged" } else { "Not converged" }, profile.iterations, residual),
+        format!("{:>5} {:>12} {:>10} {:>12}", "step", "twist_deg", "pitch_A", "phi_J_kg"),
+    ];
+    for (k, ((twist, pitch), phi)) in profile.twists.iter().zip(&profile.pitches).zip(&profile.potentials).enumerate() {
+        text.push(format!("{:>5} {:>12.6} {:>10.4} {:>12.4e}", k + 1, twist, pitch, phi));
+    }
+    if profile.lost_mass > 0.0 {
+        text.push(format!("Warning: up to {:.2} Da per iteration fell outside the grid", profile.lost_mass));
+    }
+    let mut json = Json::object()
+        .with("environment", preset.name())
+        .with("converged", profile.converged)
+        .with("iterations", profile.iterations)
+        .with("residuals", profile.residuals.as_slice())
+        .with("twists", profile.twists.as_slice())
+        .with("pitches", profile.pitches.as_slice())
+        .with("potentials", profile.potentials.as_slice())
+        .with("lost_mass_da", profile.lost_mass);
+    if let Some(out) = opts.value("--out") {
+        write_structure(&profile.structure, StructureFormat::Pdb, out).map_err(|e| runtime(out, e))?;
+        text.push(format!("Wrote relaxed structure to {}", out));
+        json = json.with("out", out);
+    }
+    Ok(Report { text, json })
+}
+
+fn cmd_export(opts: &Options) -> Result<Report, CliError> {
+    let out = opts.require("--out")?;
+    let molecule = load_molecule(opts)?;
+    write_output(&molecule, opts, out)?;
+    Ok(Report {
+        text: vec![format!("Wrote {} atoms to {}", molecule.atoms.len(), out)],
+        json: molecule_summary(&molecule).with("out", out),
+    })
+}
+
+fn cmd_compare(opts: &Options) -> Result<Report, CliError> {
+    if opts.flag("--format") && !opts.flag("--out") {
+        return Err(CliError::Usage("--format only applies with --out".to_string()));
+    }
+    let path = opts.require("--reference")?;
+    let reference = read_structure(path).map_err(|e| runtime(path, e))?;
+    let molecule = load_molecule(opts)?;
+    let fit = molecule.superpose(&reference)?;
+    let unfitted = molecule.rmsd_to(&reference)?;
+    let residues = molecule.residue_deviations(&reference, &fit)?;
+    let worst = residues.iter().max_by(|a, b| a.rmsd.total_cmp(&b.rmsd));
+    let (rg, rg_reference) = (molecule.radius_of_gyration(), reference.radius_of_gyration());
+
+    let mut text = vec![
+        format!("Compared {} atoms against {}", molecule.atoms.len(), path),
+        format!("RMSD: {:.4} A after superposition, {:.4} A as given", fit.rmsd, unfitted),
+        format!("Radius of gyration: {:.4} A (reference {:.4} A)", rg, rg_reference),
+    ];
+    if let Some(worst) = worst {
+        text.push(format!("Largest residue deviation: {} {} {} at {:.4} A RMSD over {} atoms", worst.chain_id, worst.residue_name, worst.residue_seq, worst.rmsd, worst.atoms));
+    }
+    let residue_json: Vec<Json> = residues
+        .iter()
+        .map(|r| {
+            Json::object()
+                .with("chain", r.chain_id.as_str())
+                .with("residue_seq", r.residue_seq as f64)
+                .with("residue_name", r.residue_name.as_str())
+                .with("atoms", r.atoms)
+                .with("rmsd", r.rmsd)
+                .with("max_deviation", r.max_deviation)
+        })
+        .collect();
+    let mut json = Json::object()
+        .with("reference", path)
+        .with("atoms", molecule.atoms.len())
+        .with("rmsd", fit.rmsd)
+        .with("rmsd_unfitted", unfitted)
+        .with("rotation", fit.rotation.iter().map(|row| row.to_vec()).collect::<Vec<_>>())
+        .with("translation", fit.translation())
+        .with("radius_of_gyration", rg)
+        .with("reference_radius_of_gyration", rg_reference)
+        .with("principal_moments", molecule.principal_axes().moments.to_vec())
+        .with("reference_principal_moments", reference.principal_axes().moments.to_vec())
+        .with("residues", residue_json);
+    if let Some(profile) = opts.value("--profile") {
+        write_residue_profile(profile, &residues).map_err(|e| runtime(profile, e))?;
+        text.push(format!("Wrote {} residues to {}", residues.len(), profile));
+        json = json.with("profile", profile);
+    }
+    if let Some(out) = opts.value("--out") {
+        write_output(&fit.transform(&molecule), opts, out)?;
+        text.push(format!("Wrote the superposed structure to {}", out));
+        json = json.with("out", out);
+    }
+    Ok(Report { text, json })
+}
+
+fn write_residue_profile(path: &str, residues: &[ResidueDeviation]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(out, "chain\tresidue_seq\tresidue_name\tatoms\trmsd_A\tmax_deviation_A")?;
+    for r in residues {
+        writeln!(out, "{}\t{}\t{}\t{}\t{:.6}\t{:.6}", r.chain_id, r.residue_seq, r.residue_name, r.atoms, r.rmsd, r.max_deviation)?;
+    }
+    out.flush()
+}
+
+fn cmd_run(opts: &Options) -> Result<Report, CliError> {
+    let files: Vec<&str> = opts.require("--scenario")?.split(',').filter(|f| !f.is_empty()).collect();
+    if files.is_empty() {
+        return Err(CliError::Usage("--scenario needs at least one file".to_string()));
+    }
+    if opts.flag("--dry-run") && (opts.flag("--manifest") || opts.flag("--threads")) {
+        return Err(CliError::Usage("--dry-run does not run anything, so --manifest and --threads do not apply".to_string()));
+    }
+    let threads = opts.count("--threads")?.unwrap_or_else(default_threads);
+
+    let mut scenarios: Vec<(&str, Scenario)> = Vec::new();
+    for file in &files {
+        for scenario in load_scenarios(file).map_err(GqbmeError::from)? {
+            if let Some((other, _)) = scenarios.iter().find(|(_, s)| s.name == scenario.name) {
+                return Err(CliError::Runtime(format!("scenario '{}' appears in both {} and {}", scenario.name, other, file)));
+            }
+            scenarios.push((file, scenario));
+        }
+    }
+
+    if opts.flag("--dry-run") {
+        let text = scenarios
+            .iter()
+            .map(|(_, s)| format!("{}: {}, {} via {}, {} environment", s.name, s.molecule, s.solver.targets.name(), s.solver.method.name(), s.environment.name()))
+            .collect();
+        let json = Json::object().with("scenarios", scenarios.iter().map(|(_, s)| s.config.clone()).collect::<Vec<_>>());
+        return Ok(Report { text, json });
+    }
+
+    let manifest_path = match opts.value("--manifest") {
+        Some(path) => std::path::PathBuf::from(path),
+        None => std::path::Path::new(files[0]).with_extension("manifest.json"),
+    };
+    let mut text = Vec::new();
+    let mut runs = Vec::new();
+    let mut failures = Vec::new();
+    for (file, scenario) in &scenarios {
+        let entry = Json::object().with("name", scenario.name.as_str()).with("file", *file).with("config", scenario.config.clone());
+        match scenario.run(threads) {
+            Ok(results) => {
+                let potential = results.get("potential");
+                let bound = |key: &str| potential.and_then(|p| p.get(key)).and_then(Json::as_f64).unwrap_or(f64::NAN);
+                let written = results.get("outputs").and_then(Json::as_array).map_or(0, |o| o.len());
+                let ratio = match results.get("electrostatics").and_then(|e| e.get("energy")).and_then(|e| e.get("gravity_to_electrostatic")).and_then(Json::as_f64) {
+                    Some(ratio) => format!("{:.3e}", ratio),
+                    None => "n/a".to_string(),
+                };
+                text.push(format!("{}: ok, potential {:.6e} to {:.6e} J/kg, grav/elec {}, {} file(s) written", scenario.name, bound("min"), bound("max"), ratio, written));
+                runs.push(entry.with("status", "ok").with("results", results));
+            }
+            Err(err) => {
+                text.push(format!("{}: failed: {}", scenario.name, err));
+                failures.push(format!("{} ({})", scenario.name, err));
+                runs.push(entry.with("status", "error").with("error", err.to_string()));
+            }
+        }
+    }
+
+    let manifest = Json::object()
+        .with("gqbme_version", env!("CARGO_PKG_VERSION"))
+        .with("scenario_files", files.clone())
+        .with("scenarios", scenarios.len())
+        .with("failed", failures.len())
+        .with("runs", runs);
+    let manifest_name = manifest_path.display().to_string();
+    std::fs::write(&manifest_path, manifest.to_pretty_string() + "\n").map_err(|e| runtime(&manifest_name, e))?;
+    if !failures.is_empty() {
+        return Err(CliError::Runtime(format!("{} of {} scenarios failed: {}; manifest written to {}", failures.len(), scenarios.len(), failures.join("; "), manifest_name)));
+    }
+    text.push(format!("Wrote manifest for {} scenarios to {}", scenarios.len(), manifest_name));
+    Ok(Report { text, json: manifest.with("manifest", manifest_name) })
+}
+
+fn cmd_md(opts: &Options) -> Result<Report, CliError> {
+    let mut params = MdParameters::default();
+    if let Some(dt) = opts.positive("--dt")? {
+        if dt > MAX_TIME_STEP {
+            return Err(CliError::Usage(format!("--dt must be at most {} ps, got {}", MAX_TIME_STEP, dt)));
+        }
+        params.time_step = dt;
+    }
+    if let Some(temperature) = opts.non_negative("--temperature")? {
+        params.temperature = temperature;
+    }
+    if let Some(friction) = opts.non_negative("--friction")? {
+        params.friction = friction;
+    }
+    if let Some(seed) = opts.parse_value::<u64>("--seed")? {
+        params.seed = seed;
+    }
+    params.gravity_scale = opts.finite("--gravity-scale")?;
+
+    let mut sim = MdSimulation::new(load_molecule(opts)?, params);
+    if let Some(path) = opts.value("--restart") {
+        let text = std::fs::read_to_string(path).map_err(|e| runtime(path, e))?;
+        sim.restore_checkpoint(&text).map_err(|e| runtime(path, e))?;
+    }
+
+    let create = |path: &str| -> Result<Box<dyn Write>, CliError> {
+        let file = std::fs::File::create(path).map_err(|e| runtime(path, e))?;
+        Ok(Box::new(io::BufWriter::new(file)))
+    };
+    let every = opts.count("--every")?.unwrap_or(100);
+    let mut output = MdOutput {
+        trajectory: opts.value("--traj").map(create).transpose()?,
+        trajectory_every: every,
+        energy_log: opts.value("--energy").map(create).transpose()?,
+        energy_every: every,
+        energy_header_written: false,
+        checkpoint: opts.value("--checkpoint").map(Into::into),
+        checkpoint_every: opts.count("--checkpoint-every")?.unwrap_or(every * 10),
+    };
+    let steps = opts.count("--steps")?.unwrap_or(1000);
+    sim.run(steps, &mut output).map_err(|e| CliError::Runtime(e.to_string()))?;
+
+    let e = sim.energies();
+    let text = vec![
+        format!("Ran {} steps ({:.3} ps) on {} atoms", steps, sim.time(), sim.molecule.atoms.len()),
+        format!("T = {:.1} K, E_total = {:.4e} kJ/mol, E_gravity = {:.4e} kJ/mol, drift = {:.4} A", sim.temperature(), e.total(), e.gravity, sim.drift_from_start()),
+    ];
+    let json = Json::object()
+        .with("steps", steps)
+        .with("time_ps", sim.time())
+        .with("atoms", sim.molecule.atoms.len())
+        .with("temperature_k", sim.temperature())
+        .with("drift_a", sim.drift_from_start())
+        .with(
+            "energies_kj_mol",
+            Json::object()
+                .with("kinetic", e.kinetic)
+                .with("bond", e.bond)
+                .with("angle", e.angle)
+                .with("dihedral", e.dihedral)
+                .with("lennard_jones", e.lennard_jones)
+                .with("gravity", e.gravity)
+                .with("total", e.total()),
+        );
+    Ok(Report { text, json })
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: gqbme <command> [args]");
-        return;
-    }
-
-    match args[1].as_str() {
-        "generate_dna" => {
-            let num_bp = args[2].parse::<usize>().unwrap_or(10);
-            let dna = generate_dna_helix(num_bp, 10.0, 34.0);
-            
-            // Create a grid and map DNA
-            let mut grid = MassDensityGrid::new(
-                Vector3::new(-20.0, -20.0, 0.0),
-                1.0,
-                (40, 40, 100)
-            );
-            grid.map_biomolecule(&dna);
-            
-            // Calculate potential at the center
-            let com = dna.center_of_mass();
-            let phi = RelativisticSolver::calculate_potential(&com, &grid);
-            
-            println!("DNA Generated with {} base pairs", num_bp);
-            println!("Center of Mass: {:?}, Gravitational Potential: {:.2e}", com, phi);
-            
-            // Output atom positions for visualization
-            for (i, atom) in dna.atoms.iter().enumerate() {
-                println!("ATOM {} {} {} {} {}", i, atom.element, atom.position.x, atom.position.y, atom.position.z);
-            }
-        }
-        _ => println!("Unknown command"),
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(name) = args.first() else {
+        print_usage();
+        process::exit(2);
+    };
+    match name.as_str() {
+        "help" | "--help" | "-h" => {
+            match args.get(1) {
+                Some(topic) => match COMMANDS.iter().find(|c| c.name == topic) {
+                    Some(command) => print_command_help(command),
+                    None => {
+                        eprintln!("error: unknown command '{}'", topic);
+                        process::exit(2);
+                    }
+                },
+                None => print_usage(),
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let Some(command) = COMMANDS.iter().find(|c| c.name == name) else {
+        eprintln!("error: unknown command '{}'", name);
+        eprintln!("Run 'gqbme help' for the list of commands.");
+        process::exit(2);
+    };
+    let json_mode = args[1..].iter().any(|a| a == "--json");
+    let result = Options::parse(&args[1..], command.options).and_then(|opts| {
+        if opts.flag("--help") {
+            print_command_help(command);
+            process::exit(0);
+        }
+        (command.run)(&opts)
+    });
+
+    match result {
+        Ok(report) if json_mode => {
+            let mut json = Json::object().with("command", command.name);
+            if let Json::Object(members) = report.json {
+                for (key, value) in members {
+                    json = json.with(&key, value);
+                }
+            }
+            // A closed pipe (e.g. `| head`) is not worth a panic
+            let _ = writeln!(io::stdout().lock(), "{}", json);
+        }
+        Ok(report) => {
+            let mut out = io::stdout().lock();
+            for line in report.text {
+                if writeln!(out, "{}", line).is_err() {
+                    break;
+                }
+            }
+        }
+        Err(err) => {
+            if json_mode {
+                println!("{}", error_json(command.name, &err));
+            } else {
+                eprintln!("error: {}", err);
+                if let CliError::Usage(_) = err {
+                    eprintln!("Run 'gqbme {} --help' for its options.", command.name);
+                }
+            }
+            process::exit(err.exit_code());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn command(name: &str) -> &'static Command {
+        COMMANDS.iter().find(|c| c.name == name).expect("known command")
+    }
+
+    fn run(name: &str, list: &[&str]) -> Result<Report, CliError> {
+        let command = command(name);
+        Options::parse(&args(list), command.options).and_then(|opts| (command.run)(&opts))
+    }
+
+    fn usage(result: Result<impl Sized, CliError>) -> String {
+        match result {
+            Err(err @ CliError::Usage(_)) => {
+                assert_eq!(err.exit_code(), 2);
+                err.to_string()
+            }
+            Err(err) => panic!("expected a usage error, got {:?}", err),
+            Ok(_) => panic!("expected a usage error"),
+        }
+    }
+
+    #[test]
+    fn options_take_separate_and_inline_values_and_bare_flags() {
+        let opts = Options::parse(&args(&["--num-bp", "3", "--resolution=2.5", "--json"]), command("map").options).unwrap();
+        assert_eq!(opts.value("--num-bp"), Some("3"));
+        assert_eq!(opts.positive("--resolution").unwrap(), Some(2.5));
+        assert!(opts.flag("--json") && !opts.flag("--sparse"));
+        assert_eq!(opts.value("--json"), None);
+    }
+
+    #[test]
+    fn malformed_invocations_are_usage_errors() {
+        let map = command("map").options;
+        let parse = |list: &[&str]| Options::parse(&args(list), map);
+        assert!(usage(parse(&["--bogus"])).contains("unknown option '--bogus'"));
+        assert!(usage(parse(&["stray"])).contains("unexpected argument 'stray'"));
+        assert!(usage(parse(&["--num-bp", "3", "--num-bp", "4"])).contains("more than once"));
+        assert!(usage(parse(&["--num-bp", "--json"])).contains("requires a value <N>"));
+        assert!(usage(parse(&["--json=yes"])).contains("does not take a value"));
+
+        let opts = parse(&["--num-bp", "0", "--resolution", "nan", "--padding", "-1", "--radius", "ten"]).unwrap();
+        assert!(usage(opts.count("--num-bp")).contains("at least 1"));
+        assert!(usage(opts.positive("--resolution")).contains("must be positive"));
+        assert!(usage(opts.non_negative("--padding")).contains("non-negative"));
+        assert!(usage(opts.finite("--radius")).contains("invalid --radius 'ten'"));
+        assert!(usage(opts.require("--out")).contains("missing required option --out"));
+    }
+
+    #[test]
+    fn unusable_values_exit_2_before_any_work() {
+        assert!(usage(run("md", &["--num-bp", "2", "--dt", "1e10"])).contains("--dt must be at most"));
+        assert!(usage(run("md", &["--num-bp", "2", "--dt", "inf"])).contains("--dt must be positive"));
+        assert!(usage(run("torsion", &["--sequence", "ACGT", "--coupling", "nan"])).contains("--coupling must be finite"));
+        assert!(usage(run("torsion", &["--sequence", " "])).contains("--sequence has no bases"));
+        let out = env::temp_dir().join("gqbme-never-written.pdb");
+        let out = out.to_str().unwrap();
+        assert!(usage(run("export", &["--sequence", "", "--out", out])).contains("--sequence has no bases"));
+        assert!(usage(run("export", &["--peptide", "", "--out", out])).contains("--peptide has no residues"));
+        assert!(usage(run("export", &["--sequence", "ACGU", "--out", out])).contains("invalid residue 'U' at position 4"));
+        assert!(usage(run("export", &["--sequence", "ACGT", "--form", "Q", "--out", out])).contains("expected A, B or Z"));
+        assert!(!std::path::Path::new(out).exists());
+    }
+
+    #[test]
+    fn failures_while_running_exit_1() {
+        let err = run("map", &["--num-bp", "3", "--resolution", "1e-6"]).err().expect("grid too large");
+        assert!(matches!(err, CliError::Runtime(_)) && err.exit_code() == 1, "{:?}", err);
+        assert!(err.to_string().contains("too large to allocate"));
+
+        let missing = env::temp_dir().join("gqbme-missing-input.pdb");
+        let err = run("map", &["--input", missing.to_str().unwrap()]).err().expect("unreadable input");
+        assert_eq!((err.kind(), err.exit_code()), ("runtime", 1));
+        assert!(err.to_string().starts_with(missing.to_str().unwrap()), "{}", err);
+    }
+
+    #[test]
+    fn json_errors_name_the_command_message_and_kind() {
+        let err = usage(run("md", &["--num-bp", "2", "--dt", "1e10"]));
+        let json = Json::parse(&error_json("md", &CliError::Usage(err.clone())).to_string()).unwrap();
+        let keys: Vec<&str> = json.members().unwrap().iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["command", "error", "kind"]);
+        assert_eq!(json.get("command").and_then(Json::as_str), Some("md"));
+        assert_eq!(json.get("error").and_then(Json::as_str), Some(err.as_str()));
+        assert_eq!(json.get("kind").and_then(Json::as_str), Some("usage"));
+        let runtime = error_json("map", &CliError::Runtime("boom".to_string()));
+        assert_eq!(runtime.get("kind").and_then(Json::as_str), Some("runtime"));
+    }
+
+    #[test]
+    fn successful_commands_report_json() {
+        let report = run("torsion", &["--sequence", "CGCG", "--form", "z", "--coupling", "0"]).unwrap();
+        let twists: Vec<f64> = report.json.get("twists").and_then(Json::as_array).unwrap().iter().filter_map(Json::as_f64).collect();
+        assert_eq!(twists, [-9.0, -51.0, -9.0]);
+        assert_eq!(report.json.get("converged").and_then(Json::as_bool), Some(true));
+    }
+
+    #[test]
+    fn run_records_every_scenario_in_the_manifest_even_when_one_fails() {
+        let dir = env::temp_dir().join(format!("gqbme-{}-run", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("batch.json");
+        std::fs::write(&file, r#"{"defaults": {"molecule": {"num_bp": 3}, "solver": {"method": "grid"}, "grid": {"resolution": 2}},
+            "scenarios": [{"name": "coarse", "outputs": {"potentials": "{name}.tsv"}}, {"name": "tiny", "grid": {"resolution": 1e-9}}]}"#).unwrap();
+        let manifest_path = dir.join("batch.manifest.json");
+
+        let err = run("run", &["--scenario", file.to_str().unwrap(), "--threads", "1"]).err().expect("one scenario fails");
+        assert_eq!(err.exit_code(), 1);
+        assert!(err.to_string().starts_with("1 of 2 scenarios failed: tiny (grid dimensions"), "{}", err);
+        let manifest = Json::parse(&std::fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let keys: Vec<&str> = manifest.members().unwrap().iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["gqbme_version", "scenario_files", "scenarios", "failed", "runs"]);
+        assert_eq!(manifest.get("scenarios").and_then(Json::as_f64), Some(2.0));
+        assert_eq!(manifest.get("failed").and_then(Json::as_f64), Some(1.0));
+        let runs = manifest.get("runs").and_then(Json::as_array).unwrap();
+        let field = |i: usize, key: &str| runs[i].get(key).and_then(Json::as_str).map(str::to_string);
+        assert_eq!((field(0, "name").as_deref(), field(0, "status").as_deref()), (Some("coarse"), Some("ok")));
+        assert_eq!(field(0, "file").as_deref(), file.to_str());
+        let results = runs[0].get("results").unwrap();
+        for key in ["molecule", "grid", "diagnostics", "potential", "electrostatics", "outputs"] {
+            assert!(results.get(key).is_some(), "results.{}", key);
+        }
+        assert_eq!(results.get("outputs").and_then(Json::as_array).map(|o| o.len()), Some(1));
+        assert_eq!((field(1, "name").as_deref(), field(1, "status").as_deref()), (Some("tiny"), Some("error")));
+        assert!(field(1, "error").unwrap().contains("too large to allocate"));
+        assert!(runs[1].get("results").is_none());
+        // The resolved config, defaults merged in, travels with each entry
+        assert_eq!(runs[1].get("config").and_then(|c| c.get("solver")).and_then(|s| s.get("method")).and_then(Json::as_str), Some("grid"));
+    }
+
+    #[test]
+    fn export_writes_a_readable_structure_in_the_chosen_format() {
+        let expected = DnaBuilder::new("ACGT", HelixForm::A).unwrap().build();
+        for (file, extra) in [("export.pdb", &[][..]), ("export.xyz", &[][..]), ("export.out", &["--format", "xyz"][..])] {
+            let path = env::temp_dir().join(format!("gqbme-{}-{}", process::id(), file));
+            let out = path.to_str().unwrap();
+            let mut list = vec!["--sequence", "ACGT", "--form", "A", "--out", out];
+            list.extend_from_slice(extra);
+            let report = run("export", &list).unwrap();
+            assert_eq!(report.json.get("out").and_then(Json::as_str), Some(out));
+            let text = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let back = if file == "export.pdb" { g_qbme::structure_io::parse_pdb(&text) } else { g_qbme::structure_io::parse_xyz(&text) }.unwrap();
+            assert_eq!(back.atoms.len(), expected.atoms.len(), "{}", file);
+            assert!(back.atoms.iter().zip(&expected.atoms).all(|(a, b)| a.element == b.element && a.position.dist(&b.position) < 1e-3), "{}", file);
+            assert_eq!(back.atoms[0].record.is_some(), file == "export.pdb");
+        }
+
+        // Coordinates PDB cannot hold are a runtime failure, and nothing is left behind
+        let path = env::temp_dir().join(format!("gqbme-{}-wide.pdb", process::id()));
+        let err = run("export", &["--num-bp", "4", "--radius", "20000", "--out", path.to_str().unwrap()]).err().expect("out of PDB range");
+        assert_eq!(err.exit_code(), 1);
+        assert!(err.to_string().contains("write XYZ instead"), "{}", err);
+        assert!(!path.exists());
+        assert!(usage(run("export", &["--num-bp", "4", "--out", "x.pdb", "--format", "mol2"])).contains("expected pdb or xyz"));
     }
 }
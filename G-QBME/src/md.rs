@@ -0,0 +1,808 @@
+use crate::bonds::Bond;
+use crate::gqbme_engine::{Biomolecule, RelativisticSolver, Vector3, ANGSTROM_TO_M, DALTON_TO_KG, G_CONSTANT};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+
+// Internal units: Angstrom, Dalton, picosecond, kJ/mol.
+/// 1 (kJ/mol)/Da expressed in (A/ps)^2; also converts kJ/mol/A per Da into A/ps^2
+const MD_UNIT: f64 = 100.0;
+/// Boltzmann constant in kJ/mol/K
+pub const BOLTZMANN_KJ_MOL: f64 = 0.008_314_462_618;
+const AVOGADRO: f64 = 6.022_140_76e23;
+/// Newtons to kJ/mol/A
+const NEWTON_TO_KJ_MOL_A: f64 = AVOGADRO * 1e-3 * ANGSTROM_TO_M;
+
+/// Generic force-field constants; equilibrium values come from the starting structure
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForceField {
+    pub bond_k: f64,      // kJ/mol/A^2, E = k/2 (r - r0)^2
+    pub angle_k: f64,     // kJ/mol/rad^2
+    pub dihedral_k: f64,  // kJ/mol/rad^2, harmonic about the starting torsion
+    pub lj_epsilon: f64,  // kJ/mol, the same well depth for every pair
+    pub lj_cutoff: f64,   // Angstroms; the potential is shifted to zero here
+    pub scale_14: f64,    // LJ scale for atoms three bonds apart
+}
+
+impl Default for ForceField {
+    fn default() -> Self {
+        Self {
+            bond_k: 2500.0,
+            angle_k: 500.0,
+            dihedral_k: 10.0,
+            lj_epsilon: 0.4,
+            lj_cutoff: 10.0,
+            scale_14: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HarmonicBond {
+    pub i: usize,
+    pub j: usize,
+    pub r0: f64, // Angstroms
+}
+
+/// Angle i-j-k with vertex j
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HarmonicAngle {
+    pub i: usize,
+    pub j: usize,
+    pub k: usize,
+    pub theta0: f64, // radians
+}
+
+/// Proper dihedral i-j-k-l about the j-k bond
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HarmonicDihedral {
+    pub i: usize,
+    pub j: usize,
+    pub k: usize,
+    pub l: usize,
+    pub phi0: f64, // radians
+}
+
+/// Bonded terms and non-bonded exclusions derived from a bond list
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    pub bonds: Vec<HarmonicBond>,
+    pub angles: Vec<HarmonicAngle>,
+    pub dihedrals: Vec<HarmonicDihedral>,
+    excluded: HashSet<(usize, usize)>, // 1-2 and 1-3 pairs
+    pairs_14: HashSet<(usize, usize)>,
+}
+
+fn pair_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+impl Topology {
+    /// Enumerates angles and dihedrals from `bonds`, taking the geometry of `molecule` as equilibrium
+    pub fn from_bonds(molecule: &Biomolecule, bonds: &[Bond]) -> Self {
+        let pos = |i: usize| molecule.atoms[i].position;
+        let mut neighbours = vec![Vec::new(); molecule.atoms.len()];
+        for b in bonds {
+            neighbours[b.i].push(b.j);
+            neighbours[b.j].push(b.i);
+        }
+
+        let mut topology = Topology::default();
+        for b in bonds {
+            topology.bonds.push(HarmonicBond { i: b.i, j: b.j, r0: pos(b.i).dist(&pos(b.j)) });
+            topology.excluded.insert(pair_key(b.i, b.j));
+        }
+        for (j, around) in neighbours.iter().enumerate() {
+            for (a, &i) in around.iter().enumerate() {
+                for &k in &around[a + 1..] {
+                    topology.angles.push(HarmonicAngle { i, j, k, theta0: angle(pos(i), pos(j), pos(k)) });
+                    topology.excluded.insert(pair_key(i, k));
+                }
+            }
+        }
+        for b in bonds {
+            let (j, k) = (b.i, b.j);
+            for &i in neighbours[j].iter().filter(|&&i| i != k) {
+                for &l in neighbours[k].iter().filter(|&&l| l != j && l != i) {
+                    topology.dihedrals.push(HarmonicDihedral { i, j, k, l, phi0: dihedral(pos(i), pos(j), pos(k), pos(l)) });
+                    let key = pair_key(i, l);
+                    if !topology.excluded.contains(&key) {
+                        topology.pairs_14.insert(key);
+                    }
+                }
+            }
+        }
+        topology
+    }
+}
+
+fn angle(a: Vector3, b: Vector3, c: Vector3) -> f64 {
+    let (u, v) = (a - b, c - b);
+    (u.dot(&v) / (u.norm() * v.norm())).clamp(-1.0, 1.0).acos()
+}
+
+fn dihedral(a: Vector3, b: Vector3, c: Vector3, d: Vector3) -> f64 {
+    let (b1, b2, b3) = (b - a, c - b, d - c);
+    let (m, n) = (b1.cross(&b2), b2.cross(&b3));
+    (b2.norm() * b1.dot(&n)).atan2(m.dot(&n))
+}
+
+/// Wraps an angle difference into [-pi, pi]
+fn wrap(delta: f64) -> f64 {
+    let two_pi = 2.0 * std::f64::consts::PI;
+    delta - two_pi * (delta / two_pi).round()
+}
+
+/// Energy terms in kJ/mol
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MdEnergies {
+    pub kinetic: f64,
+    pub bond: f64,
+    pub angle: f64,
+    pub dihedral: f64,
+    pub lennard_jones: f64,
+    pub gravity: f64, // already multiplied by `gravity_scale`
+}
+
+impl MdEnergies {
+    pub fn potential(&self) -> f64 {
+        self.bond + self.angle + self.dihedral + self.lennard_jones + self.gravity
+    }
+
+    pub fn total(&self) -> f64 {
+        self.kinetic + self.potential()
+    }
+}
+
+/// Run settings for `MdSimulation`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MdParameters {
+    pub time_step: f64,   // ps
+    pub temperature: f64, // K, for initial velocities and the thermostat
+    pub friction: f64,    // Langevin collision rate in 1/ps; 0 gives plain velocity Verlet
+    /// Multiplier on the Newtonian gravity between atoms; `None` leaves gravity out
+    pub gravity_scale: Option<f64>,
+    pub seed: u64,
+    pub force_field: ForceField,
+}
+
+impl Default for MdParameters {
+    fn default() -> Self {
+        Self {
+            time_step: 0.001,
+            temperature: 300.0,
+            friction: 1.0,
+            gravity_scale: None,
+            seed: 0x5eed,
+            force_field: ForceField::default(),
+        }
+    }
+}
+
+/// Largest time step the CLI accepts, ps; velocity Verlet goes unstable above 2/\omega, just
+/// under 4 fs for a C-H bond at the default `bond_k`
+pub const MAX_TIME_STEP: f64 = 0.003;
+
+/// xorshift64* generator; the state is saved in checkpoints so restarts are exact
+#[derive(Debug, Clone, Copy)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // splitmix64 scrambles small seeds into a non-zero state
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng((z ^ (z >> 31)).max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in (0, 1]
+    fn uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal via Box-Muller
+    fn gaussian(&mut self) -> f64 {
+        let (u, v) = (self.uniform(), self.uniform());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    fn gaussian_vector(&mut self) -> Vector3 {
+        Vector3::new(self.gaussian(), self.gaussian(), self.gaussian())
+    }
+}
+
+/// Errors raised while restoring a checkpoint
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Malformed { line: usize, reason: String },
+    /// The checkpoint was written for a different system
+    AtomCountMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "I/O error: {}", err),
+            CheckpointError::Malformed { line, reason } => write!(f, "checkpoint line {}: {}", line, reason),
+            CheckpointError::AtomCountMismatch { expected, found } => {
+                write!(f, "checkpoint has {} atoms but the system has {}", found, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(err: std::io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+const CHECKPOINT_MAGIC: &str = "G-QBME-CHECKPOINT 1";
+
+/// Where and how often `MdSimulation::run` writes its output
+#[derive(Default)]
+pub struct MdOutput {
+    pub trajectory: Option<Box<dyn Write>>, // multi-frame XYZ
+    pub trajectory_every: usize,
+    pub energy_log: Option<Box<dyn Write>>, // tab-separated, one header line
+    pub energy_every: usize,
+    /// The energy log already holds its header; false for a new file, so a restarted run
+    /// writing to a fresh log still gets one
+    pub energy_header_written: bool,
+    pub checkpoint: Option<std::path::PathBuf>, // overwritten at each checkpoint
+    pub checkpoint_every: usize,
+}
+
+fn due(step: usize, every: usize) -> bool {
+    every > 0 && step.is_multiple_of(every)
+}
+
+/// Langevin molecular dynamics on a `Biomolecule`
+/// Integrates with the OBABO splitting: an Ornstein-Uhlenbeck half step around a
+/// velocity-Verlet step, which reduces to plain velocity Verlet at zero friction.
+pub struct MdSimulation {
+    pub molecule: Biomolecule, // current positions
+    pub velocities: Vec<Vector3>, // A/ps
+    pub params: MdParameters,
+    topology: Topology,
+    reference: Vec<Vector3>,
+    forces: Vec<Vector3>, // kJ/mol/A
+    rng: Rng,
+    step: usize,
+    time: f64, // ps
+}
+
+impl MdSimulation {
+    /// Builds the topology from bonds inferred on `molecule`, whose geometry becomes the
+    /// equilibrium, and draws Maxwell-Boltzmann velocities at `params.temperature`
+    pub fn new(molecule: Biomolecule, params: MdParameters) -> Self {
+        let bonds = molecule.infer_bonds();
+        let topology = Topology::from_bonds(&molecule, &bonds);
+        Self::with_topology(molecule, topology, params)
+    }
+
+    pub fn with_topology(molecule: Biomolecule, topology: Topology, params: MdParameters) -> Self {
+        let mut rng = Rng::new(params.seed);
+        let kt = BOLTZMANN_KJ_MOL * params.temperature;
+        let mut velocities: Vec<Vector3> = molecule
+            .atoms
+            .iter()
+            .map(|a| rng.gaussian_vector() * (MD_UNIT * kt / a.mass).sqrt())
+            .collect();
+        remove_net_momentum(&molecule, &mut velocities);
+
+        let reference = molecule.atoms.iter().map(|a| a.position).collect();
+        let mut sim = Self {
+            forces: vec![Vector3::default(); molecule.atoms.len()],
+            molecule,
+            velocities,
+            params,
+            topology,
+            reference,
+            rng,
+            step: 0,
+            time: 0.0,
+        };
+        sim.forces = sim.compute_forces().0;
+        sim
+    }
+
+    /// Current forces in kJ/mol/A
+    pub fn forces(&self) -> &[Vector3] {
+        &self.forces
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    pub fn step_count(&self) -> usize {
+        self.step
+    }
+
+    /// Simulated time in ps
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Instantaneous temperature from the kinetic energy, 3N - 3 degrees of freedom
+    pub fn temperature(&self) -> f64 {
+        let dof = (3 * self.molecule.atoms.len()).saturating_sub(3).max(1) as f64;
+        2.0 * self.kinetic_energy() / (dof * BOLTZMANN_KJ_MOL)
+    }
+
+    fn kinetic_energy(&self) -> f64 {
+        self.molecule.atoms.iter().zip(&self.velocities).map(|(a, v)| 0.5 * a.mass * v.dot(v) / MD_UNIT).sum()
+    }
+
+    /// Root-mean-square displacement (A) from the starting coordinates, without superposition
+    pub fn drift_from_start(&self) -> f64 {
+        let n = self.reference.len().max(1) as f64;
+        let sum: f64 = self.molecule.atoms.iter().zip(&self.reference).map(|(a, r)| (a.position - *r).dot(&(a.position - *r))).sum();
+        (sum / n).sqrt()
+    }
+
+    pub fn energies(&self) -> MdEnergies {
+        let mut e = self.compute_forces().1;
+        e.kinetic = self.kinetic_energy();
+        e
+    }
+
+    fn ornstein_uhlenbeck(&mut self, dt: f64) {
+        if self.params.friction <= 0.0 {
+            return;
+        }
+        let c1 = (-self.params.friction * dt).exp();
+        let kt = BOLTZMANN_KJ_MOL * self.params.temperature;
+        for (v, atom) in self.velocities.iter_mut().zip(&self.molecule.atoms) {
+            let sigma = ((1.0 - c1 * c1) * MD_UNIT * kt / atom.mass).sqrt();
+            *v = *v * c1 + self.rng.gaussian_vector() * sigma;
+        }
+    }
+
+    fn kick(&mut self, dt: f64) {
+        for ((v, f), atom) in self.velocities.iter_mut().zip(&self.forces).zip(&self.molecule.atoms) {
+            *v += *f * (MD_UNIT * dt / atom.mass);
+        }
+    }
+
+    pub fn step(&mut self) {
+        let dt = self.params.time_step;
+        self.ornstein_uhlenbeck(0.5 * dt);
+        self.kick(0.5 * dt);
+        for (atom, v) in self.molecule.atoms.iter_mut().zip(&self.velocities) {
+            atom.position += *v * dt;
+        }
+        self.forces = self.compute_forces().0;
+        self.kick(0.5 * dt);
+        self.ornstein_uhlenbeck(0.5 * dt);
+        self.step += 1;
+        self.time += dt;
+    }
+
+    /// Advances `steps` steps, writing frames, energies and checkpoints as configured;
+    /// the checkpoint is also written after the last step so the run can always be resumed
+    pub fn run(&mut self, steps: usize, output: &mut MdOutput) -> io::Result<()> {
+        if let Some(log) = output.energy_log.as_mut()
+            && !output.energy_header_written
+        {
+            writeln!(log, "step\ttime_ps\tkinetic\tbond\tangle\tdihedral\tlj\tgravity\ttotal\ttemperature_K\tdrift_A")?;
+            output.energy_header_written = true;
+        }
+        for _ in 0..steps {
+            self.step();
+            if due(self.step, output.trajectory_every)
+                && let Some(traj) = output.trajectory.as_mut()
+            {
+                self.molecule.write_xyz(traj, &format!("step {} t = {:.4} ps", self.step, self.time))?;
+            }
+            if due(self.step, output.energy_every)
+                && let Some(log) = output.energy_log.as_mut()
+            {
+                let e = self.energies();
+                writeln!(
+                    log,
+                    "{}\t{:.4}\t{:.6e}\t{:.6e}\t{:.6e}\t{:.6e}\t{:.6e}\t{:.6e}\t{:.6e}\t{:.2}\t{:.6e}",
+                    self.step,
+                    self.time,
+                    e.kinetic,
+                    e.bond,
+                    e.angle,
+                    e.dihedral,
+                    e.lennard_jones,
+                    e.gravity,
+                    e.total(),
+                    self.temperature(),
+                    self.drift_from_start()
+                )?;
+            }
+            if due(self.step, output.checkpoint_every) {
+                self.save_checkpoint(output)?;
+            }
+        }
+        if steps > 0 && !due(self.step, output.checkpoint_every) {
+            self.save_checkpoint(output)?;
+        }
+        if let Some(traj) = output.trajectory.as_mut() {
+            traj.flush()?;
+        }
+        if let Some(log) = output.energy_log.as_mut() {
+            log.flush()?;
+        }
+        Ok(())
+    }
+
+    fn save_checkpoint(&self, output: &MdOutput) -> io::Result<()> {
+        if let Some(path) = output.checkpoint.as_ref() {
+            let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+            self.write_checkpoint(&mut file)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Saves step, time, RNG state, positions and velocities at full precision
+    pub fn write_checkpoint<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{}", CHECKPOINT_MAGIC)?;
+        writeln!(out, "step {} time {:.17e} rng {}", self.step, self.time, self.rng.0)?;
+        writeln!(out, "atoms {}", self.molecule.atoms.len())?;
+        for (atom, v) in self.molecule.atoms.iter().zip(&self.velocities) {
+            let p = atom.position;
+            writeln!(out, "{:.17e} {:.17e} {:.17e} {:.17e} {:.17e} {:.17e}", p.x, p.y, p.z, v.x, v.y, v.z)?;
+        }
+        Ok(())
+    }
+
+    /// Restores a checkpoint written for this system; the topology and the drift
+    /// reference stay those of the molecule passed to `new`
+    pub fn restore_checkpoint(&mut self, text: &str) -> Result<(), CheckpointError> {
+        let malformed = |line: usize, reason: &str| CheckpointError::Malformed { line, reason: reason.to_string() };
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l));
+        match lines.next() {
+            Some((_, magic)) if magic.trim() == CHECKPOINT_MAGIC => {}
+            _ => return Err(malformed(1, "missing checkpoint header")),
+        }
+
+        let (n, state) = lines.next().ok_or_else(|| malformed(2, "missing step line"))?;
+        let fields: Vec<&str> = state.split_whitespace().collect();
+        let (step, time, rng) = match fields.as_slice() {
+            ["step", s, "time", t, "rng", r] => (
+                s.parse::<usize>().map_err(|_| malformed(n, "invalid step"))?,
+                t.parse::<f64>().map_err(|_| malformed(n, "invalid time"))?,
+                r.parse::<u64>().map_err(|_| malformed(n, "invalid rng state"))?,
+            ),
+            _ => return Err(malformed(n, "expected 'step <n> time <t> rng <state>'")),
+        };
+
+        let (n, count) = lines.next().ok_or_else(|| malformed(3, "missing atom count"))?;
+        let count = count
+            .strip_prefix("atoms ")
+            .and_then(|c| c.trim().parse::<usize>().ok())
+            .ok_or_else(|| malformed(n, "expected 'atoms <n>'"))?;
+        if count != self.molecule.atoms.len() {
+            return Err(CheckpointError::AtomCountMismatch { expected: self.molecule.atoms.len(), found: count });
+        }
+
+        let mut positions = Vec::with_capacity(count);
+        let mut velocities = Vec::with_capacity(count);
+        let mut last = n;
+        for _ in 0..count {
+            // Like the header lines above, a missing line is reported by the number it should have had
+            let (n, line) = lines.next().ok_or_else(|| malformed(last + 1, &format!("expected {} atom lines, file ends after {}", count, last - 3)))?;
+            last = n;
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>().map_err(|_| malformed(n, "invalid number")))
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.len() != 6 {
+                return Err(malformed(n, "expected x y z vx vy vz"));
+            }
+            positions.push(Vector3::new(values[0], values[1], values[2]));
+            velocities.push(Vector3::new(values[3], values[4], values[5]));
+        }
+
+        for (atom, p) in self.molecule.atoms.iter_mut().zip(positions) {
+            atom.position = p;
+        }
+        self.velocities = velocities;
+        self.step = step;
+        self.time = time;
+        self.rng = Rng(rng.max(1));
+        self.forces = self.compute_forces().0;
+        Ok(())
+    }
+
+    /// Forces (kJ/mol/A) and potential energy terms at the current positions
+    fn compute_forces(&self) -> (Vec<Vector3>, MdEnergies) {
+        let ff = &self.params.force_field;
+        let pos: Vec<Vector3> = self.molecule.atoms.iter().map(|a| a.position).collect();
+        let mut f = vec![Vector3::default(); pos.len()];
+        let mut e = MdEnergies::default();
+
+        for b in &self.topology.bonds {
+            let d = pos[b.i] - pos[b.j];
+            let r = d.norm();
+            if r == 0.0 {
+                continue;
+            }
+            let stretch = r - b.r0;
+            e.bond += 0.5 * ff.bond_k * stretch * stretch;
+            let fi = d * (-ff.bond_k * stretch / r);
+            f[b.i] += fi;
+            f[b.j] += -fi;
+        }
+
+        for a in &self.topology.angles {
+            let (u, v) = (pos[a.i] - pos[a.j], pos[a.k] - pos[a.j]);
+            let (lu, lv) = (u.norm(), v.norm());
+            let cos = (u.dot(&v) / (lu * lv)).clamp(-1.0, 1.0);
+            let theta = cos.acos();
+            let delta = theta - a.theta0;
+            e.angle += 0.5 * ff.angle_k * delta * delta;
+            let sin = (1.0 - cos * cos).sqrt().max(1e-8);
+            // d\theta/dr_i = -(v/(|u||v|) - cos u/|u|^2) / sin, and likewise for r_k
+            let dtheta_i = (v * (1.0 / (lu * lv)) - u * (cos / (lu * lu))) * (-1.0 / sin);
+            let dtheta_k = (u * (1.0 / (lu * lv)) - v * (cos / (lv * lv))) * (-1.0 / sin);
+            let scale = -ff.angle_k * delta;
+            f[a.i] += dtheta_i * scale;
+            f[a.k] += dtheta_k * scale;
+            f[a.j] += (dtheta_i + dtheta_k) * (-scale);
+        }
+
+        for d in &self.topology.dihedrals {
+            let (b1, b2, b3) = (pos[d.j] - pos[d.i], pos[d.k] - pos[d.j], pos[d.l] - pos[d.k]);
+            let (m, n) = (b1.cross(&b2), b2.cross(&b3));
+            let (m2, n2, lb2) = (m.dot(&m), n.dot(&n), b2.norm());
+            if m2 < 1e-12 || n2 < 1e-12 {
+                continue; // collinear atoms leave the torsion undefined
+            }
+            let phi = (lb2 * b1.dot(&n)).atan2(m.dot(&n));
+            let delta = wrap(phi - d.phi0);
+            e.dihedral += 0.5 * ff.dihedral_k * delta * delta;
+            // Gradients of phi (Blondel & Karplus 1996, with b1 = r_j - r_i)
+            let dphi_i = m * (-lb2 / m2);
+            let dphi_l = n * (lb2 / n2);
+            let p = b1.dot(&b2) / (lb2 * lb2);
+            let q = b3.dot(&b2) / (lb2 * lb2);
+            let dphi_j = dphi_l * q - dphi_i * (1.0 + p);
+            let dphi_k = dphi_i * p - dphi_l * (1.0 + q);
+            let scale = -ff.dihedral_k * delta;
+            f[d.i] += dphi_i * scale;
+            f[d.j] += dphi_j * scale;
+            f[d.k] += dphi_k * scale;
+            f[d.l] += dphi_l * scale;
+        }
+
+        e.lennard_jones = self.lennard_jones(&pos, &mut f);
+
+        if let Some(scale) = self.params.gravity_scale {
+            let gravity = RelativisticSolver::atom_forces_direct(&self.molecule);
+            for (fi, g) in f.iter_mut().zip(&gravity.forces) {
+                *fi += *g * (scale * NEWTON_TO_KJ_MOL_A);
+            }
+            e.gravity = scale * gravitational_energy(&self.molecule);
+        }
+        (f, e)
+    }
+
+    /// Shifted 12-6 potential over a cell list with cell edge = cutoff
+    fn lennard_jones(&self, pos: &[Vector3], f: &mut [Vector3]) -> f64 {
+        let ff = &self.params.force_field;
+        let cutoff = ff.lj_cutoff;
+        if cutoff <= 0.0 || ff.lj_epsilon == 0.0 {
+            return 0.0;
+        }
+        let key = |p: Vector3| ((p.x / cutoff).floor() as i64, (p.y / cutoff).floor() as i64, (p.z / cutoff).floor() as i64);
+        let mut cells: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        for (i, &p) in pos.iter().enumerate() {
+            cells.entry(key(p)).or_default().push(i);
+        }
+        // sigma = 2 r_vdW / 2^{1/6}, so the minimum sits at the sum of vdW radii
+        let sigmas: Vec<f64> = self.molecule.atoms.iter().map(|a| 2.0 * a.element.vdw_radius() / 2f64.powf(1.0 / 6.0)).collect();
+
+        let mut energy = 0.0;
+        for i in 0..pos.len() {
+            let (cx, cy, cz) = key(pos[i]);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let Some(members) = cells.get(&(cx + dx, cy + dy, cz + dz)) else {
+                            continue;
+                        };
+                        for &j in members.iter().filter(|&&j| j > i) {
+                            let key = (i, j);
+                            if self.topology.excluded.contains(&key) {
+                                continue;
+                            }
+                            let d = pos[i] - pos[j];
+                            let r2 = d.dot(&d);
+                            if r2 >= cutoff * cutoff || r2 == 0.0 {
+                                continue;
+                            }
+                            let scale = if self.topology.pairs_14.contains(&key) { ff.scale_14 } else { 1.0 };
+                            let sigma = 0.5 * (sigmas[i] + sigmas[j]);
+                            let eps = ff.lj_epsilon * scale;
+                            let lj = |r2: f64| {
+                                let s6 = (sigma * sigma / r2).powi(3);
+                                4.0 * eps * (s6 * s6 - s6)
+                            };
+                            energy += lj(r2) - lj(cutoff * cutoff);
+                            let s6 = (sigma * sigma / r2).powi(3);
+                            let fi = d * (24.0 * eps * (2.0 * s6 * s6 - s6) / r2);
+                            f[i] += fi;
+                            f[j] += -fi;
+                        }
+                    }
+                }
+            }
+        }
+        energy
+    }
+}
+
+/// Newtonian pair energy -\sum G m_i m_j / r_{ij} in kJ/mol
+fn gravitational_energy(molecule: &Biomolecule) -> f64 {
+    let atoms = &molecule.atoms;
+    let mut energy = 0.0;
+    for i in 0..atoms.len() {
+        for j in (i + 1)..atoms.len() {
+            let r = atoms[i].position.dist(&atoms[j].position) * ANGSTROM_TO_M;
+            if r > 0.0 {
+                energy -= G_CONSTANT * atoms[i].mass * atoms[j].mass * DALTON_TO_KG * DALTON_TO_KG / r;
+            }
+        }
+    }
+    energy * AVOGADRO * 1e-3
+}
+
+fn remove_net_momentum(molecule: &Biomolecule, velocities: &mut [Vector3]) {
+    let total_mass: f64 = molecule.atoms.iter().map(|a| a.mass).sum();
+    if total_mass <= 0.0 {
+        return;
+    }
+    let momentum = molecule.atoms.iter().zip(velocities.iter()).fold(Vector3::default(), |acc, (a, v)| acc + *v * a.mass);
+    let drift = momentum * (1.0 / total_mass);
+    for v in velocities.iter_mut() {
+        *v += -drift;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna_builder::{DnaBuilder, HelixForm};
+    use crate::element::Element;
+
+    /// Two-base-pair duplex with every atom nudged off its equilibrium, so all terms carry force
+    fn strained(params: MdParameters) -> MdSimulation {
+        let molecule = DnaBuilder::new("AC", HelixForm::B).expect("valid sequence").build();
+        let mut sim = MdSimulation::new(molecule, params);
+        let mut rng = Rng::new(7);
+        for atom in &mut sim.molecule.atoms {
+            atom.position += rng.gaussian_vector() * 0.05;
+        }
+        sim.forces = sim.compute_forces().0;
+        sim
+    }
+
+    #[test]
+    fn chain_topology_has_one_of_each_term() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, 0.0, 0.0, 0.0);
+        molecule.add_element(Element::C, 1.5, 0.0, 0.0);
+        molecule.add_element(Element::C, 2.0, 1.4, 0.0);
+        molecule.add_element(Element::C, 3.5, 1.4, 0.5);
+        let bonds: Vec<Bond> = (0..3).map(|i| Bond { i, j: i + 1, length: 1.5 }).collect();
+        let topology = Topology::from_bonds(&molecule, &bonds);
+        assert_eq!((topology.bonds.len(), topology.angles.len(), topology.dihedrals.len()), (3, 2, 1));
+        assert!(topology.excluded.contains(&(0, 2)) && topology.excluded.contains(&(1, 3)));
+        assert!(!topology.excluded.contains(&(0, 3)));
+        assert!(topology.pairs_14.contains(&(0, 3)));
+        let d = topology.dihedrals[0];
+        assert!((d.phi0 - dihedral(molecule.atoms[0].position, molecule.atoms[1].position, molecule.atoms[2].position, molecule.atoms[3].position)).abs() < 1e-15);
+    }
+
+    #[test]
+    fn forces_are_minus_the_energy_gradient() {
+        // Scaled gravity so it contributes at the same order as the force field
+        let params = MdParameters { gravity_scale: Some(1e31), ..MdParameters::default() };
+        let mut sim = strained(params);
+        let forces = sim.forces().to_vec();
+        let largest = forces.iter().map(|f| f.norm()).fold(0.0, f64::max);
+        assert!(sim.energies().gravity.abs() > 1e-2, "{:?}", sim.energies());
+
+        let h = 1e-5;
+        for i in (0..sim.molecule.atoms.len()).step_by(3) {
+            for axis in 0..3 {
+                let shift = |sim: &mut MdSimulation, by: f64| {
+                    let p = &mut sim.molecule.atoms[i].position;
+                    match axis {
+                        0 => p.x += by,
+                        1 => p.y += by,
+                        _ => p.z += by,
+                    }
+                };
+                shift(&mut sim, h);
+                let up = sim.energies().potential();
+                shift(&mut sim, -2.0 * h);
+                let down = sim.energies().potential();
+                shift(&mut sim, h);
+                let numeric = -(up - down) / (2.0 * h);
+                let analytic = [forces[i].x, forces[i].y, forces[i].z][axis];
+                assert!((numeric - analytic).abs() < 1e-5 * largest, "atom {} axis {}: {} vs {}", i, axis, numeric, analytic);
+            }
+        }
+    }
+
+    /// Largest excursion of the total energy over 0.1 ps of unthermostatted dynamics
+    fn energy_error(time_step: f64) -> f64 {
+        let params = MdParameters { friction: 0.0, time_step, ..MdParameters::default() };
+        let mut sim = strained(params);
+        let start = sim.energies().total();
+        let mut worst: f64 = 0.0;
+        for _ in 0..(0.1 / time_step).round() as usize {
+            sim.step();
+            worst = worst.max((sim.energies().total() - start).abs());
+        }
+        worst
+    }
+
+    #[test]
+    fn velocity_verlet_energy_error_is_second_order() {
+        let (coarse, fine) = (energy_error(0.0005), energy_error(0.00025));
+        assert!(coarse < 5.0, "{} kJ/mol", coarse); // against about 500 kJ/mol of kinetic energy
+        assert!(fine < coarse / 3.0, "{} then {}", coarse, fine);
+    }
+
+    #[test]
+    fn initial_velocities_carry_no_momentum() {
+        let sim = strained(MdParameters::default());
+        let momentum = sim.molecule.atoms.iter().zip(&sim.velocities).fold(Vector3::default(), |acc, (a, v)| acc + *v * a.mass);
+        assert!(momentum.norm() < 1e-9, "{:?}", momentum);
+        assert!(sim.temperature() > 0.0);
+    }
+
+    #[test]
+    fn restored_checkpoint_continues_bit_identically() {
+        let mut sim = strained(MdParameters::default());
+        for _ in 0..5 {
+            sim.step();
+        }
+        let mut saved = Vec::new();
+        sim.write_checkpoint(&mut saved).expect("write to memory");
+        for _ in 0..5 {
+            sim.step();
+        }
+
+        let mut resumed = strained(MdParameters::default());
+        resumed.restore_checkpoint(std::str::from_utf8(&saved).expect("utf-8")).expect("valid checkpoint");
+        assert_eq!(resumed.step_count(), 5);
+        for _ in 0..5 {
+            resumed.step();
+        }
+        for (a, b) in sim.molecule.atoms.iter().zip(&resumed.molecule.atoms) {
+            assert_eq!(a.position, b.position);
+        }
+        assert_eq!(sim.velocities, resumed.velocities);
+        assert_eq!(sim.time(), resumed.time());
+    }
+
+    #[test]
+    fn checkpoint_for_another_system_is_rejected() {
+        let mut sim = strained(MdParameters::default());
+        let text = format!("{}\nstep 0 time 0 rng 1\natoms 2\n", CHECKPOINT_MAGIC);
+        assert!(matches!(sim.restore_checkpoint(&text), Err(CheckpointError::AtomCountMismatch { found: 2, .. })));
+        assert!(matches!(sim.restore_checkpoint("not a checkpoint"), Err(CheckpointError::Malformed { line: 1, .. })));
+    }
+}
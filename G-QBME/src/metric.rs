@@ -0,0 +1,234 @@
+use crate::bonds::Bond;
+use crate::gqbme_engine::{Biomolecule, RelativisticSolver, Vector3, SPEED_OF_LIGHT};
+use crate::linalg::symmetric_eigen;
+use crate::poisson::PotentialGrid;
+
+/// Weak-field metric ds^2 = -(1+2\epsilon)c^2dt^2 + (1-2\epsilon)d\mathbf{x}^2 with \epsilon = \Phi/c^2
+/// Molecular potentials give \epsilon ~ 1e-44, far below f64 resolution around 1, so the
+/// `*_offset` methods return deviations from flat space computed without cancellation.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinearizedMetric {
+    pub epsilon: f64,
+}
+
+impl LinearizedMetric {
+    /// Metric for a potential \Phi in J/kg
+    pub fn from_potential(phi: f64) -> Self {
+        Self { epsilon: phi / (SPEED_OF_LIGHT * SPEED_OF_LIGHT) }
+    }
+
+    /// g_{\mu\nu} in (ct, x, y, z) coordinates
+    pub fn components(&self) -> [[f64; 4]; 4] {
+        let mut g = [[0.0; 4]; 4];
+        g[0][0] = -(1.0 + 2.0 * self.epsilon);
+        for (a, row) in g.iter_mut().enumerate().skip(1) {
+            row[a] = 1.0 - 2.0 * self.epsilon;
+        }
+        g
+    }
+
+    /// Clock rate d\tau/dt = \sqrt{-g_{tt}}
+    pub fn time_dilation(&self) -> f64 {
+        (1.0 + 2.0 * self.epsilon).sqrt()
+    }
+
+    /// d\tau/dt - 1, negative in a potential well
+    pub fn time_dilation_offset(&self) -> f64 {
+        sqrt_one_plus_minus_one(2.0 * self.epsilon)
+    }
+
+    /// Proper length per coordinate length, \sqrt{g_{xx}}
+    pub fn spatial_scale(&self) -> f64 {
+        (1.0 - 2.0 * self.epsilon).sqrt()
+    }
+
+    /// \sqrt{g_{xx}} - 1, positive in a potential well
+    pub fn spatial_scale_offset(&self) -> f64 {
+        sqrt_one_plus_minus_one(-2.0 * self.epsilon)
+    }
+}
+
+/// \sqrt{1+x} - 1 without losing small x
+fn sqrt_one_plus_minus_one(x: f64) -> f64 {
+    x / ((1.0 + x).sqrt() + 1.0)
+}
+
+/// Coordinate and proper length of a path, in Angstroms
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProperLength {
+    pub coordinate: f64,
+    pub offset: f64, // proper minus coordinate length
+}
+
+impl ProperLength {
+    pub fn proper(&self) -> f64 {
+        self.coordinate + self.offset
+    }
+}
+
+/// Proper length of one bonded pair
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BondProperLength {
+    pub bond: Bond,
+    pub length: ProperLength,
+}
+
+/// Straight line fitted through the atoms, spanning their projections
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HelixAxis {
+    pub center: Vector3,    // centre of mass, Angstroms
+    pub direction: Vector3, // unit vector of largest positional variance
+    pub start: f64,         // lowest projection onto `direction`, Angstroms from `center`
+    pub end: f64,           // highest projection
+}
+
+impl HelixAxis {
+    /// Principal axis of the mass-weighted positions; `None` for fewer than two atoms
+    pub fn fit(molecule: &Biomolecule) -> Option<Self> {
+        if molecule.atoms.len() < 2 {
+            return None;
+        }
+        let center = molecule.center_of_mass();
+        let mut cov = [[0.0; 3]; 3];
+        for atom in &molecule.atoms {
+            let r = atom.position - center;
+            let r = [r.x, r.y, r.z];
+            for a in 0..3 {
+                for b in 0..3 {
+                    cov[a][b] += atom.mass * r[a] * r[b];
+                }
+            }
+        }
+
+        let eigen = symmetric_eigen(&cov.map(|row| row.to_vec()));
+        if eigen.values[2] <= 0.0 {
+            return None;
+        }
+        let v = &eigen.vectors[2];
+        let direction = Vector3::new(v[0], v[1], v[2]);
+
+        let (start, end) = molecule.atoms.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), atom| {
+            let t = (atom.position - center).dot(&direction);
+            (lo.min(t), hi.max(t))
+        });
+        Some(Self { center, direction, start, end })
+    }
+
+    pub fn point(&self, t: f64) -> Vector3 {
+        self.center + self.direction * t
+    }
+}
+
+/// \int \sqrt{g_{xx}} |d\mathbf{x}| along the segment a -> b with composite Simpson's rule,
+/// using at least one panel per voxel; `None` if the segment leaves the grid
+fn proper_segment(a: Vector3, b: Vector3, potential: &PotentialGrid) -> Option<ProperLength> {
+    let coordinate = a.dist(&b);
+    let panels = ((coordinate / potential.resolution).ceil() as usize).max(1) * 2;
+    let step = coordinate / panels as f64;
+    let mut offset = 0.0;
+    for k in 0..=panels {
+        let point = a + (b - a) * (k as f64 / panels as f64);
+        let weight = if k == 0 || k == panels { 1.0 } else if k % 2 == 1 { 4.0 } else { 2.0 };
+        offset += weight * LinearizedMetric::from_potential(potential.sample(&point)?).spatial_scale_offset();
+    }
+    Some(ProperLength { coordinate, offset: offset * step / 3.0 })
+}
+
+impl RelativisticSolver {
+    /// Metric at a point from the interpolated potential; `None` outside the grid
+    pub fn metric_at(position: &Vector3, potential: &PotentialGrid) -> Option<LinearizedMetric> {
+        potential.sample(position).map(LinearizedMetric::from_potential)
+    }
+
+    /// d\tau/dt - 1 for every atom, i.e. how much slower its clock runs than one at \Phi = 0
+    pub fn proper_time_offsets(molecule: &Biomolecule, potential: &PotentialGrid) -> Option<Vec<f64>> {
+        molecule
+            .atoms
+            .iter()
+            .map(|atom| Self::metric_at(&atom.position, potential).map(|g| g.time_dilation_offset()))
+            .collect()
+    }
+
+    /// Proper length of each bond, integrated through the potential along the bond vector
+    pub fn proper_bond_lengths(molecule: &Biomolecule, bonds: &[Bond], potential: &PotentialGrid) -> Option<Vec<BondProperLength>> {
+        bonds
+            .iter()
+            .map(|&bond| {
+                let length = proper_segment(molecule.atoms[bond.i].position, molecule.atoms[bond.j].position, potential)?;
+                Some(BondProperLength { bond, length })
+            })
+            .collect()
+    }
+
+    /// Proper length of the fitted helix axis from the first to the last atom projection
+    pub fn proper_axis_length(molecule: &Biomolecule, potential: &PotentialGrid) -> Option<ProperLength> {
+        let axis = HelixAxis::fit(molecule)?;
+        proper_segment(axis.point(axis.start), axis.point(axis.end), potential)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::Element;
+
+    #[test]
+    fn offsets_match_the_closed_forms_and_survive_tiny_potentials() {
+        let g = LinearizedMetric { epsilon: -1e-3 };
+        assert!((g.time_dilation_offset() - (g.time_dilation() - 1.0)).abs() < 1e-15);
+        assert!((g.spatial_scale_offset() - (g.spatial_scale() - 1.0)).abs() < 1e-15);
+        let c = g.components();
+        assert_eq!(c[0][0], -(1.0 - 2e-3));
+        assert_eq!(c[3][3], 1.0 + 2e-3);
+        assert_eq!(c[0][1], 0.0);
+
+        // Molecular scale: the plain forms round to exactly 1, the offsets keep \epsilon
+        let g = LinearizedMetric::from_potential(-1e-27);
+        assert_eq!(g.time_dilation(), 1.0);
+        assert!((g.time_dilation_offset() / g.epsilon - 1.0).abs() < 1e-12);
+        assert!((g.spatial_scale_offset() / g.epsilon + 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn axis_fit_finds_the_top_eigenvector_off_the_coordinate_axes() {
+        // Covariance [[2, 0, 0], [0, 1.9, 1.9], [0, 1.9, 1.9]] per unit mass: x holds the largest
+        // diagonal entry and is an eigenvector, but (0, 1, 1)/\sqrt2 carries 3.8
+        let t = 0.95f64.sqrt();
+        let mut molecule = Biomolecule::new();
+        for (x, y, z) in [(1.0, 0.0, 0.0), (-1.0, 0.0, 0.0), (0.0, t, t), (0.0, -t, -t)] {
+            molecule.add_element(Element::C, x, y, z);
+        }
+        let axis = HelixAxis::fit(&molecule).unwrap();
+        let expected = Vector3::new(0.0, 1.0, 1.0) * (0.5f64).sqrt();
+        assert!((axis.direction.dot(&expected).abs() - 1.0).abs() < 1e-12, "{:?}", axis.direction);
+        assert!((axis.end - axis.start - 2.0 * (2.0 * 0.95f64).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn axis_fit_needs_two_distinct_positions() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, 1.0, 2.0, 3.0);
+        assert!(HelixAxis::fit(&molecule).is_none());
+        molecule.add_element(Element::N, 1.0, 2.0, 3.0);
+        assert!(HelixAxis::fit(&molecule).is_none());
+    }
+
+    #[test]
+    fn proper_length_in_a_uniform_potential_scales_the_coordinate_length() {
+        let phi = -1e-20;
+        let potential = PotentialGrid { origin: Vector3::new(0.0, 0.0, 0.0), resolution: 1.0, dimensions: (6, 6, 6), data: vec![phi; 216] };
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, 1.0, 1.0, 1.0);
+        molecule.add_element(Element::C, 4.0, 3.0, 1.0);
+        let length = RelativisticSolver::proper_axis_length(&molecule, &potential).unwrap();
+        let coordinate = 13.0f64.sqrt();
+        assert!((length.coordinate - coordinate).abs() < 1e-12);
+        let expected = coordinate * LinearizedMetric::from_potential(phi).spatial_scale_offset();
+        assert!((length.offset / expected - 1.0).abs() < 1e-12, "{} vs {}", length.offset, expected);
+
+        let offsets = RelativisticSolver::proper_time_offsets(&molecule, &potential).unwrap();
+        assert!(offsets.iter().all(|&o| (o / LinearizedMetric::from_potential(phi).time_dilation_offset() - 1.0).abs() < 1e-12));
+        molecule.add_element(Element::C, 9.0, 1.0, 1.0);
+        assert!(RelativisticSolver::proper_time_offsets(&molecule, &potential).is_none());
+    }
+}
@@ -0,0 +1,458 @@
+use crate::density_grid::DensityGrid;
+use crate::gqbme_engine::{check_lattice, MassDensityGrid, Vector3};
+use crate::poisson::PotentialGrid;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// MRC2014 main header length in bytes
+const HEADER_LEN: usize = 1024;
+
+const DENSITY_LABEL: &str = "G-QBME mass density (Da per voxel)";
+
+/// Errors raised while reading MRC/CCP4 maps
+#[derive(Debug)]
+pub enum MrcError {
+    Io(std::io::Error),
+    /// The file ends before the header or voxel data is complete
+    Truncated { expected: usize, found: usize },
+    /// Only modes 0 (int8), 1 (int16), 2 (float32) and 6 (uint16) are read
+    UnsupportedMode(i32),
+    /// A header field is out of range
+    InvalidHeader(String),
+    /// `MassDensityGrid` needs cubic voxels
+    AnisotropicVoxels { x: f64, y: f64, z: f64 },
+}
+
+impl fmt::Display for MrcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MrcError::Io(err) => write!(f, "I/O error: {}", err),
+            MrcError::Truncated { expected, found } => {
+                write!(f, "map truncated: expected {} bytes, found {}", expected, found)
+            }
+            MrcError::UnsupportedMode(mode) => write!(f, "unsupported MRC data mode {}", mode),
+            MrcError::InvalidHeader(reason) => write!(f, "invalid MRC header ({})", reason),
+            MrcError::AnisotropicVoxels { x, y, z } => {
+                write!(f, "voxels are not cubic ({:.4} x {:.4} x {:.4} A)", x, y, z)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MrcError {}
+
+impl From<std::io::Error> for MrcError {
+    fn from(err: std::io::Error) -> Self {
+        MrcError::Io(err)
+    }
+}
+
+/// Lattice shared by density and potential grids, in our x-major layout
+struct Volume {
+    origin: Vector3,
+    resolution: f64,
+    dimensions: (usize, usize, usize),
+    data: Vec<f64>,
+}
+
+/// Reads 4-byte header words in the file's byte order
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+}
+
+impl HeaderReader<'_> {
+    fn word(&self, index: usize) -> [u8; 4] {
+        let mut w = [0u8; 4];
+        w.copy_from_slice(&self.bytes[4 * index..4 * index + 4]);
+        w
+    }
+
+    fn int(&self, index: usize) -> i32 {
+        if self.little_endian { i32::from_le_bytes(self.word(index)) } else { i32::from_be_bytes(self.word(index)) }
+    }
+
+    fn float(&self, index: usize) -> f64 {
+        let v = if self.little_endian { f32::from_le_bytes(self.word(index)) } else { f32::from_be_bytes(self.word(index)) };
+        v as f64
+    }
+}
+
+fn parse_volume(bytes: &[u8]) -> Result<Volume, MrcError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(MrcError::Truncated { expected: HEADER_LEN, found: bytes.len() });
+    }
+    // MACHST: 0x44 0x44 (or 0x44 0x41) is little-endian, 0x11 0x11 big-endian. Old
+    // files leave it blank, so fall back to whichever byte order gives a sane mode.
+    let little_endian = match bytes[212] {
+        0x44 => true,
+        0x11 => false,
+        _ => (0..=16).contains(&i32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]])),
+    };
+    let h = HeaderReader { bytes, little_endian };
+
+    let counts = [h.int(0), h.int(1), h.int(2)];
+    if counts.iter().any(|&n| n <= 0) {
+        return Err(MrcError::InvalidHeader(format!("non-positive dimensions {:?}", counts)));
+    }
+    let counts = counts.map(|n| n as usize);
+    let mode = h.int(3);
+    let starts = [h.int(4), h.int(5), h.int(6)];
+    let sampling = [h.int(7), h.int(8), h.int(9)];
+    let cell = [h.float(10), h.float(11), h.float(12)];
+
+    // MAPC/MAPR/MAPS give the spatial axis (1 = X) of columns, rows and sections
+    let mut axes = [h.int(16), h.int(17), h.int(18)];
+    if axes == [0, 0, 0] {
+        axes = [1, 2, 3];
+    }
+    let mut sorted = axes;
+    sorted.sort_unstable();
+    if sorted != [1, 2, 3] {
+        return Err(MrcError::InvalidHeader(format!("axis order {:?} is not a permutation of 1, 2, 3", axes)));
+    }
+    let axes = axes.map(|a| (a - 1) as usize);
+
+    let mut dims_xyz = [0usize; 3];
+    let mut start_xyz = [0i32; 3];
+    for file_axis in 0..3 {
+        dims_xyz[axes[file_axis]] = counts[file_axis];
+        start_xyz[axes[file_axis]] = starts[file_axis];
+    }
+
+    // MX/MY/MZ and the cell lengths are already in X, Y, Z order
+    let mut voxel = [0.0; 3];
+    for a in 0..3 {
+        let m = if sampling[a] > 0 { sampling[a] as f64 } else { dims_xyz[a] as f64 };
+        voxel[a] = if cell[a] > 0.0 { cell[a] / m } else { 1.0 };
+    }
+    let resolution = voxel[0];
+    if voxel.iter().any(|&v| ((v - resolution) / resolution).abs() > 1e-4) {
+        return Err(MrcError::AnisotropicVoxels { x: voxel[0], y: voxel[1], z: voxel[2] });
+    }
+
+    // Follow ChimeraX: a non-zero ORIGIN wins, otherwise the start indices place the map
+    let origin_field = [h.float(49), h.float(50), h.float(51)];
+    let origin = if origin_field.iter().any(|&o| o != 0.0) {
+        Vector3::new(origin_field[0], origin_field[1], origin_field[2])
+    } else {
+        Vector3::new(start_xyz[0] as f64 * voxel[0], start_xyz[1] as f64 * voxel[1], start_xyz[2] as f64 * voxel[2])
+    };
+
+    let value_size = match mode {
+        0 => 1,
+        1 | 6 => 2,
+        2 => 4,
+        other => return Err(MrcError::UnsupportedMode(other)),
+    };
+    let extended = h.int(23).max(0) as usize;
+    let offset = HEADER_LEN + extended;
+    let too_large = || MrcError::InvalidHeader(format!("dimensions {:?} with {} extended header bytes overflow the address space", counts, extended));
+    let count = counts[0].checked_mul(counts[1]).and_then(|n| n.checked_mul(counts[2])).ok_or_else(too_large)?;
+    let expected = count.checked_mul(value_size).and_then(|n| n.checked_add(offset)).ok_or_else(too_large)?;
+    if bytes.len() < expected {
+        return Err(MrcError::Truncated { expected, found: bytes.len() });
+    }
+
+    let value = |i: usize| -> f64 {
+        let b = &bytes[offset + i * value_size..offset + (i + 1) * value_size];
+        match (mode, little_endian) {
+            (0, _) => b[0] as i8 as f64,
+            (1, true) => i16::from_le_bytes([b[0], b[1]]) as f64,
+            (1, false) => i16::from_be_bytes([b[0], b[1]]) as f64,
+            (6, true) => u16::from_le_bytes([b[0], b[1]]) as f64,
+            (6, false) => u16::from_be_bytes([b[0], b[1]]) as f64,
+            (_, true) => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            (_, false) => f32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        }
+    };
+
+    let (ny, nz) = (dims_xyz[1], dims_xyz[2]);
+    let mut data = vec![0.0; count];
+    let mut i = 0;
+    for s in 0..counts[2] {
+        for r in 0..counts[1] {
+            for c in 0..counts[0] {
+                let mut xyz = [0usize; 3];
+                xyz[axes[0]] = c;
+                xyz[axes[1]] = r;
+                xyz[axes[2]] = s;
+                data[(xyz[0] * ny + xyz[1]) * nz + xyz[2]] = value(i);
+                i += 1;
+            }
+        }
+    }
+
+    Ok(Volume {
+        origin,
+        resolution,
+        dimensions: (dims_xyz[0], dims_xyz[1], dims_xyz[2]),
+        data,
+    })
+}
+
+/// Writes a little-endian MRC2014 float32 map with X as the fastest axis
+fn write_volume<W: Write>(out: &mut W, origin: Vector3, resolution: f64, dims: (usize, usize, usize), data: &[f64], label: &str) -> io::Result<()> {
+    let (min, max, sum, sum_sq) = data.iter().fold((f64::INFINITY, f64::NEG_INFINITY, 0.0, 0.0), |(lo, hi, s, s2), &v| (lo.min(v), hi.max(v), s + v, s2 + v * v));
+    let stats = VolumeStats { min, max, sum, sum_sq, count: data.len() };
+    let (_, ny, nz) = dims;
+    write_volume_with(out, origin, resolution, dims, &stats, &|x, y, z| data[(x * ny + y) * nz + z], label)
+}
+
+/// Value range and moments of a volume, for the MRC header
+struct VolumeStats {
+    min: f64,
+    max: f64,
+    sum: f64,
+    sum_sq: f64,
+    count: usize,
+}
+
+/// Streams a density grid of any backend; stats come from its non-zero voxels plus the implicit zeros
+pub(crate) fn write_density<G: DensityGrid + ?Sized>(out: &mut dyn Write, grid: &G) -> io::Result<()> {
+    let count = grid.voxel_count();
+    let (mut min, mut max, mut sum, mut sum_sq, mut nonzero) = (f64::INFINITY, f64::NEG_INFINITY, 0.0, 0.0, 0usize);
+    grid.for_each_nonzero(&mut |_, v| {
+        min = min.min(v);
+        max = max.max(v);
+        sum += v;
+        sum_sq += v * v;
+        nonzero += 1;
+    });
+    if nonzero < count {
+        min = min.min(0.0);
+        max = max.max(0.0);
+    }
+    let stats = VolumeStats { min, max, sum, sum_sq, count };
+    write_volume_with(out, grid.origin(), grid.resolution(), grid.dimensions(), &stats, &|x, y, z| grid.mass_at(x, y, z), DENSITY_LABEL)
+}
+
+fn write_volume_with<W: Write + ?Sized>(
+    out: &mut W,
+    origin: Vector3,
+    resolution: f64,
+    dims: (usize, usize, usize),
+    stats: &VolumeStats,
+    value: &dyn Fn(usize, usize, usize) -> f64,
+    label: &str,
+) -> io::Result<()> {
+    let (nx, ny, nz) = dims;
+    let too_large = |n: usize| i32::try_from(n).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "grid too large for MRC"));
+    let counts = [too_large(nx)?, too_large(ny)?, too_large(nz)?];
+
+    let n = stats.count.max(1) as f64;
+    let mean = stats.sum / n;
+    let rms = (stats.sum_sq / n - mean * mean).max(0.0).sqrt();
+    let (min, max) = if stats.count == 0 { (0.0, 0.0) } else { (stats.min, stats.max) };
+
+    let mut header = [0u8; HEADER_LEN];
+    let mut put_int = |index: usize, v: i32| header[4 * index..4 * index + 4].copy_from_slice(&v.to_le_bytes());
+    for (a, &n) in counts.iter().enumerate() {
+        put_int(a, n); // NX, NY, NZ
+        put_int(7 + a, n); // MX, MY, MZ
+        put_int(16 + a, a as i32 + 1); // MAPC, MAPR, MAPS
+    }
+    put_int(3, 2); // float32
+    put_int(22, 1); // ISPG: a single volume
+    put_int(27, 20140); // NVERSION
+    put_int(55, 1); // NLABL
+    let mut put_float = |index: usize, v: f64| header[4 * index..4 * index + 4].copy_from_slice(&(v as f32).to_le_bytes());
+    for (a, &n) in counts.iter().enumerate() {
+        put_float(10 + a, n as f64 * resolution);
+        put_float(13 + a, 90.0);
+    }
+    put_float(19, min);
+    put_float(20, max);
+    put_float(21, mean);
+    put_float(49, origin.x);
+    put_float(50, origin.y);
+    put_float(51, origin.z);
+    put_float(54, rms);
+    header[208..212].copy_from_slice(b"MAP ");
+    header[212..216].copy_from_slice(&[0x44, 0x44, 0x00, 0x00]);
+    let label = label.as_bytes();
+    let len = label.len().min(80);
+    header[224..224 + len].copy_from_slice(&label[..len]);
+    out.write_all(&header)?;
+
+    let mut row = Vec::with_capacity(4 * nx);
+    for z in 0..nz {
+        for y in 0..ny {
+            row.clear();
+            for x in 0..nx {
+                row.extend_from_slice(&(value(x, y, z) as f32).to_le_bytes());
+            }
+            out.write_all(&row)?;
+        }
+    }
+    Ok(())
+}
+
+impl MassDensityGrid {
+    /// Parses an MRC/CCP4 map; voxel values are taken as-is (Daltons for our own maps)
+    pub fn from_mrc_bytes(bytes: &[u8]) -> Result<Self, MrcError> {
+        let v = parse_volume(bytes)?;
+        let mut grid = Self::try_new(v.origin, v.resolution, v.dimensions).map_err(|e| MrcError::InvalidHeader(e.to_string()))?;
+        grid.data = v.data;
+        Ok(grid)
+    }
+
+    pub fn read_mrc<P: AsRef<Path>>(path: P) -> Result<Self, MrcError> {
+        Self::from_mrc_bytes(&fs::read(path)?)
+    }
+
+    /// Writes the grid as a float32 MRC2014 map; ORIGIN is the centre of voxel (0, 0, 0)
+    pub fn write_mrc<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_volume(out, self.origin, self.resolution, self.dimensions, &self.data, DENSITY_LABEL)
+    }
+
+    pub fn save_mrc<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        self.write_mrc(&mut out)?;
+        out.flush()
+    }
+}
+
+impl PotentialGrid {
+    pub fn from_mrc_bytes(bytes: &[u8]) -> Result<Self, MrcError> {
+        let v = parse_volume(bytes)?;
+        check_lattice(v.origin, v.resolution, v.dimensions).map_err(|e| MrcError::InvalidHeader(e.to_string()))?;
+        Ok(Self {
+            origin: v.origin,
+            resolution: v.resolution,
+            dimensions: v.dimensions,
+            data: v.data,
+        })
+    }
+
+    pub fn read_mrc<P: AsRef<Path>>(path: P) -> Result<Self, MrcError> {
+        Self::from_mrc_bytes(&fs::read(path)?)
+    }
+
+    /// Writes \Phi (J/kg) as a float32 MRC2014 map on the same lattice as its density
+    pub fn write_mrc<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_volume(out, self.origin, self.resolution, self.dimensions, &self.data, "G-QBME gravitational potential (J/kg)")
+    }
+
+    pub fn save_mrc<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        self.write_mrc(&mut out)?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_grid() -> MassDensityGrid {
+        let mut grid = MassDensityGrid::new(Vector3::new(-3.5, 2.0, 10.25), 0.5, (3, 4, 5));
+        for (i, v) in grid.data.iter_mut().enumerate() {
+            *v = (i as f64 * 0.37).sin() * 12.5;
+        }
+        grid
+    }
+
+    /// Bare little-endian header: dimensions, mode and 1 A voxels
+    fn header(counts: [i32; 3], mode: i32) -> Vec<u8> {
+        let mut bytes = vec![0u8; HEADER_LEN];
+        let mut put = |index: usize, v: [u8; 4]| bytes[4 * index..4 * index + 4].copy_from_slice(&v);
+        for (a, &n) in counts.iter().enumerate() {
+            put(a, n.to_le_bytes());
+            put(7 + a, n.to_le_bytes());
+            put(10 + a, (n as f32).to_le_bytes());
+            put(16 + a, (a as i32 + 1).to_le_bytes());
+        }
+        put(3, mode.to_le_bytes());
+        bytes[212] = 0x44;
+        bytes
+    }
+
+    #[test]
+    fn density_round_trips_through_float32() {
+        let grid = sample_grid();
+        let mut bytes = Vec::new();
+        grid.write_mrc(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + 4 * grid.data.len());
+
+        let back = MassDensityGrid::from_mrc_bytes(&bytes).unwrap();
+        assert_eq!(back.dimensions, grid.dimensions);
+        assert_eq!(back.resolution, grid.resolution);
+        assert_eq!((back.origin.x, back.origin.y, back.origin.z), (grid.origin.x, grid.origin.y, grid.origin.z));
+        for (a, b) in grid.data.iter().zip(&back.data) {
+            assert_eq!(*a as f32 as f64, *b);
+        }
+    }
+
+    #[test]
+    fn potential_round_trips_through_float32() {
+        let grid = sample_grid();
+        let potential = PotentialGrid { origin: grid.origin, resolution: grid.resolution, dimensions: grid.dimensions, data: grid.data.iter().map(|v| -1e-20 * v).collect() };
+        let mut bytes = Vec::new();
+        potential.write_mrc(&mut bytes).unwrap();
+        let back = PotentialGrid::from_mrc_bytes(&bytes).unwrap();
+        assert_eq!(back.dimensions, potential.dimensions);
+        for (a, b) in potential.data.iter().zip(&back.data) {
+            assert_eq!(*a as f32 as f64, *b);
+        }
+    }
+
+    #[test]
+    fn reads_permuted_axes_and_int16_voxels() {
+        // Columns along Z, rows along X, sections along Y
+        let mut bytes = header([2, 3, 4], 1);
+        for (a, axis) in [3i32, 1, 2].iter().enumerate() {
+            bytes[4 * (16 + a)..4 * (16 + a) + 4].copy_from_slice(&axis.to_le_bytes());
+        }
+        // Sampling and cell are in X, Y, Z order: 3, 4, 2 voxels
+        for (a, n) in [3i32, 4, 2].iter().enumerate() {
+            bytes[4 * (7 + a)..4 * (7 + a) + 4].copy_from_slice(&n.to_le_bytes());
+            bytes[4 * (10 + a)..4 * (10 + a) + 4].copy_from_slice(&(*n as f32).to_le_bytes());
+        }
+        for i in 0..24i16 {
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+        let grid = MassDensityGrid::from_mrc_bytes(&bytes).unwrap();
+        assert_eq!(grid.dimensions, (3, 4, 2));
+        // File value i sits at column c = i % 2 (Z), row r = i / 2 % 3 (X), section s = i / 6 (Y)
+        for i in 0..24usize {
+            let (z, x, y) = (i % 2, i / 2 % 3, i / 6);
+            assert_eq!(grid.data[grid.get_index(x, y, z)], i as f64);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_headers() {
+        assert!(matches!(MassDensityGrid::from_mrc_bytes(&[0u8; 100]), Err(MrcError::Truncated { expected: HEADER_LEN, found: 100 })));
+
+        let bytes = header([2, 2, 2], 2);
+        assert!(matches!(MassDensityGrid::from_mrc_bytes(&bytes), Err(MrcError::Truncated { found: HEADER_LEN, .. })));
+        assert!(matches!(MassDensityGrid::from_mrc_bytes(&header([2, 0, 2], 2)), Err(MrcError::InvalidHeader(_))));
+        assert!(matches!(MassDensityGrid::from_mrc_bytes(&header([2, 2, 2], 3)), Err(MrcError::UnsupportedMode(3))));
+
+        let mut axes = header([1, 1, 1], 0);
+        axes[4 * 16..4 * 16 + 4].copy_from_slice(&2i32.to_le_bytes());
+        axes.push(0);
+        assert!(matches!(MassDensityGrid::from_mrc_bytes(&axes), Err(MrcError::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn rejects_dimensions_that_overflow_the_voxel_count() {
+        for mode in [0, 2] {
+            let bytes = header([1 << 30, 1 << 30, 16], mode);
+            assert!(matches!(MassDensityGrid::from_mrc_bytes(&bytes), Err(MrcError::InvalidHeader(_))), "mode {}", mode);
+            assert!(matches!(PotentialGrid::from_mrc_bytes(&bytes), Err(MrcError::InvalidHeader(_))), "mode {}", mode);
+        }
+    }
+
+    #[test]
+    fn rejects_a_non_finite_origin() {
+        let mut bytes = header([1, 1, 1], 2);
+        bytes[4 * 49..4 * 49 + 4].copy_from_slice(&f32::NAN.to_le_bytes());
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        assert!(matches!(MassDensityGrid::from_mrc_bytes(&bytes), Err(MrcError::InvalidHeader(_))));
+        assert!(matches!(PotentialGrid::from_mrc_bytes(&bytes), Err(MrcError::InvalidHeader(_))));
+    }
+}
@@ -0,0 +1,396 @@
+use crate::density_grid::DensityGrid;
+use crate::gqbme_engine::{Biomolecule, Vector3, ANGSTROM_TO_M, DALTON_TO_KG, G_CONSTANT};
+
+const LEAF_SIZE: usize = 8;
+const MAX_DEPTH: usize = 40;
+
+/// Highest multipole kept per octree node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MultipoleOrder {
+    Monopole,
+    #[default]
+    Quadrupole,
+}
+
+/// A point mass (position in Angstroms, mass in Daltons)
+#[derive(Debug, Clone, Copy)]
+struct Source {
+    position: Vector3,
+    mass: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    center: Vector3, // geometric centre of the cell
+    half_width: f64,
+    com: Vector3,
+    mass: f64,
+    quadrupole: [f64; 6], // traceless Q_ij about `com`: xx, yy, zz, xy, xz, yz
+    children: Vec<usize>,
+    start: usize, // range into `Octree::order` for leaves
+    end: usize,
+}
+
+/// Barnes-Hut octree over point masses with multipole expansions per node
+#[derive(Debug, Clone)]
+pub struct Octree {
+    sources: Vec<Source>,
+    order: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl Octree {
+    /// Builds a tree over every atom of `molecule`
+    pub fn from_biomolecule(molecule: &Biomolecule) -> Self {
+        Self::build(molecule.atoms.iter().map(|a| Source { position: a.position, mass: a.mass }).collect())
+    }
+
+    /// Builds a tree over the occupied voxels of `grid`, each treated as a point mass
+    pub fn from_grid<G: DensityGrid + ?Sized>(grid: &G) -> Self {
+        Self::build(grid.occupied().into_iter().map(|(position, mass)| Source { position, mass }).collect())
+    }
+
+    fn build(sources: Vec<Source>) -> Self {
+        let mut tree = Octree { order: (0..sources.len()).collect(), sources, nodes: Vec::new() };
+        if tree.sources.is_empty() {
+            return tree;
+        }
+
+        let mut lo = tree.sources[0].position;
+        let mut hi = lo;
+        for s in &tree.sources {
+            lo = Vector3::new(lo.x.min(s.position.x), lo.y.min(s.position.y), lo.z.min(s.position.z));
+            hi = Vector3::new(hi.x.max(s.position.x), hi.y.max(s.position.y), hi.z.max(s.position.z));
+        }
+        let center = (lo + hi) * 0.5;
+        let half_width = ((hi.x - lo.x).max(hi.y - lo.y).max(hi.z - lo.z) * 0.5).max(1e-6) * (1.0 + 1e-9);
+        let n = tree.sources.len();
+        tree.build_node(center, half_width, 0, n, 0);
+        tree
+    }
+
+    /// Recursively partitions `order[start..end]`; returns the new node index
+    fn build_node(&mut self, center: Vector3, half_width: f64, start: usize, end: usize, depth: usize) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            center,
+            half_width,
+            com: center,
+            mass: 0.0,
+            quadrupole: [0.0; 6],
+            children: Vec::new(),
+            start,
+            end,
+        });
+
+        if end - start > LEAF_SIZE && depth < MAX_DEPTH {
+            let octant = |p: &Vector3| ((p.x >= center.x) as usize) | (((p.y >= center.y) as usize) << 1) | (((p.z >= center.z) as usize) << 2);
+            let sources = &self.sources;
+            self.order[start..end].sort_by_key(|&i| octant(&sources[i].position));
+
+            let mut cursor = start;
+            let mut children = Vec::new();
+            for oct in 0..8 {
+                let begin = cursor;
+                while cursor < end && octant(&self.sources[self.order[cursor]].position) == oct {
+                    cursor += 1;
+                }
+                if cursor > begin {
+                    let q = half_width * 0.5;
+                    let offset = Vector3::new(
+                        if oct & 1 != 0 { q } else { -q },
+                        if oct & 2 != 0 { q } else { -q },
+                        if oct & 4 != 0 { q } else { -q },
+                    );
+                    children.push(self.build_node(center + offset, q, begin, cursor, depth + 1));
+                }
+            }
+            self.nodes[id].children = children;
+        }
+        self.compute_moments(id);
+        id
+    }
+
+    fn compute_moments(&mut self, id: usize) {
+        // (position, mass, own quadrupole) of every constituent
+        let parts: Vec<(Vector3, f64, [f64; 6])> = if self.nodes[id].children.is_empty() {
+            let node = &self.nodes[id];
+            self.order[node.start..node.end]
+                .iter()
+                .map(|&i| (self.sources[i].position, self.sources[i].mass, [0.0; 6]))
+                .collect()
+        } else {
+            self.nodes[id].children.iter().map(|&c| (self.nodes[c].com, self.nodes[c].mass, self.nodes[c].quadrupole)).collect()
+        };
+
+        let mass: f64 = parts.iter().map(|p| p.1).sum();
+        let com = if mass > 0.0 {
+            parts.iter().fold(Vector3::new(0.0, 0.0, 0.0), |acc, p| acc + p.0 * p.1) * (1.0 / mass)
+        } else {
+            self.nodes[id].center
+        };
+
+        // Parallel-axis shift: Q = sum_c [Q_c + m_c (3 d d^T - |d|^2 I)]
+        let mut q = [0.0; 6];
+        for (pos, m, qc) in &parts {
+            let d = *pos - com;
+            let d2 = d.dot(&d);
+            q[0] += qc[0] + m * (3.0 * d.x * d.x - d2);
+            q[1] += qc[1] + m * (3.0 * d.y * d.y - d2);
+            q[2] += qc[2] + m * (3.0 * d.z * d.z - d2);
+            q[3] += qc[3] + m * 3.0 * d.x * d.y;
+            q[4] += qc[4] + m * 3.0 * d.x * d.z;
+            q[5] += qc[5] + m * 3.0 * d.y * d.z;
+        }
+
+        let node = &mut self.nodes[id];
+        node.mass = mass;
+        node.com = com;
+        node.quadrupole = q;
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    pub fn total_mass(&self) -> f64 {
+        self.nodes.first().map_or(0.0, |n| n.mass)
+    }
+}
+
+/// Running tally of one tree walk, in Angstrom/Dalton units (converted at the end)
+#[derive(Default)]
+struct Walk {
+    potential: f64,
+    field: Vector3,
+    interactions: usize,
+}
+
+/// Barnes-Hut evaluator with tunable opening angle and expansion order
+#[derive(Debug, Clone)]
+pub struct OctreeSolver {
+    pub tree: Octree,
+    /// A node is accepted when (cell width / distance) < theta; 0 reproduces the direct sum
+    pub theta: f64,
+    pub order: MultipoleOrder,
+}
+
+/// Tree-versus-direct comparison over a set of targets
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AccuracyReport {
+    pub targets: usize,
+    pub max_relative_error: f64,
+    pub rms_relative_error: f64,
+    pub max_field_relative_error: f64,
+    pub mean_interactions: f64, // node/particle interactions per target for the tree walk
+}
+
+impl OctreeSolver {
+    pub fn new(tree: Octree, theta: f64, order: MultipoleOrder) -> Self {
+        Self { tree, theta, order }
+    }
+
+    /// Gravitational potential \Phi (J/kg) at `position` (Angstroms)
+    pub fn potential_at(&self, position: &Vector3) -> f64 {
+        self.walk(position).potential
+    }
+
+    /// Gravitational field g = -\nabla\Phi (m/s^2) at `position` (Angstroms)
+    pub fn field_at(&self, position: &Vector3) -> Vector3 {
+        self.walk(position).field
+    }
+
+    pub fn potentials(&self, targets: &[Vector3]) -> Vec<f64> {
+        targets.iter().map(|p| self.potential_at(p)).collect()
+    }
+
+    pub fn fields(&self, targets: &[Vector3]) -> Vec<Vector3> {
+        targets.iter().map(|p| self.field_at(p)).collect()
+    }
+
+    /// Exact O(N) sum over all sources, skipping coincident points like `calculate_potential`
+    pub fn direct_potential_and_field(&self, position: &Vector3) -> (f64, Vector3) {
+        let mut walk = Walk::default();
+        for s in &self.tree.sources {
+            accumulate_point(&mut walk, position, &s.position, s.mass);
+        }
+        let walk = finish(walk);
+        (walk.potential, walk.field)
+    }
+
+    /// Compares the tree walk against the direct sum at `targets`
+    pub fn accuracy_report(&self, targets: &[Vector3]) -> AccuracyReport {
+        let mut report = AccuracyReport { targets: targets.len(), ..Default::default() };
+        if targets.is_empty() {
+            return report;
+        }
+        let mut sum_sq = 0.0;
+        let mut interactions = 0usize;
+        for p in targets {
+            let walk = self.walk(p);
+            interactions += walk.interactions;
+            let (phi, g) = self.direct_potential_and_field(p);
+            let rel = if phi != 0.0 { ((walk.potential - phi) / phi).abs() } else { 0.0 };
+            let g_norm = g.norm();
+            let rel_g = if g_norm > 0.0 { (walk.field - g).norm() / g_norm } else { 0.0 };
+            report.max_relative_error = report.max_relative_error.max(rel);
+            report.max_field_relative_error = report.max_field_relative_error.max(rel_g);
+            sum_sq += rel * rel;
+        }
+        report.rms_relative_error = (sum_sq / targets.len() as f64).sqrt();
+        report.mean_interactions = interactions as f64 / targets.len() as f64;
+        report
+    }
+
+    fn walk(&self, position: &Vector3) -> Walk {
+        let mut walk = Walk::default();
+        if self.tree.nodes.is_empty() {
+            return walk;
+        }
+        let mut stack = vec![0usize];
+        while let Some(id) = stack.pop() {
+            let node = &self.tree.nodes[id];
+            let r = *position - node.com;
+            let dist = r.norm();
+            let inside = (position.x - node.center.x).abs() <= node.half_width
+                && (position.y - node.center.y).abs() <= node.half_width
+                && (position.z - node.center.z).abs() <= node.half_width;
+
+            if !inside && dist > 0.0 && 2.0 * node.half_width < self.theta * dist {
+                self.accumulate_node(&mut walk, r, dist, node);
+            } else if node.children.is_empty() {
+                for &i in &self.tree.order[node.start..node.end] {
+                    let s = &self.tree.sources[i];
+                    accumulate_point(&mut walk, position, &s.position, s.mass);
+                }
+            } else {
+                stack.extend(node.children.iter().copied());
+            }
+        }
+        finish(walk)
+    }
+
+    fn accumulate_node(&self, walk: &mut Walk, r: Vector3, dist: f64, node: &Node) {
+        walk.interactions += 1;
+        let inv = 1.0 / dist;
+        let inv3 = inv * inv * inv;
+        walk.potential -= node.mass * inv;
+        walk.field += r * (-node.mass * inv3);
+
+        if self.order == MultipoleOrder::Quadrupole {
+            let q = &node.quadrupole;
+            let qr = Vector3::new(
+                q[0] * r.x + q[3] * r.y + q[4] * r.z,
+                q[3] * r.x + q[1] * r.y + q[5] * r.z,
+                q[4] * r.x + q[5] * r.y + q[2] * r.z,
+            );
+            let rqr = r.dot(&qr);
+            let inv5 = inv3 * inv * inv;
+            let inv7 = inv5 * inv * inv;
+            // \Phi_q = -rQr / (2 r^5);  g_q = -\nabla\Phi_q = Qr / r^5 - 5 (rQr) r / (2 r^7)
+            walk.potential -= 0.5 * rqr * inv5;
+            walk.field += qr * inv5 - r * (2.5 * rqr * inv7);
+        }
+    }
+}
+
+fn accumulate_point(walk: &mut Walk, position: &Vector3, source: &Vector3, mass: f64) {
+    let r = *position - *source;
+    let dist = r.norm();
+    if dist > 0.0 {
+        walk.interactions += 1;
+        walk.potential -= mass / dist;
+        walk.field += r * (-mass / (dist * dist * dist));
+    }
+}
+
+/// Converts Dalton/Angstrom accumulators to SI (J/kg and m/s^2)
+fn finish(mut walk: Walk) -> Walk {
+    let k = G_CONSTANT * DALTON_TO_KG;
+    walk.potential *= k / ANGSTROM_TO_M;
+    walk.field = walk.field * (k / (ANGSTROM_TO_M * ANGSTROM_TO_M));
+    walk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::density_grid::DensityGrid;
+    use crate::dna_builder::{DnaBuilder, HelixForm};
+    use crate::element::Element;
+    use crate::gqbme_engine::{MassDensityGrid, RelativisticSolver};
+    use crate::grid_sizing::GridFit;
+
+    fn helix() -> Biomolecule {
+        DnaBuilder::new("ACGTTGCAAGCT", HelixForm::B).expect("valid sequence").build()
+    }
+
+    /// Atom positions nudged off the atoms, plus points well outside the molecule
+    fn targets(molecule: &Biomolecule) -> Vec<Vector3> {
+        let near = molecule.atoms.iter().step_by(17).map(|a| a.position + Vector3::new(0.3, -0.2, 0.1));
+        let far = (0..8).map(|k| Vector3::new(60.0 * (k as f64).cos(), 60.0 * (k as f64).sin(), 20.0 + 5.0 * k as f64));
+        near.chain(far).collect()
+    }
+
+    #[test]
+    fn zero_opening_angle_reproduces_the_direct_sum() {
+        let molecule = helix();
+        let solver = OctreeSolver::new(Octree::from_biomolecule(&molecule), 0.0, MultipoleOrder::Quadrupole);
+        assert_eq!(solver.tree.len(), molecule.atoms.len());
+        let total: f64 = molecule.atoms.iter().map(|a| a.mass).sum();
+        assert!((solver.tree.total_mass() - total).abs() < 1e-9 * total);
+
+        let report = solver.accuracy_report(&targets(&molecule));
+        assert!(report.max_relative_error < 1e-12, "{:?}", report);
+        assert!(report.max_field_relative_error < 1e-12, "{:?}", report);
+        assert!(report.mean_interactions >= molecule.atoms.len() as f64 - 1.0);
+    }
+
+    #[test]
+    fn quadrupoles_beat_monopoles_at_the_same_opening_angle() {
+        let molecule = helix();
+        let targets = targets(&molecule);
+        let tree = Octree::from_biomolecule(&molecule);
+        let monopole = OctreeSolver::new(tree.clone(), 0.5, MultipoleOrder::Monopole).accuracy_report(&targets);
+        let quadrupole = OctreeSolver::new(tree, 0.5, MultipoleOrder::Quadrupole).accuracy_report(&targets);
+        assert!(quadrupole.max_relative_error < 1e-3, "{:?}", quadrupole);
+        assert!(quadrupole.rms_relative_error < monopole.rms_relative_error, "{:?} vs {:?}", quadrupole, monopole);
+        assert!(quadrupole.mean_interactions < molecule.atoms.len() as f64 / 2.0, "{:?}", quadrupole);
+    }
+
+    #[test]
+    fn far_field_of_a_symmetric_pair_is_a_point_mass_plus_its_quadrupole() {
+        let mut molecule = Biomolecule::new();
+        molecule.add_element(Element::C, -1.0, 0.0, 0.0);
+        molecule.add_element(Element::C, 1.0, 0.0, 0.0);
+        let solver = OctreeSolver::new(Octree::from_biomolecule(&molecule), 1.0, MultipoleOrder::Quadrupole);
+        // Along the pair: -Gm (1/(r-1) + 1/(r+1)) = -2Gm/r (1 + 1/r^2 + ...), the quadrupole gives the 1/r^3 term
+        let r = 50.0;
+        let (phi, g) = solver.direct_potential_and_field(&Vector3::new(r, 0.0, 0.0));
+        let tree_phi = solver.potential_at(&Vector3::new(r, 0.0, 0.0));
+        // What is left is the 1/r^5 hexadecapole, (1/r)^4 ~ 1.6e-7 relative
+        assert!(((tree_phi - phi) / phi).abs() < 1e-6, "{} vs {}", tree_phi, phi);
+        let monopole = OctreeSolver { order: MultipoleOrder::Monopole, ..solver.clone() }.potential_at(&Vector3::new(r, 0.0, 0.0));
+        assert!(((monopole - phi) / phi).abs() > 1e-4, "the quadrupole term is {} of the potential", ((monopole - phi) / phi).abs());
+        let tree_g = solver.field_at(&Vector3::new(r, 0.0, 0.0));
+        assert!((tree_g - g).norm() / g.norm() < 5e-6);
+        assert!(g.x < 0.0, "gravity points back at the pair");
+    }
+
+    #[test]
+    fn grid_tree_matches_the_direct_grid_sum() {
+        let molecule = helix();
+        let mut grid = MassDensityGrid::fitted_to(&molecule, &GridFit::new(3.0, 1.0)).expect("small grid");
+        let _ = grid.map_biomolecule(&molecule);
+        let solver = OctreeSolver::new(Octree::from_grid(&grid), 0.0, MultipoleOrder::Quadrupole);
+        for target in targets(&molecule) {
+            let direct = RelativisticSolver::calculate_potential(&target, &grid);
+            let tree = solver.potential_at(&target);
+            assert!(((tree - direct) / direct).abs() < 1e-12, "{:?}: {} vs {}", target, tree, direct);
+        }
+    }
+}
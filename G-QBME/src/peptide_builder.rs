@@ -0,0 +1,532 @@
+use crate::dna_builder::SequenceError;
+use crate::element::Element;
+use crate::gqbme_engine::{Atom, AtomRecord, Biomolecule, Vector3};
+
+/// Side-chain atom literal; a macro so each table stays a promotable constant
+macro_rules! sc {
+    ($name:literal, $a:literal, $b:literal, $c:literal, $bond:expr, $angle:expr, $torsion:expr) => {
+        SideChainAtom { name: $name, refs: [$a, $b, $c], bond: $bond, angle: $angle, torsion: $torsion }
+    };
+}
+
+/// Backbone dihedrals of one residue, in degrees
+/// `omega` is the CA(i)-C(i)-N(i+1)-CA(i+1) peptide torsion following this residue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dihedrals {
+    pub phi: f64,
+    pub psi: f64,
+    pub omega: f64,
+}
+
+/// Idealised secondary-structure presets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondaryStructure {
+    AlphaHelix,
+    BetaStrand,
+    Polyproline,
+    Extended,
+}
+
+impl SecondaryStructure {
+    pub fn dihedrals(self) -> Dihedrals {
+        let (phi, psi) = match self {
+            SecondaryStructure::AlphaHelix => (-57.0, -47.0),
+            SecondaryStructure::BetaStrand => (-119.0, 113.0),
+            SecondaryStructure::Polyproline => (-75.0, 145.0),
+            SecondaryStructure::Extended => (-180.0, 180.0),
+        };
+        Dihedrals { phi, psi, omega: 180.0 }
+    }
+}
+
+/// The twenty standard amino acids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AminoAcid {
+    Ala,
+    Arg,
+    Asn,
+    Asp,
+    Cys,
+    Gln,
+    Glu,
+    Gly,
+    His,
+    Ile,
+    Leu,
+    Lys,
+    Met,
+    Phe,
+    Pro,
+    Ser,
+    Thr,
+    Trp,
+    Tyr,
+    Val,
+}
+
+impl AminoAcid {
+    pub fn from_one_letter(c: char) -> Option<Self> {
+        use AminoAcid::*;
+        Some(match c.to_ascii_uppercase() {
+            'A' => Ala,
+            'R' => Arg,
+            'N' => Asn,
+            'D' => Asp,
+            'C' => Cys,
+            'Q' => Gln,
+            'E' => Glu,
+            'G' => Gly,
+            'H' => His,
+            'I' => Ile,
+            'L' => Leu,
+            'K' => Lys,
+            'M' => Met,
+            'F' => Phe,
+            'P' => Pro,
+            'S' => Ser,
+            'T' => Thr,
+            'W' => Trp,
+            'Y' => Tyr,
+            'V' => Val,
+            _ => return None,
+        })
+    }
+
+    /// PDB residue name
+    pub fn three_letter(self) -> &'static str {
+        use AminoAcid::*;
+        match self {
+            Ala => "ALA",
+            Arg => "ARG",
+            Asn => "ASN",
+            Asp => "ASP",
+            Cys => "CYS",
+            Gln => "GLN",
+            Glu => "GLU",
+            Gly => "GLY",
+            His => "HIS",
+            Ile => "ILE",
+            Leu => "LEU",
+            Lys => "LYS",
+            Met => "MET",
+            Phe => "PHE",
+            Pro => "PRO",
+            Ser => "SER",
+            Thr => "THR",
+            Trp => "TRP",
+            Tyr => "TYR",
+            Val => "VAL",
+        }
+    }
+
+    /// Side-chain heavy atoms beyond CB as internal coordinates
+    fn side_chain(self) -> &'static [SideChainAtom] {
+        use AminoAcid::*;
+        use Torsion::{Chi, Fixed};
+        match self {
+            Gly | Ala => &[],
+            Ser => &[sc!("OG", "N", "CA", "CB", 1.417, 110.8, Chi(0, 0.0))],
+            Cys => &[sc!("SG", "N", "CA", "CB", 1.808, 113.8, Chi(0, 0.0))],
+            Val => &[
+                sc!("CG1", "N", "CA", "CB", 1.527, 110.7, Chi(0, 0.0)),
+                sc!("CG2", "N", "CA", "CB", 1.527, 110.4, Chi(0, 120.0)),
+            ],
+            Thr => &[
+                sc!("OG1", "N", "CA", "CB", 1.433, 109.2, Chi(0, 0.0)),
+                sc!("CG2", "N", "CA", "CB", 1.530, 111.1, Chi(0, -120.0)),
+            ],
+            Ile => &[
+                sc!("CG1", "N", "CA", "CB", 1.527, 110.7, Chi(0, 0.0)),
+                sc!("CG2", "N", "CA", "CB", 1.527, 110.4, Chi(0, -120.0)),
+                sc!("CD1", "CA", "CB", "CG1", 1.520, 113.97, Chi(1, 0.0)),
+            ],
+            Leu => &[
+                sc!("CG", "N", "CA", "CB", 1.530, 116.1, Chi(0, 0.0)),
+                sc!("CD1", "CA", "CB", "CG", 1.524, 110.27, Chi(1, 0.0)),
+                sc!("CD2", "CA", "CB", "CG", 1.525, 110.58, Chi(1, 120.0)),
+            ],
+            Met => &[
+                sc!("CG", "N", "CA", "CB", 1.520, 114.1, Chi(0, 0.0)),
+                sc!("SD", "CA", "CB", "CG", 1.810, 112.7, Chi(1, 0.0)),
+                sc!("CE", "CB", "CG", "SD", 1.791, 100.6, Chi(2, 0.0)),
+            ],
+            Phe => &[
+                sc!("CG", "N", "CA", "CB", 1.500, 113.85, Chi(0, 0.0)),
+                sc!("CD1", "CA", "CB", "CG", 1.390, 120.0, Chi(1, 0.0)),
+                sc!("CD2", "CA", "CB", "CG", 1.390, 120.0, Chi(1, 180.0)),
+                sc!("CE1", "CB", "CG", "CD1", 1.390, 120.0, Fixed(180.0)),
+                sc!("CE2", "CB", "CG", "CD2", 1.390, 120.0, Fixed(180.0)),
+                sc!("CZ", "CG", "CD1", "CE1", 1.390, 120.0, Fixed(0.0)),
+            ],
+            Tyr => &[
+                sc!("CG", "N", "CA", "CB", 1.510, 113.8, Chi(0, 0.0)),
+                sc!("CD1", "CA", "CB", "CG", 1.390, 120.98, Chi(1, 0.0)),
+                sc!("CD2", "CA", "CB", "CG", 1.390, 120.82, Chi(1, 180.0)),
+                sc!("CE1", "CB", "CG", "CD1", 1.390, 120.0, Fixed(180.0)),
+                sc!("CE2", "CB", "CG", "CD2", 1.390, 120.0, Fixed(180.0)),
+                sc!("CZ", "CG", "CD1", "CE1", 1.390, 120.0, Fixed(0.0)),
+                sc!("OH", "CD1", "CE1", "CZ", 1.390, 119.78, Fixed(180.0)),
+            ],
+            Trp => &[
+                sc!("CG", "N", "CA", "CB", 1.500, 114.1, Chi(0, 0.0)),
+                sc!("CD1", "CA", "CB", "CG", 1.370, 127.07, Chi(1, 0.0)),
+                sc!("CD2", "CA", "CB", "CG", 1.430, 126.66, Chi(1, 180.0)),
+                sc!("NE1", "CB", "CG", "CD1", 1.380, 108.5, Fixed(180.0)),
+                sc!("CE2", "CB", "CG", "CD2", 1.400, 108.5, Fixed(180.0)),
+                sc!("CE3", "CB", "CG", "CD2", 1.400, 133.83, Fixed(0.0)),
+                sc!("CZ2", "CG", "CD2", "CE2", 1.400, 120.0, Fixed(180.0)),
+                sc!("CZ3", "CG", "CD2", "CE3", 1.400, 120.0, Fixed(180.0)),
+                sc!("CH2", "CD2", "CE2", "CZ2", 1.400, 120.0, Fixed(0.0)),
+            ],
+            His => &[
+                sc!("CG", "N", "CA", "CB", 1.490, 113.74, Chi(0, 0.0)),
+                sc!("ND1", "CA", "CB", "CG", 1.380, 122.85, Chi(1, 0.0)),
+                sc!("CD2", "CA", "CB", "CG", 1.360, 130.61, Chi(1, 180.0)),
+                sc!("CE1", "CB", "CG", "ND1", 1.320, 108.5, Fixed(180.0)),
+                sc!("NE2", "CB", "CG", "CD2", 1.350, 108.5, Fixed(180.0)),
+            ],
+            Asp => &[
+                sc!("CG", "N", "CA", "CB", 1.520, 113.06, Chi(0, 0.0)),
+                sc!("OD1", "CA", "CB", "CG", 1.250, 119.22, Chi(1, 0.0)),
+                sc!("OD2", "CA", "CB", "CG", 1.250, 118.22, Chi(1, 180.0)),
+            ],
+            Asn => &[
+                sc!("CG", "N", "CA", "CB", 1.520, 112.62, Chi(0, 0.0)),
+                sc!("OD1", "CA", "CB", "CG", 1.230, 120.85, Chi(1, 0.0)),
+                sc!("ND2", "CA", "CB", "CG", 1.330, 116.48, Chi(1, 180.0)),
+            ],
+            Glu => &[
+                sc!("CG", "N", "CA", "CB", 1.520, 113.82, Chi(0, 0.0)),
+                sc!("CD", "CA", "CB", "CG", 1.520, 113.31, Chi(1, 0.0)),
+                sc!("OE1", "CB", "CG", "CD", 1.250, 119.02, Chi(2, 0.0)),
+                sc!("OE2", "CB", "CG", "CD", 1.250, 118.08, Chi(2, 180.0)),
+            ],
+            Gln => &[
+                sc!("CG", "N", "CA", "CB", 1.520, 113.75, Chi(0, 0.0)),
+                sc!("CD", "CA", "CB", "CG", 1.520, 112.78, Chi(1, 0.0)),
+                sc!("OE1", "CB", "CG", "CD", 1.240, 120.86, Chi(2, 0.0)),
+                sc!("NE2", "CB", "CG", "CD", 1.330, 116.50, Chi(2, 180.0)),
+            ],
+            Lys => &[
+                sc!("CG", "N", "CA", "CB", 1.520, 113.83, Chi(0, 0.0)),
+                sc!("CD", "CA", "CB", "CG", 1.520, 111.79, Chi(1, 0.0)),
+                sc!("CE", "CB", "CG", "CD", 1.520, 111.68, Chi(2, 0.0)),
+                sc!("NZ", "CG", "CD", "CE", 1.490, 111.70, Chi(3, 0.0)),
+            ],
+            Arg => &[
+                sc!("CG", "N", "CA", "CB", 1.520, 113.83, Chi(0, 0.0)),
+                sc!("CD", "CA", "CB", "CG", 1.520, 111.79, Chi(1, 0.0)),
+                sc!("NE", "CB", "CG", "CD", 1.460, 111.68, Chi(2, 0.0)),
+                sc!("CZ", "CG", "CD", "NE", 1.330, 124.79, Chi(3, 0.0)),
+                sc!("NH1", "CD", "NE", "CZ", 1.330, 120.64, Fixed(0.0)),
+                sc!("NH2", "CD", "NE", "CZ", 1.330, 119.63, Fixed(180.0)),
+            ],
+            Pro => &[
+                sc!("CG", "N", "CA", "CB", 1.495, 104.21, Chi(0, 0.0)),
+                sc!("CD", "CA", "CB", "CG", 1.500, 105.03, Chi(1, 0.0)),
+            ],
+        }
+    }
+
+    /// Most common rotamer (degrees) for chi1..chi4
+    pub fn default_chis(self) -> [f64; 4] {
+        use AminoAcid::*;
+        match self {
+            Ser => [-63.0, 0.0, 0.0, 0.0],
+            Cys => [-62.0, 0.0, 0.0, 0.0],
+            Val => [175.0, 0.0, 0.0, 0.0],
+            Thr => [60.0, 0.0, 0.0, 0.0],
+            Ile => [-60.0, 170.0, 0.0, 0.0],
+            Leu => [-60.0, 175.0, 0.0, 0.0],
+            Met => [-65.0, 180.0, 70.0, 0.0],
+            Phe | Tyr => [-64.0, 93.0, 0.0, 0.0],
+            Trp => [-66.0, 96.0, 0.0, 0.0],
+            His => [-63.0, -75.0, 0.0, 0.0],
+            Asp => [-70.0, -15.0, 0.0, 0.0],
+            Asn => [-65.0, -20.0, 0.0, 0.0],
+            Glu | Gln => [-65.0, 180.0, -40.0, 0.0],
+            Lys => [-65.0, 180.0, 180.0, 180.0],
+            Arg => [-65.0, 180.0, 180.0, 180.0],
+            // Closes the ring with a planar N for phi near -65; other phi pucker the nitrogen
+            Pro => [28.0, -36.0, 0.0, 0.0],
+            Gly | Ala => [0.0; 4],
+        }
+    }
+}
+
+/// Dihedral driving a side-chain atom: a chi angle (index, offset) or a fixed value
+#[derive(Debug, Clone, Copy)]
+enum Torsion {
+    Chi(usize, f64),
+    Fixed(f64),
+}
+
+/// Places `name` from three already-placed atoms of the same residue
+#[derive(Debug, Clone, Copy)]
+struct SideChainAtom {
+    name: &'static str,
+    refs: [&'static str; 3],
+    bond: f64,
+    angle: f64,
+    torsion: Torsion,
+}
+
+impl std::str::FromStr for SecondaryStructure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "helix" | "alpha" => Ok(SecondaryStructure::AlphaHelix),
+            "strand" | "beta" => Ok(SecondaryStructure::BetaStrand),
+            "ppii" | "polyproline" => Ok(SecondaryStructure::Polyproline),
+            "extended" => Ok(SecondaryStructure::Extended),
+            other => Err(format!("unknown secondary structure '{}' (expected helix, strand, ppii or extended)", other)),
+        }
+    }
+}
+
+// Engh & Huber backbone geometry (Angstroms, degrees)
+const N_CA: f64 = 1.458;
+const CA_C: f64 = 1.525;
+const C_N: f64 = 1.329;
+const C_O: f64 = 1.231;
+const CA_CB: f64 = 1.530;
+const N_CA_C: f64 = 111.2;
+const CA_C_N: f64 = 116.2;
+const C_N_CA: f64 = 121.7;
+const CA_C_O: f64 = 120.5;
+const N_CA_CB: f64 = 110.5;
+/// Proline's pyrrolidine ring pinches N-CA-CB so that CD closes back onto N
+const N_CA_CB_PRO: f64 = 103.0;
+/// Improper C-N-CA-CB torsion giving L-chirality at CA
+const C_N_CA_CB: f64 = -122.6;
+
+/// Natural extension reference frame: places d so that |cd| = bond,
+/// angle(b, c, d) = angle and dihedral(a, b, c, d) = torsion (degrees)
+fn place_atom(a: Vector3, b: Vector3, c: Vector3, bond: f64, angle: f64, torsion: f64) -> Vector3 {
+    let bc = c - b;
+    let bc = bc * (1.0 / bc.norm());
+    let n = (b - a).cross(&bc);
+    let n = n * (1.0 / n.norm());
+    let m = n.cross(&bc);
+    let (sa, ca) = angle.to_radians().sin_cos();
+    let (st, ct) = torsion.to_radians().sin_cos();
+    c + bc * (-bond * ca) + m * (bond * sa * ct) + n * (bond * sa * st)
+}
+
+/// Polypeptide builder from sequence and backbone dihedrals
+#[derive(Debug, Clone)]
+pub struct PeptideBuilder {
+    pub sequence: Vec<AminoAcid>,
+    pub dihedrals: Vec<Dihedrals>,
+}
+
+impl PeptideBuilder {
+    /// Parses a one-letter sequence; all residues start as an alpha helix
+    pub fn new(sequence: &str) -> Result<Self, SequenceError> {
+        let sequence = sequence
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .enumerate()
+            .map(|(position, found)| AminoAcid::from_one_letter(found).ok_or(SequenceError { position, found }))
+            .collect::<Result<Vec<_>, _>>()?;
+        let dihedrals = vec![SecondaryStructure::AlphaHelix.dihedrals(); sequence.len()];
+        Ok(Self { sequence, dihedrals })
+    }
+
+    pub fn with_secondary_structure(mut self, preset: SecondaryStructure) -> Self {
+        self.dihedrals = vec![preset.dihedrals(); self.sequence.len()];
+        self
+    }
+
+    /// Per-residue dihedrals; returns `None` when the count does not match the sequence
+    pub fn with_dihedrals(mut self, dihedrals: Vec<Dihedrals>) -> Option<Self> {
+        if dihedrals.len() != self.sequence.len() {
+            return None;
+        }
+        self.dihedrals = dihedrals;
+        Some(self)
+    }
+
+    /// Builds N, CA, C, O and side-chain heavy atoms (plus OXT on the C terminus)
+    pub fn build(&self) -> Biomolecule {
+        let mut molecule = Biomolecule::new();
+        let n_res = self.sequence.len();
+        if n_res == 0 {
+            return molecule;
+        }
+
+        // Seed the first residue in the xy plane
+        let mut n = Vector3::new(0.0, 0.0, 0.0);
+        let mut ca = Vector3::new(N_CA, 0.0, 0.0);
+        let theta = (180.0 - N_CA_C).to_radians();
+        let mut c = ca + Vector3::new(CA_C * theta.cos(), CA_C * theta.sin(), 0.0);
+
+        for (i, &residue) in self.sequence.iter().enumerate() {
+            if i > 0 {
+                let prev = self.dihedrals[i - 1];
+                let next_n = place_atom(n, ca, c, C_N, CA_C_N, prev.psi);
+                let next_ca = place_atom(ca, c, next_n, N_CA, C_N_CA, prev.omega);
+                let next_c = place_atom(c, next_n, next_ca, CA_C, N_CA_C, self.dihedrals[i].phi);
+                n = next_n;
+                ca = next_ca;
+                c = next_c;
+            }
+            let psi = self.dihedrals[i].psi;
+            let o = place_atom(n, ca, c, C_O, CA_C_O, psi + 180.0);
+
+            let mut placed: Vec<(&'static str, Vector3)> = vec![("N", n), ("CA", ca), ("C", c), ("O", o)];
+            if residue != AminoAcid::Gly {
+                let angle = if residue == AminoAcid::Pro { N_CA_CB_PRO } else { N_CA_CB };
+                placed.push(("CB", place_atom(c, n, ca, CA_CB, angle, C_N_CA_CB)));
+            }
+            let chis = residue.default_chis();
+            for atom in residue.side_chain() {
+                let find = |name: &str| placed.iter().find(|(n, _)| *n == name).map(|(_, p)| *p).expect("reference atom placed earlier");
+                let torsion = match atom.torsion {
+                    Torsion::Chi(k, offset) => chis[k] + offset,
+                    Torsion::Fixed(value) => value,
+                };
+                let position = place_atom(find(atom.refs[0]), find(atom.refs[1]), find(atom.refs[2]), atom.bond, atom.angle, torsion);
+                placed.push((atom.name, position));
+            }
+            if i == n_res - 1 {
+                placed.push(("OXT", place_atom(n, ca, c, C_O, CA_C_O, psi)));
+            }
+
+            for (name, position) in placed {
+                let element = Element::from_symbol(&name[..1]).expect("protein elements are tabulated");
+                molecule.atoms.push(Atom {
+                    record: Some(AtomRecord {
+                        serial: molecule.atoms.len() + 1,
+                        name: name.to_string(),
+                        residue_name: residue.three_letter().to_string(),
+                        residue_seq: i as i32 + 1,
+                        chain_id: "A".to_string(),
+                        hetero: false,
+                    }),
+                    ..Atom::new(element, position)
+                });
+            }
+        }
+        molecule
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(molecule: &Biomolecule, seq: i32, name: &str) -> Vector3 {
+        molecule
+            .atoms
+            .iter()
+            .find(|a| a.record.as_ref().is_some_and(|r| r.residue_seq == seq && r.name == name))
+            .map(|a| a.position)
+            .unwrap_or_else(|| panic!("residue {} has no {}", seq, name))
+    }
+
+    fn angle(a: Vector3, b: Vector3, c: Vector3) -> f64 {
+        let (u, v) = (a - b, c - b);
+        (u.dot(&v) / (u.norm() * v.norm())).acos().to_degrees()
+    }
+
+    fn dihedral(a: Vector3, b: Vector3, c: Vector3, d: Vector3) -> f64 {
+        let (b1, b2, b3) = (b - a, c - b, d - c);
+        let (m, n) = (b1.cross(&b2), b2.cross(&b3));
+        (b2.norm() * b1.dot(&n)).atan2(m.dot(&n)).to_degrees()
+    }
+
+    /// Difference of two angles in degrees, wrapped into [-180, 180]
+    fn wrapped(a: f64, b: f64) -> f64 {
+        (a - b + 540.0).rem_euclid(360.0) - 180.0
+    }
+
+    #[test]
+    fn backbone_bonds_and_angles_match_the_tables() {
+        for preset in [SecondaryStructure::AlphaHelix, SecondaryStructure::BetaStrand, SecondaryStructure::Polyproline, SecondaryStructure::Extended] {
+            let molecule = PeptideBuilder::new("AGPSWK").unwrap().with_secondary_structure(preset).build();
+            for seq in 1..=6 {
+                let (n, ca, c, o) = (atom(&molecule, seq, "N"), atom(&molecule, seq, "CA"), atom(&molecule, seq, "C"), atom(&molecule, seq, "O"));
+                assert!((n.dist(&ca) - N_CA).abs() < 1e-9 && (ca.dist(&c) - CA_C).abs() < 1e-9 && (c.dist(&o) - C_O).abs() < 1e-9);
+                assert!((angle(n, ca, c) - N_CA_C).abs() < 1e-6 && (angle(ca, c, o) - CA_C_O).abs() < 1e-6);
+                if seq != 2 {
+                    let cb = atom(&molecule, seq, "CB");
+                    let expected = if seq == 3 { N_CA_CB_PRO } else { N_CA_CB };
+                    assert!((ca.dist(&cb) - CA_CB).abs() < 1e-9 && (angle(n, ca, cb) - expected).abs() < 1e-6, "{:?} residue {}", preset, seq);
+                }
+                if seq < 6 {
+                    let (next_n, next_ca) = (atom(&molecule, seq + 1, "N"), atom(&molecule, seq + 1, "CA"));
+                    assert!((c.dist(&next_n) - C_N).abs() < 1e-9, "{:?} C{}-N{}", preset, seq, seq + 1);
+                    assert!((angle(ca, c, next_n) - CA_C_N).abs() < 1e-6 && (angle(c, next_n, next_ca) - C_N_CA).abs() < 1e-6);
+                }
+            }
+            let last_c = atom(&molecule, 6, "C");
+            assert!((last_c.dist(&atom(&molecule, 6, "OXT")) - C_O).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn measured_dihedrals_reproduce_the_requested_ones() {
+        let requested = vec![
+            Dihedrals { phi: -60.0, psi: -45.0, omega: 180.0 },
+            Dihedrals { phi: -135.0, psi: 135.0, omega: -172.0 },
+            Dihedrals { phi: -75.0, psi: 150.0, omega: 0.0 }, // cis peptide before the proline
+            Dihedrals { phi: -70.0, psi: 160.0, omega: 178.0 },
+            Dihedrals { phi: 60.0, psi: 40.0, omega: 180.0 }, // left-handed glycine
+            Dihedrals { phi: -100.0, psi: -20.0, omega: 180.0 },
+        ];
+        let molecule = PeptideBuilder::new("ASTPGL").unwrap().with_dihedrals(requested.clone()).unwrap().build();
+        let backbone = |seq: i32| (atom(&molecule, seq, "N"), atom(&molecule, seq, "CA"), atom(&molecule, seq, "C"));
+        for (i, d) in requested.iter().enumerate() {
+            let seq = i as i32 + 1;
+            let (n, ca, c) = backbone(seq);
+            if seq > 1 {
+                let phi = dihedral(backbone(seq - 1).2, n, ca, c);
+                assert!(wrapped(phi, d.phi).abs() < 1e-6, "phi {}: {} vs {}", seq, phi, d.phi);
+            }
+            if seq < 6 {
+                let (next_n, next_ca, _) = backbone(seq + 1);
+                let (psi, omega) = (dihedral(n, ca, c, next_n), dihedral(ca, c, next_n, next_ca));
+                assert!(wrapped(psi, d.psi).abs() < 1e-6, "psi {}: {} vs {}", seq, psi, d.psi);
+                assert!(wrapped(omega, d.omega).abs() < 1e-6, "omega {}: {} vs {}", seq, omega, d.omega);
+            }
+            // The carbonyl O sits trans to the next N, so it carries psi too, even on the last residue
+            let o_torsion = dihedral(n, ca, c, atom(&molecule, seq, "O"));
+            assert!(wrapped(o_torsion, d.psi + 180.0).abs() < 1e-6, "O {}", seq);
+        }
+    }
+
+    #[test]
+    fn alpha_carbons_are_l() {
+        let molecule = PeptideBuilder::new("ARNDCQEHILKMFPSTWYV").unwrap().with_secondary_structure(SecondaryStructure::BetaStrand).build();
+        for seq in 1..=19 {
+            let (n, ca, c, cb) = (atom(&molecule, seq, "N"), atom(&molecule, seq, "CA"), atom(&molecule, seq, "C"), atom(&molecule, seq, "CB"));
+            // Signed volume is positive for L (CO, R, N clockwise seen from the CA hydrogen)
+            let volume = (n - ca).dot(&(c - ca).cross(&(cb - ca)));
+            assert!(volume > 2.0, "residue {}: signed volume {:.3}", seq, volume);
+            assert!(wrapped(dihedral(c, n, ca, cb), C_N_CA_CB).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn residues_carry_their_heavy_atoms() {
+        let sequence = "GASCVTPILDNMEQKHFRYW";
+        let molecule = PeptideBuilder::new(sequence).unwrap().build();
+        let counts: Vec<usize> = (1..=20).map(|seq| molecule.atoms.iter().filter(|a| a.record.as_ref().is_some_and(|r| r.residue_seq == seq)).count()).collect();
+        // The C-terminal residue adds OXT
+        assert_eq!(counts, [4, 5, 6, 6, 7, 7, 7, 8, 8, 8, 8, 8, 9, 9, 9, 10, 11, 11, 12, 15]);
+        let names: Vec<&str> = molecule.atoms.iter().filter_map(|a| a.record.as_ref()).filter(|r| r.residue_seq == 1).map(|r| r.residue_name.as_str()).collect();
+        assert_eq!(names, ["GLY"; 4]);
+        assert!(molecule.atoms.iter().all(|a| !a.record.as_ref().unwrap().hetero && a.position.x.is_finite()));
+    }
+
+    #[test]
+    fn unknown_residue_letters_are_rejected() {
+        assert_eq!(PeptideBuilder::new("AC XG").unwrap_err(), SequenceError { position: 2, found: 'X' });
+        assert_eq!(PeptideBuilder::new("ACB").unwrap_err().to_string(), "invalid residue 'B' at position 3");
+        assert!(PeptideBuilder::new("acd\nef").is_ok_and(|b| b.sequence.len() == 5));
+        assert!(PeptideBuilder::new("ACD").unwrap().with_dihedrals(vec![SecondaryStructure::Extended.dihedrals(); 2]).is_none());
+        assert!(PeptideBuilder::new("").unwrap().build().atoms.is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::density_grid::DensityGrid;
+use crate::fft::{frequency_index, Complex, Fft3};
+use crate::gqbme_engine::{Vector3, ANGSTROM_TO_M};
+use crate::kernel::PairKernel;
+use std::f64::consts::PI;
+
+/// Potential at the centre of a uniform unit cube of unit mass (G = 1)
+/// Used as the self-interaction term of the isolated Green's function.
+const CUBE_SELF_POTENTIAL: f64 = 2.380_077_364;
+
+/// Boundary treatment for the Poisson solve
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// Free space: the density is zero-padded to twice its size (Hockney-Eastwood)
+    #[default]
+    Isolated,
+    /// The box tiles space; the mean density is removed (k = 0 mode dropped)
+    Periodic,
+}
+
+impl std::str::FromStr for Boundary {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "isolated" => Ok(Boundary::Isolated),
+            "periodic" => Ok(Boundary::Periodic),
+            other => Err(format!("unknown boundary '{}' (expected isolated or periodic)", other)),
+        }
+    }
+}
+
+/// Gravitational potential \Phi(\mathbf{r}) sampled on the same lattice as a density grid
+#[derive(Debug, Clone)]
+pub struct PotentialGrid {
+    pub origin: Vector3,
+    pub resolution: f64, // voxel edge in Angstroms
+    pub dimensions: (usize, usize, usize),
+    pub data: Vec<f64>, // J/kg, same layout as MassDensityGrid
+}
+
+impl PotentialGrid {
+    pub fn value(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[x * self.dimensions.1 * self.dimensions.2 + y * self.dimensions.2 + z]
+    }
+
+    /// Trilinear interpolation of \Phi at `position`; `None` outside the lattice
+    pub fn sample(&self, position: &Vector3) -> Option<f64> {
+        let corners = self.corners(position)?;
+        Some(corners.iter().map(|&(x, y, z, w)| w * self.value(x, y, z)).sum())
+    }
+
+    /// Trilinear interpolation of the finite-difference gradient \nabla\Phi (J/kg/m)
+    pub fn gradient_at(&self, position: &Vector3) -> Option<Vector3> {
+        let corners = self.corners(position)?;
+        Some(corners.iter().fold(Vector3::default(), |acc, &(x, y, z, w)| acc + self.node_gradient(x, y, z) * w))
+    }
+
+    /// Gravitational field g = -\nabla\Phi (m/s^2) at `position`
+    pub fn field_at(&self, position: &Vector3) -> Option<Vector3> {
+        self.gradient_at(position).map(|g| -g)
+    }
+
+    /// Central differences in the interior, one-sided on the faces
+    fn node_gradient(&self, x: usize, y: usize, z: usize) -> Vector3 {
+        let h = self.resolution * ANGSTROM_TO_M;
+        let (nx, ny, nz) = self.dimensions;
+        let diff = |i: usize, n: usize, at: &dyn Fn(usize) -> f64| -> f64 {
+            if n < 2 {
+                0.0
+            } else if i == 0 {
+                (at(1) - at(0)) / h
+            } else if i == n - 1 {
+                (at(n - 1) - at(n - 2)) / h
+            } else {
+                (at(i + 1) - at(i - 1)) / (2.0 * h)
+            }
+        };
+        Vector3::new(
+            diff(x, nx, &|i| self.value(i, y, z)),
+            diff(y, ny, &|i| self.value(x, i, z)),
+            diff(z, nz, &|i| self.value(x, y, i)),
+        )
+    }
+
+    fn corners(&self, position: &Vector3) -> Option<[(usize, usize, usize, f64); 8]> {
+        lattice_corners(self.origin, self.resolution, self.dimensions, position)
+    }
+}
+
+/// The eight lattice corners around `position` with their trilinear weights; `None` outside the lattice
+pub(crate) fn lattice_corners(origin: Vector3, resolution: f64, dimensions: (usize, usize, usize), position: &Vector3) -> Option<[(usize, usize, usize, f64); 8]> {
+    let rel = [
+        (position.x - origin.x) / resolution,
+        (position.y - origin.y) / resolution,
+        (position.z - origin.z) / resolution,
+    ];
+    let dims = [dimensions.0, dimensions.1, dimensions.2];
+    let mut base = [0usize; 3];
+    let mut frac = [0.0; 3];
+    for a in 0..3 {
+        if !(rel[a] >= 0.0 && rel[a] <= (dims[a] - 1) as f64) {
+            return None;
+        }
+        // Clamp so the upper corner stays inside on the last plane
+        let i = (rel[a].floor() as usize).min(dims[a].saturating_sub(2));
+        base[a] = i;
+        frac[a] = rel[a] - i as f64;
+    }
+
+    let mut out = [(0, 0, 0, 0.0); 8];
+    for (corner, slot) in out.iter_mut().enumerate() {
+        let mut w = 1.0;
+        let mut idx = [0usize; 3];
+        for a in 0..3 {
+            let upper = (corner >> a) & 1 == 1;
+            idx[a] = (base[a] + upper as usize).min(dims[a] - 1);
+            w *= if upper { frac[a] } else { 1.0 - frac[a] };
+        }
+        *slot = (idx[0], idx[1], idx[2], w);
+    }
+    Some(out)
+}
+
+/// FFT solver for \nabla^2 \Phi = 4 \pi G \rho on any `DensityGrid`
+/// The FFT works on the dense (for isolated boundaries, doubled) lattice whatever the backend.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoissonSolver {
+    pub boundary: Boundary,
+    /// Gravity by default; a Coulomb or Debye-Hueckel kernel solves for the electrostatic
+    /// potential (V) of a charge grid instead
+    pub kernel: PairKernel,
+}
+
+impl PoissonSolver {
+    pub fn new(boundary: Boundary) -> Self {
+        Self { boundary, kernel: PairKernel::gravity() }
+    }
+
+    pub fn with_kernel(mut self, kernel: PairKernel) -> Self {
+        self.kernel = kernel;
+        self
+    }
+
+    /// Solves for the potential on every voxel of `grid`
+    pub fn solve<G: DensityGrid + ?Sized>(&self, grid: &G) -> PotentialGrid {
+        let data = match self.boundary {
+            Boundary::Isolated => solve_isolated(grid, &self.kernel),
+            Boundary::Periodic => solve_periodic(grid, &self.kernel),
+        };
+        PotentialGrid {
+            origin: grid.origin(),
+            resolution: grid.resolution(),
+            dimensions: grid.dimensions(),
+            data,
+        }
+    }
+}
+
+/// The kernel's potential per SI unit of source at `r` metres, with the uniform-cube self term at r = 0
+fn green_at(kernel: &PairKernel, r: f64, h: f64) -> f64 {
+    let mut g = if r > 0.0 { kernel.coupling / r } else { kernel.coupling * CUBE_SELF_POTENTIAL / h };
+    if let Some(l) = kernel.screening_length {
+        // Inside the self cube the screening is kept to first order in h / \lambda
+        g = if r > 0.0 { g * (-r / l).exp() } else { g - kernel.coupling / l };
+    }
+    if kernel.attractive { -g } else { g }
+}
+
+/// Convolves the voxel sources with the kernel (-G/r for gravity) on a doubled, zero-padded lattice
+fn solve_isolated<G: DensityGrid + ?Sized>(grid: &G, kernel: &PairKernel) -> Vec<f64> {
+    let (nx, ny, nz) = grid.dimensions();
+    let (px, py, pz) = (2 * nx, 2 * ny, 2 * nz);
+    let fft = Fft3::new((px, py, pz));
+    let h = grid.resolution() * ANGSTROM_TO_M;
+
+    let mut mass = vec![Complex::ZERO; px * py * pz];
+    grid.for_each_nonzero(&mut |idx, m| {
+        let (x, y, z) = (idx / (ny * nz), (idx / nz) % ny, idx % nz);
+        mass[(x * py + y) * pz + z] = Complex::new(m * kernel.unit, 0.0);
+    });
+
+    // Periodic images of the kernel sit beyond the padding, so the wrap is exact
+    let mut green = vec![Complex::ZERO; px * py * pz];
+    for x in 0..px {
+        let dx = x.min(px - x) as f64;
+        for y in 0..py {
+            let dy = y.min(py - y) as f64;
+            for z in 0..pz {
+                let dz = z.min(pz - z) as f64;
+                let r = (dx * dx + dy * dy + dz * dz).sqrt() * h;
+                green[(x * py + y) * pz + z] = Complex::new(green_at(kernel, r, h), 0.0);
+            }
+        }
+    }
+
+    fft.forward(&mut mass);
+    fft.forward(&mut green);
+    for (m, g) in mass.iter_mut().zip(&green) {
+        *m = *m * *g;
+    }
+    fft.inverse(&mut mass);
+
+    let mut out = vec![0.0; nx * ny * nz];
+    for x in 0..nx {
+        for y in 0..ny {
+            for z in 0..nz {
+                out[(x * ny + y) * nz + z] = mass[(x * py + y) * pz + z].re;
+            }
+        }
+    }
+    out
+}
+
+/// Spectral solve \hat\Phi_k = -4 \pi G \hat\rho_k / k^2 (gravity) or
+/// \hat\phi_k = 4 \pi k_e \hat\rho_k / (\epsilon_r (k^2 + \kappa^2)) (electrostatics); the k = 0 mode is
+/// removed unless the kernel is screened
+fn solve_periodic<G: DensityGrid + ?Sized>(grid: &G, kernel: &PairKernel) -> Vec<f64> {
+    let (nx, ny, nz) = grid.dimensions();
+    let fft = Fft3::new((nx, ny, nz));
+    let h = grid.resolution() * ANGSTROM_TO_M;
+    let voxel_volume = h * h * h;
+
+    let mut rho = vec![Complex::ZERO; nx * ny * nz];
+    grid.for_each_nonzero(&mut |idx, m| rho[idx] = Complex::new(m * kernel.unit / voxel_volume, 0.0));
+    fft.forward(&mut rho);
+
+    let (lx, ly, lz) = (nx as f64 * h, ny as f64 * h, nz as f64 * h);
+    let kappa2 = kernel.kappa() * kernel.kappa();
+    let sign = if kernel.attractive { -1.0 } else { 1.0 };
+    for x in 0..nx {
+        let kx = 2.0 * PI * frequency_index(x, nx) / lx;
+        for y in 0..ny {
+            let ky = 2.0 * PI * frequency_index(y, ny) / ly;
+            for z in 0..nz {
+                let kz = 2.0 * PI * frequency_index(z, nz) / lz;
+                let k2 = kx * kx + ky * ky + kz * kz;
+                let idx = (x * ny + y) * nz + z;
+                let denominator = k2 + kappa2;
+                rho[idx] = if denominator > 0.0 { rho[idx].scale(sign * 4.0 * PI * kernel.coupling / denominator) } else { Complex::ZERO };
+            }
+        }
+    }
+    fft.inverse(&mut rho);
+    rho.iter().map(|c| c.re).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gqbme_engine::{MassDensityGrid, DALTON_TO_KG, G_CONSTANT};
+
+    /// 1000 Da in the centre voxel of a 16^3 grid of 1 A voxels
+    fn point_mass() -> (MassDensityGrid, f64) {
+        let mut grid = MassDensityGrid::new(Vector3::new(0.0, 0.0, 0.0), 1.0, (16, 16, 16));
+        grid.add_mass(8, 8, 8, 1000.0);
+        (grid, 1000.0 * DALTON_TO_KG)
+    }
+
+    #[test]
+    fn isolated_point_mass_matches_newton() {
+        let (grid, mass) = point_mass();
+        let potential = PoissonSolver::new(Boundary::Isolated).solve(&grid);
+        for (x, y, z) in [(8, 8, 11), (8, 3, 8), (2, 8, 8), (12, 12, 12), (0, 0, 0), (15, 15, 15)] {
+            let r = grid.voxel_position(x, y, z).dist(&grid.voxel_position(8, 8, 8)) * ANGSTROM_TO_M;
+            let expected = -G_CONSTANT * mass / r;
+            let got = potential.value(x, y, z);
+            assert!((got - expected).abs() < 1e-9 * expected.abs(), "({}, {}, {}): {} vs {}", x, y, z, got, expected);
+        }
+        assert!(potential.value(8, 8, 8) < potential.value(8, 8, 9), "the source voxel should be the deepest");
+    }
+
+    #[test]
+    fn periodic_solution_has_zero_mean() {
+        let (mut grid, _) = point_mass();
+        grid.add_mass(2, 13, 5, 250.0);
+        let potential = PoissonSolver::new(Boundary::Periodic).solve(&grid);
+        let mean = potential.data.iter().sum::<f64>() / potential.data.len() as f64;
+        let scale = potential.data.iter().map(|v| v.abs()).fold(0.0, f64::max);
+        assert!(scale > 0.0);
+        assert!(mean.abs() < 1e-12 * scale, "mean {} against a largest |Phi| of {}", mean, scale);
+        assert!(potential.value(8, 8, 8) < potential.value(0, 0, 0));
+    }
+
+    #[test]
+    fn boundary_names_parse() {
+        assert_eq!("Periodic".parse::<Boundary>(), Ok(Boundary::Periodic));
+        assert_eq!("isolated".parse::<Boundary>(), Ok(Boundary::Isolated));
+        assert!("open".parse::<Boundary>().is_err());
+    }
+}
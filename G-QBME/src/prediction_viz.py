@@ -5,6 +5,8 @@ import numpy as np
 import plotly.graph_objects as go
 from quantum_kernel import QuantumBioSolver
 import os
+import json
+import tempfile
 
 st.set_page_config(page_title="G-QBME: Gravitational Bio-Engine", layout="wide")
 
@@ -34,33 +36,42 @@ def run_rust_engine(num_bp):
     # Path to the compiled Rust binary
     binary_path = "./target/release/g_qbme"
     if not os.path.exists(binary_path):
-        return None, "Binary not found"
+        return None, None, "Binary not found"
     
-    result = subprocess.run([binary_path, "generate_dna", str(num_bp)], 
+    result = subprocess.run([binary_path, "potential", "--num-bp", str(num_bp),
+                             "--method", "grid", "--json"],
                             capture_output=True, text=True, cwd="./")
-    return result.stdout, result.stderr
+    if result.returncode != 0:
+        return None, None, result.stderr
+
+    # Structure comes from the export command rather than stdout scraping
+    with tempfile.TemporaryDirectory() as tmp:
+        xyz_path = os.path.join(tmp, "helix.xyz")
+        export = subprocess.run([binary_path, "export", "--num-bp", str(num_bp),
+                                 "--format", "xyz", "--out", xyz_path],
+                                capture_output=True, text=True, cwd="./")
+        if export.returncode != 0:
+            return None, None, export.stderr
+        atoms = read_xyz(xyz_path)
+    return result.stdout, atoms, result.stderr
+
+def read_xyz(path):
+    with open(path) as fh:
+        lines = fh.read().splitlines()
+    count = int(lines[0])
+    atoms = []
+    for i, line in enumerate(lines[2:2 + count]):
+        element, x, y, z = line.split()[:4]
+        atoms.append({"id": i, "element": element, "x": float(x), "y": float(y), "z": float(z)})
+    return atoms
 
 if st.button("Run Unified Simulation"):
     with st.spinner("Calculating Spacetime Metric & Quantum Perturbations..."):
         # 1. Run Rust Engine
-        output, error = run_rust_engine(num_bp)
+        output, atoms, error = run_rust_engine(num_bp)
         
         if output:
-            lines = output.split("\n")
-            atoms = []
-            phi = 0.0
-            for line in lines:
-                if line.startswith("ATOM"):
-                    parts = line.split()
-                    atoms.append({
-                        "id": parts[1],
-                        "element": parts[2],
-                        "x": float(parts[3]),
-                        "y": float(parts[4]),
-                        "z": float(parts[5])
-                    })
-                if "Gravitational Potential" in line:
-                    phi = float(line.split(":")[2].strip())
+            phi = json.loads(output)["potential"]
             
             df = pd.DataFrame(atoms)
             
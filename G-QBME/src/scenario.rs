@@ -0,0 +1,799 @@
+use crate::density_grid::DensityGrid;
+use crate::deposition::DepositionScheme;
+use crate::dna_builder::{DnaBuilder, HelixForm};
+use crate::electrostatics::{charge_deposition_json, ChargeModel, Medium};
+use crate::environment::EnvironmentPreset;
+use crate::batch::{fields_from_sources, potentials_from_sources};
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{generate_dna_helix, Biomolecule, MassDensityGrid, RelativisticSolver, Vector3};
+use crate::grid_sizing::GridFit;
+use crate::json::{Json, JsonError};
+use crate::octree::{MultipoleOrder, Octree, OctreeSolver};
+use crate::peptide_builder::{PeptideBuilder, SecondaryStructure};
+use crate::poisson::{Boundary, PoissonSolver};
+use crate::sparse_grid::SparseDensityGrid;
+use crate::structure_io::{read_structure, write_structure, StructureFormat};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// How \Phi is evaluated at a set of targets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PotentialMethod {
+    /// Exact sum over the atoms
+    #[default]
+    Direct,
+    /// Barnes-Hut tree walk with opening angle theta
+    Octree,
+    /// Exact sum over the voxels of the deposited density grid
+    Grid,
+}
+
+impl PotentialMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            PotentialMethod::Direct => "direct",
+            PotentialMethod::Octree => "octree",
+            PotentialMethod::Grid => "grid",
+        }
+    }
+}
+
+impl std::str::FromStr for PotentialMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "direct" => Ok(PotentialMethod::Direct),
+            "octree" | "tree" => Ok(PotentialMethod::Octree),
+            "grid" => Ok(PotentialMethod::Grid),
+            other => Err(format!("unknown method '{}' (expected direct, octree or grid)", other)),
+        }
+    }
+}
+
+/// Where a scenario evaluates \Phi
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TargetSet {
+    #[default]
+    CenterOfMass,
+    Atoms,
+    /// Every voxel centre of the fitted grid
+    GridNodes,
+}
+
+impl TargetSet {
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetSet::CenterOfMass => "com",
+            TargetSet::Atoms => "atoms",
+            TargetSet::GridNodes => "grid",
+        }
+    }
+}
+
+impl std::str::FromStr for TargetSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "com" => Ok(TargetSet::CenterOfMass),
+            "atoms" => Ok(TargetSet::Atoms),
+            "grid" => Ok(TargetSet::GridNodes),
+            other => Err(format!("unknown target set '{}' (expected com, atoms or grid)", other)),
+        }
+    }
+}
+
+/// Where a scenario's molecule comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoleculeSource {
+    /// Coarse phosphate helix from `generate_dna_helix`
+    Helix { num_base_pairs: usize, radius: f64, pitch: f64 },
+    Dna { sequence: String, form: HelixForm },
+    Peptide { sequence: String, secondary_structure: SecondaryStructure },
+    /// PDB, mmCIF or XYZ file, read by `read_structure` according to its extension
+    File(PathBuf),
+}
+
+impl Default for MoleculeSource {
+    fn default() -> Self {
+        MoleculeSource::Helix { num_base_pairs: 10, radius: 10.0, pitch: 34.0 }
+    }
+}
+
+impl MoleculeSource {
+    pub fn build(&self) -> Result<Biomolecule, GqbmeError> {
+        let molecule = match self {
+            MoleculeSource::Helix { num_base_pairs, radius, pitch } => generate_dna_helix(*num_base_pairs, *radius, *pitch),
+            MoleculeSource::Dna { sequence, form } => DnaBuilder::new(sequence, *form)?.build(),
+            MoleculeSource::Peptide { sequence, secondary_structure } => PeptideBuilder::new(sequence)?.with_secondary_structure(*secondary_structure).build(),
+            MoleculeSource::File(path) => {
+                let molecule = at_path(path, read_structure(path))?;
+                at_path(path, molecule.validate())?;
+                return Ok(molecule);
+            }
+        };
+        molecule.validate()?;
+        Ok(molecule)
+    }
+}
+
+impl fmt::Display for MoleculeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoleculeSource::Helix { num_base_pairs, radius, pitch } => write!(f, "{} bp phosphate helix (radius {} A, pitch {} A)", num_base_pairs, radius, pitch),
+            MoleculeSource::Dna { sequence, form } => write!(f, "{:?}-DNA {}", form, sequence),
+            MoleculeSource::Peptide { sequence, secondary_structure } => write!(f, "peptide {} ({:?})", sequence, secondary_structure),
+            MoleculeSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// What to compute once the molecule is built
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolverSettings {
+    pub method: PotentialMethod,
+    pub theta: f64, // octree opening angle
+    pub targets: TargetSet,
+    /// Also solve the Poisson equation over the whole grid with this boundary
+    pub poisson: Option<Boundary>,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self { method: PotentialMethod::Direct, theta: 0.5, targets: TargetSet::CenterOfMass, poisson: None }
+    }
+}
+
+/// Files a scenario writes; relative paths resolve against the scenario file's directory
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScenarioOutputs {
+    pub structure: Option<PathBuf>,     // PDB, or XYZ by extension
+    pub density: Option<PathBuf>,       // MRC of the deposited mass
+    pub potential_map: Option<PathBuf>, // MRC of the Poisson solution
+    pub potentials: Option<PathBuf>,    // TSV of \Phi and g at the targets
+}
+
+impl ScenarioOutputs {
+    /// (key, path) for every requested output
+    pub fn paths(&self) -> Vec<(&'static str, &Path)> {
+        [("structure", &self.structure), ("density", &self.density), ("potential_map", &self.potential_map), ("potentials", &self.potentials)]
+            .into_iter()
+            .filter_map(|(key, path)| path.as_deref().map(|p| (key, p)))
+            .collect()
+    }
+}
+
+/// One fully specified experiment: molecule, grid, environment, charges, solver and outputs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub molecule: MoleculeSource,
+    pub grid: GridFit,
+    pub scheme: DepositionScheme,
+    pub strict: bool, // lost mass, or lost charge on a charge grid, is an error
+    pub sparse: bool, // `SparseDensityGrid` storage instead of dense
+    pub environment: EnvironmentPreset,
+    pub charges: ChargeModel, // partial charges for the electrostatics that run alongside gravity
+    pub medium: Medium,
+    pub solver: SolverSettings,
+    pub outputs: ScenarioOutputs,
+    /// The scenario as resolved from its file, with defaults merged and sweep values applied
+    pub config: Json,
+}
+
+/// Why a scenario file could not be turned into scenarios
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, error: JsonError },
+    /// `scenario` names the entry at fault, or "file" for the document's own layout
+    Invalid { scenario: String, message: String },
+    /// Reading a scenario's input or writing one of its outputs failed
+    File { path: PathBuf, error: Box<GqbmeError> },
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ScenarioError::Parse { path, error } => write!(f, "{}: {}", path.display(), error),
+            ScenarioError::Invalid { scenario, message } => write!(f, "scenario '{}': {}", scenario, message),
+            ScenarioError::File { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScenarioError::Io { error, .. } => Some(error),
+            ScenarioError::Parse { error, .. } => Some(error),
+            ScenarioError::File { error, .. } => Some(error.as_ref()),
+            ScenarioError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Tags a failure with the file it concerns
+fn at_path<T, E: Into<GqbmeError>>(path: &Path, result: Result<T, E>) -> Result<T, GqbmeError> {
+    result.map_err(|error| ScenarioError::File { path: path.to_path_buf(), error: Box::new(error.into()) }.into())
+}
+
+fn invalid(scenario: &str, message: impl Into<String>) -> ScenarioError {
+    ScenarioError::Invalid { scenario: scenario.to_string(), message: message.into() }
+}
+
+const SCENARIO_KEYS: &[&str] = &["name", "description", "molecule", "grid", "environment", "electrostatics", "solver", "outputs"];
+
+/// Reads and expands every scenario in a JSON file
+pub fn load_scenarios<P: AsRef<Path>>(path: P) -> Result<Vec<Scenario>, ScenarioError> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path).map_err(|error| ScenarioError::Io { path: path.to_path_buf(), error })?;
+    let document = Json::parse(&text).map_err(|error| ScenarioError::Parse { path: path.to_path_buf(), error })?;
+    parse_scenarios(&document, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Expands a scenario document: one scenario object, or `{"defaults": {..}, "scenarios": [..]}`
+/// Each entry is merged over the defaults, then its optional `"sweep": {"grid.resolution": [1, 0.5], ..}`
+/// multiplies it into the cartesian product of the listed values, first key slowest. Names must come
+/// out unique and no two scenarios may write the same file; `{name}` in an output path expands per scenario.
+pub fn parse_scenarios(document: &Json, base_dir: &Path) -> Result<Vec<Scenario>, ScenarioError> {
+    let (defaults, entries) = match document.get("scenarios") {
+        Some(list) => {
+            check_keys("file", "the document", document, &["defaults", "scenarios"])?;
+            let entries = list.as_array().filter(|l| !l.is_empty()).ok_or_else(|| invalid("file", "\"scenarios\" must be a non-empty array"))?;
+            (document.get("defaults").cloned().unwrap_or_else(Json::object), entries.to_vec())
+        }
+        None => (Json::object(), vec![document.clone()]),
+    };
+    check_keys("file", "defaults", &defaults, SCENARIO_KEYS)?;
+
+    let mut configs = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let label = format!("#{}", index + 1);
+        if entry.members().is_none() {
+            return Err(invalid(&label, "each scenario must be an object"));
+        }
+        let mut config = merge(&defaults, entry);
+        let sweep = take(&mut config, "sweep");
+        if config.get("name").is_none() {
+            config = config.with("name", format!("scenario-{}", index + 1));
+        }
+        configs.extend(expand_sweep(config, sweep.as_ref(), &label)?);
+    }
+
+    let mut scenarios: Vec<Scenario> = Vec::new();
+    for config in configs {
+        let scenario = Scenario::from_json(&config, base_dir)?;
+        if scenarios.iter().any(|s| s.name == scenario.name) {
+            return Err(invalid(&scenario.name, "the name is used more than once"));
+        }
+        for (key, path) in scenario.outputs.paths() {
+            if let Some(other) = scenarios.iter().find(|s| s.outputs.paths().iter().any(|(_, p)| *p == path)) {
+                return Err(invalid(&scenario.name, format!("outputs.{} {} is also written by '{}'; use {{name}} in the path", key, path.display(), other.name)));
+            }
+        }
+        scenarios.push(scenario);
+    }
+    Ok(scenarios)
+}
+
+/// `over` on top of `base`, merging objects key by key
+fn merge(base: &Json, over: &Json) -> Json {
+    match (base, over) {
+        (Json::Object(_), Json::Object(members)) => members.iter().fold(base.clone(), |merged, (key, value)| {
+            let value = match merged.get(key) {
+                Some(existing) => merge(existing, value),
+                None => value.clone(),
+            };
+            merged.with(key, value)
+        }),
+        _ => over.clone(),
+    }
+}
+
+/// Removes `key` from an object, returning its value
+fn take(object: &mut Json, key: &str) -> Option<Json> {
+    let Json::Object(members) = object else { return None };
+    let index = members.iter().position(|(k, _)| k == key)?;
+    Some(members.remove(index).1)
+}
+
+fn expand_sweep(config: Json, sweep: Option<&Json>, label: &str) -> Result<Vec<Json>, ScenarioError> {
+    let Some(sweep) = sweep else { return Ok(vec![config]) };
+    let axes = sweep.members().ok_or_else(|| invalid(label, "sweep must be an object of \"dotted.key\": [values]"))?;
+    let mut expanded = vec![config];
+    for (path, values) in axes {
+        let values = values.as_array().filter(|v| !v.is_empty()).ok_or_else(|| invalid(label, format!("sweep.{} must be a non-empty array", path)))?;
+        if path.split('.').next().is_none_or(|top| !SCENARIO_KEYS.contains(&top) || top == "name") {
+            return Err(invalid(label, format!("cannot sweep '{}'", path)));
+        }
+        let mut next = Vec::with_capacity(expanded.len() * values.len());
+        for config in &expanded {
+            for value in values {
+                let name = format!("{}-{}", config.get("name").and_then(Json::as_str).unwrap_or(label), name_part(value));
+                next.push(set_path(config.clone(), path, value.clone()).with("name", name));
+            }
+        }
+        expanded = next;
+    }
+    Ok(expanded)
+}
+
+/// A sweep value as it appears in generated names, safe in file names
+fn name_part(value: &Json) -> String {
+    let text = match value {
+        Json::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    text.chars().map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' }).collect()
+}
+
+/// Sets `a.b.c` in `config`, creating (or replacing non-object) intermediate objects
+fn set_path(config: Json, path: &str, value: Json) -> Json {
+    match path.split_once('.') {
+        None => config.with(path, value),
+        Some((head, rest)) => {
+            let inner = config.get(head).filter(|v| v.members().is_some()).cloned().unwrap_or_else(Json::object);
+            config.with(head, set_path(inner, rest, value))
+        }
+    }
+}
+
+fn check_keys(scenario: &str, path: &str, value: &Json, allowed: &[&str]) -> Result<(), ScenarioError> {
+    let members = value.members().ok_or_else(|| invalid(scenario, format!("{} must be an object", path)))?;
+    match members.iter().find(|(k, _)| !allowed.contains(&k.as_str())) {
+        Some((key, _)) => Err(invalid(scenario, format!("unknown key '{}' in {} (expected {})", key, path, allowed.join(", ")))),
+        None => Ok(()),
+    }
+}
+
+/// Typed reads from one object of a scenario, with errors naming `section.key`
+struct Section<'a> {
+    scenario: &'a str,
+    name: &'static str,
+    value: Json,
+}
+
+impl<'a> Section<'a> {
+    /// `config[name]` (an empty object if absent), which may only use `allowed` keys
+    fn new(scenario: &'a str, config: &Json, name: &'static str, allowed: &[&str]) -> Result<Self, ScenarioError> {
+        let value = config.get(name).cloned().unwrap_or_else(Json::object);
+        check_keys(scenario, name, &value, allowed)?;
+        Ok(Self { scenario, name, value })
+    }
+
+    fn has(&self, key: &str) -> bool {
+        self.value.get(key).is_some()
+    }
+
+    fn error(&self, key: &str, message: &str) -> ScenarioError {
+        invalid(self.scenario, format!("{}.{} {}", self.name, key, message))
+    }
+
+    fn number(&self, key: &str) -> Result<Option<f64>, ScenarioError> {
+        match self.value.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_f64().map(Some).ok_or_else(|| self.error(key, "must be a number")),
+        }
+    }
+
+    fn positive(&self, key: &str) -> Result<Option<f64>, ScenarioError> {
+        match self.number(key)? {
+            Some(v) if v <= 0.0 => Err(self.error(key, "must be positive")),
+            other => Ok(other),
+        }
+    }
+
+    fn non_negative(&self, key: &str) -> Result<Option<f64>, ScenarioError> {
+        match self.number(key)? {
+            Some(v) if v < 0.0 => Err(self.error(key, "must be non-negative")),
+            other => Ok(other),
+        }
+    }
+
+    /// A whole number of at least 1
+    fn count(&self, key: &str) -> Result<Option<usize>, ScenarioError> {
+        match self.number(key)? {
+            Some(v) if v < 1.0 || v.fract() != 0.0 || v > u32::MAX as f64 => Err(self.error(key, "must be a whole number of at least 1")),
+            other => Ok(other.map(|v| v as usize)),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<&str>, ScenarioError> {
+        match self.value.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_str().map(Some).ok_or_else(|| self.error(key, "must be a string")),
+        }
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>, ScenarioError> {
+        match self.value.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_bool().map(Some).ok_or_else(|| self.error(key, "must be true or false")),
+        }
+    }
+
+    fn parsed<T: std::str::FromStr<Err = String>>(&self, key: &str) -> Result<Option<T>, ScenarioError> {
+        self.string(key)?.map(|s| s.parse().map_err(|e: String| self.error(key, &format!("is invalid: {}", e)))).transpose()
+    }
+
+    /// A path relative to `base_dir`, with `{name}` replaced by the scenario name
+    fn path(&self, key: &str, base_dir: &Path) -> Result<Option<PathBuf>, ScenarioError> {
+        Ok(self.string(key)?.map(|p| base_dir.join(p.replace("{name}", self.scenario))))
+    }
+}
+
+impl Scenario {
+    /// Reads one resolved scenario object (no defaults or sweep); paths resolve against `base_dir`
+    pub fn from_json(config: &Json, base_dir: &Path) -> Result<Self, ScenarioError> {
+        let name = match config.get("name") {
+            Some(Json::String(name)) if !name.is_empty() => name.as_str(),
+            _ => return Err(invalid("file", "every scenario needs a non-empty string \"name\"")),
+        };
+        check_keys(name, "the scenario", config, SCENARIO_KEYS)?;
+        if config.get("description").is_some_and(|d| d.as_str().is_none()) {
+            return Err(invalid(name, "description must be a string"));
+        }
+
+        let molecule = Section::new(name, config, "molecule", &["input", "sequence", "form", "peptide", "ss", "num_bp", "radius", "pitch"])?;
+        let sources: Vec<&str> = ["input", "sequence", "peptide", "num_bp"].into_iter().filter(|k| molecule.has(k)).collect();
+        if sources.len() > 1 {
+            return Err(invalid(name, format!("molecule has more than one source: {}", sources.join(", "))));
+        }
+        for (option, source) in [("form", "sequence"), ("ss", "peptide"), ("radius", "num_bp"), ("pitch", "num_bp")] {
+            if molecule.has(option) && !molecule.has(source) {
+                return Err(invalid(name, format!("molecule.{} only applies with molecule.{}", option, source)));
+            }
+        }
+        let source = if let Some(path) = molecule.path("input", base_dir)? {
+            MoleculeSource::File(path)
+        } else if let Some(sequence) = molecule.string("sequence")? {
+            MoleculeSource::Dna { sequence: sequence.to_string(), form: molecule.parsed("form")?.unwrap_or_default() }
+        } else if let Some(sequence) = molecule.string("peptide")? {
+            MoleculeSource::Peptide {
+                sequence: sequence.to_string(),
+                secondary_structure: molecule.parsed("ss")?.unwrap_or(SecondaryStructure::AlphaHelix),
+            }
+        } else {
+            MoleculeSource::Helix {
+                num_base_pairs: molecule.count("num_bp")?.unwrap_or(10),
+                radius: molecule.positive("radius")?.unwrap_or(10.0),
+                pitch: molecule.positive("pitch")?.unwrap_or(34.0),
+            }
+        };
+
+        let grid = Section::new(name, config, "grid", &["resolution", "padding", "max_mem_mb", "scheme", "strict", "sparse"])?;
+        let sparse = grid.boolean("sparse")?.unwrap_or(false);
+        let mut fit = GridFit::default();
+        if let Some(resolution) = grid.positive("resolution")? {
+            fit.resolution = resolution;
+        }
+        if let Some(padding) = grid.non_negative("padding")? {
+            fit.padding = padding;
+        }
+        if let Some(mb) = grid.count("max_mem_mb")? {
+            if sparse {
+                return Err(invalid(name, "grid.max_mem_mb sizes dense storage and cannot be combined with grid.sparse"));
+            }
+            fit = fit.with_max_bytes(mb.saturating_mul(1024 * 1024));
+        }
+
+        let environment = match config.get("environment") {
+            None => EnvironmentPreset::Vacuum,
+            Some(Json::String(preset)) => preset.parse().map_err(|e: String| invalid(name, format!("environment is invalid: {}", e)))?,
+            Some(_) => return Err(invalid(name, "environment must be a preset name")),
+        };
+
+        let electrostatics = Section::new(name, config, "electrostatics", &["charges", "dielectric", "ionic_strength", "temperature"])?;
+        let mut medium = Medium::default();
+        if let Some(dielectric) = electrostatics.positive("dielectric")? {
+            medium.relative_permittivity = dielectric;
+        }
+        if let Some(ionic_strength) = electrostatics.non_negative("ionic_strength")? {
+            medium.ionic_strength = ionic_strength;
+        }
+        if let Some(temperature) = electrostatics.positive("temperature")? {
+            medium.temperature = temperature;
+        }
+
+        let solver = Section::new(name, config, "solver", &["method", "theta", "at", "poisson"])?;
+        let defaults = SolverSettings::default();
+        let solver_settings = SolverSettings {
+            method: solver.parsed("method")?.unwrap_or(defaults.method),
+            theta: solver.non_negative("theta")?.unwrap_or(defaults.theta),
+            targets: solver.parsed("at")?.unwrap_or(defaults.targets),
+            poisson: solver.parsed("poisson")?,
+        };
+
+        let outputs = Section::new(name, config, "outputs", &["structure", "density", "potential_map", "potentials"])?;
+        let outputs = ScenarioOutputs {
+            structure: outputs.path("structure", base_dir)?,
+            density: outputs.path("density", base_dir)?,
+            potential_map: outputs.path("potential_map", base_dir)?,
+            potentials: outputs.path("potentials", base_dir)?,
+        };
+        if outputs.potential_map.is_some() && solver_settings.poisson.is_none() {
+            return Err(invalid(name, "outputs.potential_map needs solver.poisson"));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            molecule: source,
+            grid: fit,
+            scheme: grid.parsed("scheme")?.unwrap_or_default(),
+            strict: grid.boolean("strict")?.unwrap_or(false),
+            sparse,
+            environment,
+            charges: electrostatics.parsed("charges")?.unwrap_or_default(),
+            medium,
+            solver: solver_settings,
+            outputs,
+            config: config.clone(),
+        })
+    }
+
+    /// A density grid is built when the method, targets, Poisson solve or outputs need one
+    pub fn needs_grid(&self) -> bool {
+        self.solver.method == PotentialMethod::Grid || self.solver.targets == TargetSet::GridNodes || self.solver.poisson.is_some() || self.outputs.density.is_some()
+    }
+
+    /// Builds the molecule, deposits and solves as configured, writes the outputs and
+    /// returns the results. Every value depends only on the scenario, never on `threads`.
+    pub fn run(&self, threads: usize) -> Result<Json, GqbmeError> {
+        let mut molecule = self.molecule.build()?;
+        let charged_atoms = molecule.assign_charges(self.charges);
+        let com = molecule.try_center_of_mass()?;
+        let environment = self.environment.environment().with_reference(com);
+
+        let mut results = Json::object().with(
+            "molecule",
+            Json::object()
+                .with("atoms", molecule.atoms.len())
+                .with("mass_da", molecule.atoms.iter().map(|a| a.mass).sum::<f64>())
+                .with("center_of_mass", com)
+                .with("radius_of_gyration", molecule.radius_of_gyration()),
+        );
+
+        // Resolution and padding come from the file unchecked beyond their sign; `try_fitted_to`
+        // rejects the rest, so a bad grid fails this scenario rather than the batch
+        let fitted = || -> Result<Box<dyn DensityGrid + Send + Sync>, GqbmeError> {
+            Ok(if self.sparse {
+                Box::new(SparseDensityGrid::try_fitted_to(&molecule, &self.grid)?)
+            } else {
+                Box::new(MassDensityGrid::try_fitted_to(&molecule, &self.grid)?)
+            })
+        };
+        let grid = if self.needs_grid() {
+            let mut grid = fitted()?;
+            let diagnostics = if self.strict {
+                grid.try_map_biomolecule_with(&molecule, self.scheme)?
+            } else {
+                grid.map_biomolecule_diagnosed(&molecule, self.scheme)?
+            };
+            results = results
+                .with(
+                    "grid",
+                    Json::object()
+                        .with("origin", grid.origin())
+                        .with("resolution", grid.resolution())
+                        .with("dimensions", vec![grid.dimensions().0, grid.dimensions().1, grid.dimensions().2])
+                        .with("storage", if self.sparse { "sparse" } else { "dense" }),
+                )
+                .with("diagnostics", &diagnostics);
+            Some(grid)
+        } else {
+            None
+        };
+
+        let targets = match (self.solver.targets, &grid) {
+            (TargetSet::CenterOfMass, _) => vec![com],
+            (TargetSet::Atoms, _) => molecule.atoms.iter().map(|a| a.position).collect(),
+            (TargetSet::GridNodes, Some(grid)) => grid.voxel_centres(),
+            (TargetSet::GridNodes, None) => unreachable!("needs_grid covers grid targets"),
+        };
+        let (self_potentials, fields): (Vec<f64>, Vec<Vector3>) = match (self.solver.method, &grid) {
+            (PotentialMethod::Direct, _) => {
+                let solver = OctreeSolver::new(Octree::from_biomolecule(&molecule), 0.0, MultipoleOrder::default());
+                solver.direct_with_threads(&targets, threads).into_iter().unzip()
+            }
+            (PotentialMethod::Octree, _) => {
+                let solver = OctreeSolver::new(Octree::from_biomolecule(&molecule), self.solver.theta, MultipoleOrder::default());
+                (solver.potentials_with_threads(&targets, threads), solver.fields_with_threads(&targets, threads))
+            }
+            (PotentialMethod::Grid, Some(grid)) => (
+                RelativisticSolver::calculate_potentials_with_threads(&targets, &**grid, threads),
+                RelativisticSolver::calculate_fields_with_threads(&targets, &**grid, threads),
+            ),
+            (PotentialMethod::Grid, None) => unreachable!("needs_grid covers the grid method"),
+        };
+        let env_potentials: Vec<f64> = targets.iter().map(|p| environment.potential_at(p)).collect();
+        let fields: Vec<Vector3> = fields.iter().zip(&targets).map(|(g, p)| *g + environment.field_at(p)).collect();
+
+        let range = |values: &[f64]| values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let totals: Vec<f64> = self_potentials.iter().zip(&env_potentials).map(|(a, b)| a + b).collect();
+        let (min, max) = range(&totals);
+        let (self_min, self_max) = range(&self_potentials);
+        results = results.with(
+            "potential",
+            Json::object()
+                .with("method", self.solver.method.name())
+                .with("environment", self.environment.name())
+                .with("count", targets.len())
+                .with("min", min)
+                .with("max", max)
+                .with("mean", totals.iter().sum::<f64>() / totals.len() as f64)
+                .with("self_min", self_min)
+                .with("self_max", self_max)
+                .with("environment_at_com", environment.reference_potential())
+                .with("field_magnitude_max", fields.iter().map(|g| g.norm()).fold(0.0, f64::max)),
+        );
+
+        let mut potential_map = None;
+        if let (Some(boundary), Some(grid)) = (self.solver.poisson, &grid) {
+            let solved = PoissonSolver::new(boundary).solve(&**grid);
+            let (lo, hi) = range(&solved.data);
+            results = results.with("poisson", Json::object().with("min", lo).with("max", hi).with("at_com", solved.sample(&com)));
+            potential_map = Some(solved);
+        }
+
+        // Electrostatics on the same targets and solvers; the octree's multipoles assume
+        // same-sign sources, so that method falls back to exact charge sums
+        let kernel = self.medium.kernel();
+        let (charge_grid, deposition) = match grid {
+            Some(_) if self.solver.method == PotentialMethod::Grid || self.solver.poisson.is_some() => {
+                let mut charge_grid = fitted()?;
+                let deposition = if self.strict { charge_grid.try_map_charges_with(&molecule, self.scheme)? } else { charge_grid.map_charges_with(&molecule, self.scheme) };
+                (Some(charge_grid), Some(deposition))
+            }
+            _ => (None, None),
+        };
+        let (charge_method, charges) = match &charge_grid {
+            Some(charge_grid) if self.solver.method == PotentialMethod::Grid => ("grid", charge_grid.sources()),
+            _ => ("direct", molecule.charge_sources()),
+        };
+        let phi = potentials_from_sources(&targets, &charges, &kernel, threads);
+        let (phi_min, phi_max) = range(&phi);
+        let strongest = fields_from_sources(&targets, &charges, &kernel, threads).iter().map(|e| e.norm()).fold(0.0, f64::max);
+        let energy = molecule.interaction_energies(&self.medium, threads);
+        let mut electrostatics = Json::object()
+            .with("charges", self.charges.name())
+            .with("charged_atoms", charged_atoms)
+            .with("net_charge_e", molecule.net_charge())
+            .with("medium", &self.medium)
+            .with("method", charge_method)
+            .with("min", phi_min)
+            .with("max", phi_max)
+            .with("mean", phi.iter().sum::<f64>() / phi.len() as f64)
+            .with("field_magnitude_max", strongest)
+            .with("energy", &energy)
+            .with("deposition", deposition.as_ref().map(charge_deposition_json));
+        if let (Some(boundary), Some(charge_grid)) = (self.solver.poisson, &charge_grid) {
+            let solved = PoissonSolver::new(boundary).with_kernel(kernel).solve(&**charge_grid);
+            let (lo, hi) = range(&solved.data);
+            electrostatics = electrostatics.with("poisson", Json::object().with("min", lo).with("max", hi).with("at_com", solved.sample(&com)));
+        }
+        results = results.with("electrostatics", electrostatics);
+
+        let mut written = Vec::new();
+        for (key, path) in self.outputs.paths() {
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                at_path(dir, std::fs::create_dir_all(dir))?;
+            }
+            let written_ok = match key {
+                "structure" => {
+                    let format = path.extension().and_then(|e| e.to_str()).and_then(|e| e.parse().ok()).unwrap_or(StructureFormat::Pdb);
+                    write_structure(&molecule, format, path)
+                }
+                "density" => grid.as_ref().expect("density output builds a grid").export_mrc(path),
+                "potential_map" => potential_map.as_ref().expect("checked when parsed").save_mrc(path),
+                _ => write_potential_table(path, &targets, &self_potentials, &env_potentials, &fields),
+            };
+            at_path(path, written_ok)?;
+            written.push(Json::object().with("kind", key).with("path", path.display().to_string()));
+        }
+        Ok(results.with("outputs", written))
+    }
+}
+
+/// One row per target: self and environment \Phi separately, then the total field
+pub fn write_potential_table<P: AsRef<Path>>(path: P, targets: &[Vector3], self_potentials: &[f64], env_potentials: &[f64], fields: &[Vector3]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(out, "index\tx_A\ty_A\tz_A\tphi_self_J_kg\tphi_env_J_kg\tgx_m_s2\tgy_m_s2\tgz_m_s2")?;
+    for (i, (((p, phi), env), g)) in targets.iter().zip(self_potentials).zip(env_potentials).zip(fields).enumerate() {
+        writeln!(out, "{}\t{:.6}\t{:.6}\t{:.6}\t{:.9e}\t{:.9e}\t{:.9e}\t{:.9e}\t{:.9e}", i, p.x, p.y, p.z, phi, env, g.x, g.y, g.z)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Vec<Scenario>, ScenarioError> {
+        parse_scenarios(&Json::parse(text).unwrap(), Path::new("runs"))
+    }
+
+    fn rejection(text: &str) -> String {
+        match parse(text) {
+            Err(err @ ScenarioError::Invalid { .. }) => err.to_string(),
+            other => panic!("expected an invalid scenario, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn entries_override_the_defaults_key_by_key() {
+        let scenarios = parse(
+            r#"{"defaults": {"molecule": {"num_bp": 4, "radius": 12}, "grid": {"resolution": 2, "padding": 3}, "solver": {"method": "octree"}},
+                "scenarios": [{"name": "plain"}, {"name": "fine", "molecule": {"num_bp": 6}, "grid": {"resolution": 0.5}, "environment": "earth"}]}"#,
+        )
+        .unwrap();
+        let [plain, fine] = &scenarios[..] else { panic!("two scenarios") };
+        assert_eq!(plain.molecule, MoleculeSource::Helix { num_base_pairs: 4, radius: 12.0, pitch: 34.0 });
+        assert_eq!((plain.grid.resolution, plain.grid.padding), (2.0, 3.0));
+        assert_eq!(plain.environment, EnvironmentPreset::Vacuum);
+        // Only the keys an entry names change; its siblings keep the defaults
+        assert_eq!(fine.molecule, MoleculeSource::Helix { num_base_pairs: 6, radius: 12.0, pitch: 34.0 });
+        assert_eq!((fine.grid.resolution, fine.grid.padding), (0.5, 3.0));
+        assert_eq!(fine.solver.method, PotentialMethod::Octree);
+        assert_eq!(fine.environment, EnvironmentPreset::EarthSurface);
+        assert_eq!(fine.config.get("grid").and_then(|g| g.get("padding")).and_then(Json::as_f64), Some(3.0));
+    }
+
+    #[test]
+    fn sweeps_expand_to_the_cartesian_product_first_key_slowest() {
+        let scenarios = parse(
+            r#"{"scenarios": [{"name": "scan", "sweep": {"grid.resolution": [1, 0.5], "solver.method": ["direct", "octree", "grid"]},
+                               "outputs": {"potentials": "out/{name}.tsv"}}, {}]}"#,
+        )
+        .unwrap();
+        let names: Vec<&str> = scenarios.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["scan-1-direct", "scan-1-octree", "scan-1-grid", "scan-0.5-direct", "scan-0.5-octree", "scan-0.5-grid", "scenario-2"]
+        );
+        assert_eq!(scenarios[4].grid.resolution, 0.5);
+        assert_eq!(scenarios[4].solver.method, PotentialMethod::Octree);
+        assert_eq!(scenarios[4].outputs.potentials.as_deref(), Some(Path::new("runs/out/scan-0.5-octree.tsv")));
+        assert!(scenarios[4].config.get("sweep").is_none());
+
+        assert!(rejection(r#"{"name": "s", "sweep": {"name": ["a", "b"]}}"#).contains("cannot sweep 'name'"));
+        assert!(rejection(r#"{"name": "s", "sweep": {"grid.resolution": []}}"#).contains("sweep.grid.resolution must be a non-empty array"));
+    }
+
+    #[test]
+    fn duplicate_names_and_shared_outputs_are_rejected() {
+        let err = rejection(r#"{"scenarios": [{"name": "twin"}, {"name": "twin"}]}"#);
+        assert_eq!(err, "scenario 'twin': the name is used more than once");
+        // A sweep naming its values the same way also collides
+        assert!(rejection(r#"{"name": "s", "sweep": {"grid.padding": [1, 1.0]}}"#).contains("used more than once"));
+
+        let err = rejection(r#"{"defaults": {"outputs": {"structure": "model.pdb"}}, "scenarios": [{"name": "a"}, {"name": "b"}]}"#);
+        assert!(err.starts_with("scenario 'b': outputs.structure") && err.contains("also written by 'a'"), "{}", err);
+        let err = rejection(r#"{"scenarios": [{"name": "a", "outputs": {"density": "x.mrc"}}, {"name": "b", "outputs": {"potentials": "x.mrc"}}]}"#);
+        assert!(err.contains("outputs.potentials") && err.contains("use {name}"), "{}", err);
+        assert_eq!(parse(r#"{"defaults": {"outputs": {"structure": "{name}.pdb"}}, "scenarios": [{"name": "a"}, {"name": "b"}]}"#).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn unusable_grids_fail_the_run_instead_of_panicking() {
+        let scenarios = parse(
+            r#"{"defaults": {"molecule": {"num_bp": 3}, "solver": {"method": "grid"}},
+                "scenarios": [{"name": "tiny", "grid": {"resolution": 1e-9}}, {"name": "padded", "grid": {"padding": 1e300}},
+                              {"name": "coarse", "grid": {"resolution": 1e300}}, {"name": "capped", "grid": {"resolution": 1e-9, "max_mem_mb": 1}}]}"#,
+        )
+        .unwrap();
+        for scenario in &scenarios[..2] {
+            assert!(matches!(scenario.run(1), Err(GqbmeError::GridTooLarge { .. })), "{}", scenario.name);
+        }
+        // A single voxel, or a resolution coarsened under the cap, still runs
+        for scenario in &scenarios[2..] {
+            let results = scenario.run(1).unwrap();
+            assert!(results.get("grid").is_some(), "{}", scenario.name);
+        }
+        assert!(rejection(r#"{"name": "s", "grid": {"resolution": 0}}"#).contains("grid.resolution must be positive"));
+        assert!(rejection(r#"{"name": "s", "grid": {"padding": -1}}"#).contains("grid.padding must be non-negative"));
+    }
+}
@@ -0,0 +1,386 @@
+use crate::density_grid::DensityGrid;
+use crate::fft::{frequency_index, Complex, Fft3};
+use crate::gqbme_engine::{MassDensityGrid, Vector3, ANGSTROM_TO_M, DALTON_TO_KG, ELECTRON_MASS_DA, HBAR};
+use crate::poisson::{Boundary, PoissonSolver, PotentialGrid};
+use std::f64::consts::PI;
+
+/// Complex wavefunction \psi on the lattice of a `MassDensityGrid`
+/// Values are in m^{-3/2}, normalised so that \sum |\psi|^2 dV = 1.
+#[derive(Debug, Clone)]
+pub struct Wavefunction {
+    pub origin: Vector3,
+    pub resolution: f64, // voxel edge in Angstroms
+    pub dimensions: (usize, usize, usize),
+    pub data: Vec<Complex>,
+}
+
+impl Wavefunction {
+    /// Normalised Gaussian packet of width `sigma` (Angstroms) with mean wavevector `k` (1/Angstrom)
+    pub fn gaussian(grid: &MassDensityGrid, center: Vector3, sigma: f64, k: Vector3) -> Self {
+        let (nx, ny, nz) = grid.dimensions;
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for x in 0..nx {
+            for y in 0..ny {
+                for z in 0..nz {
+                    let r = grid.voxel_position(x, y, z) - center;
+                    let amplitude = (-r.dot(&r) / (4.0 * sigma * sigma)).exp();
+                    data.push(Complex::from_polar(amplitude, k.dot(&r)));
+                }
+            }
+        }
+        let mut psi = Self {
+            origin: grid.origin,
+            resolution: grid.resolution,
+            dimensions: grid.dimensions,
+            data,
+        };
+        psi.normalize();
+        psi
+    }
+
+    fn voxel_volume(&self) -> f64 {
+        (self.resolution * ANGSTROM_TO_M).powi(3)
+    }
+
+    /// \int |\psi|^2 dV
+    pub fn norm(&self) -> f64 {
+        self.data.iter().map(|c| c.norm_sqr()).sum::<f64>() * self.voxel_volume()
+    }
+
+    pub fn normalize(&mut self) {
+        let norm = self.norm();
+        if norm > 0.0 {
+            let s = 1.0 / norm.sqrt();
+            self.data.iter_mut().for_each(|c| *c = c.scale(s));
+        }
+    }
+
+    /// Probability per voxel, |\psi|^2 dV
+    pub fn probabilities(&self) -> Vec<f64> {
+        let dv = self.voxel_volume();
+        self.data.iter().map(|c| c.norm_sqr() * dv).collect()
+    }
+
+    /// Expectation value of position in Angstroms
+    pub fn mean_position(&self) -> Vector3 {
+        let (_, ny, nz) = self.dimensions;
+        let mut mean = Vector3::default();
+        for (idx, p) in self.probabilities().into_iter().enumerate() {
+            let (x, y, z) = (idx / (ny * nz), (idx / nz) % ny, idx % nz);
+            mean += Vector3::new(
+                self.origin.x + x as f64 * self.resolution,
+                self.origin.y + y as f64 * self.resolution,
+                self.origin.z + z as f64 * self.resolution,
+            ) * p;
+        }
+        mean * (1.0 / self.norm().max(f64::MIN_POSITIVE))
+    }
+}
+
+/// Physical parameters of the Schrödinger-Newton evolution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnParameters {
+    pub mass: f64,        // particle mass in Daltons, also the mass sourcing self-gravity
+    pub time_step: f64,   // seconds
+    pub boundary: Boundary,
+    pub self_gravity: bool, // include \Phi sourced by m |\psi|^2
+}
+
+impl Default for SnParameters {
+    fn default() -> Self {
+        Self {
+            mass: ELECTRON_MASS_DA,
+            time_step: 1e-18,
+            boundary: Boundary::Isolated,
+            self_gravity: true,
+        }
+    }
+}
+
+/// Energy expectation values in Joules
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EnergyBreakdown {
+    pub kinetic: f64,
+    pub external: f64,
+    pub atomic: f64,       // <m \Phi_{atoms}>
+    pub self_gravity: f64, // \frac{1}{2} <m \Phi_{\psi}>, halved so the pair energy is not double counted
+    pub total: f64,
+}
+
+/// Diagnostics sampled during `SchrodingerNewton::run`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepDiagnostics {
+    pub step: usize,
+    pub time: f64, // seconds
+    pub norm: f64,
+    pub energy: EnergyBreakdown,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EvolutionReport {
+    pub samples: Vec<StepDiagnostics>,
+    pub max_norm_drift: f64,   // max |N(t) - N(0)|
+    pub max_energy_drift: f64, // max |E(t) - E(0)| / |E(0)|, or absolute if E(0) = 0
+}
+
+/// Split-step Fourier propagator for
+/// i\hbar \partial_t \psi = -\frac{\hbar^2}{2m}\nabla^2\psi + (V_{ext} + m\Phi)\psi,
+/// \nabla^2\Phi = 4\pi G(\rho_{atoms} + m|\psi|^2)
+/// The kinetic step treats the box as periodic, so keep \psi away from the faces.
+#[derive(Debug, Clone)]
+pub struct SchrodingerNewton {
+    pub params: SnParameters,
+    psi: Wavefunction,
+    fft: Fft3,
+    k_squared: Vec<f64>,         // |k|^2 in m^-2 for each Fourier bin
+    atom_potential: Vec<f64>,    // \Phi_{atoms}, J/kg, fixed during the run
+    self_potential: Vec<f64>,    // \Phi_{\psi}, J/kg, re-solved every step
+    external: Option<Vec<f64>>, // V_{ext}, J per voxel
+    time: f64,
+    steps: usize,
+}
+
+impl SchrodingerNewton {
+    /// Sets up the propagator; `None` if `psi` is not on the lattice of `grid`
+    /// \Phi is linear in the source, so the atomic part is solved once here and only the
+    /// |\psi|^2 part is re-solved as \psi evolves.
+    pub fn new(grid: &MassDensityGrid, psi: Wavefunction, params: SnParameters) -> Option<Self> {
+        if psi.dimensions != grid.dimensions || psi.resolution != grid.resolution || psi.origin != grid.origin {
+            return None;
+        }
+        let solver = PoissonSolver::new(params.boundary);
+        let atom_potential = solver.solve(grid).data;
+
+        let (nx, ny, nz) = grid.dimensions;
+        let h = grid.resolution * ANGSTROM_TO_M;
+        let wave = |i: usize, n: usize| 2.0 * PI * frequency_index(i, n) / (n as f64 * h);
+        let mut k_squared = Vec::with_capacity(nx * ny * nz);
+        for x in 0..nx {
+            for y in 0..ny {
+                for z in 0..nz {
+                    let (kx, ky, kz) = (wave(x, nx), wave(y, ny), wave(z, nz));
+                    k_squared.push(kx * kx + ky * ky + kz * kz);
+                }
+            }
+        }
+
+        let mut sn = Self {
+            params,
+            fft: Fft3::new(grid.dimensions),
+            k_squared,
+            atom_potential,
+            self_potential: vec![0.0; nx * ny * nz],
+            external: None,
+            psi,
+            time: 0.0,
+            steps: 0,
+        };
+        sn.update_self_potential();
+        Some(sn)
+    }
+
+    /// Adds an external potential energy (J) per voxel; `None` if the length is wrong
+    pub fn with_external_potential(mut self, potential: Vec<f64>) -> Option<Self> {
+        if potential.len() != self.psi.data.len() {
+            return None;
+        }
+        self.external = Some(potential);
+        Some(self)
+    }
+
+    pub fn wavefunction(&self) -> &Wavefunction {
+        &self.psi
+    }
+
+    /// Elapsed simulated time in seconds
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Total \Phi = \Phi_{atoms} + \Phi_{\psi} on the lattice
+    pub fn potential(&self) -> PotentialGrid {
+        PotentialGrid {
+            origin: self.psi.origin,
+            resolution: self.psi.resolution,
+            dimensions: self.psi.dimensions,
+            data: self.atom_potential.iter().zip(&self.self_potential).map(|(a, s)| a + s).collect(),
+        }
+    }
+
+    fn mass_kg(&self) -> f64 {
+        self.params.mass * DALTON_TO_KG
+    }
+
+    fn update_self_potential(&mut self) {
+        if !self.params.self_gravity {
+            return;
+        }
+        let mut source = MassDensityGrid::new(self.psi.origin, self.psi.resolution, self.psi.dimensions);
+        // Voxel masses in Daltons, as the Poisson solver expects
+        for (m, p) in source.data.iter_mut().zip(self.psi.probabilities()) {
+            *m = self.params.mass * p;
+        }
+        self.self_potential = PoissonSolver::new(self.params.boundary).solve(&source).data;
+    }
+
+    /// Potential energy V = V_{ext} + m\Phi (J) at voxel `i`
+    fn potential_energy(&self, i: usize) -> f64 {
+        let external = self.external.as_ref().map_or(0.0, |v| v[i]);
+        external + self.mass_kg() * (self.atom_potential[i] + self.self_potential[i])
+    }
+
+    fn potential_half_step(&mut self) {
+        let factor = -0.5 * self.params.time_step / HBAR;
+        for i in 0..self.psi.data.len() {
+            let phase = Complex::from_polar(1.0, factor * self.potential_energy(i));
+            self.psi.data[i] = self.psi.data[i] * phase;
+        }
+    }
+
+    /// One Strang step: half potential kick, full kinetic drift in k-space,
+    /// re-solve \Phi_{\psi} from the drifted density, second half kick
+    pub fn step(&mut self) {
+        self.potential_half_step();
+
+        self.fft.forward(&mut self.psi.data);
+        let factor = -HBAR * self.params.time_step / (2.0 * self.mass_kg());
+        for (c, &k2) in self.psi.data.iter_mut().zip(&self.k_squared) {
+            *c = *c * Complex::from_polar(1.0, factor * k2);
+        }
+        self.fft.inverse(&mut self.psi.data);
+
+        self.update_self_potential();
+        self.potential_half_step();
+        self.time += self.params.time_step;
+        self.steps += 1;
+    }
+
+    /// Expectation values of each energy term for the current \psi
+    pub fn energy(&self) -> EnergyBreakdown {
+        let dv = self.psi.voxel_volume();
+        let m = self.mass_kg();
+
+        let mut spectrum = self.psi.data.clone();
+        self.fft.forward(&mut spectrum);
+        // Parseval for the unnormalised forward transform: \sum|\psi|^2 = \frac{1}{N}\sum|\hat\psi|^2
+        let n = spectrum.len() as f64;
+        let kinetic = spectrum.iter().zip(&self.k_squared).map(|(c, &k2)| c.norm_sqr() * k2).sum::<f64>() * HBAR * HBAR / (2.0 * m) * dv / n;
+
+        let (mut external, mut atomic, mut self_gravity) = (0.0, 0.0, 0.0);
+        for (i, p) in self.psi.probabilities().into_iter().enumerate() {
+            external += self.external.as_ref().map_or(0.0, |v| v[i]) * p;
+            atomic += m * self.atom_potential[i] * p;
+            self_gravity += 0.5 * m * self.self_potential[i] * p;
+        }
+        EnergyBreakdown {
+            kinetic,
+            external,
+            atomic,
+            self_gravity,
+            total: kinetic + external + atomic + self_gravity,
+        }
+    }
+
+    fn diagnostics(&self) -> StepDiagnostics {
+        StepDiagnostics {
+            step: self.steps,
+            time: self.time,
+            norm: self.psi.norm(),
+            energy: self.energy(),
+        }
+    }
+
+    /// Advances `steps` steps, sampling diagnostics every `sample_every` steps (and at both ends)
+    pub fn run(&mut self, steps: usize, sample_every: usize) -> EvolutionReport {
+        let first = self.diagnostics();
+        let mut report = EvolutionReport { samples: vec![first], ..EvolutionReport::default() };
+        for i in 1..=steps {
+            self.step();
+            if i == steps || (sample_every > 0 && i % sample_every == 0) {
+                let sample = self.diagnostics();
+                let energy_scale = if first.energy.total != 0.0 { first.energy.total.abs() } else { 1.0 };
+                report.max_norm_drift = report.max_norm_drift.max((sample.norm - first.norm).abs());
+                report.max_energy_drift = report.max_energy_drift.max((sample.energy.total - first.energy.total).abs() / energy_scale);
+                report.samples.push(sample);
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 16 A box centred on the origin node
+    fn empty_grid(n: usize) -> MassDensityGrid {
+        let half = (n / 2) as f64 * 0.5;
+        MassDensityGrid::new(Vector3::new(-half, -half, -half), 0.5, (n, n, n))
+    }
+
+    fn without_self_gravity() -> SnParameters {
+        SnParameters { self_gravity: false, ..SnParameters::default() }
+    }
+
+    #[test]
+    fn gaussian_packet_is_normalised_with_the_analytic_kinetic_energy() {
+        let grid = empty_grid(32);
+        let (sigma, k) = (1.0, 0.5);
+        let center = Vector3::new(-0.5, 0.0, 0.0);
+        let psi = Wavefunction::gaussian(&grid, center, sigma, Vector3::new(k, 0.0, 0.0));
+        assert!((psi.norm() - 1.0).abs() < 1e-12);
+        assert!((psi.mean_position() - center).norm() < 1e-9);
+
+        let sn = SchrodingerNewton::new(&grid, psi, without_self_gravity()).unwrap();
+        // <p^2> / 2m with <k^2> = k0^2 + 3 / (4 sigma^2) for |\psi|^2 of variance sigma^2
+        let m = ELECTRON_MASS_DA * DALTON_TO_KG;
+        let k2 = (k * k + 3.0 / (4.0 * sigma * sigma)) / (ANGSTROM_TO_M * ANGSTROM_TO_M);
+        let expected = HBAR * HBAR * k2 / (2.0 * m);
+        let energy = sn.energy();
+        assert!((energy.kinetic / expected - 1.0).abs() < 1e-6, "{} vs {}", energy.kinetic, expected);
+        assert_eq!(energy.total, energy.kinetic);
+    }
+
+    #[test]
+    fn evolution_keeps_the_norm_and_the_energy() {
+        let grid = empty_grid(16);
+        let psi = Wavefunction::gaussian(&grid, Vector3::default(), 1.0, Vector3::new(0.3, -0.2, 0.0));
+        let mut sn = SchrodingerNewton::new(&grid, psi, SnParameters::default()).unwrap();
+        let report = sn.run(12, 4);
+        assert_eq!(report.samples.len(), 4);
+        assert!(report.max_norm_drift < 1e-12, "norm drift {}", report.max_norm_drift);
+        assert!(report.max_energy_drift < 1e-9, "energy drift {}", report.max_energy_drift);
+        assert!((sn.time() - 12.0 * SnParameters::default().time_step).abs() < 1e-30);
+        // Self-gravity is attractive, and negligible beside the kinetic energy of an electron
+        let energy = report.samples[0].energy;
+        assert!(energy.self_gravity < 0.0 && energy.self_gravity.abs() < 1e-30 * energy.kinetic);
+        assert_eq!(energy.atomic, 0.0);
+    }
+
+    #[test]
+    fn free_packet_moves_at_the_group_velocity() {
+        let grid = empty_grid(32);
+        let k = 0.5;
+        let psi = Wavefunction::gaussian(&grid, Vector3::new(-1.0, 0.0, 0.0), 1.0, Vector3::new(k, 0.0, 0.0));
+        let mut sn = SchrodingerNewton::new(&grid, psi, without_self_gravity()).unwrap();
+        let report = sn.run(100, 0);
+        assert!(report.max_norm_drift < 1e-12);
+        // v = \hbar k / m in m/s, with k in 1/m
+        let velocity = HBAR * (k / ANGSTROM_TO_M) / (ELECTRON_MASS_DA * DALTON_TO_KG);
+        let expected = -1.0 + velocity * sn.time() / ANGSTROM_TO_M;
+        let mean = sn.wavefunction().mean_position();
+        assert!((mean.x - expected).abs() < 1e-3, "{} vs {}", mean.x, expected);
+        assert!(mean.y.abs() < 1e-9 && mean.z.abs() < 1e-9);
+    }
+
+    #[test]
+    fn rejects_a_wavefunction_on_another_lattice() {
+        let grid = empty_grid(16);
+        let other = MassDensityGrid::new(grid.origin, 0.5, (8, 16, 16));
+        let psi = Wavefunction::gaussian(&other, Vector3::default(), 1.0, Vector3::default());
+        assert!(SchrodingerNewton::new(&grid, psi, without_self_gravity()).is_none());
+        let psi = Wavefunction::gaussian(&grid, Vector3::default(), 1.0, Vector3::default());
+        let sn = SchrodingerNewton::new(&grid, psi, without_self_gravity()).unwrap();
+        assert!(sn.with_external_potential(vec![0.0; 10]).is_none());
+    }
+}
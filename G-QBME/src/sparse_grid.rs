@@ -0,0 +1,204 @@
+use crate::density_grid::DensityGrid;
+use crate::diagnostics::check_fit;
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{check_lattice, Biomolecule, MassDensityGrid, Vector3};
+use crate::grid_sizing::GridFit;
+use std::collections::BTreeMap;
+
+/// Voxels along each edge of a storage block
+const BLOCK: usize = 8;
+const BLOCK_VOXELS: usize = BLOCK * BLOCK * BLOCK;
+
+type Block = Box<[f64; BLOCK_VOXELS]>;
+
+/// Mass density grid that allocates only the 8^3-voxel blocks holding mass
+/// A 0.25 A grid around a long helix is almost all empty space, which costs nothing here.
+/// Blocks are keyed by block coordinate in a `BTreeMap`, whose order lets `for_each_nonzero`
+/// walk voxels in dense index order without sorting.
+#[derive(Debug, Clone)]
+pub struct SparseDensityGrid {
+    pub origin: Vector3,
+    pub resolution: f64, // size of one voxel in Angstroms
+    pub dimensions: (usize, usize, usize),
+    blocks: BTreeMap<(usize, usize, usize), Block>,
+}
+
+impl SparseDensityGrid {
+    /// Empty grid; panics on the inputs `try_new` rejects
+    pub fn new(origin: Vector3, resolution: f64, dims: (usize, usize, usize)) -> Self {
+        Self::try_new(origin, resolution, dims).unwrap_or_else(|e| panic!("SparseDensityGrid::new: {}", e))
+    }
+
+    /// Empty grid under the same rules as `MassDensityGrid::try_new`, except that the
+    /// voxel count only has to fit a `usize` index since nothing is allocated up front
+    pub fn try_new(origin: Vector3, resolution: f64, dims: (usize, usize, usize)) -> Result<Self, GqbmeError> {
+        check_lattice(origin, resolution, dims)?;
+        Ok(Self {
+            origin,
+            resolution,
+            dimensions: dims,
+            blocks: BTreeMap::new(),
+        })
+    }
+
+    /// Empty grid on `fit.lattice(molecule)`; `fit.max_bytes` bounds dense storage and is ignored
+    pub fn fitted_to(molecule: &Biomolecule, fit: &GridFit) -> Result<Self, GqbmeError> {
+        let (origin, resolution, dims) = GridFit { max_bytes: None, ..*fit }.lattice(molecule)?;
+        Self::try_new(origin, resolution, dims)
+    }
+
+    /// `fitted_to` for a validated, non-empty molecule and a usable `fit`
+    pub fn try_fitted_to(molecule: &Biomolecule, fit: &GridFit) -> Result<Self, GqbmeError> {
+        check_fit(molecule, fit)?;
+        Self::fitted_to(molecule, fit)
+    }
+
+    /// Sparse copy of the non-zero voxels of `dense`
+    pub fn from_dense(dense: &MassDensityGrid) -> Self {
+        let mut sparse = Self::new(dense.origin, dense.resolution, dense.dimensions);
+        let (_, ny, nz) = dense.dimensions;
+        dense.for_each_nonzero(&mut |idx, mass| sparse.add_mass(idx / (ny * nz), (idx / nz) % ny, idx % nz, mass));
+        sparse
+    }
+
+    /// Allocated 8^3 blocks
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Block key and offset within the block of voxel (x, y, z)
+    fn locate(x: usize, y: usize, z: usize) -> ((usize, usize, usize), usize) {
+        ((x / BLOCK, y / BLOCK, z / BLOCK), ((x % BLOCK) * BLOCK + y % BLOCK) * BLOCK + z % BLOCK)
+    }
+}
+
+impl DensityGrid for SparseDensityGrid {
+    fn origin(&self) -> Vector3 {
+        self.origin
+    }
+
+    fn resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    fn dimensions(&self) -> (usize, usize, usize) {
+        self.dimensions
+    }
+
+    fn mass_at(&self, x: usize, y: usize, z: usize) -> f64 {
+        let (key, offset) = Self::locate(x, y, z);
+        self.blocks.get(&key).map_or(0.0, |block| block[offset])
+    }
+
+    fn add_mass(&mut self, x: usize, y: usize, z: usize, mass: f64) {
+        let (nx, ny, nz) = self.dimensions;
+        assert!(x < nx && y < ny && z < nz, "voxel ({}, {}, {}) outside a {:?} grid", x, y, z, self.dimensions);
+        if mass == 0.0 {
+            return;
+        }
+        let (key, offset) = Self::locate(x, y, z);
+        self.blocks.entry(key).or_insert_with(|| Box::new([0.0; BLOCK_VOXELS]))[offset] += mass;
+    }
+
+    fn for_each_nonzero(&self, f: &mut dyn FnMut(usize, f64)) {
+        let (_, ny, nz) = self.dimensions;
+        let blocks: Vec<(&(usize, usize, usize), &Block)> = self.blocks.iter().collect();
+        // Keys sort by (bx, by, bz): a run of equal bx covers eight x-planes and, within it,
+        // a run of equal by covers eight y-rows, so x-, y- then z-order matches the dense layout
+        for slab in blocks.chunk_by(|a, b| a.0.0 == b.0.0) {
+            let bx = slab[0].0.0;
+            for lx in 0..BLOCK {
+                let x = bx * BLOCK + lx;
+                for row in slab.chunk_by(|a, b| a.0.1 == b.0.1) {
+                    let by = row[0].0.1;
+                    for ly in 0..BLOCK {
+                        let y = by * BLOCK + ly;
+                        let start = (lx * BLOCK + ly) * BLOCK;
+                        for &(&(_, _, bz), block) in row {
+                            for (lz, &mass) in block[start..start + BLOCK].iter().enumerate() {
+                                if mass != 0.0 {
+                                    f((x * ny + y) * nz + bz * BLOCK + lz, mass);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn storage_bytes(&self) -> usize {
+        self.blocks.len() * std::mem::size_of::<[f64; BLOCK_VOXELS]>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deposition::DepositionScheme;
+    use crate::dna_builder::{DnaBuilder, HelixForm};
+    use crate::gqbme_engine::RelativisticSolver;
+    use crate::poisson::{Boundary, PoissonSolver};
+
+    /// The same deposition into both backends, on axes that are not multiples of the block edge
+    fn pair() -> (Biomolecule, MassDensityGrid, SparseDensityGrid) {
+        let molecule = DnaBuilder::new("ACGT", HelixForm::B).expect("valid sequence").build();
+        let fit = GridFit::new(3.0, 0.9);
+        let mut dense = MassDensityGrid::fitted_to(&molecule, &fit).expect("small grid");
+        let mut sparse = SparseDensityGrid::fitted_to(&molecule, &fit).expect("small grid");
+        assert!(dense.map_biomolecule_with(&molecule, DepositionScheme::TriangularShapedCloud).is_lossless());
+        assert!(sparse.map_biomolecule_with(&molecule, DepositionScheme::TriangularShapedCloud).is_lossless());
+        let (nx, ny, nz) = dense.dimensions;
+        assert!(![nx, ny, nz].iter().all(|n| n.is_multiple_of(BLOCK)), "{:?}", dense.dimensions);
+        (molecule, dense, sparse)
+    }
+
+    fn visits<G: DensityGrid>(grid: &G) -> Vec<(usize, f64)> {
+        let mut out = Vec::new();
+        grid.for_each_nonzero(&mut |index, mass| out.push((index, mass)));
+        out
+    }
+
+    #[test]
+    fn sparse_visits_voxels_in_dense_order() {
+        let (_, dense, sparse) = pair();
+        let visited = visits(&sparse);
+        assert_eq!(visited, visits(&dense));
+        assert!(visited.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(sparse.total_mass(), dense.total_mass());
+        assert_eq!(sparse.to_dense().expect("small grid").data, dense.data);
+        assert_eq!(visits(&SparseDensityGrid::from_dense(&dense)), visited);
+    }
+
+    #[test]
+    fn sparse_and_dense_give_identical_potentials() {
+        let (molecule, dense, sparse) = pair();
+        for atom in molecule.atoms.iter().step_by(17) {
+            let p = atom.position + Vector3::new(0.1, 0.2, -0.3);
+            assert_eq!(RelativisticSolver::calculate_potential(&p, &sparse), RelativisticSolver::calculate_potential(&p, &dense));
+            assert_eq!(RelativisticSolver::calculate_field(&p, &sparse), RelativisticSolver::calculate_field(&p, &dense));
+        }
+        for boundary in [Boundary::Isolated, Boundary::Periodic] {
+            let solver = PoissonSolver::new(boundary);
+            assert_eq!(solver.solve(&sparse).data, solver.solve(&dense).data, "{:?}", boundary);
+        }
+    }
+
+    #[test]
+    fn sparse_storage_covers_only_occupied_blocks() {
+        let molecule = DnaBuilder::new("ACGTACGTACGTACGTACGT", HelixForm::B).expect("valid sequence").build();
+        let fit = GridFit::new(5.0, 0.25);
+        let mut sparse = SparseDensityGrid::fitted_to(&molecule, &fit).expect("small grid");
+        assert_eq!(sparse.storage_bytes(), 0);
+        assert!(sparse.map_biomolecule(&molecule).is_lossless());
+        assert_eq!(sparse.storage_bytes(), sparse.block_count() * BLOCK_VOXELS * 8);
+        assert!(sparse.storage_bytes() * 10 < sparse.voxel_count() * 8, "{} blocks for {} voxels", sparse.block_count(), sparse.voxel_count());
+    }
+
+    #[test]
+    #[should_panic(expected = "outside")]
+    fn add_mass_outside_the_grid_panics() {
+        let mut sparse = SparseDensityGrid::new(Vector3::default(), 1.0, (4, 4, 4));
+        sparse.add_mass(0, 4, 0, 1.0);
+    }
+}
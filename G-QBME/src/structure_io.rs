@@ -0,0 +1,771 @@
+use crate::element::Element;
+use crate::gqbme_engine::{Atom, AtomRecord, Biomolecule, Vector3};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Errors raised while reading PDB, mmCIF or XYZ structures
+#[derive(Debug)]
+pub enum StructureError {
+    Io(std::io::Error),
+    /// A record is too short or structurally broken
+    MalformedRecord { line: usize, reason: String },
+    /// A numeric field could not be parsed
+    InvalidNumber { line: usize, field: &'static str, value: String },
+    /// The element symbol is not in the element table
+    UnknownElement { line: usize, symbol: String },
+    /// A required `_atom_site` column is absent from the mmCIF loop
+    MissingColumn(&'static str),
+    /// The input contained no atom records at all
+    NoAtoms,
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructureError::Io(err) => write!(f, "I/O error: {}", err),
+            StructureError::MalformedRecord { line, reason } => {
+                write!(f, "line {}: malformed record ({})", line, reason)
+            }
+            StructureError::InvalidNumber { line, field, value } => {
+                write!(f, "line {}: invalid {} '{}'", line, field, value)
+            }
+            StructureError::UnknownElement { line, symbol } => {
+                write!(f, "line {}: unknown element '{}'", line, symbol)
+            }
+            StructureError::MissingColumn(name) => {
+                write!(f, "mmCIF _atom_site loop lacks column '{}'", name)
+            }
+            StructureError::NoAtoms => write!(f, "no atom records found"),
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+impl From<std::io::Error> for StructureError {
+    fn from(err: std::io::Error) -> Self {
+        StructureError::Io(err)
+    }
+}
+
+fn build_atom(line: usize, symbol: &str, position: Vector3, record: AtomRecord) -> Result<Atom, StructureError> {
+    let element = Element::from_symbol(symbol).map_err(|e| StructureError::UnknownElement { line, symbol: e.0 })?;
+    Ok(Atom {
+        record: Some(record),
+        ..Atom::new(element, position)
+    })
+}
+
+fn parse_field<T: std::str::FromStr>(line: usize, field: &'static str, value: &str) -> Result<T, StructureError> {
+    value.trim().parse::<T>().map_err(|_| StructureError::InvalidNumber {
+        line,
+        field,
+        value: value.trim().to_string(),
+    })
+}
+
+/// Fixed-column slice of a PDB line (1-based, inclusive columns)
+fn columns(text: &str, start: usize, end: usize) -> &str {
+    let end = end.min(text.len());
+    if start > end {
+        return "";
+    }
+    text.get(start - 1..end).unwrap_or("")
+}
+
+/// Keeps the first alternate location seen for each atom, whatever its label
+#[derive(Default)]
+struct AltLocFilter {
+    chosen: HashMap<String, String>, // atom identity -> its kept location
+}
+
+impl AltLocFilter {
+    /// Whether the record of atom `key` at `alt_loc` is kept; blank locations always are
+    fn keep(&mut self, key: String, alt_loc: &str) -> bool {
+        alt_loc.trim().is_empty() || *self.chosen.entry(key).or_insert_with(|| alt_loc.to_string()) == alt_loc
+    }
+}
+
+/// Guesses the element from a PDB atom name when columns 77-78 are blank
+fn element_from_atom_name(name_field: &str) -> String {
+    // Columns 13-14 hold the right-justified element symbol; a leading digit
+    // (e.g. "1HB ") or a blank in column 13 means a one-letter element.
+    let name_field = format!("{:<4}", name_field);
+    let bytes = name_field.as_bytes();
+    if bytes[0] == b' ' || bytes[0].is_ascii_digit() {
+        (bytes[1] as char).to_string()
+    } else {
+        let two = name_field[..2].to_ascii_uppercase();
+        if Element::from_symbol(&two).is_ok() && !name_field[..1].eq_ignore_ascii_case("H") {
+            two
+        } else {
+            (bytes[0] as char).to_string()
+        }
+    }
+}
+
+/// Parses ATOM/HETATM records of a PDB file into a `Biomolecule`
+/// Only the first MODEL and the first alternate location of each atom are kept.
+pub fn parse_pdb(text: &str) -> Result<Biomolecule, StructureError> {
+    let mut molecule = Biomolecule::new();
+    let mut alt_locs = AltLocFilter::default();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line = idx + 1;
+        let record_name = columns(raw, 1, 6).trim_end();
+        if record_name == "ENDMDL" {
+            break;
+        }
+        let hetero = match record_name {
+            "ATOM" => false,
+            "HETATM" => true,
+            _ => continue,
+        };
+        if !raw.is_ascii() {
+            return Err(StructureError::MalformedRecord { line, reason: "non-ASCII characters".to_string() });
+        }
+        if raw.len() < 54 {
+            return Err(StructureError::MalformedRecord {
+                line,
+                reason: format!("coordinate columns missing ({} characters)", raw.len()),
+            });
+        }
+
+        // Atom name, then chain, residue number and insertion code
+        let key = format!("{}|{}", columns(raw, 13, 16), columns(raw, 22, 27));
+        if !alt_locs.keep(key, columns(raw, 17, 17)) {
+            continue;
+        }
+
+        let position = Vector3::new(
+            parse_field(line, "x coordinate", columns(raw, 31, 38))?,
+            parse_field(line, "y coordinate", columns(raw, 39, 46))?,
+            parse_field(line, "z coordinate", columns(raw, 47, 54))?,
+        );
+        let record = AtomRecord {
+            serial: parse_field(line, "atom serial", columns(raw, 7, 11))?,
+            name: columns(raw, 13, 16).trim().to_string(),
+            residue_name: columns(raw, 18, 20).trim().to_string(),
+            residue_seq: parse_field(line, "residue number", columns(raw, 23, 26))?,
+            chain_id: columns(raw, 22, 22).trim().to_string(),
+            hetero,
+        };
+
+        let element_field = columns(raw, 77, 78).trim();
+        let symbol = if element_field.is_empty() {
+            element_from_atom_name(columns(raw, 13, 16))
+        } else {
+            element_field.to_string()
+        };
+        molecule.atoms.push(build_atom(line, &symbol, position, record)?);
+    }
+
+    if molecule.atoms.is_empty() {
+        return Err(StructureError::NoAtoms);
+    }
+    Ok(molecule)
+}
+
+/// Splits an mmCIF data line into tokens, honouring single and double quotes
+fn tokenize_cif(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if chars[i] == '\'' || chars[i] == '"' {
+            // A quote only closes when followed by whitespace or end of line
+            let quote = chars[i];
+            let start = i + 1;
+            let mut end = start;
+            while end < chars.len() && !(chars[end] == quote && (end + 1 == chars.len() || chars[end + 1].is_whitespace())) {
+                end += 1;
+            }
+            tokens.push(chars[start..end.min(chars.len())].iter().collect());
+            i = end + 1;
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        }
+    }
+    tokens
+}
+
+fn cif_null(value: &str) -> bool {
+    value == "." || value == "?"
+}
+
+/// Column lookup for the `_atom_site` loop
+struct AtomSiteColumns {
+    group: Option<usize>,
+    id: Option<usize>,
+    type_symbol: usize,
+    atom_name: Option<usize>,
+    residue_name: Option<usize>,
+    residue_seq: Option<usize>,
+    chain_id: Option<usize>,
+    alt_id: Option<usize>,
+    model: Option<usize>,
+    x: usize,
+    y: usize,
+    z: usize,
+}
+
+impl AtomSiteColumns {
+    fn from_headers(headers: &[String]) -> Result<Self, StructureError> {
+        let find = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let first = |names: &[&str]| names.iter().find_map(|n| find(n));
+        let require = |name: &'static str| find(name).ok_or(StructureError::MissingColumn(name));
+        Ok(Self {
+            group: find("group_PDB"),
+            id: find("id"),
+            type_symbol: require("type_symbol")?,
+            atom_name: first(&["auth_atom_id", "label_atom_id"]),
+            residue_name: first(&["auth_comp_id", "label_comp_id"]),
+            residue_seq: first(&["auth_seq_id", "label_seq_id"]),
+            chain_id: first(&["auth_asym_id", "label_asym_id"]),
+            alt_id: find("label_alt_id"),
+            model: find("pdbx_PDB_model_num"),
+            x: require("Cartn_x")?,
+            y: require("Cartn_y")?,
+            z: require("Cartn_z")?,
+        })
+    }
+}
+
+/// Parses the `_atom_site` loop of an mmCIF file into a `Biomolecule`
+/// Only the first model and the first alternate location of each atom are kept.
+pub fn parse_mmcif(text: &str) -> Result<Biomolecule, StructureError> {
+    let mut molecule = Biomolecule::new();
+    let lines: Vec<&str> = text.lines().collect();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if lines[i].trim() != "loop_" {
+            i += 1;
+            continue;
+        }
+        i += 1;
+        let mut headers = Vec::new();
+        let mut is_atom_site = false;
+        while i < lines.len() && lines[i].trim_start().starts_with('_') {
+            let item = lines[i].trim();
+            if let Some(name) = item.strip_prefix("_atom_site.") {
+                is_atom_site = true;
+                headers.push(name.to_string());
+            } else {
+                headers.push(item.to_string());
+            }
+            i += 1;
+        }
+        if !is_atom_site {
+            continue;
+        }
+
+        let cols = AtomSiteColumns::from_headers(&headers)?;
+        let mut first_model: Option<String> = None;
+        let mut alt_locs = AltLocFilter::default();
+        while i < lines.len() {
+            let raw = lines[i];
+            let trimmed = raw.trim();
+            if trimmed.starts_with("loop_") || trimmed.starts_with('_') || trimmed.starts_with("data_") || trimmed.starts_with('#') {
+                break;
+            }
+            i += 1;
+            if trimmed.is_empty() {
+                continue;
+            }
+            let line = i;
+            let tokens = tokenize_cif(raw);
+            if tokens.len() != headers.len() {
+                return Err(StructureError::MalformedRecord {
+                    line,
+                    reason: format!("expected {} fields, found {}", headers.len(), tokens.len()),
+                });
+            }
+            let get = |col: Option<usize>| col.map(|c| tokens[c].as_str()).filter(|v| !cif_null(v));
+
+            if let Some(model) = get(cols.model) {
+                match &first_model {
+                    None => first_model = Some(model.to_string()),
+                    Some(m) if m != model => continue,
+                    _ => {}
+                }
+            }
+            if let Some(alt) = get(cols.alt_id) {
+                let key = format!("{}|{}|{}", get(cols.atom_name).unwrap_or(""), get(cols.chain_id).unwrap_or(""), get(cols.residue_seq).unwrap_or(""));
+                if !alt_locs.keep(key, alt) {
+                    continue;
+                }
+            }
+
+            let position = Vector3::new(
+                parse_field(line, "Cartn_x", &tokens[cols.x])?,
+                parse_field(line, "Cartn_y", &tokens[cols.y])?,
+                parse_field(line, "Cartn_z", &tokens[cols.z])?,
+            );
+            let record = AtomRecord {
+                serial: match get(cols.id) {
+                    Some(v) => parse_field(line, "atom id", v)?,
+                    None => molecule.atoms.len() + 1,
+                },
+                name: get(cols.atom_name).unwrap_or("").to_string(),
+                residue_name: get(cols.residue_name).unwrap_or("").to_string(),
+                residue_seq: match get(cols.residue_seq) {
+                    Some(v) => parse_field(line, "residue number", v)?,
+                    None => 0,
+                },
+                chain_id: get(cols.chain_id).unwrap_or("").to_string(),
+                hetero: get(cols.group) == Some("HETATM"),
+            };
+            molecule.atoms.push(build_atom(line, &tokens[cols.type_symbol], position, record)?);
+        }
+        break;
+    }
+
+    if molecule.atoms.is_empty() {
+        return Err(StructureError::NoAtoms);
+    }
+    Ok(molecule)
+}
+
+/// Parses the first frame of an XYZ file: atom count, comment line, then `element x y z`
+/// XYZ carries no residue information, so the atoms have no `AtomRecord`.
+pub fn parse_xyz(text: &str) -> Result<Biomolecule, StructureError> {
+    let mut lines = text.lines().enumerate().map(|(idx, raw)| (idx + 1, raw));
+    let count: usize = match lines.next() {
+        Some((line, raw)) => parse_field(line, "atom count", raw)?,
+        None => return Err(StructureError::NoAtoms),
+    };
+    let mut last_line = lines.next().map_or(1, |(line, _)| line);
+
+    let mut molecule = Biomolecule::new();
+    for (line, raw) in lines.take(count) {
+        last_line = line;
+        let fields: Vec<&str> = raw.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(StructureError::MalformedRecord {
+                line,
+                reason: format!("expected 'element x y z', found {} fields", fields.len()),
+            });
+        }
+        let element = Element::from_symbol(fields[0]).map_err(|e| StructureError::UnknownElement { line, symbol: e.0 })?;
+        let position = Vector3::new(
+            parse_field(line, "x coordinate", fields[1])?,
+            parse_field(line, "y coordinate", fields[2])?,
+            parse_field(line, "z coordinate", fields[3])?,
+        );
+        molecule.atoms.push(Atom::new(element, position));
+    }
+
+    if molecule.atoms.len() < count {
+        return Err(StructureError::MalformedRecord {
+            line: last_line,
+            reason: format!("expected {} atoms, found {}", count, molecule.atoms.len()),
+        });
+    }
+    if molecule.atoms.is_empty() {
+        return Err(StructureError::NoAtoms);
+    }
+    Ok(molecule)
+}
+
+/// Reads a structure file, choosing PDB, mmCIF or XYZ from the extension (or the contents)
+pub fn read_structure<P: AsRef<Path>>(path: P) -> Result<Biomolecule, StructureError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    match ext.as_str() {
+        "cif" | "mmcif" => parse_mmcif(&text),
+        "pdb" | "ent" => parse_pdb(&text),
+        "xyz" => parse_xyz(&text),
+        _ if text.lines().any(|l| l.starts_with("_atom_site.")) => parse_mmcif(&text),
+        _ => parse_pdb(&text),
+    }
+}
+
+/// Pads a PDB atom name so one-letter elements start in column 14
+fn pdb_atom_name(name: &str, element: &str) -> String {
+    if name.len() < 4 && element.len() == 1 {
+        format!(" {:<3}", name)
+    } else {
+        format!("{:<4}", name)
+    }
+}
+
+/// Truncates a string to the width of a fixed PDB column
+fn fit(value: &str, width: usize) -> &str {
+    value.get(..width.min(value.len())).unwrap_or(value)
+}
+
+impl Biomolecule {
+    /// Fails with `InvalidInput` if a coordinate is non-finite or does not fit PDB's %8.3f
+    /// columns (-999.999 to 9999.999 A), which would shift every later field
+    fn check_pdb_coordinates(&self) -> io::Result<()> {
+        for (i, atom) in self.atoms.iter().enumerate() {
+            let p = atom.position;
+            if let Some(v) = [p.x, p.y, p.z].into_iter().find(|v| !(*v > -999.9995 && *v < 9999.9995)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("atom {} coordinate {} does not fit PDB columns (-999.999 to 9999.999 A); write XYZ instead", i, v),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes ATOM/HETATM records in PDB format, checking every coordinate first
+    /// Atoms without an `AtomRecord` are written as chain A residue 1 of "UNK".
+    pub fn write_pdb<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.check_pdb_coordinates()?;
+        for (i, atom) in self.atoms.iter().enumerate() {
+            let fallback = AtomRecord {
+                serial: i + 1,
+                name: atom.element.symbol().to_ascii_uppercase(),
+                residue_name: "UNK".to_string(),
+                residue_seq: 1,
+                chain_id: "A".to_string(),
+                hetero: false,
+            };
+            let record = atom.record.as_ref().unwrap_or(&fallback);
+            let serial = if record.serial > 0 && record.serial <= 99_999 { record.serial } else { (i + 1) % 100_000 };
+            // resSeq has four columns; larger numbers wrap as serials do so later columns stay aligned
+            let residue_seq = if (-999..=9_999).contains(&record.residue_seq) { record.residue_seq } else { record.residue_seq.rem_euclid(10_000) };
+            let element = atom.element.symbol().to_ascii_uppercase();
+            writeln!(
+                out,
+                "{:<6}{:>5} {}{:1}{:>3} {:1}{:>4}{:1}   {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}          {:>2}{:2}",
+                if record.hetero { "HETATM" } else { "ATOM" },
+                serial,
+                pdb_atom_name(fit(&record.name, 4), &element),
+                "",
+                fit(&record.residue_name, 3),
+                fit(&record.chain_id, 1),
+                residue_seq,
+                "",
+                atom.position.x,
+                atom.position.y,
+                atom.position.z,
+                1.0,
+                0.0,
+                fit(&element, 2),
+                "",
+            )?;
+        }
+        writeln!(out, "END")
+    }
+
+    /// Writes a single XYZ frame: atom count, comment line, then `element x y z`
+    pub fn write_xyz<W: Write>(&self, out: &mut W, comment: &str) -> io::Result<()> {
+        writeln!(out, "{}", self.atoms.len())?;
+        writeln!(out, "{}", comment.replace('\n', " "))?;
+        for atom in &self.atoms {
+            writeln!(
+                out,
+                "{:<2} {:>14.6} {:>14.6} {:>14.6}",
+                atom.element, atom.position.x, atom.position.y, atom.position.z
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Renders the molecule as a PDB string; fails like `write_pdb` on out-of-range coordinates
+    pub fn to_pdb_string(&self) -> io::Result<String> {
+        let mut buf = Vec::new();
+        self.write_pdb(&mut buf)?;
+        Ok(String::from_utf8(buf).expect("PDB output is ASCII"))
+    }
+
+    /// Renders the molecule as a single-frame XYZ string
+    pub fn to_xyz_string(&self, comment: &str) -> String {
+        let mut buf = Vec::new();
+        self.write_xyz(&mut buf, comment).expect("writing to a Vec cannot fail");
+        String::from_utf8(buf).expect("XYZ output is UTF-8")
+    }
+}
+
+/// Writes a multi-frame XYZ trajectory (concatenated frames, as read by VMD and PyMOL)
+pub fn write_xyz_trajectory<W: Write>(frames: &[Biomolecule], out: &mut W) -> io::Result<()> {
+    for (i, frame) in frames.iter().enumerate() {
+        frame.write_xyz(out, &format!("frame {}", i))?;
+    }
+    Ok(())
+}
+
+/// Output formats understood by `write_structure`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructureFormat {
+    Pdb,
+    Xyz,
+}
+
+impl std::str::FromStr for StructureFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pdb" => Ok(StructureFormat::Pdb),
+            "xyz" => Ok(StructureFormat::Xyz),
+            other => Err(format!("unknown structure format '{}' (expected pdb or xyz)", other)),
+        }
+    }
+}
+
+/// Writes a molecule to `path` in the requested format
+/// A PDB that cannot hold the coordinates is refused before `path` is created.
+pub fn write_structure<P: AsRef<Path>>(molecule: &Biomolecule, format: StructureFormat, path: P) -> io::Result<()> {
+    if format == StructureFormat::Pdb {
+        molecule.check_pdb_coordinates()?;
+    }
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    match format {
+        StructureFormat::Pdb => molecule.write_pdb(&mut out)?,
+        StructureFormat::Xyz => molecule.write_xyz(&mut out, "generated by g_qbme")?,
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One fixed-column ATOM/HETATM line; `name` is the raw four-column field
+    fn pdb_line(record: &str, serial: usize, name: &str, (residue, seq): (&str, i32), xyz: [f64; 3], (alt, occupancy): (&str, f64), element: &str) -> String {
+        format!(
+            "{:<6}{:>5} {:<4}{:1}{:>3} {:1}{:>4}    {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}          {:>2}",
+            record, serial, name, alt, residue, "A", seq, xyz[0], xyz[1], xyz[2], occupancy, 20.0, element
+        )
+    }
+
+    fn names(molecule: &Biomolecule) -> Vec<&str> {
+        molecule.atoms.iter().map(|a| a.record.as_ref().unwrap().name.as_str()).collect()
+    }
+
+    #[test]
+    fn hetatm_records_and_waters_are_kept_and_flagged() {
+        let text = [
+            pdb_line("ATOM", 1, " CA ", ("GLY", 1), [1.0, 2.0, 3.0], ("", 1.0), "C"),
+            pdb_line("HETATM", 2, "ZN  ", (" ZN", 101), [4.0, 5.0, 6.0], ("", 1.0), "ZN"),
+            pdb_line("HETATM", 3, " O  ", ("HOH", 201), [7.0, 8.0, 9.0], ("", 1.0), "O"),
+            "TER       4      HOH A 201".to_string(),
+        ]
+        .join("\n");
+        let molecule = parse_pdb(&text).unwrap();
+        let hetero: Vec<bool> = molecule.atoms.iter().map(|a| a.record.as_ref().unwrap().hetero).collect();
+        assert_eq!(hetero, [false, true, true]);
+        assert_eq!(molecule.atoms[1].element.symbol(), "Zn");
+        let record = molecule.atoms[2].record.as_ref().unwrap();
+        assert_eq!((record.serial, record.residue_name.as_str(), record.residue_seq, record.chain_id.as_str()), (3, "HOH", 201, "A"));
+        assert_eq!(molecule.atoms[2].position, Vector3::new(7.0, 8.0, 9.0));
+
+        // Callers drop waters (or all ligands) by record
+        let dry = molecule.atoms.iter().filter(|a| a.record.as_ref().is_some_and(|r| r.residue_name != "HOH")).count();
+        assert_eq!(dry, 2);
+
+        let cif = "data_x\nloop_\n_atom_site.group_PDB\n_atom_site.id\n_atom_site.type_symbol\n_atom_site.label_atom_id\n_atom_site.label_comp_id\n\
+                   _atom_site.label_asym_id\n_atom_site.label_seq_id\n_atom_site.Cartn_x\n_atom_site.Cartn_y\n_atom_site.Cartn_z\n\
+                   ATOM 1 C CA GLY A 1 1.0 2.0 3.0\nHETATM 2 O O HOH B . 7.0 8.0 9.0\n#\n";
+        let molecule = parse_mmcif(cif).unwrap();
+        let record = molecule.atoms[1].record.as_ref().unwrap();
+        assert!(record.hetero && !molecule.atoms[0].record.as_ref().unwrap().hetero);
+        assert_eq!((record.residue_name.as_str(), record.residue_seq), ("HOH", 0));
+    }
+
+    #[test]
+    fn first_alternate_location_wins_over_higher_occupancy() {
+        let text = [
+            pdb_line("ATOM", 1, " N  ", ("SER", 5), [0.0, 0.0, 0.0], ("", 1.0), "N"),
+            pdb_line("ATOM", 2, " OG ", ("SER", 5), [1.0, 0.0, 0.0], ("A", 0.30), "O"),
+            pdb_line("ATOM", 3, " OG ", ("SER", 5), [2.0, 0.0, 0.0], ("B", 0.70), "O"),
+            // Another atom may start with B; its own first location is kept
+            pdb_line("ATOM", 4, " CB ", ("SER", 5), [3.0, 0.0, 0.0], ("B", 0.70), "C"),
+            pdb_line("ATOM", 5, " CB ", ("SER", 5), [4.0, 0.0, 0.0], ("A", 0.30), "C"),
+            pdb_line("ATOM", 6, " OG ", ("SER", 6), [5.0, 0.0, 0.0], ("A", 0.50), "O"),
+        ]
+        .join("\n");
+        let molecule = parse_pdb(&text).unwrap();
+        let x: Vec<f64> = molecule.atoms.iter().map(|a| a.position.x).collect();
+        assert_eq!(x, [0.0, 1.0, 3.0, 5.0]);
+
+        let cif = "data_x\nloop_\n_atom_site.id\n_atom_site.type_symbol\n_atom_site.label_atom_id\n_atom_site.label_alt_id\n\
+                   _atom_site.label_asym_id\n_atom_site.label_seq_id\n_atom_site.occupancy\n_atom_site.Cartn_x\n_atom_site.Cartn_y\n_atom_site.Cartn_z\n\
+                   1 N N . A 5 1.0 0.0 0 0\n2 O OG A A 5 0.3 1.0 0 0\n3 O OG B A 5 0.7 2.0 0 0\n4 O OG B A 6 0.7 5.0 0 0\n";
+        let x: Vec<f64> = parse_mmcif(cif).unwrap().atoms.iter().map(|a| a.position.x).collect();
+        assert_eq!(x, [0.0, 1.0, 5.0]);
+    }
+
+    #[test]
+    fn blank_element_columns_fall_back_to_the_atom_name() {
+        let text = [
+            (" CA ", "C"),  // alpha carbon: column 13 blank
+            ("CA  ", "Ca"), // calcium ion: two-letter symbol from column 13
+            ("1HB ", "H"),  // leading digit
+            ("HG12", "H"),  // four-character hydrogen name, not mercury
+            ("FE  ", "Fe"),
+            (" O5'", "O"),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| pdb_line("HETATM", i + 1, name, ("LIG", 1), [0.0; 3], ("", 1.0), ""))
+        .collect::<Vec<_>>()
+        .join("\n");
+        let molecule = parse_pdb(&text).unwrap();
+        let symbols: Vec<&str> = molecule.atoms.iter().map(|a| a.element.symbol()).collect();
+        assert_eq!(symbols, ["C", "Ca", "H", "H", "Fe", "O"]);
+        assert_eq!(names(&molecule), ["CA", "CA", "1HB", "HG12", "FE", "O5'"]);
+
+        // An explicit element column overrides the name, and unknown names are errors
+        let explicit = pdb_line("ATOM", 1, " CA ", ("GLY", 1), [0.0; 3], ("", 1.0), "SE");
+        assert_eq!(parse_pdb(&explicit).unwrap().atoms[0].element.symbol(), "Se");
+        let unknown = pdb_line("HETATM", 7, " XX ", ("LIG", 1), [0.0; 3], ("", 1.0), "");
+        assert!(matches!(parse_pdb(&unknown), Err(StructureError::UnknownElement { line: 1, ref symbol }) if symbol == "X"));
+    }
+
+    #[test]
+    fn only_the_first_model_is_read() {
+        let text = [
+            "MODEL        1".to_string(),
+            pdb_line("ATOM", 1, " P  ", (" DA", 1), [1.0, 0.0, 0.0], ("", 1.0), "P"),
+            pdb_line("ATOM", 2, " OP1", (" DA", 1), [2.0, 0.0, 0.0], ("", 1.0), "O"),
+            "ENDMDL".to_string(),
+            "MODEL        2".to_string(),
+            pdb_line("ATOM", 1, " P  ", (" DA", 1), [9.0, 0.0, 0.0], ("", 1.0), "P"),
+            pdb_line("ATOM", 2, " OP1", (" DA", 1), [9.0, 0.0, 0.0], ("", 1.0), "O"),
+            "ENDMDL".to_string(),
+        ]
+        .join("\n");
+        let molecule = parse_pdb(&text).unwrap();
+        assert_eq!(molecule.atoms.iter().map(|a| a.position.x).collect::<Vec<_>>(), [1.0, 2.0]);
+
+        // mmCIF keeps the first model number it meets, even interleaved
+        let cif = "data_x\nloop_\n_atom_site.group_PDB\n_atom_site.type_symbol\n_atom_site.label_atom_id\n_atom_site.Cartn_x\n_atom_site.Cartn_y\n\
+                   _atom_site.Cartn_z\n_atom_site.pdbx_PDB_model_num\nATOM P P 1.0 0 0 2\nATOM P P 9.0 0 0 1\nATOM O OP1 2.0 0 0 2\n";
+        let molecule = parse_mmcif(cif).unwrap();
+        assert_eq!(molecule.atoms.iter().map(|a| a.position.x).collect::<Vec<_>>(), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn malformed_coordinates_report_their_line() {
+        let good = pdb_line("ATOM", 1, " P  ", (" DA", 1), [1.0, 0.0, 0.0], ("", 1.0), "P");
+        let mut bad = pdb_line("ATOM", 2, " OP1", (" DA", 1), [2.0, 0.0, 0.0], ("", 1.0), "O");
+        bad.replace_range(38..46, "   1.x00");
+        let text = ["REMARK   1 header".to_string(), good.clone(), bad].join("\n");
+        match parse_pdb(&text) {
+            Err(StructureError::InvalidNumber { line, field, value }) => assert_eq!((line, field, value.as_str()), (3, "y coordinate", "1.x00")),
+            other => panic!("expected an invalid y coordinate, got {:?}", other),
+        }
+        let truncated = [good, "ATOM      2  OP1  DA A   1       2.000".to_string()].join("\n");
+        assert!(matches!(parse_pdb(&truncated), Err(StructureError::MalformedRecord { line: 2, .. })));
+        assert!(matches!(parse_pdb("HEADER    DNA\nEND\n"), Err(StructureError::NoAtoms)));
+
+        let cif = "data_x\nloop_\n_atom_site.type_symbol\n_atom_site.Cartn_x\n_atom_site.Cartn_y\n_atom_site.Cartn_z\nP 1.0 0 0\nO 2.0 nan? 0\n";
+        let err = parse_mmcif(cif).unwrap_err();
+        assert!(matches!(err, StructureError::InvalidNumber { line: 8, field: "Cartn_y", .. }), "{:?}", err);
+        assert_eq!(err.to_string(), "line 8: invalid Cartn_y 'nan?'");
+        let short = "data_x\nloop_\n_atom_site.type_symbol\n_atom_site.Cartn_x\n_atom_site.Cartn_y\n_atom_site.Cartn_z\nP 1.0 0\n";
+        assert!(matches!(parse_mmcif(short), Err(StructureError::MalformedRecord { line: 7, .. })));
+        assert!(matches!(parse_mmcif("data_x\nloop_\n_atom_site.type_symbol\n_atom_site.Cartn_x\nP 1.0\n"), Err(StructureError::MissingColumn("Cartn_y"))));
+    }
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("gqbme-{}-{}", std::process::id(), name))
+    }
+
+    /// A helix plus a HETATM zinc and an atom without any record
+    fn sample() -> Biomolecule {
+        let mut molecule = crate::dna_builder::DnaBuilder::new("ACG", crate::dna_builder::HelixForm::B).unwrap().build();
+        let zinc = AtomRecord { serial: 200, name: "ZN".to_string(), residue_name: "ZN".to_string(), residue_seq: 101, chain_id: "C".to_string(), hetero: true };
+        molecule.atoms.push(Atom { record: Some(zinc), ..Atom::new(Element::from_symbol("Zn").unwrap(), Vector3::new(-999.999, 9_999.999, 0.0005)) });
+        molecule.add_atom("Na", 1.0, -2.0, 3.0).unwrap();
+        molecule
+    }
+
+    #[test]
+    fn pdb_round_trips_through_the_reader() {
+        let molecule = sample();
+        let path = scratch("round-trip.pdb");
+        write_structure(&molecule, StructureFormat::Pdb, &path).unwrap();
+        let back = read_structure(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(back.atoms.len(), molecule.atoms.len());
+        for (i, (a, b)) in molecule.atoms.iter().zip(&back.atoms).enumerate() {
+            assert_eq!(a.element, b.element, "atom {}", i);
+            assert!(a.position.dist(&b.position) < 1e-3, "atom {}: {:?} vs {:?}", i, a.position, b.position);
+            if let Some(record) = &a.record {
+                assert_eq!(Some(record), b.record.as_ref(), "atom {}", i);
+            }
+        }
+        // Recordless atoms come back as UNK residue 1 of chain A, serial by position
+        let unk = back.atoms.last().unwrap().record.clone().unwrap();
+        assert_eq!((unk.serial, unk.name.as_str(), unk.residue_name.as_str(), unk.residue_seq, unk.chain_id.as_str()), (molecule.atoms.len(), "NA", "UNK", 1, "A"));
+        assert_eq!(back.atoms.last().unwrap().element.symbol(), "Na");
+        assert!(molecule.to_pdb_string().unwrap().ends_with("END\n"));
+    }
+
+    #[test]
+    fn pdb_refuses_coordinates_outside_its_columns() {
+        for x in [10000.0, 9999.9996, -1000.0, -999.9996, f64::NAN, f64::INFINITY] {
+            let mut molecule = sample();
+            molecule.atoms[4].position.x = x;
+            let err = molecule.to_pdb_string().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "x = {}", x);
+            assert!(err.to_string().starts_with("atom 4 coordinate"), "{}", err);
+
+            // Nothing is written, not even an empty file; XYZ has no such limit
+            let path = scratch("out-of-range.pdb");
+            assert!(write_structure(&molecule, StructureFormat::Pdb, &path).is_err());
+            assert!(!path.exists());
+            if x.is_finite() {
+                let path = scratch("out-of-range.xyz");
+                write_structure(&molecule, StructureFormat::Xyz, &path).unwrap();
+                assert_eq!(read_structure(&path).unwrap().atoms[4].position.x, x);
+                std::fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn xyz_frames_round_trip_and_concatenate() {
+        let molecule = sample();
+        let text = molecule.to_xyz_string("two\nlines");
+        assert_eq!(text.lines().nth(1), Some("two lines"));
+        let back = parse_xyz(&text).unwrap();
+        assert_eq!(back.atoms.len(), molecule.atoms.len());
+        for (a, b) in molecule.atoms.iter().zip(&back.atoms) {
+            assert_eq!(a.element, b.element);
+            assert!(a.position.dist(&b.position) < 1e-6);
+            assert!(b.record.is_none());
+        }
+
+        let mut shifted = molecule.clone();
+        for atom in &mut shifted.atoms {
+            atom.position += Vector3::new(5.0, 0.0, 0.0);
+        }
+        let mut buf = Vec::new();
+        write_xyz_trajectory(&[molecule.clone(), shifted], &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let frame_lines = molecule.atoms.len() + 2;
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2 * frame_lines);
+        assert_eq!((lines[1], lines[frame_lines + 1]), ("frame 0", "frame 1"));
+        // Each frame is a complete XYZ file; the reader takes the first
+        let second = parse_xyz(&lines[frame_lines..].join("\n")).unwrap();
+        assert!((second.atoms[0].position.x - molecule.atoms[0].position.x - 5.0).abs() < 1e-6);
+        let path = scratch("trajectory.xyz");
+        std::fs::write(&path, &text).unwrap();
+        let first = read_structure(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(first.atoms.len(), molecule.atoms.len());
+        assert!(first.atoms[0].position.dist(&molecule.atoms[0].position) < 1e-6);
+    }
+}
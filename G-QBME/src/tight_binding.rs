@@ -0,0 +1,326 @@
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{Biomolecule, Vector3, DALTON_TO_KG, ELECTRON_MASS_DA};
+use crate::kernel::ELEMENTARY_CHARGE;
+use crate::linalg::{lanczos_lowest, symmetric_eigen};
+
+/// Hole-transport parameters for the base-stacking model, energies in eV
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TightBindingParameters {
+    /// On-site energies of A, C, G and T (U takes T's), roughly the bases' ionization potentials
+    pub on_site: [f64; 4],
+    pub hopping: f64,            // coupling between sites stacked `stacking_distance` apart, eV
+    pub stacking_distance: f64,  // Angstroms
+    pub hopping_decay: f64,      // 1/Angstrom; t(d) = hopping e^{-decay (d - stacking_distance)}
+    pub particle_mass: f64,      // Daltons, of the carrier that feels m\Phi
+}
+
+impl Default for TightBindingParameters {
+    /// Voityuk-style base energies, 0.1 eV stacking coupling and the electron mass
+    fn default() -> Self {
+        Self { on_site: [8.24, 8.87, 7.75, 9.14], hopping: 0.1, stacking_distance: 3.38, hopping_decay: 0.7, particle_mass: ELECTRON_MASS_DA }
+    }
+}
+
+/// A base pair of a duplex, or a single base of an unpaired strand
+#[derive(Debug, Clone, PartialEq)]
+pub struct Site {
+    pub label: String,     // "A1:B20 G-C", or "A3 T" when unpaired
+    pub position: Vector3, // centroid of the base atoms, Angstroms
+    pub energy: f64,       // on-site energy, eV; the lower base of a pair holds the carrier
+}
+
+/// Which eigensolver `TightBinding::spectrum` uses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EigenSolver {
+    /// Dense up to `DENSE_LIMIT` sites, Lanczos beyond
+    #[default]
+    Auto,
+    /// Cyclic Jacobi on the full matrix (`linalg::symmetric_eigen`)
+    Dense,
+    /// Krylov iteration touching only the stored couplings (`linalg::lanczos_lowest`)
+    Lanczos,
+}
+
+impl EigenSolver {
+    /// Largest site count `Auto` still solves densely
+    pub const DENSE_LIMIT: usize = 128;
+
+    /// The solver `Auto` stands for on a problem of `sites` sites
+    pub fn resolve(self, sites: usize) -> Self {
+        match self {
+            EigenSolver::Auto if sites <= Self::DENSE_LIMIT => EigenSolver::Dense,
+            EigenSolver::Auto => EigenSolver::Lanczos,
+            chosen => chosen,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EigenSolver::Auto => "auto",
+            EigenSolver::Dense => "dense",
+            EigenSolver::Lanczos => "lanczos",
+        }
+    }
+}
+
+impl std::str::FromStr for EigenSolver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(EigenSolver::Auto),
+            "dense" | "jacobi" => Ok(EigenSolver::Dense),
+            "lanczos" => Ok(EigenSolver::Lanczos),
+            other => Err(format!("unknown eigensolver '{}' (expected auto, dense or lanczos)", other)),
+        }
+    }
+}
+
+/// Lowest eigenpairs of one Hamiltonian, eV
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    pub values: Vec<f64>,
+    /// `vectors[k]` holds state k's amplitude on every site
+    pub vectors: Vec<Vec<f64>>,
+    pub solver: EigenSolver, // the one that ran, never `Auto`
+    pub iterations: usize,   // Jacobi sweeps or Lanczos steps
+}
+
+/// Low-lying levels of H0 and of H' = H0 + V, with the shifts V causes
+/// `first_order[k]` = <\psi_k|V|\psi_k> stays resolvable when V is far below the rounding of
+/// H0's eigenvalues, as self-gravity is, but only holds while V is small against the level
+/// spacing; `direct[k]` is the plain difference of the two solves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyShifts {
+    pub unperturbed: Spectrum,
+    pub perturbed: Spectrum,
+    pub first_order: Vec<f64>,
+    pub direct: Vec<f64>,
+}
+
+/// Nearest-neighbour tight-binding Hamiltonian over the stacked bases of a nucleic acid,
+/// the Rust port of `quantum_kernel.py`'s H' = H0 + m\Phi I with real sites in place of a
+/// random matrix
+#[derive(Debug, Clone, PartialEq)]
+pub struct TightBinding {
+    pub sites: Vec<Site>,
+    /// (i, j, t) with i < j; H_ij = -t
+    pub couplings: Vec<(usize, usize, f64)>,
+    pub particle_mass: f64, // Daltons
+}
+
+/// Nucleotide letter of a residue name ("DG", "G", "DT", "U", ...)
+fn base_letter(residue_name: &str) -> Option<usize> {
+    let name = residue_name.trim();
+    let letter = if name.len() == 2 && name.starts_with('D') { &name[1..] } else { name };
+    match letter {
+        "A" => Some(0),
+        "C" => Some(1),
+        "G" => Some(2),
+        "T" | "U" => Some(3),
+        _ => None,
+    }
+}
+
+/// (residue_seq, base as an index into `on_site`, base-atom positions) of one nucleotide
+type BaseResidue = (i32, usize, Vec<Vector3>);
+
+/// Base atoms: neither sugar (primed names) nor phosphate
+fn is_base_atom(name: &str) -> bool {
+    !name.contains('\'') && !name.contains('*') && !matches!(name, "P" | "OP1" | "OP2" | "OP3" | "O1P" | "O2P" | "O3P")
+}
+
+impl TightBinding {
+    /// One site per base pair when the molecule's first two nucleotide chains have equal
+    /// length (paired antiparallel, as `DnaBuilder` emits them), otherwise one per base of the
+    /// first nucleotide chain. Consecutive sites couple with a distance-dependent hopping.
+    pub fn from_biomolecule(molecule: &Biomolecule, params: &TightBindingParameters) -> Result<Self, GqbmeError> {
+        // Nucleotide residues grouped into chains, both in order of appearance
+        let mut chains: Vec<(String, Vec<BaseResidue>)> = Vec::new();
+        for atom in &molecule.atoms {
+            let Some(record) = &atom.record else { continue };
+            let Some(base) = base_letter(&record.residue_name) else { continue };
+            if chains.last().is_none_or(|(id, _)| *id != record.chain_id) {
+                chains.push((record.chain_id.clone(), Vec::new()));
+            }
+            let residues = &mut chains.last_mut().expect("pushed above").1;
+            if residues.last().is_none_or(|&(seq, _, _)| seq != record.residue_seq) {
+                residues.push((record.residue_seq, base, Vec::new()));
+            }
+            if is_base_atom(&record.name) {
+                residues.last_mut().expect("pushed above").2.push(atom.position);
+            }
+        }
+        let centroid = |points: &[Vector3]| points.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, p| sum + *p) * (1.0 / points.len() as f64);
+        let letter = |base: usize| ['A', 'C', 'G', 'T'][base];
+
+        let mut sites = Vec::new();
+        match chains.as_slice() {
+            [] => return Err(GqbmeError::NoNucleotides),
+            [(id1, strand1), (id2, strand2), ..] if strand1.len() == strand2.len() => {
+                for ((seq1, base1, atoms1), (seq2, base2, atoms2)) in strand1.iter().zip(strand2.iter().rev()) {
+                    let atoms: Vec<Vector3> = atoms1.iter().chain(atoms2).copied().collect();
+                    if atoms.is_empty() {
+                        return Err(GqbmeError::NoNucleotides);
+                    }
+                    sites.push(Site {
+                        label: format!("{}{}:{}{} {}-{}", id1, seq1, id2, seq2, letter(*base1), letter(*base2)),
+                        position: centroid(&atoms),
+                        energy: params.on_site[*base1].min(params.on_site[*base2]),
+                    });
+                }
+            }
+            [(id, strand), ..] => {
+                for (seq, base, atoms) in strand {
+                    if atoms.is_empty() {
+                        return Err(GqbmeError::NoNucleotides);
+                    }
+                    sites.push(Site { label: format!("{}{} {}", id, seq, letter(*base)), position: centroid(atoms), energy: params.on_site[*base] });
+                }
+            }
+        }
+
+        let couplings = sites
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                let d = (pair[1].position - pair[0].position).norm();
+                (i, i + 1, params.hopping * (-params.hopping_decay * (d - params.stacking_distance)).exp())
+            })
+            .collect();
+        Ok(Self { sites, couplings, particle_mass: params.particle_mass })
+    }
+
+    pub fn len(&self) -> usize {
+        self.sites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+
+    pub fn site_positions(&self) -> Vec<Vector3> {
+        self.sites.iter().map(|s| s.position).collect()
+    }
+
+    /// On-site m\Phi in eV from \Phi (J/kg) sampled at each site
+    pub fn gravitational_terms(&self, potentials: &[f64]) -> Vec<f64> {
+        assert_eq!(potentials.len(), self.len(), "one potential per site required");
+        let mass = self.particle_mass * DALTON_TO_KG;
+        potentials.iter().map(|phi| mass * phi / ELEMENTARY_CHARGE).collect()
+    }
+
+    /// Full matrix of H0 + diag(`on_site`), eV
+    pub fn dense(&self, on_site: &[f64]) -> Vec<Vec<f64>> {
+        let n = self.len();
+        let mut h = vec![vec![0.0; n]; n];
+        for (i, site) in self.sites.iter().enumerate() {
+            h[i][i] = site.energy + on_site[i];
+        }
+        for &(i, j, t) in &self.couplings {
+            h[i][j] = -t;
+            h[j][i] = -t;
+        }
+        h
+    }
+
+    /// y = (H0 + diag(`on_site`)) x from the stored couplings
+    pub fn apply(&self, on_site: &[f64], x: &[f64], y: &mut [f64]) {
+        for (i, site) in self.sites.iter().enumerate() {
+            y[i] = (site.energy + on_site[i]) * x[i];
+        }
+        for &(i, j, t) in &self.couplings {
+            y[i] -= t * x[j];
+            y[j] -= t * x[i];
+        }
+    }
+
+    /// Lowest `count` levels of H0 + diag(`on_site`)
+    pub fn spectrum(&self, on_site: &[f64], count: usize, solver: EigenSolver) -> Spectrum {
+        assert_eq!(on_site.len(), self.len(), "one on-site term per site required");
+        let count = count.min(self.len());
+        let solver = solver.resolve(self.len());
+        if solver == EigenSolver::Dense {
+            let eigen = symmetric_eigen(&self.dense(on_site));
+            return Spectrum { values: eigen.values[..count].to_vec(), vectors: eigen.vectors[..count].to_vec(), solver, iterations: eigen.sweeps };
+        }
+        let eigen = lanczos_lowest(self.len(), count, 1e-12, |x, y| self.apply(on_site, x, y));
+        Spectrum { values: eigen.values, vectors: eigen.vectors, solver, iterations: eigen.steps }
+    }
+
+    /// Lowest `count` levels with and without the on-site perturbation `on_site` (eV)
+    pub fn energy_shifts(&self, on_site: &[f64], count: usize, solver: EigenSolver) -> EnergyShifts {
+        let unperturbed = self.spectrum(&vec![0.0; self.len()], count, solver);
+        let perturbed = self.spectrum(on_site, count, solver);
+        let first_order = unperturbed.vectors.iter().map(|psi| psi.iter().zip(on_site).map(|(a, v)| a * a * v).sum()).collect();
+        let direct = perturbed.values.iter().zip(&unperturbed.values).map(|(e, e0)| e - e0).collect();
+        EnergyShifts { unperturbed, perturbed, first_order, direct }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(energies: &[f64], t: f64) -> TightBinding {
+        let sites = energies
+            .iter()
+            .enumerate()
+            .map(|(i, &energy)| Site { label: format!("A{}", i + 1), position: Vector3::new(0.0, 0.0, 3.38 * i as f64), energy })
+            .collect();
+        let couplings = (1..energies.len()).map(|i| (i - 1, i, t)).collect();
+        TightBinding { sites, couplings, particle_mass: ELECTRON_MASS_DA }
+    }
+
+    #[test]
+    fn two_sites_split_by_twice_the_hopping() {
+        let dimer = model(&[7.75, 7.75], 0.1);
+        for solver in [EigenSolver::Dense, EigenSolver::Lanczos] {
+            let spectrum = dimer.spectrum(&[0.0, 0.0], 2, solver);
+            assert_eq!(spectrum.solver, solver);
+            assert!((spectrum.values[0] - 7.65).abs() < 1e-12, "{:?}: {}", solver, spectrum.values[0]);
+            assert!((spectrum.values[1] - 7.85).abs() < 1e-12, "{:?}: {}", solver, spectrum.values[1]);
+            // Bonding state: equal amplitudes of one sign
+            let psi = &spectrum.vectors[0];
+            assert!((psi[0] - psi[1]).abs() < 1e-10 && (psi[0].abs() - 0.5f64.sqrt()).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn solvers_agree_and_vectors_are_orthonormal() {
+        let energies: Vec<f64> = (0..30).map(|i| [8.24, 8.87, 7.75, 9.14][i * 7 % 4] + 0.01 * i as f64).collect();
+        let chain = model(&energies, 0.1);
+        let zero = vec![0.0; chain.len()];
+        let dense = chain.spectrum(&zero, 4, EigenSolver::Dense);
+        let lanczos = chain.spectrum(&zero, 4, EigenSolver::Lanczos);
+        for k in 0..4 {
+            assert!((dense.values[k] - lanczos.values[k]).abs() < 1e-10, "level {}: {} vs {}", k, dense.values[k], lanczos.values[k]);
+        }
+        for spectrum in [&dense, &lanczos] {
+            for (a, u) in spectrum.vectors.iter().enumerate() {
+                for (b, v) in spectrum.vectors.iter().enumerate() {
+                    let dot: f64 = u.iter().zip(v).map(|(x, y)| x * y).sum();
+                    assert!((dot - if a == b { 1.0 } else { 0.0 }).abs() < 1e-10, "{:?} <v{}|v{}> = {}", spectrum.solver, a, b, dot);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn first_order_shift_matches_direct_for_a_small_uniform_term() {
+        let chain = model(&[8.24, 7.75, 8.87, 7.75, 9.14, 8.24], 0.1);
+        let shift = vec![1e-3; chain.len()];
+        let shifts = chain.energy_shifts(&shift, 3, EigenSolver::Dense);
+        for k in 0..3 {
+            assert!((shifts.first_order[k] - 1e-3).abs() < 1e-12);
+            assert!((shifts.direct[k] - shifts.first_order[k]).abs() < 1e-10, "level {}: direct {} vs first order {}", k, shifts.direct[k], shifts.first_order[k]);
+        }
+    }
+
+    #[test]
+    fn base_letters() {
+        assert_eq!(base_letter("DG"), Some(2));
+        assert_eq!(base_letter(" U "), Some(3));
+        assert_eq!(base_letter("ALA"), None);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::density_grid::DensityGrid;
+use crate::deposition::DepositionScheme;
+use crate::dna_builder::DnaBuilder;
+use crate::environment::Environment;
+use crate::error::GqbmeError;
+use crate::gqbme_engine::{Biomolecule, MassDensityGrid, RelativisticSolver, TORSION_COUPLING};
+use crate::grid_sizing::GridFit;
+use crate::poisson::{Boundary, PoissonSolver};
+
+/// Self-consistent helix relaxation: twist -> structure -> \Phi -> twist
+/// Each step's twist is reset to \Omega_0 + \alpha\Phi_k, where \Omega_0 is the form's canonical
+/// twist for that step and \Phi_k is sampled midway between the two base-pair centres, until no twist moves by more than `tolerance`.
+#[derive(Debug, Clone)]
+pub struct TorsionFeedback {
+    pub builder: DnaBuilder,
+    pub fit: GridFit,
+    pub scheme: DepositionScheme,
+    pub boundary: Boundary,
+    pub coupling: f64,  // \alpha, degrees per J/kg
+    pub tolerance: f64, // degrees
+    pub max_iterations: usize,
+    /// Fraction of the new twist taken each iteration; below 1 damps oscillation
+    pub mixing: f64,
+    /// Background gravity added to the self-potential at every step midpoint
+    pub environment: Environment,
+}
+
+/// Output of `TorsionFeedback::run`
+#[derive(Debug, Clone)]
+pub struct TorsionProfile {
+    pub structure: Biomolecule,
+    pub twists: Vec<f64>,     // degrees, one per base-pair step
+    pub pitches: Vec<f64>,    // local pitch rise * 360 / |twist|, Angstroms
+    pub potentials: Vec<f64>, // self plus environment \Phi at each step midpoint of `structure`, J/kg
+    pub iterations: usize,
+    pub converged: bool,
+    pub residuals: Vec<f64>, // max |\Delta twist| per iteration, degrees
+    pub lost_mass: f64,      // most Daltons any iteration deposited off the grid; nonzero biases \Phi
+}
+
+impl TorsionFeedback {
+    pub fn new(builder: DnaBuilder) -> Self {
+        Self {
+            builder,
+            fit: GridFit::default(),
+            scheme: DepositionScheme::CloudInCell,
+            boundary: Boundary::Isolated,
+            coupling: TORSION_COUPLING,
+            tolerance: 1e-6,
+            max_iterations: 50,
+            mixing: 1.0,
+            environment: Environment::vacuum(),
+        }
+    }
+
+    /// \Phi at the midpoint of every base-pair step of the helix built with `twists`,
+    /// with the mass (Daltons) the deposition lost off the grid
+    fn step_potentials(&self, structure: &Biomolecule, twists: &[f64]) -> Result<(Vec<f64>, f64), GqbmeError> {
+        let mut grid = MassDensityGrid::try_fitted_to(structure, &self.fit)?;
+        let deposition = grid.map_biomolecule_with(structure, self.scheme);
+        let potential = PoissonSolver::new(self.boundary).solve(&grid);
+
+        let frames = self.builder.frames(twists);
+        let potentials = frames
+            .windows(2)
+            .map(|pair| {
+                let midpoint = (pair[0].origin + pair[1].origin) * 0.5;
+                let own = potential.sample(&midpoint).unwrap_or_else(|| RelativisticSolver::calculate_potential(&midpoint, &grid));
+                own + self.environment.potential_at(&midpoint)
+            })
+            .collect();
+        Ok((potentials, deposition.lost_mass))
+    }
+
+    /// Iterates to convergence or `max_iterations`; a non-finite twist, from a NaN potential
+    /// or coupling, is `TorsionDiverged` rather than a converged profile of NaNs
+    pub fn run(&self) -> Result<TorsionProfile, GqbmeError> {
+        let geometry = self.builder.form.geometry();
+        let canonical = self.builder.canonical_twists();
+        let steps = canonical.len();
+        let mut twists = canonical.clone();
+        let mut structure = self.builder.build_with_twists(&twists)?;
+        let mut potentials = Vec::new();
+        let mut residuals = Vec::new();
+        let mut lost_mass: f64 = 0.0;
+        let mut converged = steps == 0;
+
+        while !converged && residuals.len() < self.max_iterations {
+            let (sampled, lost) = self.step_potentials(&structure, &twists)?;
+            lost_mass = lost_mass.max(lost);
+            let mut residual: f64 = 0.0;
+            for ((twist, &phi), &base) in twists.iter_mut().zip(&sampled).zip(&canonical) {
+                let target = RelativisticSolver::compute_metric_torsion_with(base, phi, self.coupling);
+                let updated = *twist + self.mixing * (target - *twist);
+                // f64::max would skip a NaN and let the loop report convergence
+                if !updated.is_finite() {
+                    return Err(GqbmeError::TorsionDiverged { iteration: residuals.len() + 1 });
+                }
+                residual = residual.max((updated - *twist).abs());
+                *twist = updated;
+            }
+            residuals.push(residual);
+            structure = self.builder.build_with_twists(&twists)?;
+            converged = residual <= self.tolerance;
+        }
+        // Resample on the final structure so the potentials describe the helix returned
+        if steps > 0 {
+            let (sampled, lost) = self.step_potentials(&structure, &twists)?;
+            potentials = sampled;
+            lost_mass = lost_mass.max(lost);
+        }
+
+        let pitches = twists
+            .iter()
+            .map(|t| if *t != 0.0 { geometry.rise * 360.0 / t.abs() } else { f64::INFINITY })
+            .collect();
+        Ok(TorsionProfile {
+            structure,
+            twists,
+            pitches,
+            potentials,
+            iterations: residuals.len(),
+            converged,
+            residuals,
+            lost_mass,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dna_builder::HelixForm;
+
+    fn feedback(coupling: f64) -> TorsionFeedback {
+        let builder = DnaBuilder::new("ACGT", HelixForm::B).expect("valid sequence");
+        TorsionFeedback { fit: GridFit::new(3.0, 1.5), coupling, tolerance: 1e-9, ..TorsionFeedback::new(builder) }
+    }
+
+    /// Enough coupling to unwind each step by a few hundredths of a degree
+    const STRONG: f64 = 1e23;
+
+    #[test]
+    fn zero_coupling_leaves_the_canonical_twist_fixed() {
+        let profile = feedback(0.0).run().expect("small grid");
+        let twist = HelixForm::B.geometry().twist;
+        assert!(profile.converged);
+        assert_eq!((profile.iterations, profile.residuals.as_slice()), (1, &[0.0][..]));
+        assert!(profile.twists.iter().all(|&t| t == twist), "{:?}", profile.twists);
+        assert_eq!(profile.potentials.len(), 3);
+        assert!(profile.potentials.iter().all(|&phi| phi < 0.0));
+    }
+
+    #[test]
+    fn feedback_converges_to_a_self_consistent_twist() {
+        let profile = feedback(STRONG).run().expect("small grid");
+        let twist = HelixForm::B.geometry().twist;
+        assert!(profile.converged, "{:?}", profile.residuals);
+        assert!(profile.residuals.windows(2).all(|w| w[1] < w[0]), "{:?}", profile.residuals);
+        for (&t, &phi) in profile.twists.iter().zip(&profile.potentials) {
+            // Attractive \Phi unwinds the helix
+            assert!(t < twist - 0.01, "{} vs {}", t, twist);
+            // The potentials are those of the returned structure, so they reproduce its twists
+            assert!((t - RelativisticSolver::compute_metric_torsion_with(twist, phi, STRONG)).abs() < 1e-8);
+        }
+        let rebuilt = feedback(0.0).builder.build_with_twists(&profile.twists).expect("one twist per step");
+        assert!(profile.structure.atoms.iter().zip(&rebuilt.atoms).all(|(a, b)| a.position == b.position));
+    }
+
+    #[test]
+    fn mixing_damps_each_step_but_reaches_the_same_fixed_point() {
+        let full = feedback(STRONG).run().expect("small grid");
+        let damped = TorsionFeedback { mixing: 0.5, ..feedback(STRONG) }.run().expect("small grid");
+        assert!(damped.converged);
+        assert!((damped.residuals[0] / full.residuals[0] - 0.5).abs() < 1e-12);
+        assert!(damped.iterations > full.iterations);
+        for (a, b) in full.twists.iter().zip(&damped.twists) {
+            assert!((a - b).abs() < 1e-7, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn z_dna_relaxes_about_its_alternating_twists() {
+        let builder = DnaBuilder::new("CGCG", HelixForm::Z).expect("valid sequence");
+        let feedback = |coupling| TorsionFeedback { fit: GridFit::new(3.0, 1.5), coupling, tolerance: 1e-9, ..TorsionFeedback::new(builder.clone()) };
+        assert_eq!(feedback(0.0).run().expect("small grid").twists, [-9.0, -51.0, -9.0]);
+        let profile = feedback(STRONG).run().expect("small grid");
+        assert!(profile.converged);
+        for ((&t, &phi), base) in profile.twists.iter().zip(&profile.potentials).zip([-9.0, -51.0, -9.0]) {
+            assert!((t - RelativisticSolver::compute_metric_torsion_with(base, phi, STRONG)).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn non_finite_coupling_diverges() {
+        assert!(matches!(feedback(f64::NAN).run(), Err(GqbmeError::TorsionDiverged { iteration: 1 })));
+        assert!(matches!(feedback(f64::INFINITY).run(), Err(GqbmeError::TorsionDiverged { iteration: 1 })));
+    }
+
+    #[test]
+    fn oversized_grid_is_an_error() {
+        let feedback = TorsionFeedback { fit: GridFit::new(3.0, 1e-6), ..feedback(0.0) };
+        assert!(matches!(feedback.run(), Err(GqbmeError::GridTooLarge { .. })));
+    }
+}